@@ -15,6 +15,9 @@ pub const OPERATOR_CERT: CheckedEnv<String> = CheckedEnv::new("AGENT_OPERATOR_CE
 /// Enables Prometheus metrics export point and sets its address.
 pub const METRICS: CheckedEnv<SocketAddr> = CheckedEnv::new("MIRRORD_AGENT_METRICS");
 
+/// Enables OpenTelemetry trace export and sets the OTLP (gRPC) collector endpoint.
+pub const OTLP_ENDPOINT: CheckedEnv<String> = CheckedEnv::new("MIRRORD_AGENT_OTLP_ENDPOINT");
+
 /// Used to inform the agent that the target pod is in a mesh.
 pub const IN_SERVICE_MESH: CheckedEnv<bool> = CheckedEnv::new("MIRRORD_AGENT_IN_SERVICE_MESH");
 
@@ -41,6 +44,18 @@ pub const DNS_TIMEOUT: CheckedEnv<u32> = CheckedEnv::new("MIRRORD_AGENT_DNS_TIME
 /// Sets a hard limit on DNS query attempts.
 pub const DNS_ATTEMPTS: CheckedEnv<u32> = CheckedEnv::new("MIRRORD_AGENT_DNS_ATTEMPTS");
 
+/// Sets the budget (in bytes) for data read on behalf of a single client, but not yet sent to it.
+pub const CLIENT_MAX_PENDING_BYTES: CheckedEnv<u32> =
+    CheckedEnv::new("MIRRORD_AGENT_CLIENT_MAX_PENDING_BYTES");
+
+/// Sets a limit on in-progress DNS queries and outgoing connection attempts of a single client.
+pub const CLIENT_MAX_IN_FLIGHT_REQUESTS: CheckedEnv<u32> =
+    CheckedEnv::new("MIRRORD_AGENT_CLIENT_MAX_IN_FLIGHT_REQUESTS");
+
+/// Sets how many file operations of a single client can be executed concurrently.
+pub const CLIENT_FILE_WORKERS: CheckedEnv<u32> =
+    CheckedEnv::new("MIRRORD_AGENT_CLIENT_FILE_WORKERS");
+
 /// Used in incoming traffic redirection to produce correct iptables rules.
 pub const POD_IPS: CheckedEnv<Vec<IpAddr>> = CheckedEnv::new("MIRRORD_AGENT_POD_IPS");
 
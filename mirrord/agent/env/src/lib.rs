@@ -1,4 +1,6 @@
-//! This crate defines the mirrord-agent environment as it is prepared in its Pod spec.
+//! This crate defines the mirrord-agent environment as it is prepared in its Pod spec, and the
+//! part of the target's environment that is not passed to the local application (see
+//! [`target_env`]).
 //!
 //! Beware that any changes made here must be backward compatible (except for changes to
 //! [`mesh::MeshVendor`]).
@@ -18,3 +20,4 @@ pub mod checked_env;
 pub mod envs;
 pub mod mesh;
 pub mod steal_tls;
+pub mod target_env;
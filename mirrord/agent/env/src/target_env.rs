@@ -0,0 +1,47 @@
+//! Filtering of the target's environment before it is passed to the local application.
+
+/// Environment variables of the target that are never passed to the local application, as they
+/// describe the target's own filesystem and toolchains (e.g. `PATH` and `HOME`).
+///
+/// Applied by the agent when it reads the environment of the target process, and by the CLI when
+/// it resolves the environment with the Kubernetes API.
+pub const EXCLUDED_ENV_VARS: &[&str] = &[
+    "BUNDLER_ORIG_BUNDLER_ORIG_MANPATH",
+    "BUNDLER_ORIG_BUNDLER_VERSION",
+    "BUNDLER_ORIG_BUNDLE_BIN_PATH",
+    "BUNDLER_ORIG_BUNDLE_GEMFILE",
+    "BUNDLER_ORIG_GEM_HOME",
+    "BUNDLER_ORIG_MANPATH",
+    "BUNDLER_ORIG_PATH",
+    "BUNDLER_ORIG_RB_USER_INSTALL",
+    "BUNDLER_ORIG_RUBYLIB",
+    "BUNDLER_ORIG_RUBYOPT",
+    "BUNDLER_VERSION",
+    "BUNDLE_APP_CONFIG",
+    "BUNDLE_BIN_PATH",
+    "BUNDLE_FORCE_RUBY_PLATFORM",
+    "BUNDLE_GEMFILE",
+    "BUNDLE_GEM_PATH",
+    "BUNDLE_PATH",
+    "BUNDLE_WITHOUT",
+    "CATALINA_HOME",
+    "CLASSPATH",
+    "DOTNET_EnableDiagnostics",
+    "DOTNET_STARTUP_HOOKS",
+    "GEM_HOME",
+    "GEM_PATH",
+    "GOPATH",
+    "GOMODCACHE",
+    "HOME",
+    "HOMEPATH",
+    "JAVA_EXE",
+    "JAVA_HOME",
+    "JAVA_TOOL_OPTIONS",
+    "PATH",
+    "PWD",
+    "PYTHONPATH",
+    "RUBYLIB",
+    "RUBYOPT",
+    "RUST_LOG",
+    "_JAVA_OPTIONS",
+];
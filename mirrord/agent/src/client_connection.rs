@@ -4,15 +4,13 @@ use std::{
     sync::Arc,
 };
 
-use actix_codec::Framed;
-use futures::{SinkExt, TryStreamExt};
-use mirrord_protocol::{ClientMessage, DaemonCodec, DaemonMessage};
+use mirrord_protocol::{ClientMessage, DaemonMessage};
+use mirrord_protocol_io::{Agent, Connection, ProtocolError, TxHandle};
 use mirrord_tls_util::{GetSanError, HasSubjectAlternateNames};
 use thiserror::Error;
 use tokio::net::TcpStream;
 use tokio_rustls::{
     TlsConnector,
-    client::TlsStream,
     rustls::{ClientConfig, RootCertStore, pki_types::ServerName},
 };
 use tracing::Level;
@@ -82,9 +80,14 @@ pub(crate) enum TlsSetupError {
 }
 
 /// Wrapper over client's network connection with the agent.
+///
+/// Messages are written through a [`Connection`], which tracks the compression switched on by the
+/// client and interleaves the queues returned from [`Self::tx_handle`], so that control messages
+/// (e.g. pongs) are not stuck behind bulk transfers.
 pub struct ClientConnection {
-    framed: ConnectionFramed,
+    connection: Connection<Agent>,
     client_id: ClientId,
+    uses_tls: bool,
 }
 
 impl ClientConnection {
@@ -97,39 +100,77 @@ impl ClientConnection {
         client_id: u32,
         tls: Option<AgentTlsConnector>,
     ) -> io::Result<Self> {
-        let framed = match tls {
+        let uses_tls = tls.is_some();
+        let connection = match tls {
             Some(connector) => {
                 let tls_stream = connector
                     .inner
                     .connect(connector.server_name.clone(), stream)
                     .await?;
 
-                ConnectionFramed::Tls(Box::new(Framed::new(tls_stream, DaemonCodec::default())))
+                Connection::from_stream(tls_stream).await
             }
-            None => ConnectionFramed::Tcp(Framed::new(stream, DaemonCodec::default())),
-        };
+            None => Connection::from_stream(stream).await,
+        }
+        .map_err(|ProtocolError::IO(error)| error)?;
 
-        Ok(Self { framed, client_id })
+        Ok(Self {
+            connection,
+            client_id,
+            uses_tls,
+        })
     }
 
-    /// Sends a [`DaemonMessage`] to the client.
+    /// Sends a [`DaemonMessage`] to the client, through the default queue.
+    ///
+    /// Fails if the connection is already closed.
     #[tracing::instrument(level = "trace", err)]
-    pub async fn send(&mut self, message: DaemonMessage) -> io::Result<()> {
-        match &mut self.framed {
-            ConnectionFramed::Tcp(framed) => framed.send(message).await?,
-            ConnectionFramed::Tls(framed) => framed.send(message).await?,
+    pub async fn send(&self, message: DaemonMessage) -> io::Result<()> {
+        if self.connection.is_closed() {
+            return Err(io::ErrorKind::BrokenPipe.into());
         }
 
+        self.connection.send(message).await;
+
         Ok(())
     }
 
-    /// Receives a [`ClientMessage`] from the client.
-    #[tracing::instrument(level = "trace", err)]
-    pub async fn receive(&mut self) -> io::Result<Option<ClientMessage>> {
-        match &mut self.framed {
-            ConnectionFramed::Tcp(framed) => framed.try_next().await,
-            ConnectionFramed::Tls(framed) => framed.try_next().await,
+    /// Sends a [`DaemonMessage`] to the client, through a queue returned from
+    /// [`Self::tx_handle`].
+    ///
+    /// Fails if the connection is already closed.
+    #[tracing::instrument(level = "trace", skip(queue), err)]
+    pub async fn send_through(
+        &self,
+        queue: &TxHandle<Agent>,
+        message: DaemonMessage,
+    ) -> io::Result<()> {
+        if self.connection.is_closed() {
+            return Err(io::ErrorKind::BrokenPipe.into());
         }
+
+        queue.send(message).await;
+
+        Ok(())
+    }
+
+    /// Returns a handle to a new queue, messages sent through it are delivered in order, but may
+    /// be interleaved with the messages from other queues.
+    pub fn tx_handle(&self) -> TxHandle<Agent> {
+        self.connection.tx_handle()
+    }
+
+    /// Closes the connection, waiting until all queued messages are sent.
+    pub async fn close(self) {
+        self.connection.close().await
+    }
+
+    /// Receives a [`ClientMessage`] from the client.
+    ///
+    /// Returns [`None`] when the connection is closed, errors are logged by the [`Connection`].
+    #[tracing::instrument(level = "trace")]
+    pub async fn receive(&mut self) -> Option<ClientMessage> {
+        self.connection.recv().await
     }
 }
 
@@ -137,25 +178,16 @@ impl fmt::Debug for ClientConnection {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ClientConnection")
             .field("client_id", &self.client_id)
-            .field(
-                "uses_tls",
-                &matches!(self.framed, ConnectionFramed::Tls(..)),
-            )
+            .field("uses_tls", &self.uses_tls)
             .finish()
     }
 }
 
-/// Enum wraps whole [`Framed`] instead of just [`TcpStream`]/[`TlsStream`], so we don't have to
-/// implement [`AsyncRead`](actix_codec::AsyncRead) and [`AsyncWrite`](actix_codec::AsyncWrite).
-enum ConnectionFramed {
-    Tcp(Framed<TcpStream, DaemonCodec>),
-    Tls(Box<Framed<TlsStream<TcpStream>, DaemonCodec>>),
-}
-
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
 
+    use actix_codec::Framed;
     use futures::StreamExt;
     use mirrord_protocol::ClientCodec;
     use tokio::net::{TcpListener, TcpStream};
@@ -195,7 +227,7 @@ mod test {
         tokio::join!(
             async move {
                 let stream = TcpStream::connect(addr).await.unwrap();
-                let mut connection = ClientConnection::new(stream, 0, Some(connector))
+                let connection = ClientConnection::new(stream, 0, Some(connector))
                     .await
                     .unwrap();
                 connection
@@ -2,13 +2,27 @@ use std::{collections::HashMap, sync::Arc};
 
 use crate::{
     error::AgentResult,
-    runtime::{Container, ContainerInfo, ContainerRuntime},
+    runtime::{
+        Container, ContainerInfo, ContainerRuntime, ContainerRuntimeError, ContainerSnapshot,
+        SnapshotMount,
+    },
+    util::path_resolver::InTargetPathResolver,
 };
 
+/// How the agent accesses the filesystem of the container.
+#[derive(Debug)]
+enum ContainerRoot {
+    /// The container process is running, its filesystem is available under `/proc/{pid}/root`.
+    Process(u64),
+    /// The container process is not running (e.g. it's crash-looping), its root filesystem
+    /// snapshot is mounted read-only in the agent.
+    Snapshot(SnapshotMount),
+}
+
 #[derive(Debug)]
 struct Inner {
-    /// Cached process ID of the container.
-    pid: u64,
+    /// Cached process ID of the container, or the mounted root filesystem snapshot.
+    root: ContainerRoot,
     /// Cached environment of the container.
     raw_env: HashMap<String, String>,
 }
@@ -21,18 +35,52 @@ pub(crate) struct ContainerHandle(Arc<Inner>);
 
 impl ContainerHandle {
     /// Retrieve info about the container and initialize this struct.
+    ///
+    /// When the container process is not running, falls back to mounting the container's root
+    /// filesystem snapshot, so that file operations still work.
     #[tracing::instrument(level = "trace")]
     pub(crate) async fn new(container: Container) -> AgentResult<Self> {
-        let ContainerInfo { pid, env: raw_env } = container.get_info().await?;
+        let (root, raw_env) = match container.get_info().await {
+            Ok(ContainerInfo { pid, env }) => (ContainerRoot::Process(pid), env),
+            Err(error @ ContainerRuntimeError::NotRunning(..)) => {
+                tracing::warn!(
+                    %error,
+                    "Failed to get the target container process, \
+                    falling back to its root filesystem snapshot.",
+                );
+
+                let ContainerSnapshot { layers, env } =
+                    container.get_snapshot().await.map_err(|snapshot_error| {
+                        tracing::warn!(%snapshot_error, "Failed to get the root filesystem snapshot.");
+                        error
+                    })?;
 
-        let inner = Inner { pid, raw_env };
+                (ContainerRoot::Snapshot(SnapshotMount::new(&layers)?), env)
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let inner = Inner { root, raw_env };
 
         Ok(Self(inner.into()))
     }
 
-    /// Return the process ID of the container.
-    pub(crate) fn pid(&self) -> u64 {
-        self.0.pid
+    /// Return the process ID of the container, [`None`] if the container process is not running.
+    pub(crate) fn pid(&self) -> Option<u64> {
+        match &self.0.root {
+            ContainerRoot::Process(pid) => Some(*pid),
+            ContainerRoot::Snapshot(..) => None,
+        }
+    }
+
+    /// Return a resolver for paths in the container filesystem.
+    pub(crate) fn path_resolver(&self) -> InTargetPathResolver {
+        match &self.0.root {
+            ContainerRoot::Process(pid) => InTargetPathResolver::new(*pid),
+            ContainerRoot::Snapshot(mount) => {
+                InTargetPathResolver::with_root_path(mount.path().to_path_buf())
+            }
+        }
     }
 
     /// Return environment variables from the container.
@@ -31,7 +31,10 @@ use tokio::{
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, warn};
 
-use crate::{error::AgentResult, metrics::DNS_REQUEST_COUNT, task::status::BgTaskStatus};
+use crate::{
+    error::AgentResult, limits::ClientLimits, metrics::DNS_REQUEST_COUNT,
+    task::status::BgTaskStatus,
+};
 
 #[derive(Debug)]
 pub(crate) enum ClientGetAddrInfoRequest {
@@ -53,6 +56,8 @@ impl ClientGetAddrInfoRequest {
 pub(crate) struct DnsCommand {
     request: ClientGetAddrInfoRequest,
     response_tx: oneshot::Sender<Result<DnsLookup, ResolveErrorKindInternal>>,
+    /// Limits of the client that issued the request.
+    limits: ClientLimits,
 }
 
 /// Background task for resolving hostnames to IP addresses.
@@ -201,14 +206,13 @@ impl DnsWorker {
         let timeout = self.timeout;
         let attempts = self.attempts;
         let support_ipv6 = self.support_ipv6;
+        let request = message.request.into_v2();
+        let limits = message.limits;
 
-        let handle = self.tasks.spawn(Self::do_lookup(
-            etc_path,
-            message.request.into_v2(),
-            attempts,
-            timeout,
-            support_ipv6,
-        ));
+        let handle = self.tasks.spawn(async move {
+            let _permit = limits.acquire_request().await;
+            Self::do_lookup(etc_path, request, attempts, timeout, support_ipv6).await
+        });
         self.response_txs.insert(handle.id(), message.response_tx);
 
         DNS_REQUEST_COUNT.fetch_add(1, Ordering::Relaxed);
@@ -268,14 +272,21 @@ pub(crate) struct DnsApi {
     /// [`DnsWorker`] processes all requests concurrently, so we use a combination of [`oneshot`]
     /// channels and [`FuturesOrdered`] to preserve order of responses.
     responses: FuturesOrdered<oneshot::Receiver<Result<DnsLookup, ResolveErrorKindInternal>>>,
+    /// Caps the amount of concurrent lookups of the client.
+    limits: ClientLimits,
 }
 
 impl DnsApi {
-    pub(crate) fn new(task_status: BgTaskStatus, task_sender: Sender<DnsCommand>) -> Self {
+    pub(crate) fn new(
+        task_status: BgTaskStatus,
+        task_sender: Sender<DnsCommand>,
+        limits: ClientLimits,
+    ) -> Self {
         Self {
             task_status,
             request_tx: task_sender,
             responses: Default::default(),
+            limits,
         }
     }
 
@@ -291,6 +302,7 @@ impl DnsApi {
         let command = DnsCommand {
             request,
             response_tx,
+            limits: self.limits.clone(),
         };
         if self.request_tx.send(command).await.is_err() {
             return Err(self.task_status.wait_assert_running().await);
@@ -21,8 +21,10 @@ use mirrord_agent_iptables::{
     error::{IPTablesError, IPTablesResult},
 };
 use mirrord_protocol::{
-    ClientMessage, DaemonMessage, GetEnvVarsRequest, ResponseError, dns::ReverseDnsLookupResponse,
+    ClientMessage, DaemonMessage, GetEnvVarsRequest, IdentifiedFileRequest, ResponseError,
+    dns::ReverseDnsLookupResponse,
 };
+use mirrord_protocol_io::{Agent, TxHandle};
 use tokio::{
     net::{TcpListener, TcpSocket, TcpStream},
     process::Command,
@@ -43,16 +45,20 @@ use crate::{
     dns::{self, DnsApi},
     env,
     error::{AgentError, AgentResult},
-    file::FileManager,
+    file::FileWorkerPool,
     incoming::MirrorHandle,
+    limits::ClientLimits,
     metrics,
     mirror::TcpMirrorApi,
     namespace::NamespaceType,
+    otel,
     outgoing::{TcpOutgoingApi, UdpOutgoingApi},
     runtime::{self, get_container},
     steal::{StealerCommand, TcpStealerApi},
     task::{BgTaskRuntime, RuntimeNamespace, status::BgTaskStatus},
-    util::{ClientId, protocol_version::ClientProtocolVersion},
+    util::{
+        ClientId, path_resolver::InTargetPathResolver, protocol_version::ClientProtocolVersion,
+    },
 };
 
 mod setup;
@@ -78,6 +84,12 @@ or the previous agent failed to clean up before exit. \
 The leftover rules were cleaned and the agent is starting. \
 To allow concurrent sessions, consider using the operator available in mirrord for Teams.";
 
+/// Error message sent to the client when it uses a network feature, but the target container is
+/// not running (files are served from its rootfs snapshot).
+const TARGET_NOT_RUNNING_MESSAGE: &str = "The target container is not running, only its files are \
+available. Incoming traffic, outgoing traffic and DNS would not reach the target's network. \
+Disable `feature.network` to use mirrord with this target.";
+
 /// Keeps track of next client id.
 /// Stores common data used when serving client connections.
 /// Can be cheaply cloned and passed to per-client background tasks.
@@ -143,7 +155,7 @@ impl State {
             cli::Mode::Targetless => (false, None),
         };
 
-        let network_runtime = match container.as_ref().map(ContainerHandle::pid) {
+        let network_runtime = match container.as_ref().and_then(ContainerHandle::pid) {
             Some(pid) if ephemeral.not() => {
                 BgTaskRuntime::spawn(Some(RuntimeNamespace::new(pid, NamespaceType::Net)))
             }
@@ -152,17 +164,31 @@ impl State {
         }
         .await?;
 
-        let env_pid = match container.as_ref().map(ContainerHandle::pid) {
-            Some(pid) => pid.to_string(),
-            None => "self".to_string(),
-        };
-        let environ_path = PathBuf::from("/proc").join(env_pid).join("environ");
-        match env::get_proc_environ(environ_path).await {
-            Ok(environ) => env.extend(environ.into_iter()),
-            Err(err) => {
-                error!("Failed to get process environment variables: {err:?}");
-            }
+        if container
+            .as_ref()
+            .is_some_and(|container| container.pid().is_none())
+            && ephemeral.not()
+        {
+            warn!(
+                "Target container process is not running, serving files from its rootfs snapshot \
+                with network features disabled"
+            );
+        }
+
+        // When the target container process is not running, we only have the env from its spec.
+        let env_pid = match &container {
+            Some(container) => container.pid().map(|pid| pid.to_string()),
+            None => Some("self".to_string()),
         };
+        if let Some(env_pid) = env_pid {
+            let environ_path = PathBuf::from("/proc").join(env_pid).join("environ");
+            match env::get_proc_environ(environ_path).await {
+                Ok(environ) => env.extend(environ.into_iter()),
+                Err(err) => {
+                    error!("Failed to get process environment variables: {err:?}");
+                }
+            };
+        }
 
         Ok(State {
             next_client_id: Default::default(),
@@ -174,9 +200,37 @@ impl State {
         })
     }
 
-    /// Return the process ID of the target container if there is one.
+    /// Return the process ID of the target container if there is one, and its process is running.
     pub fn container_pid(&self) -> Option<u64> {
-        self.container.as_ref().map(ContainerHandle::pid)
+        self.container.as_ref().and_then(ContainerHandle::pid)
+    }
+
+    /// Return the process ID of the target container if its network namespace should be used by
+    /// the network features (steal, sniff, iptables), [`None`] when targetless or when
+    /// [`Self::is_network_unavailable`].
+    pub fn network_pid(&self) -> Option<u64> {
+        if self.is_network_unavailable() {
+            return None;
+        }
+
+        self.container_pid()
+    }
+
+    /// Whether there is a target container, but its network namespace is unavailable, as its
+    /// process is not running.
+    ///
+    /// Network features would run in the agent's own namespace then, so they're disabled, see
+    /// [`TARGET_NOT_RUNNING_MESSAGE`].
+    fn is_network_unavailable(&self) -> bool {
+        self.ephemeral.not() && self.container.is_some() && self.container_pid().is_none()
+    }
+
+    /// Return a resolver for paths in the target container filesystem, [`None`] when targetless.
+    pub fn path_resolver(&self) -> Option<InTargetPathResolver> {
+        self.container
+            .as_ref()
+            .map(ContainerHandle::path_resolver)
+            .or_else(|| self.ephemeral.then(|| InTargetPathResolver::new(1)))
     }
 
     pub async fn serve_client_connection(
@@ -261,11 +315,38 @@ struct BackgroundTasks {
     mirror_handle: Option<MirrorHandle>,
 }
 
+/// Separate queues of the [`ClientConnection`] for the messages coming from the background tasks,
+/// so that e.g. a big file transfer doesn't hold back the stolen traffic.
+struct ResponseQueues {
+    mirror: TxHandle<Agent>,
+    steal: TxHandle<Agent>,
+    tcp_outgoing: TxHandle<Agent>,
+    udp_outgoing: TxHandle<Agent>,
+    dns: TxHandle<Agent>,
+    files: TxHandle<Agent>,
+}
+
+impl ResponseQueues {
+    fn new(connection: &ClientConnection) -> Self {
+        Self {
+            mirror: connection.tx_handle(),
+            steal: connection.tx_handle(),
+            tcp_outgoing: connection.tx_handle(),
+            udp_outgoing: connection.tx_handle(),
+            dns: connection.tx_handle(),
+            files: connection.tx_handle(),
+        }
+    }
+}
+
 struct ClientConnectionHandler {
     id: ClientId,
-    /// Handles mirrord's file operations, see [`FileManager`].
-    file_manager: FileManager,
+    /// Handles mirrord's file operations, see [`FileWorkerPool`].
+    file_pool: FileWorkerPool,
     connection: ClientConnection,
+    /// Responses to the client messages go through the default queue of the [`ClientConnection`],
+    /// everything else through one of these.
+    queues: ResponseQueues,
     /// [`None`] when targetless.
     tcp_mirror_api: Option<TcpMirrorApi>,
     /// [`None`] when targetless.
@@ -291,34 +372,31 @@ impl ClientConnectionHandler {
     #[tracing::instrument(level = Level::TRACE, skip(connection, bg_tasks, state), err)]
     pub async fn new(
         id: ClientId,
-        mut connection: ClientConnection,
+        connection: ClientConnection,
         bg_tasks: BackgroundTasks,
         state: State,
     ) -> AgentResult<Self> {
         let protocol_version = ClientProtocolVersion::default();
 
-        let pid = state.container_pid();
+        let limits = ClientLimits::new();
 
-        let file_manager = FileManager::new(pid.or_else(|| state.ephemeral.then_some(1)));
+        let file_pool = FileWorkerPool::new(state.path_resolver(), limits.clone())?;
 
         let tcp_mirror_api = bg_tasks
             .mirror_handle
             .map(|mirror_handle| TcpMirrorApi::new(mirror_handle, protocol_version.clone()));
-        let tcp_stealer_api = Self::create_stealer_api(
-            id,
-            protocol_version.clone(),
-            bg_tasks.stealer,
-            &mut connection,
-        )
-        .await?;
-        let dns_api = Self::create_dns_api(bg_tasks.dns);
+        let tcp_stealer_api =
+            Self::create_stealer_api(id, protocol_version.clone(), bg_tasks.stealer, &connection)
+                .await?;
+        let dns_api = Self::create_dns_api(bg_tasks.dns, limits.clone());
 
-        let tcp_outgoing_api = TcpOutgoingApi::new(&state.network_runtime);
-        let udp_outgoing_api = UdpOutgoingApi::new(&state.network_runtime);
+        let tcp_outgoing_api = TcpOutgoingApi::new(&state.network_runtime, &limits);
+        let udp_outgoing_api = UdpOutgoingApi::new(&state.network_runtime, &limits);
 
         let client_handler = Self {
             id,
-            file_manager,
+            file_pool,
+            queues: ResponseQueues::new(&connection),
             connection,
             tcp_mirror_api,
             tcp_stealer_api,
@@ -339,7 +417,7 @@ impl ClientConnectionHandler {
         id: ClientId,
         protocol_version: ClientProtocolVersion,
         task: BackgroundTask<StealerCommand>,
-        connection: &mut ClientConnection,
+        connection: &ClientConnection,
     ) -> AgentResult<Option<TcpStealerApi>> {
         match task {
             BackgroundTask::Running(stealer_status, stealer_sender) => {
@@ -361,10 +439,10 @@ impl ClientConnectionHandler {
         }
     }
 
-    fn create_dns_api(task: BackgroundTask<DnsCommand>) -> DnsApi {
+    fn create_dns_api(task: BackgroundTask<DnsCommand>, limits: ClientLimits) -> DnsApi {
         match task {
             BackgroundTask::Running(task_status, task_sender) => {
-                DnsApi::new(task_status, task_sender)
+                DnsApi::new(task_status, task_sender, limits)
             }
             BackgroundTask::Disabled => unreachable!("dns task is never disabled"),
         }
@@ -378,7 +456,7 @@ impl ClientConnectionHandler {
         let error = loop {
             select! {
                 message = self.connection.receive() => {
-                    let Some(message) = message? else {
+                    let Some(message) = message else {
                         debug!("Client {} disconnected", self.id);
                         return Ok(());
                     };
@@ -403,7 +481,7 @@ impl ClientConnectionHandler {
                     }}
                 }, if self.tcp_mirror_api.is_some() => match message {
                     Ok(message) => {
-                        self.respond(message).await?;
+                        self.respond_through(&self.queues.mirror, message).await?;
                     }
                     Err(e) => break e,
                 },
@@ -414,29 +492,41 @@ impl ClientConnectionHandler {
                         unreachable!()
                     }}
                 }, if self.tcp_stealer_api.is_some() => match message {
-                    Ok(message) => self.respond(message).await?,
+                    Ok(message) => self.respond_through(&self.queues.steal, message).await?,
                     Err(e) => break e,
                 },
                 message = self.tcp_outgoing_api.recv_from_task() => match message {
                     Ok(message) => {
                         // Being explicit here.
-                        // Throttle permits should be dropped only when the message has been sent and flushed.
+                        // Throttle permits should be dropped only when the message has been queued,
+                        // the queue is bounded, so this still slows down the task.
                         let _throttle = message.throttle;
-                        self.respond(message.message).await?
+                        self.respond_through(&self.queues.tcp_outgoing, message.message).await?
                     },
                     Err(e) => break e,
                 },
                 message = self.udp_outgoing_api.recv_from_task() => match message {
                     Ok(message) => {
                         // Being explicit here.
-                        // Throttle permits should be dropped only when the message has been sent and flushed.
+                        // Throttle permits should be dropped only when the message has been queued,
+                        // the queue is bounded, so this still slows down the task.
                         let _throttle = message.throttle;
-                        self.respond(DaemonMessage::UdpOutgoing(message.message)).await?
+                        self.respond_through(&self.queues.udp_outgoing, DaemonMessage::UdpOutgoing(message.message)).await?
                     },
                     Err(e) => break e,
                 },
                 message = self.dns_api.recv() => match message {
-                    Ok(message) => self.respond(DaemonMessage::GetAddrInfoResponse(message)).await?,
+                    Ok(message) => self.respond_through(&self.queues.dns, DaemonMessage::GetAddrInfoResponse(message)).await?,
+                    Err(e) => break e,
+                },
+                message = self.file_pool.recv() => match message {
+                    Ok(Some(message)) => {
+                        // Being explicit here.
+                        // Read bytes are taken from the client's budget until queued.
+                        let _throttle = message.throttle;
+                        self.respond_through(&self.queues.files, DaemonMessage::IdentifiedFile(message.message)).await?
+                    },
+                    Ok(None) => {}
                     Err(e) => break e,
                 },
                 // message = self.vpn_api.daemon_message() => match message{
@@ -456,7 +546,7 @@ impl ClientConnectionHandler {
 
     /// Sends a [`DaemonMessage`] response to the connected client (`mirrord-layer`).
     #[tracing::instrument(level = "trace", skip(self))]
-    async fn respond(&mut self, response: DaemonMessage) -> AgentResult<()> {
+    async fn respond(&self, response: DaemonMessage) -> AgentResult<()> {
         if matches!(&response, DaemonMessage::LogMessage(..)) && self.ready_for_logs.not() {
             return Ok(());
         }
@@ -464,15 +554,52 @@ impl ClientConnectionHandler {
         self.connection.send(response).await.map_err(Into::into)
     }
 
+    /// Same as [`Self::respond`], but sends the message through the given queue of the
+    /// [`ClientConnection`].
+    #[tracing::instrument(level = "trace", skip(self, queue))]
+    async fn respond_through(
+        &self,
+        queue: &TxHandle<Agent>,
+        response: DaemonMessage,
+    ) -> AgentResult<()> {
+        if matches!(&response, DaemonMessage::LogMessage(..)) && self.ready_for_logs.not() {
+            return Ok(());
+        }
+
+        self.connection
+            .send_through(queue, response)
+            .await
+            .map_err(Into::into)
+    }
+
     /// Handles incoming messages from the connected client (`mirrord-layer`).
     ///
-    /// Returns `false` if the client disconnected.
+    /// Returns `false` if the client disconnected, or was sent [`DaemonMessage::Close`] and should
+    /// be disconnected.
     #[tracing::instrument(level = Level::TRACE, skip(self), ret, err(level = Level::DEBUG))]
     async fn handle_client_message(&mut self, message: ClientMessage) -> AgentResult<bool> {
+        if self.state.is_network_unavailable()
+            && matches!(
+                message,
+                ClientMessage::Tcp(..)
+                    | ClientMessage::TcpSteal(..)
+                    | ClientMessage::TcpOutgoing(..)
+                    | ClientMessage::UdpOutgoing(..)
+                    | ClientMessage::GetAddrInfoRequest(..)
+                    | ClientMessage::GetAddrInfoRequestV2(..)
+                    | ClientMessage::ReverseDnsLookup(..)
+            )
+        {
+            self.respond(DaemonMessage::Close(TARGET_NOT_RUNNING_MESSAGE.into()))
+                .await?;
+            return Ok(false);
+        }
+
         match message {
             ClientMessage::FileRequest(req) => {
-                if let Some(response) = self.file_manager.handle_message(req)? {
-                    self.respond(DaemonMessage::File(response))
+                if let Some(response) = self.file_pool.handle_request(req).await? {
+                    let _throttle = response.throttle;
+                    self.respond(DaemonMessage::File(response.message))
                         .await
                         .inspect_err(|fail| {
                             error!(
@@ -482,6 +609,9 @@ impl ClientConnectionHandler {
                         })?
                 }
             }
+            ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest { id, request }) => {
+                self.file_pool.start_request(id, request).await?
+            }
             ClientMessage::TcpOutgoing(layer_message) => {
                 self.tcp_outgoing_api.send_to_task(layer_message).await?
             }
@@ -578,6 +708,12 @@ impl ClientConnectionHandler {
                 ))
                 .await?;
             }
+            ClientMessage::SwitchCompression(algorithm) => {
+                // The client compresses its messages from now on, and so do we, starting right
+                // after this response.
+                self.respond(DaemonMessage::SwitchCompressionResponse(algorithm))
+                    .await?;
+            }
             ClientMessage::ReadyForLogs => {
                 self.ready_for_logs = true;
             }
@@ -614,12 +750,13 @@ pub async fn notify_client_about_dirty_iptables(
     // ungracefully).
     match first_connection {
         Ok(Ok((stream, ..))) => {
-            let mut connection = ClientConnection::new(stream, 0, tls_connector).await?;
+            let connection = ClientConnection::new(stream, 0, tls_connector).await?;
             connection
                 .send(DaemonMessage::Close(
                     DIRTY_IPTABLES_ERROR_MESSAGE.to_string(),
                 ))
                 .await?;
+            connection.close().await;
         }
 
         Ok(Err(error)) => {
@@ -772,8 +909,9 @@ async fn start_agent(args: Args) -> AgentResult<()> {
     // Check that chain names won't conflict with another agent or failed cleanup.
     // This check is only relevant if we have a target.
     // If we don't have any target, the agent should be running in a fresh network namespace,
-    // and you should **not** expect that it can access iptables.
-    if let Some(target_pid) = state.container_pid() {
+    // and you should **not** expect that it can access iptables. The same goes for a target that
+    // is not running.
+    if let Some(target_pid) = state.network_pid() {
         let leftover_rules = state
             .network_runtime
             .handle()
@@ -829,7 +967,7 @@ async fn start_agent(args: Args) -> AgentResult<()> {
         });
     }
 
-    let (stealer, mirror_handle) = match state.container_pid() {
+    let (stealer, mirror_handle) = match state.network_pid() {
         None => (BackgroundTask::Disabled, None),
         Some(pid) => {
             let (steal_handle, mirror_handle) = setup::start_traffic_redirector(
@@ -1091,8 +1229,14 @@ pub async fn main() -> AgentResult<()> {
     rustls::crypto::CryptoProvider::install_default(rustls::crypto::aws_lc_rs::default_provider())
         .expect("Failed to install crypto provider");
 
+    let (otlp_layer, otlp_error) = match otel::layer() {
+        Ok(layer) => (layer, None),
+        Err(error) => (None, Some(error)),
+    };
+
     if envs::JSON_LOG.from_env_or_default() {
         tracing_subscriber::registry()
+            .with(otlp_layer)
             .with(
                 tracing_subscriber::fmt::layer()
                     .with_thread_ids(true)
@@ -1103,6 +1247,7 @@ pub async fn main() -> AgentResult<()> {
             .init();
     } else {
         tracing_subscriber::registry()
+            .with(otlp_layer)
             .with(
                 tracing_subscriber::fmt::layer()
                     .with_thread_ids(true)
@@ -1119,12 +1264,20 @@ pub async fn main() -> AgentResult<()> {
         env!("CARGO_PKG_VERSION")
     );
 
+    if let Some(error) = otlp_error {
+        warn!(%error, "Failed to set up trace export, traces will not be exported");
+    }
+
     let args = cli::parse_args();
     let second_process = std::env::var(CHILD_PROCESS_ENV).is_ok();
 
-    if args.mode.is_targetless() || second_process {
+    let result = if args.mode.is_targetless() || second_process {
         start_agent(args).await
     } else {
         start_iptable_guard(args).await
-    }
+    };
+
+    otel::shutdown();
+
+    result
 }
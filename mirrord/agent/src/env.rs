@@ -3,6 +3,7 @@ use std::{
     path::PathBuf,
 };
 
+use mirrord_agent_env::target_env::EXCLUDED_ENV_VARS;
 use mirrord_protocol::{RemoteEnvVars, RemoteResult};
 use tokio::io::AsyncReadExt;
 use wildmatch::WildMatch;
@@ -25,54 +26,12 @@ impl EnvFilter {
                 .collect()
         };
 
-        let exclude = {
-            let mut exclude = vec![
-                WildMatch::new("BUNDLER_ORIG_BUNDLER_ORIG_MANPATH"),
-                WildMatch::new("BUNDLER_ORIG_BUNDLER_VERSION"),
-                WildMatch::new("BUNDLER_ORIG_BUNDLE_BIN_PATH"),
-                WildMatch::new("BUNDLER_ORIG_BUNDLE_GEMFILE"),
-                WildMatch::new("BUNDLER_ORIG_GEM_HOME"),
-                WildMatch::new("BUNDLER_ORIG_MANPATH"),
-                WildMatch::new("BUNDLER_ORIG_PATH"),
-                WildMatch::new("BUNDLER_ORIG_RB_USER_INSTALL"),
-                WildMatch::new("BUNDLER_ORIG_RUBYLIB"),
-                WildMatch::new("BUNDLER_ORIG_RUBYOPT"),
-                WildMatch::new("BUNDLER_VERSION"),
-                WildMatch::new("BUNDLE_APP_CONFIG"),
-                WildMatch::new("BUNDLE_BIN_PATH"),
-                WildMatch::new("BUNDLE_FORCE_RUBY_PLATFORM"),
-                WildMatch::new("BUNDLE_GEMFILE"),
-                WildMatch::new("BUNDLE_GEM_PATH"),
-                WildMatch::new("BUNDLE_PATH"),
-                WildMatch::new("BUNDLE_WITHOUT"),
-                WildMatch::new("CATALINA_HOME"),
-                WildMatch::new("CLASSPATH"),
-                WildMatch::new("DOTNET_EnableDiagnostics"),
-                WildMatch::new("DOTNET_STARTUP_HOOKS"),
-                WildMatch::new("GEM_HOME"),
-                WildMatch::new("GEM_PATH"),
-                WildMatch::new("GOPATH"),
-                WildMatch::new("GOMODCACHE"),
-                WildMatch::new("HOME"),
-                WildMatch::new("HOMEPATH"),
-                WildMatch::new("JAVA_EXE"),
-                WildMatch::new("JAVA_HOME"),
-                WildMatch::new("JAVA_TOOL_OPTIONS"),
-                WildMatch::new("PATH"),
-                WildMatch::new("PWD"),
-                WildMatch::new("PYTHONPATH"),
-                WildMatch::new("RUBYLIB"),
-                WildMatch::new("RUBYOPT"),
-                WildMatch::new("RUST_LOG"),
-                WildMatch::new("_JAVA_OPTIONS"),
-            ];
-
-            for selector in &filter_env_vars {
-                exclude.push(WildMatch::new(selector));
-            }
-
-            exclude
-        };
+        let exclude = EXCLUDED_ENV_VARS
+            .iter()
+            .copied()
+            .chain(filter_env_vars.iter().map(String::as_str))
+            .map(WildMatch::new)
+            .collect();
 
         EnvFilter { include, exclude }
     }
@@ -68,6 +68,9 @@ pub(crate) enum AgentError {
 
     #[error(transparent)]
     Timeout(#[from] tokio::time::error::Elapsed),
+
+    #[error("File operation workers exited unexpectedly")]
+    FileWorkersExited,
 }
 
 pub(crate) type AgentResult<T, E = AgentError> = std::result::Result<T, E>;
@@ -4,7 +4,7 @@ use std::{
     collections::{HashMap, VecDeque, hash_map::Entry},
     fs::{File, OpenOptions, ReadDir, read_link},
     io::{self, SeekFrom, prelude::*},
-    iter::{Enumerate, Peekable},
+    iter::{Enumerate, Peekable, StepBy},
     ops::RangeInclusive,
     os::{
         fd::{AsRawFd, RawFd},
@@ -24,6 +24,10 @@ use crate::{
     error::AgentResult, metrics::OPEN_FD_COUNT, util::path_resolver::InTargetPathResolver,
 };
 
+mod pool;
+
+pub(crate) use pool::FileWorkerPool;
+
 #[derive(Debug)]
 pub enum RemoteFile {
     File(File),
@@ -78,7 +82,8 @@ pub(crate) struct FileManager {
     open_files: HashMap<u64, RemoteFile>,
     dir_streams: HashMap<u64, Enumerate<ReadDir>>,
     getdents_streams: HashMap<u64, Peekable<GetDEnts64Stream>>,
-    fds_iter: RangeInclusive<u64>,
+    /// Remote fds this manager hands out, see [`FileWorkerPool`].
+    fds_iter: StepBy<RangeInclusive<u64>>,
 }
 
 impl Drop for FileManager {
@@ -245,15 +250,16 @@ impl FileManager {
     }
 
     #[tracing::instrument(level = Level::TRACE, ret)]
-    pub fn new(pid: Option<u64>) -> Self {
-        let path_resolver = pid.map(InTargetPathResolver::new);
-
+    pub fn new(
+        path_resolver: Option<InTargetPathResolver>,
+        fds_iter: StepBy<RangeInclusive<u64>>,
+    ) -> Self {
         Self {
             path_resolver,
             open_files: Default::default(),
             dir_streams: Default::default(),
             getdents_streams: Default::default(),
-            fds_iter: (0..=u64::MAX),
+            fds_iter,
         }
     }
 
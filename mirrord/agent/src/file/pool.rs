@@ -0,0 +1,383 @@
+//! Concurrent execution of file operations, see [`FileWorkerPool`].
+
+use std::{
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use mirrord_agent_env::envs;
+use mirrord_protocol::{FileRequest, FileResponse, IdentifiedFileResponse, file::*};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{Level, Span};
+
+use super::FileManager;
+use crate::{
+    error::{AgentError, AgentResult},
+    limits::{ClientLimits, PendingBytes},
+    outgoing::Throttled,
+    util::path_resolver::InTargetPathResolver,
+};
+
+/// Configured with [`envs::CLIENT_FILE_WORKERS`].
+static FILE_WORKERS: LazyLock<usize> = LazyLock::new(|| {
+    envs::CLIENT_FILE_WORKERS
+        .try_from_env()
+        .inspect_err(|error| {
+            tracing::warn!(
+                %error,
+                "{} is invalid, using default",
+                envs::CLIENT_FILE_WORKERS.name
+            )
+        })
+        .ok()
+        .flatten()
+        .map(|value| usize::try_from(value).unwrap_or(usize::MAX))
+        .unwrap_or(FileWorkerPool::DEFAULT_WORKERS)
+});
+
+/// Where the result of a [`Job`] should go.
+enum Reply {
+    /// Plain [`FileRequest`], the client task waits for the result.
+    Inline(oneshot::Sender<JobResult>),
+    /// [`IdentifiedFileRequest`](mirrord_protocol::IdentifiedFileRequest), the result goes to
+    /// [`FileWorkerPool::recv`].
+    Identified(u64),
+}
+
+/// A single file operation sent to a worker thread.
+struct Job {
+    request: FileRequest,
+    reply: Reply,
+}
+
+/// Result of a [`Job`], holds the bytes budget taken by file reads until it's sent to the client.
+type JobResult = AgentResult<Option<Throttled<FileResponse>>>;
+
+/// Result of an identified [`Job`].
+type WorkerResponse = (u64, JobResult);
+
+/// Handle to a worker thread of the [`FileWorkerPool`].
+struct Worker {
+    tx: mpsc::Sender<Job>,
+    /// Jobs sent to this worker that are not finished yet.
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// Executes file operations of a single client on a set of worker threads, each owning its own
+/// [`FileManager`].
+///
+/// The remote fds are partitioned between the workers (worker `i` hands out fds `i`, `i + n`,
+/// `i + 2n`...), and every request that refers to an fd goes to the worker that owns it. This
+/// keeps operations on the same fd in order. Requests that only have a path go to the worker with
+/// the fewest jobs in progress.
+///
+/// File reads take from the client's bytes budget (see [`ClientLimits::reserve_file_read`]), the
+/// responses carry the budget and should be dropped only after they're sent to the client.
+///
+/// Dropping the pool stops the workers, which closes all of the client's remote files.
+pub(crate) struct FileWorkerPool {
+    workers: Vec<Worker>,
+    responses: mpsc::UnboundedReceiver<WorkerResponse>,
+}
+
+impl FileWorkerPool {
+    /// Default for [`envs::CLIENT_FILE_WORKERS`].
+    pub(crate) const DEFAULT_WORKERS: usize = 4;
+
+    /// Capacity of a single worker's job queue.
+    const WORKER_QUEUE_SIZE: usize = 128;
+
+    /// Spawns the workers, their count is configured from the agent env.
+    #[tracing::instrument(level = Level::TRACE, err)]
+    pub(crate) fn new(
+        path_resolver: Option<InTargetPathResolver>,
+        limits: ClientLimits,
+    ) -> AgentResult<Self> {
+        Self::with_workers(path_resolver, limits, *FILE_WORKERS)
+    }
+
+    pub(crate) fn with_workers(
+        path_resolver: Option<InTargetPathResolver>,
+        limits: ClientLimits,
+        workers: usize,
+    ) -> AgentResult<Self> {
+        let count = workers.max(1);
+        let (responses_tx, responses) = mpsc::unbounded_channel();
+
+        let workers = (0..count)
+            .map(|index| {
+                let manager = FileManager::new(
+                    path_resolver.clone(),
+                    (index as u64..=u64::MAX).step_by(count),
+                );
+                let (tx, rx) = mpsc::channel(Self::WORKER_QUEUE_SIZE);
+                let in_flight = Arc::new(AtomicUsize::new(0));
+
+                let responses_tx = responses_tx.clone();
+                let limits = limits.clone();
+                let worker_in_flight = in_flight.clone();
+                let span = Span::current();
+                thread::Builder::new()
+                    .name(format!("file-worker-{index}"))
+                    .spawn(move || {
+                        span.in_scope(|| {
+                            Self::run_worker(manager, limits, rx, responses_tx, worker_in_flight)
+                        })
+                    })?;
+
+                Ok(Worker { tx, in_flight })
+            })
+            .collect::<AgentResult<Vec<_>>>()?;
+
+        Ok(Self { workers, responses })
+    }
+
+    fn run_worker(
+        mut manager: FileManager,
+        limits: ClientLimits,
+        mut jobs: mpsc::Receiver<Job>,
+        responses: mpsc::UnboundedSender<WorkerResponse>,
+        in_flight: Arc<AtomicUsize>,
+    ) {
+        while let Some(Job { mut request, reply }) = jobs.blocking_recv() {
+            let throttle = Self::reserve_read(&limits, &mut request);
+            let result = manager
+                .handle_message(request)
+                .map(|response| response.map(|message| Throttled { message, throttle }));
+            in_flight.fetch_sub(1, Ordering::Relaxed);
+
+            let sent = match reply {
+                Reply::Inline(tx) => tx.send(result).is_ok(),
+                Reply::Identified(id) => responses.send((id, result)).is_ok(),
+            };
+
+            if !sent {
+                break;
+            }
+        }
+    }
+
+    /// Shortens a file read to the client's bytes budget, returns the budget taken for it.
+    fn reserve_read(limits: &ClientLimits, request: &mut FileRequest) -> Option<PendingBytes> {
+        let (FileRequest::Read(ReadFileRequest { buffer_size, .. })
+        | FileRequest::ReadLimited(ReadLimitedFileRequest { buffer_size, .. })) = request
+        else {
+            return None;
+        };
+
+        let (size, pending) = limits.reserve_file_read(*buffer_size);
+        *buffer_size = size;
+
+        Some(pending)
+    }
+
+    /// Executes a plain [`FileRequest`] and waits for its response.
+    ///
+    /// Used for clients that expect file responses in the order of their requests.
+    pub(crate) async fn handle_request(&self, request: FileRequest) -> JobResult {
+        let (tx, rx) = oneshot::channel();
+        self.dispatch(request, Reply::Inline(tx)).await?;
+        rx.await.map_err(|_| AgentError::FileWorkersExited)?
+    }
+
+    /// Starts executing an identified [`FileRequest`], its response can be later received with
+    /// [`FileWorkerPool::recv`].
+    pub(crate) async fn start_request(&self, id: u64, request: FileRequest) -> AgentResult<()> {
+        self.dispatch(request, Reply::Identified(id)).await
+    }
+
+    /// Receives the next finished identified request.
+    ///
+    /// Returns [`None`] for requests that don't produce a response, e.g. [`FileRequest::Close`].
+    pub(crate) async fn recv(&mut self) -> AgentResult<Option<Throttled<IdentifiedFileResponse>>> {
+        let (id, result) = self
+            .responses
+            .recv()
+            .await
+            .ok_or(AgentError::FileWorkersExited)?;
+
+        Ok(result?.map(|Throttled { message, throttle }| Throttled {
+            message: IdentifiedFileResponse {
+                id,
+                response: message,
+            },
+            throttle,
+        }))
+    }
+
+    async fn dispatch(&self, request: FileRequest, reply: Reply) -> AgentResult<()> {
+        let worker = match Self::request_fd(&request) {
+            Some(fd) => &self.workers[(fd % self.workers.len() as u64) as usize],
+            None => self
+                .workers
+                .iter()
+                .min_by_key(|worker| worker.in_flight.load(Ordering::Relaxed))
+                .expect("pool always has at least one worker"),
+        };
+
+        worker.in_flight.fetch_add(1, Ordering::Relaxed);
+        worker
+            .tx
+            .send(Job { request, reply })
+            .await
+            .map_err(|_| AgentError::FileWorkersExited)
+    }
+
+    /// Returns the remote fd the request operates on, if any.
+    fn request_fd(request: &FileRequest) -> Option<u64> {
+        match request {
+            FileRequest::Read(ReadFileRequest { remote_fd, .. })
+            | FileRequest::ReadLimited(ReadLimitedFileRequest { remote_fd, .. })
+            | FileRequest::WriteLimited(WriteLimitedFileRequest { remote_fd, .. })
+            | FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd })
+            | FileRequest::ReadDir(ReadDirRequest { remote_fd })
+            | FileRequest::ReadDirBatch(ReadDirBatchRequest { remote_fd, .. })
+            | FileRequest::CloseDir(CloseDirRequest { remote_fd })
+            | FileRequest::GetDEnts64(GetDEnts64Request { remote_fd, .. }) => Some(*remote_fd),
+            FileRequest::Seek(SeekFileRequest { fd, .. })
+            | FileRequest::Write(WriteFileRequest { fd, .. })
+            | FileRequest::Close(CloseFileRequest { fd })
+            | FileRequest::XstatFs(XstatFsRequest { fd })
+            | FileRequest::XstatFsV2(XstatFsRequestV2 { fd })
+            | FileRequest::Ftruncate(FtruncateRequest { fd, .. })
+            | FileRequest::Futimens(FutimensRequest { fd, .. })
+            | FileRequest::Fchown(FchownRequest { fd, .. })
+            | FileRequest::Fchmod(FchmodRequest { fd, .. }) => Some(*fd),
+            FileRequest::OpenRelative(OpenRelativeFileRequest { relative_fd, .. }) => {
+                Some(*relative_fd)
+            }
+            FileRequest::MakeDirAt(MakeDirAtRequest { dirfd, .. }) => Some(*dirfd),
+            FileRequest::UnlinkAt(UnlinkAtRequest { dirfd, .. }) => *dirfd,
+            FileRequest::Xstat(XstatRequest { fd, .. }) => *fd,
+            FileRequest::Open(..)
+            | FileRequest::Access(..)
+            | FileRequest::ReadLink(..)
+            | FileRequest::MakeDir(..)
+            | FileRequest::RemoveDir(..)
+            | FileRequest::Unlink(..)
+            | FileRequest::StatFs(..)
+            | FileRequest::StatFsV2(..)
+            | FileRequest::Rename(..) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn open(path: &str) -> FileRequest {
+        FileRequest::Open(OpenFileRequest {
+            path: PathBuf::from(path),
+            open_options: OpenOptionsInternal {
+                read: true,
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Fds handed out by different workers never collide, and requests that use them reach the
+    /// worker that owns the file.
+    #[tokio::test]
+    async fn fds_are_routed_to_their_worker() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("file"), b"pool").unwrap();
+        let resolver = InTargetPathResolver::with_root_path(root.path().to_path_buf());
+
+        let mut pool =
+            FileWorkerPool::with_workers(Some(resolver), ClientLimits::with_limits(0, 1), 3)
+                .unwrap();
+
+        for id in 0..6 {
+            pool.start_request(id, open("/file")).await.unwrap();
+        }
+
+        let mut fds = Vec::new();
+        for _ in 0..6 {
+            let response = pool.recv().await.unwrap().unwrap().message;
+            let FileResponse::Open(Ok(OpenFileResponse { fd })) = response.response else {
+                panic!("unexpected response {response:?}");
+            };
+            fds.push(fd);
+        }
+        fds.sort();
+        fds.dedup();
+        assert_eq!(fds.len(), 6);
+
+        for fd in fds {
+            let response = pool
+                .handle_request(FileRequest::Read(ReadFileRequest {
+                    remote_fd: fd,
+                    buffer_size: 16,
+                }))
+                .await
+                .unwrap()
+                .map(|response| response.message);
+            assert_eq!(
+                response,
+                Some(FileResponse::Read(Ok(ReadFileResponse {
+                    bytes: b"pool".to_vec().into(),
+                    read_amount: 4,
+                })))
+            );
+        }
+    }
+
+    /// Reads running at the same time share the client's bytes budget, until their responses are
+    /// sent.
+    #[tokio::test]
+    async fn concurrent_reads_take_pending_bytes() {
+        let budget = ClientLimits::MIN_MAX_PENDING_BYTES;
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("file"), vec![0; budget * 4]).unwrap();
+        let resolver = InTargetPathResolver::with_root_path(root.path().to_path_buf());
+        let limits = ClientLimits::with_limits(0, 1);
+
+        let mut pool = FileWorkerPool::with_workers(Some(resolver), limits.clone(), 4).unwrap();
+
+        for id in 0..4 {
+            pool.start_request(id, open("/file")).await.unwrap();
+        }
+        let mut fds = Vec::new();
+        for _ in 0..4 {
+            let response = pool.recv().await.unwrap().unwrap().message;
+            let FileResponse::Open(Ok(OpenFileResponse { fd })) = response.response else {
+                panic!("unexpected response {response:?}");
+            };
+            fds.push(fd);
+        }
+
+        for (id, fd) in fds.iter().copied().enumerate() {
+            let request = FileRequest::Read(ReadFileRequest {
+                remote_fd: fd,
+                buffer_size: budget as u64,
+            });
+            pool.start_request(id as u64, request).await.unwrap();
+        }
+        let mut reads = Vec::new();
+        for _ in 0..4 {
+            reads.push(pool.recv().await.unwrap().unwrap());
+        }
+
+        let read_bytes = reads
+            .iter()
+            .map(|read| match &read.message.response {
+                FileResponse::Read(Ok(response)) => response.read_amount as usize,
+                other => panic!("unexpected response {other:?}"),
+            })
+            .sum::<usize>();
+        assert!(
+            read_bytes <= budget + 3 * ClientLimits::MIN_FILE_READ_SIZE,
+            "reads took {read_bytes} bytes"
+        );
+        assert_eq!(limits.pending_bytes().available_permits(), 0);
+
+        drop(reads);
+        assert_eq!(limits.pending_bytes().available_permits(), budget);
+    }
+}
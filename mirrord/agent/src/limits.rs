@@ -0,0 +1,246 @@
+//! Per-client resource limits, see [`ClientLimits`].
+
+use std::sync::{Arc, LazyLock, atomic::Ordering};
+
+use mirrord_agent_env::envs;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError};
+
+use crate::metrics::{
+    CLIENT_IN_FLIGHT_REQUESTS, CLIENT_PENDING_BYTES, CLIENT_THROTTLED_COUNT, MetricGuard,
+};
+
+/// Configured with [`envs::CLIENT_MAX_PENDING_BYTES`].
+static MAX_PENDING_BYTES: LazyLock<usize> = LazyLock::new(|| {
+    envs::CLIENT_MAX_PENDING_BYTES
+        .try_from_env()
+        .inspect_err(|error| {
+            tracing::warn!(
+                %error,
+                "{} is invalid, using default",
+                envs::CLIENT_MAX_PENDING_BYTES.name
+            )
+        })
+        .ok()
+        .flatten()
+        .map(|value| usize::try_from(value).unwrap_or(usize::MAX))
+        .unwrap_or(ClientLimits::DEFAULT_MAX_PENDING_BYTES)
+});
+
+/// Configured with [`envs::CLIENT_MAX_IN_FLIGHT_REQUESTS`].
+static MAX_IN_FLIGHT_REQUESTS: LazyLock<usize> = LazyLock::new(|| {
+    envs::CLIENT_MAX_IN_FLIGHT_REQUESTS
+        .try_from_env()
+        .inspect_err(|error| {
+            tracing::warn!(
+                %error,
+                "{} is invalid, using default",
+                envs::CLIENT_MAX_IN_FLIGHT_REQUESTS.name
+            )
+        })
+        .ok()
+        .flatten()
+        .map(|value| usize::try_from(value).unwrap_or(usize::MAX))
+        .unwrap_or(ClientLimits::DEFAULT_MAX_IN_FLIGHT_REQUESTS)
+});
+
+/// Resource limits of a single agent client, shared by all of its feature tasks (file, outgoing
+/// and DNS), so that one noisy client can't starve the others.
+///
+/// Cheap to clone, clones share the limits.
+#[derive(Clone, Debug)]
+pub(crate) struct ClientLimits {
+    /// Budget for bytes read on behalf of the client (from outgoing connections and files), but
+    /// not yet sent to it.
+    pending_bytes: Arc<Semaphore>,
+    /// Cap on requests of the client that are in progress (DNS queries, outgoing connects).
+    in_flight_requests: Arc<Semaphore>,
+}
+
+impl ClientLimits {
+    /// Default for [`envs::CLIENT_MAX_PENDING_BYTES`].
+    pub(crate) const DEFAULT_MAX_PENDING_BYTES: usize = 1024 * 1024;
+
+    /// Lowest allowed value of [`envs::CLIENT_MAX_PENDING_BYTES`].
+    ///
+    /// The budget must cover a single read from an outgoing connection, otherwise reading from it
+    /// hangs forever (see `ThrottledStream`).
+    pub(crate) const MIN_MAX_PENDING_BYTES: usize = 64 * 1024;
+
+    /// Default for [`envs::CLIENT_MAX_IN_FLIGHT_REQUESTS`].
+    pub(crate) const DEFAULT_MAX_IN_FLIGHT_REQUESTS: usize = 128;
+
+    /// File reads are shortened to the available part of the bytes budget, but never below this.
+    pub(crate) const MIN_FILE_READ_SIZE: usize = 4 * 1024;
+
+    /// Creates new limits for a client, configured from the agent env.
+    pub(crate) fn new() -> Self {
+        Self::with_limits(*MAX_PENDING_BYTES, *MAX_IN_FLIGHT_REQUESTS)
+    }
+
+    pub(crate) fn with_limits(max_pending_bytes: usize, max_in_flight_requests: usize) -> Self {
+        Self {
+            pending_bytes: Arc::new(Semaphore::new(
+                max_pending_bytes.clamp(Self::MIN_MAX_PENDING_BYTES, Semaphore::MAX_PERMITS),
+            )),
+            in_flight_requests: Arc::new(Semaphore::new(
+                max_in_flight_requests.clamp(1, Semaphore::MAX_PERMITS),
+            )),
+        }
+    }
+
+    /// Budget for bytes read on behalf of the client, one permit per byte.
+    pub(crate) fn pending_bytes(&self) -> Arc<Semaphore> {
+        self.pending_bytes.clone()
+    }
+
+    /// Returns how many bytes should be read from a file for a client request of `requested`
+    /// bytes, along with the bytes budget taken for them.
+    ///
+    /// File reads are shortened to the available part of the bytes budget, so that they yield to
+    /// data that is already waiting to be sent to the client. They never wait for the budget, as
+    /// file requests can be answered while the client task is blocked on them. Instead, reads of
+    /// up to [`Self::MIN_FILE_READ_SIZE`] bytes are always allowed, and take only what's left of
+    /// the budget.
+    pub(crate) fn reserve_file_read(&self, requested: u64) -> (u64, PendingBytes) {
+        let requested = usize::try_from(requested).unwrap_or(usize::MAX);
+
+        loop {
+            let available = self.pending_bytes.available_permits();
+            let size = requested.min(available.max(Self::MIN_FILE_READ_SIZE));
+            let permits = u32::try_from(size.min(available)).unwrap_or(u32::MAX);
+
+            match self.pending_bytes.clone().try_acquire_many_owned(permits) {
+                Ok(permit) => return (size as u64, PendingBytes::new(permit)),
+                // Another read or connection took the budget in the meantime.
+                Err(TryAcquireError::NoPermits) => continue,
+                Err(TryAcquireError::Closed) => {
+                    // We never close the semaphore.
+                    panic!("client pending bytes semaphore should not be closed")
+                }
+            }
+        }
+    }
+
+    /// Waits until the client can start another request.
+    ///
+    /// The request counts as in progress until the returned [`RequestPermit`] is dropped.
+    pub(crate) async fn acquire_request(&self) -> RequestPermit {
+        let permit = match self.in_flight_requests.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(..) => {
+                let _throttled = MetricGuard::new(&CLIENT_THROTTLED_COUNT);
+
+                self.in_flight_requests
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    // We never close the semaphore.
+                    .expect("client requests semaphore should not be closed")
+            }
+        };
+
+        RequestPermit {
+            _permit: permit,
+            _in_flight: MetricGuard::new(&CLIENT_IN_FLIGHT_REQUESTS),
+        }
+    }
+}
+
+/// [`OwnedSemaphorePermit`]s for bytes that were read on behalf of a client, but not yet sent to
+/// it.
+///
+/// Tracked in [`CLIENT_PENDING_BYTES`].
+#[derive(Debug)]
+pub(crate) struct PendingBytes(OwnedSemaphorePermit);
+
+impl PendingBytes {
+    pub(crate) fn new(permit: OwnedSemaphorePermit) -> Self {
+        CLIENT_PENDING_BYTES.fetch_add(permit.num_permits(), Ordering::Relaxed);
+        Self(permit)
+    }
+}
+
+impl Drop for PendingBytes {
+    fn drop(&mut self) {
+        CLIENT_PENDING_BYTES.fetch_sub(self.0.num_permits(), Ordering::Relaxed);
+    }
+}
+
+/// Marks a client request as in progress, see [`ClientLimits::acquire_request`].
+#[derive(Debug)]
+pub(crate) struct RequestPermit {
+    _permit: OwnedSemaphorePermit,
+    _in_flight: MetricGuard,
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn in_flight_requests_are_capped() {
+        let limits = ClientLimits::with_limits(0, 2);
+
+        let first = limits.acquire_request().await;
+        let _second = limits.acquire_request().await;
+
+        let third = tokio::time::timeout(Duration::from_millis(100), limits.acquire_request());
+        assert!(third.await.is_err(), "third request should wait");
+
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(1), limits.acquire_request())
+            .await
+            .expect("request should be allowed after another one finished");
+    }
+
+    #[test]
+    fn file_reads_follow_pending_bytes() {
+        let limits = ClientLimits::with_limits(0, 1);
+        let budget = ClientLimits::MIN_MAX_PENDING_BYTES;
+
+        let (size, pending) = limits.reserve_file_read(100);
+        assert_eq!(size, 100);
+        drop(pending);
+
+        let (size, _pending) = limits.reserve_file_read(u64::MAX);
+        assert_eq!(size, budget as u64);
+        assert_eq!(limits.pending_bytes().available_permits(), 0);
+
+        let (size, _pending) = limits.reserve_file_read(u64::MAX);
+        assert_eq!(size, ClientLimits::MIN_FILE_READ_SIZE as u64);
+    }
+
+    /// Concurrent file reads share the bytes budget, which is returned when their responses are
+    /// sent.
+    #[test]
+    fn concurrent_file_reads_take_pending_bytes() {
+        let limits = ClientLimits::with_limits(0, 1);
+        let budget = ClientLimits::MIN_MAX_PENDING_BYTES;
+        let read_size = budget as u64 / 4;
+
+        let reads = std::thread::scope(|scope| {
+            (0..8)
+                .map(|_| scope.spawn(|| limits.reserve_file_read(read_size)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let total = reads.iter().map(|(size, _)| *size).sum::<u64>();
+        assert!(
+            total <= (budget + 4 * ClientLimits::MIN_FILE_READ_SIZE) as u64,
+            "reads took {total} bytes"
+        );
+        assert_eq!(
+            reads.iter().filter(|(size, _)| *size == read_size).count(),
+            4
+        );
+        assert_eq!(limits.pending_bytes().available_permits(), 0);
+
+        drop(reads);
+        assert_eq!(limits.pending_bytes().available_permits(), budget);
+    }
+}
@@ -20,9 +20,11 @@ mod error;
 mod file;
 mod http;
 mod incoming;
+mod limits;
 mod metrics;
 mod mirror;
 mod namespace;
+mod otel;
 mod outgoing;
 mod runtime;
 mod steal;
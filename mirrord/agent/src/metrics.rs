@@ -45,6 +45,18 @@ pub(crate) static TCP_OUTGOING_CONNECTION: AtomicUsize = AtomicUsize::new(0);
 
 pub(crate) static UDP_OUTGOING_CONNECTION: AtomicUsize = AtomicUsize::new(0);
 
+/// Bytes read on behalf of clients (from outgoing connections), that are waiting to be sent to
+/// them. Limited per client by `ClientLimits`.
+pub(crate) static CLIENT_PENDING_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// How many DNS queries and outgoing connection attempts of clients are in progress. Limited per
+/// client by `ClientLimits`.
+pub(crate) static CLIENT_IN_FLIGHT_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// How many client streams and requests are currently waiting, because their client used up its
+/// `ClientLimits`.
+pub(crate) static CLIENT_THROTTLED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
 /// Convenience trait for static metrics variables.
 ///
 /// We store them as [`AtomicUsize`], which is the correct type (they're all counters).
@@ -80,6 +92,9 @@ struct Metrics {
     redirected_requests: IntGauge,
     tcp_outgoing_connection: IntGauge,
     udp_outgoing_connection: IntGauge,
+    client_pending_bytes: IntGauge,
+    client_in_flight_requests: IntGauge,
+    client_throttled_count: IntGauge,
 }
 
 impl Metrics {
@@ -177,6 +192,30 @@ impl Metrics {
             IntGauge::with_opts(opts).expect("Valid at initialization!")
         };
 
+        let client_pending_bytes = {
+            let opts = Opts::new(
+                "mirrord_agent_client_pending_bytes",
+                "amount of bytes read on behalf of clients, waiting to be sent to them",
+            );
+            IntGauge::with_opts(opts).expect("Valid at initialization!")
+        };
+
+        let client_in_flight_requests = {
+            let opts = Opts::new(
+                "mirrord_agent_client_in_flight_requests",
+                "amount of in-progress dns and outgoing connect requests of clients",
+            );
+            IntGauge::with_opts(opts).expect("Valid at initialization!")
+        };
+
+        let client_throttled_count = {
+            let opts = Opts::new(
+                "mirrord_agent_client_throttled_count",
+                "amount of client streams and requests waiting on per-client resource limits",
+            );
+            IntGauge::with_opts(opts).expect("Valid at initialization!")
+        };
+
         registry
             .register(Box::new(client_count.clone()))
             .expect("Register must be valid at initialization!");
@@ -210,6 +249,15 @@ impl Metrics {
         registry
             .register(Box::new(udp_outgoing_connection.clone()))
             .expect("Register must be valid at initialization!");
+        registry
+            .register(Box::new(client_pending_bytes.clone()))
+            .expect("Register must be valid at initialization!");
+        registry
+            .register(Box::new(client_in_flight_requests.clone()))
+            .expect("Register must be valid at initialization!");
+        registry
+            .register(Box::new(client_throttled_count.clone()))
+            .expect("Register must be valid at initialization!");
 
         Self {
             registry,
@@ -224,6 +272,9 @@ impl Metrics {
             redirected_requests,
             tcp_outgoing_connection,
             udp_outgoing_connection,
+            client_pending_bytes,
+            client_in_flight_requests,
+            client_throttled_count,
         }
     }
 
@@ -246,6 +297,9 @@ impl Metrics {
             redirected_requests,
             tcp_outgoing_connection,
             udp_outgoing_connection,
+            client_pending_bytes,
+            client_in_flight_requests,
+            client_throttled_count,
         } = self;
 
         client_count.set(CLIENT_COUNT.load_as_i64());
@@ -259,6 +313,9 @@ impl Metrics {
         redirected_requests.set(REDIRECTED_REQUESTS.load_as_i64());
         tcp_outgoing_connection.set(TCP_OUTGOING_CONNECTION.load_as_i64());
         udp_outgoing_connection.set(UDP_OUTGOING_CONNECTION.load_as_i64());
+        client_pending_bytes.set(CLIENT_PENDING_BYTES.load_as_i64());
+        client_in_flight_requests.set(CLIENT_IN_FLIGHT_REQUESTS.load_as_i64());
+        client_throttled_count.set(CLIENT_THROTTLED_COUNT.load_as_i64());
 
         registry.gather()
     }
@@ -318,6 +375,7 @@ pub(crate) async fn start_metrics(
 }
 
 /// Allows for managing metrics in a RAII fashion.
+#[derive(Debug)]
 pub struct MetricGuard {
     metric: &'static AtomicUsize,
 }
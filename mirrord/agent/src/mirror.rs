@@ -9,9 +9,8 @@ use mirrord_protocol::{
     ConnectionId, DaemonMessage, LogMessage, Port, RequestId,
     tcp::{
         ChunkedRequest, ChunkedRequestBodyV1, ChunkedRequestStartV2, DaemonTcp,
-        HttpRequestMetadata, IncomingTrafficTransportType, InternalHttpBodyNew,
-        InternalHttpRequest, LayerTcp, MODE_AGNOSTIC_HTTP_REQUESTS, NewTcpConnectionV1,
-        NewTcpConnectionV2, TcpClose, TcpData,
+        IncomingTrafficTransportType, InternalHttpBodyNew, InternalHttpRequest, LayerTcp,
+        MODE_AGNOSTIC_HTTP_REQUESTS, NewTcpConnectionV1, NewTcpConnectionV2, TcpClose, TcpData,
     },
 };
 use tokio::task::JoinSet;
@@ -26,6 +25,7 @@ use crate::{
         IncomingStream, IncomingStreamItem, MirrorHandle, MirroredHttp, MirroredTraffic,
         RedirectorTaskError,
     },
+    otel,
     util::protocol_version::ClientProtocolVersion,
 };
 
@@ -279,10 +279,13 @@ impl TcpMirrorApi {
                     let message = ChunkedRequestStartV2 {
                         connection_id: id,
                         request_id: Self::REQUEST_ID,
-                        metadata: HttpRequestMetadata::V1 {
-                            source: http.info.peer_addr,
-                            destination: http.info.original_destination,
-                        },
+                        metadata: otel::http_request_metadata(
+                            &self.protocol_version,
+                            http.info.peer_addr,
+                            http.info.original_destination,
+                            &http.request_head.parts,
+                            false,
+                        ),
                         transport: http
                             .info
                             .tls_connector
@@ -0,0 +1,134 @@
+//! Optional OpenTelemetry trace export, enabled with [`envs::OTLP_ENDPOINT`].
+//!
+//! For every HTTP request sent to a client, the agent creates a span and passes its context in
+//! [`HttpRequestMetadata::V2`], so that the internal proxy can continue the trace.
+
+use std::{collections::HashMap, net::SocketAddr, ops::Not};
+
+use hyper::http::request::Parts;
+use mirrord_agent_env::envs;
+use mirrord_protocol::tcp::{HTTP_TRACE_CONTEXT_VERSION, HttpRequestMetadata};
+use opentelemetry::{
+    KeyValue,
+    trace::{TraceError, TracerProvider as _},
+};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    Resource,
+    propagation::TraceContextPropagator,
+    runtime::TokioCurrentThread,
+    trace::{Tracer, TracerProvider},
+};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::Registry;
+
+use crate::util::protocol_version::ClientProtocolVersion;
+
+/// Name of the agent in exported traces.
+const SERVICE_NAME: &str = "mirrord-agent";
+
+/// Prepares a layer that exports spans to the OpenTelemetry collector from
+/// [`envs::OTLP_ENDPOINT`], using OTLP over gRPC.
+///
+/// Returns [`None`] if the export is not enabled.
+pub(crate) fn layer() -> Result<Option<OpenTelemetryLayer<Registry, Tracer>>, TraceError> {
+    let Some(endpoint) = envs::OTLP_ENDPOINT.try_from_env().ok().flatten() else {
+        return Ok(None);
+    };
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    // The agent runs on a current thread runtime, so the exporter needs its own thread.
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, TokioCurrentThread)
+        .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)]))
+        .build();
+    let tracer = provider.tracer(SERVICE_NAME);
+
+    opentelemetry::global::set_tracer_provider(provider);
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Exports the remaining spans, if [`layer`] was installed.
+pub(crate) fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Prepares [`HttpRequestMetadata`] for an HTTP request that is about to be sent to a client.
+///
+/// If the client's [`mirrord_protocol`] version allows, the metadata carries the context of a new
+/// span for the request. The span continues the W3C trace context found in the request headers.
+pub(crate) fn http_request_metadata(
+    protocol_version: &ClientProtocolVersion,
+    source: SocketAddr,
+    destination: SocketAddr,
+    parts: &Parts,
+    stolen: bool,
+) -> HttpRequestMetadata {
+    if protocol_version.matches(&HTTP_TRACE_CONTEXT_VERSION).not() {
+        return HttpRequestMetadata::V1 {
+            source,
+            destination,
+        };
+    }
+
+    let span = tracing::info_span!(
+        "redirected_http_request",
+        stolen,
+        method = %parts.method,
+        uri = %parts.uri,
+        %source,
+        %destination,
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(&parts.headers))
+    });
+    span.set_parent(parent);
+
+    let mut trace_context = HashMap::new();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&span.context(), &mut trace_context)
+    });
+
+    HttpRequestMetadata::V2 {
+        source,
+        destination,
+        trace_context,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::Request;
+
+    use super::*;
+
+    #[test]
+    fn metadata_follows_protocol_version() {
+        let (parts, ()) = Request::get("/").body(()).unwrap().into_parts();
+        let source = "10.0.0.1:51000".parse().unwrap();
+        let destination = "10.0.0.2:80".parse().unwrap();
+
+        let old_client = "1.25.0".parse().unwrap();
+        assert_eq!(
+            http_request_metadata(&old_client, source, destination, &parts, true),
+            HttpRequestMetadata::V1 {
+                source,
+                destination
+            }
+        );
+
+        // Export is not enabled, so there's no trace context to propagate.
+        let new_client = "1.26.0".parse().unwrap();
+        let metadata = http_request_metadata(&new_client, source, destination, &parts, true);
+        assert!(matches!(metadata, HttpRequestMetadata::V2 { .. }));
+        assert_eq!(metadata.trace_context(), None);
+        assert_eq!(metadata.destination(), destination);
+    }
+}
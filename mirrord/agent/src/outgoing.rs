@@ -19,10 +19,7 @@ use streammap_ext::StreamMap;
 use tokio::{
     io::{self, AsyncWriteExt, ReadHalf, WriteHalf},
     select,
-    sync::{
-        OwnedSemaphorePermit, Semaphore,
-        mpsc::{self, Receiver, Sender, error::SendError},
-    },
+    sync::mpsc::{self, Receiver, Sender, error::SendError},
 };
 use tokio_stream::StreamExt;
 use tokio_util::io::ReaderStream;
@@ -30,6 +27,7 @@ use tracing::Level;
 
 use crate::{
     error::AgentResult,
+    limits::{ClientLimits, PendingBytes},
     metrics::TCP_OUTGOING_CONNECTION,
     outgoing::throttle::ThrottledStream,
     task::{
@@ -48,7 +46,7 @@ pub(crate) use udp::UdpOutgoingApi;
 pub(crate) struct Throttled<M> {
     pub(crate) message: M,
     /// This should be dropped **only** after sending [`Self::message`] to the client.
-    pub(crate) throttle: Option<OwnedSemaphorePermit>,
+    pub(crate) throttle: Option<PendingBytes>,
 }
 
 impl<M> From<M> for Throttled<M> {
@@ -80,7 +78,8 @@ impl TcpOutgoingApi {
     /// # Params
     ///
     /// * `runtime` - tokio runtime to spawn the background task on.
-    pub(crate) fn new(runtime: &BgTaskRuntime) -> Self {
+    /// * `limits` - resource limits of the client.
+    pub(crate) fn new(runtime: &BgTaskRuntime, limits: &ClientLimits) -> Self {
         // IMPORTANT: this makes tokio tasks spawn on `runtime`.
         // Do not remove this.
         let _rt = runtime.handle().enter();
@@ -89,8 +88,9 @@ impl TcpOutgoingApi {
         let (daemon_tx, daemon_rx) = mpsc::channel(1000);
 
         let pid = runtime.target_pid();
-        let task_status = tokio::spawn(TcpOutgoingTask::new(pid, layer_rx, daemon_tx).run())
-            .into_status("TcpOutgoingTask");
+        let task_status =
+            tokio::spawn(TcpOutgoingTask::new(pid, layer_rx, daemon_tx, limits.clone()).run())
+                .into_status("TcpOutgoingTask");
 
         Self {
             task_status,
@@ -132,7 +132,9 @@ struct TcpOutgoingTask {
     daemon_tx: Sender<Throttled<DaemonMessage>>,
     connects_v1: FuturesQueue<BoxFuture<'static, RemoteResult<Connected>>>,
     connects_v2: FuturesUnordered<BoxFuture<'static, (RemoteResult<Connected>, Uid)>>,
-    throttler: Arc<Semaphore>,
+    /// Limits incoming data we accumulate in memory before it's flushed to the client, and the
+    /// amount of concurrent connect attempts.
+    limits: ClientLimits,
 }
 
 impl Drop for TcpOutgoingTask {
@@ -155,11 +157,9 @@ impl fmt::Debug for TcpOutgoingTask {
 
 impl TcpOutgoingTask {
     /// Buffer size for reading from the outgoing connections.
-    const READ_BUFFER_SIZE: usize = 64 * 1024;
-    /// How much incoming data we can accumulate in memory, before it's flushed to the client.
     ///
-    /// This **must** be larger than [`Self::READ_BUFFER_SIZE`].
-    const THROTTLE_PERMITS: usize = 512 * 1024;
+    /// This **must not** be larger than [`ClientLimits::MIN_MAX_PENDING_BYTES`].
+    const READ_BUFFER_SIZE: usize = 64 * 1024;
 
     /// Timeout for connect attempts.
     ///
@@ -172,6 +172,7 @@ impl TcpOutgoingTask {
         pid: Option<u64>,
         layer_rx: Receiver<LayerTcpOutgoing>,
         daemon_tx: Sender<Throttled<DaemonMessage>>,
+        limits: ClientLimits,
     ) -> Self {
         Self {
             next_connection_id: 0,
@@ -182,7 +183,7 @@ impl TcpOutgoingTask {
             daemon_tx,
             connects_v1: Default::default(),
             connects_v2: Default::default(),
-            throttler: Arc::new(Semaphore::new(Self::THROTTLE_PERMITS)),
+            limits,
         }
     }
 
@@ -233,7 +234,7 @@ impl TcpOutgoingTask {
     async fn handle_connection_read(
         &mut self,
         connection_id: ConnectionId,
-        read: io::Result<Option<(Bytes, OwnedSemaphorePermit)>>,
+        read: io::Result<Option<(Bytes, PendingBytes)>>,
     ) -> Result<(), SendError<Throttled<DaemonMessage>>> {
         match read {
             // New bytes came in from a peer connection.
@@ -322,10 +323,15 @@ impl TcpOutgoingTask {
         Ok(())
     }
 
+    /// Connects to the given address, once the client is within its
+    /// [`ClientLimits::acquire_request`].
     async fn connect(
         remote_address: SocketAddress,
         target_pid: Option<u64>,
+        limits: ClientLimits,
     ) -> RemoteResult<Connected> {
+        let _permit = limits.acquire_request().await;
+
         let started_at = Instant::now();
         let socket_stream = tokio::time::timeout(
             Self::CONNECT_TIMEOUT,
@@ -363,7 +369,7 @@ impl TcpOutgoingTask {
                 connection_id,
                 ThrottledStream::new(
                     ReaderStream::with_capacity(read_half, Self::READ_BUFFER_SIZE),
-                    self.throttler.clone(),
+                    self.limits.pending_bytes(),
                 ),
             );
             TCP_OUTGOING_CONNECTION.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -398,7 +404,7 @@ impl TcpOutgoingTask {
             // We make connection to the requested address, split the stream into halves with
             // `io::split`, and put them into respective maps.
             LayerTcpOutgoing::Connect(LayerConnect { remote_address }) => {
-                let fut = Self::connect(remote_address, self.pid).boxed();
+                let fut = Self::connect(remote_address, self.pid, self.limits.clone()).boxed();
                 self.connects_v1.push(fut);
                 Ok(())
             }
@@ -407,7 +413,7 @@ impl TcpOutgoingTask {
                 uid,
                 remote_address,
             }) => {
-                let fut = Self::connect(remote_address, self.pid)
+                let fut = Self::connect(remote_address, self.pid, self.limits.clone())
                     .map(move |result| (result, uid))
                     .boxed();
                 self.connects_v2.push(fut);
@@ -7,16 +7,24 @@ use std::{
 
 use bytes::Bytes;
 use futures::{Stream, StreamExt};
-use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::sync::Semaphore;
 use tokio_util::sync::PollSemaphore;
 
+use crate::{
+    limits::PendingBytes,
+    metrics::{CLIENT_THROTTLED_COUNT, MetricGuard},
+};
+
 /// Wrapper over a [`Stream`] of [`Bytes`], that yields items only after acquiring
 /// [`Semaphore`] permit for each byte of data.
 ///
 /// E.g. if the inner stream yields 1024 bytes of data, this wrapper will yield a tuple of (data,
 /// 1024 permits).
 ///
-/// Allows for throttling consumption of incoming data.
+/// Allows for throttling consumption of incoming data. The [`Semaphore`] is shared by all streams
+/// of one client, see [`ClientLimits`](crate::limits::ClientLimits).
+///
+/// While waiting for permits, the stream is counted in [`CLIENT_THROTTLED_COUNT`].
 ///
 /// # Important
 ///
@@ -27,6 +35,7 @@ pub struct ThrottledStream<S> {
     inner: S,
     ready_data: Option<Bytes>,
     semaphore: PollSemaphore,
+    throttled: Option<MetricGuard>,
 }
 
 impl<S> ThrottledStream<S> {
@@ -35,6 +44,7 @@ impl<S> ThrottledStream<S> {
             inner,
             ready_data: None,
             semaphore: PollSemaphore::new(semaphore),
+            throttled: None,
         }
     }
 }
@@ -43,7 +53,7 @@ impl<S> Stream for ThrottledStream<S>
 where
     S: Stream<Item = io::Result<Bytes>> + Unpin,
 {
-    type Item = io::Result<(Bytes, OwnedSemaphorePermit)>;
+    type Item = io::Result<(Bytes, PendingBytes)>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
@@ -61,12 +71,17 @@ where
 
         let permits = u32::try_from(ready_data.len()).unwrap_or(u32::MAX);
         match this.semaphore.poll_acquire_many(cx, permits) {
-            Poll::Ready(Some(permits)) => Poll::Ready(Some(Ok((ready_data, permits)))),
+            Poll::Ready(Some(permits)) => {
+                this.throttled = None;
+                Poll::Ready(Some(Ok((ready_data, PendingBytes::new(permits)))))
+            }
             Poll::Ready(None) => Poll::Ready(Some(Err(io::Error::other(
                 "throttling semaphore is closed",
             )))),
             Poll::Pending => {
                 this.ready_data.replace(ready_data);
+                this.throttled
+                    .get_or_insert_with(|| MetricGuard::new(&CLIENT_THROTTLED_COUNT));
                 Poll::Pending
             }
         }
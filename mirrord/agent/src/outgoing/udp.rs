@@ -19,16 +19,14 @@ use tokio::{
     io,
     net::UdpSocket,
     select,
-    sync::{
-        OwnedSemaphorePermit, Semaphore,
-        mpsc::{self, Receiver, Sender, error::SendError},
-    },
+    sync::mpsc::{self, Receiver, Sender, error::SendError},
 };
 use tokio_util::{codec::BytesCodec, udp::UdpFramed};
 use tracing::Level;
 
 use crate::{
     error::AgentResult,
+    limits::{ClientLimits, PendingBytes},
     metrics::UDP_OUTGOING_CONNECTION,
     outgoing::{Throttled, throttle::ThrottledStream},
     task::{
@@ -51,7 +49,11 @@ struct UdpOutgoingTask {
     pid: Option<u64>,
     layer_rx: Receiver<LayerUdpOutgoing>,
     daemon_tx: Sender<Throttled<DaemonUdpOutgoing>>,
-    throttler: Arc<Semaphore>,
+    /// Limits incoming data we accumulate in memory before it's flushed to the client.
+    ///
+    /// The budget is never smaller than the maximal size of a UDP packet (64kb), see
+    /// [`ClientLimits::MIN_MAX_PENDING_BYTES`].
+    limits: ClientLimits,
 }
 
 impl Drop for UdpOutgoingTask {
@@ -73,15 +75,11 @@ impl fmt::Debug for UdpOutgoingTask {
 }
 
 impl UdpOutgoingTask {
-    /// How much incoming data we can accumulate in memory, before it's flushed to the client.
-    ///
-    /// This **must** be larger than maximal size of a UDP packet (64kb).
-    const THROTTLE_PERMITS: usize = 512 * 1024;
-
     fn new(
         pid: Option<u64>,
         layer_rx: Receiver<LayerUdpOutgoing>,
         daemon_tx: Sender<Throttled<DaemonUdpOutgoing>>,
+        limits: ClientLimits,
     ) -> Self {
         Self {
             next_connection_id: 0,
@@ -90,7 +88,7 @@ impl UdpOutgoingTask {
             pid,
             layer_rx,
             daemon_tx,
-            throttler: Arc::new(Semaphore::new(Self::THROTTLE_PERMITS)),
+            limits,
         }
     }
 
@@ -133,7 +131,7 @@ impl UdpOutgoingTask {
     async fn handle_connection_read(
         &mut self,
         connection_id: ConnectionId,
-        read: io::Result<Option<(Bytes, OwnedSemaphorePermit)>>,
+        read: io::Result<Option<(Bytes, PendingBytes)>>,
     ) -> Result<(), SendError<Throttled<DaemonUdpOutgoing>>> {
         match read {
             Ok(Some((read, permits))) => {
@@ -219,7 +217,7 @@ impl UdpOutgoingTask {
                 socket,
                 buffer: BytesMut::with_capacity(64 * 1024),
             },
-            self.throttler.clone(),
+            self.limits.pending_bytes(),
         );
 
         self.writers.insert(connection_id, (writer, peer_address));
@@ -335,7 +333,7 @@ pub(crate) struct UdpOutgoingApi {
 }
 
 impl UdpOutgoingApi {
-    pub(crate) fn new(runtime: &BgTaskRuntime) -> Self {
+    pub(crate) fn new(runtime: &BgTaskRuntime, limits: &ClientLimits) -> Self {
         // IMPORTANT: this makes tokio tasks spawn on `runtime`.
         // Do not remove this.
         let _rt = runtime.handle().enter();
@@ -343,9 +341,10 @@ impl UdpOutgoingApi {
         let (layer_tx, layer_rx) = mpsc::channel(1000);
         let (daemon_tx, daemon_rx) = mpsc::channel(1000);
 
-        let task_status =
-            tokio::spawn(UdpOutgoingTask::new(runtime.target_pid(), layer_rx, daemon_tx).run())
-                .into_status("UdpOutgoingTask");
+        let task_status = tokio::spawn(
+            UdpOutgoingTask::new(runtime.target_pid(), layer_rx, daemon_tx, limits.clone()).run(),
+        )
+        .into_status("UdpOutgoingTask");
 
         Self {
             task_status,
@@ -1,12 +1,15 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use bollard::{API_DEFAULT_VERSION, Docker, container::InspectContainerOptions};
 use containerd_client::{
     services::v1::{
-        GetContainerRequest, GetRequest, containers_client::ContainersClient,
+        Container as ContainerdRecord, GetContainerRequest, GetRequest,
+        containers_client::ContainersClient,
+        snapshots::{MountsRequest, snapshots_client::SnapshotsClient},
         tasks_client::TasksClient,
     },
     tonic::{Request, transport::Channel},
+    types::Mount,
     with_namespace,
 };
 use enum_dispatch::enum_dispatch;
@@ -20,8 +23,10 @@ use crate::{env::parse_raw_env, runtime::crio::CriOContainer};
 
 mod crio;
 mod error;
+mod snapshot;
 
 pub(crate) use error::{ContainerRuntimeError, ContainerRuntimeResult};
+pub(crate) use snapshot::{ContainerSnapshot, SnapshotMount};
 
 const CONTAINERD_DEFAULT_SOCK_PATH: &str = "/host/run/containerd/containerd.sock";
 const CONTAINERD_ALTERNATIVE_SOCK_PATH: &str = "/host/run/dockershim.sock";
@@ -57,7 +62,17 @@ impl ContainerInfo {
 #[enum_dispatch]
 pub(crate) trait ContainerRuntime {
     /// Get information about the container (pid, env).
+    ///
+    /// Fails if the container process is not running.
     async fn get_info(&self) -> ContainerRuntimeResult<ContainerInfo>;
+
+    /// Get the root filesystem layers of the container (and its env), without requiring the
+    /// container process to be running.
+    ///
+    /// Used when [`ContainerRuntime::get_info`] fails, see [`ContainerHandle::new`].
+    ///
+    /// [`ContainerHandle::new`]: crate::container_handle::ContainerHandle::new
+    async fn get_snapshot(&self) -> ContainerRuntimeResult<ContainerSnapshot>;
 }
 
 #[enum_dispatch(ContainerRuntime)]
@@ -125,10 +140,14 @@ impl ContainerRuntime for DockerContainer {
         let pid = inspect_response
             .state
             .and_then(|state| state.pid)
-            .and_then(|pid| if pid > 0 { Some(pid as u64) } else { None })
             .ok_or_else(|| {
                 ContainerRuntimeError::docker("pid not found in the runtime response")
             })?;
+        // Stopped containers report pid 0.
+        if pid <= 0 {
+            return Err(ContainerRuntimeError::NotRunning("docker"));
+        }
+        let pid = pid as u64;
 
         let raw_env = inspect_response
             .config
@@ -140,6 +159,10 @@ impl ContainerRuntime for DockerContainer {
 
         Ok(ContainerInfo::new(pid, env_vars))
     }
+
+    async fn get_snapshot(&self) -> ContainerRuntimeResult<ContainerSnapshot> {
+        Err(ContainerRuntimeError::SnapshotUnsupported("docker"))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -167,16 +190,15 @@ async fn connect(path: impl AsRef<std::path::Path>) -> ContainerRuntimeResult<Ch
 /// Connects to the given containerd socket
 /// and returns the client only if the given container
 /// exists.
+///
+/// The container does not need to have a running task.
 async fn connect_and_find_container(
     container_id: String,
     sock_path: impl AsRef<std::path::Path>,
 ) -> ContainerRuntimeResult<Channel> {
     let channel = connect(sock_path).await?;
-    let mut client = TasksClient::new(channel.clone());
-    let request = GetRequest {
-        container_id,
-        ..Default::default()
-    };
+    let mut client = ContainersClient::new(channel.clone());
+    let request = GetContainerRequest { id: container_id };
     let request = with_namespace!(request, DEFAULT_CONTAINERD_NAMESPACE);
     client
         .get(request)
@@ -186,9 +208,47 @@ async fn connect_and_find_container(
 }
 
 /// Extract from [`Spec`] struct the environment variables as HashMap<K,V>
-fn extract_env_from_containerd_spec(spec: &Spec) -> Option<HashMap<String, String>> {
+fn extract_env_from_spec(spec: &Spec) -> Option<HashMap<String, String>> {
     Some(parse_raw_env(spec.process().as_ref()?.env().as_ref()?))
 }
+
+/// Extract the root filesystem layers, topmost first, from the mounts of a containerd snapshot.
+///
+/// The overlayfs snapshotter returns a single `overlay` mount (or a `bind` mount for snapshots
+/// with a single layer), with host paths of the layers in its options.
+fn layers_from_containerd_mounts(mounts: &[Mount]) -> ContainerRuntimeResult<Vec<PathBuf>> {
+    let [mount] = mounts else {
+        return Err(ContainerRuntimeError::snapshot(format!(
+            "expected a single root filesystem mount, got {}",
+            mounts.len()
+        )));
+    };
+
+    match mount.r#type.as_str() {
+        "bind" => Ok(vec![snapshot::host_path(&mount.source)]),
+        "overlay" => {
+            let option = |name: &str| {
+                mount
+                    .options
+                    .iter()
+                    .find_map(|option| option.strip_prefix(name)?.strip_prefix('='))
+            };
+
+            let lower_dirs = option("lowerdir").ok_or_else(|| {
+                ContainerRuntimeError::snapshot("overlay mount has no `lowerdir` option")
+            })?;
+
+            Ok(option("upperdir")
+                .into_iter()
+                .chain(lower_dirs.split(':'))
+                .map(snapshot::host_path)
+                .collect())
+        }
+        other => Err(ContainerRuntimeError::snapshot(format!(
+            "unsupported snapshot mount type `{other}`"
+        ))),
+    }
+}
 impl ContainerdContainer {
     /// Get the containerd channel for a given container id.
     /// This is useful since we might have more than one
@@ -218,6 +278,37 @@ impl ContainerdContainer {
         let channel = self.get_channel().await?;
         Ok(ContainersClient::new(channel))
     }
+
+    /// Get the containerd record of the container, which holds its spec and the key of its root
+    /// filesystem snapshot.
+    async fn get_record(&self) -> ContainerRuntimeResult<ContainerdRecord> {
+        let mut client = self.get_container_client().await?;
+        let request = GetContainerRequest {
+            id: self.container_id.to_string(),
+        };
+        let request = with_namespace!(request, DEFAULT_CONTAINERD_NAMESPACE);
+
+        client
+            .get(request)
+            .await
+            .map_err(ContainerRuntimeError::containerd)?
+            .into_inner()
+            .container
+            .ok_or_else(|| {
+                ContainerRuntimeError::containerd("container not found in runtime response")
+            })
+    }
+}
+
+/// Parse the OCI [`Spec`] from the containerd record of the container.
+fn containerd_spec(record: &ContainerdRecord) -> ContainerRuntimeResult<Spec> {
+    record
+        .spec
+        .as_ref()
+        .ok_or_else(|| {
+            ContainerRuntimeError::containerd("container spec not found in runtime response")
+        })
+        .and_then(|s| serde_json::from_slice(&s.value).map_err(ContainerRuntimeError::containerd))
 }
 
 impl ContainerRuntime for ContainerdContainer {
@@ -232,7 +323,13 @@ impl ContainerRuntime for ContainerdContainer {
         let pid = client
             .get(request)
             .await
-            .map_err(ContainerRuntimeError::containerd)?
+            .map_err(|status| match status.code() {
+                // The task is deleted once the container process exits.
+                containerd_client::tonic::Code::NotFound => {
+                    ContainerRuntimeError::NotRunning("containerd")
+                }
+                _ => ContainerRuntimeError::containerd(status),
+            })?
             .into_inner()
             .process
             .ok_or_else(|| {
@@ -242,32 +339,43 @@ impl ContainerRuntime for ContainerdContainer {
             })?
             .pid;
 
-        let mut client = self.get_container_client().await?;
-        let request = GetContainerRequest {
-            id: self.container_id.to_string(),
-        };
-        let request = with_namespace!(request, DEFAULT_CONTAINERD_NAMESPACE);
+        // Stopped tasks report pid 0.
+        if pid == 0 {
+            return Err(ContainerRuntimeError::NotRunning("containerd"));
+        }
 
-        let spec: Spec = client
-            .get(request)
-            .await
-            .map_err(ContainerRuntimeError::containerd)?
-            .into_inner()
-            .container
-            .and_then(|c| c.spec)
-            .ok_or_else(|| {
-                ContainerRuntimeError::containerd("container spec not found in runtime response")
-            })
-            .and_then(|s| {
-                serde_json::from_slice(&s.value).map_err(ContainerRuntimeError::containerd)
-            })?;
+        let spec = containerd_spec(&self.get_record().await?)?;
 
-        let env_vars = extract_env_from_containerd_spec(&spec).ok_or_else(|| {
+        let env_vars = extract_env_from_spec(&spec).ok_or_else(|| {
             ContainerRuntimeError::containerd("env not found in container runtime response")
         })?;
 
         Ok(ContainerInfo::new(pid as u64, env_vars))
     }
+
+    async fn get_snapshot(&self) -> ContainerRuntimeResult<ContainerSnapshot> {
+        let record = self.get_record().await?;
+        let env = extract_env_from_spec(&containerd_spec(&record)?).unwrap_or_default();
+
+        let mut client = SnapshotsClient::new(self.get_channel().await?);
+        let request = MountsRequest {
+            snapshotter: record.snapshotter,
+            key: record.snapshot_key,
+        };
+        let request = with_namespace!(request, DEFAULT_CONTAINERD_NAMESPACE);
+
+        let mounts = client
+            .mounts(request)
+            .await
+            .map_err(ContainerRuntimeError::snapshot)?
+            .into_inner()
+            .mounts;
+
+        Ok(ContainerSnapshot {
+            layers: layers_from_containerd_mounts(&mounts)?,
+            env,
+        })
+    }
 }
 
 /// The agent is running as an ephemeral container.
@@ -384,4 +492,66 @@ impl ContainerRuntime for EphemeralContainer {
             std::env::vars().collect(),
         ))
     }
+
+    async fn get_snapshot(&self) -> ContainerRuntimeResult<ContainerSnapshot> {
+        Err(ContainerRuntimeError::SnapshotUnsupported("ephemeral"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mount(r#type: &str, source: &str, options: &[&str]) -> Mount {
+        Mount {
+            r#type: r#type.into(),
+            source: source.into(),
+            options: options.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn containerd_overlay_layers() {
+        let snapshots = "/var/lib/containerd/io.containerd.snapshotter.v1.overlayfs/snapshots";
+        let mounts = [mount(
+            "overlay",
+            "overlay",
+            &[
+                "index=off",
+                &format!("workdir={snapshots}/3/work"),
+                &format!("upperdir={snapshots}/3/fs"),
+                &format!("lowerdir={snapshots}/2/fs:{snapshots}/1/fs"),
+            ],
+        )];
+
+        let layers = layers_from_containerd_mounts(&mounts).unwrap();
+
+        assert_eq!(
+            layers,
+            ["3", "2", "1"]
+                .map(|id| PathBuf::from(format!("/host{snapshots}/{id}/fs")))
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn containerd_bind_layer() {
+        let mounts = [mount(
+            "bind",
+            "/var/lib/containerd/snapshots/1/fs",
+            &["rbind", "rw"],
+        )];
+
+        assert_eq!(
+            layers_from_containerd_mounts(&mounts).unwrap(),
+            vec![PathBuf::from("/host/var/lib/containerd/snapshots/1/fs")]
+        );
+    }
+
+    #[test]
+    fn containerd_unsupported_mounts() {
+        assert!(layers_from_containerd_mounts(&[]).is_err());
+        assert!(layers_from_containerd_mounts(&[mount("tmpfs", "tmpfs", &[])]).is_err());
+    }
 }
@@ -1,11 +1,16 @@
-use k8s_cri::v1::{ContainerStatusRequest, runtime_service_client::RuntimeServiceClient};
+use std::path::{Path, PathBuf};
+
+use k8s_cri::v1::{
+    ContainerStatusRequest, ContainerStatusResponse, runtime_service_client::RuntimeServiceClient,
+};
+use oci_spec::runtime::Spec;
 use serde::Deserialize;
 use tokio::net::UnixStream;
 use tonic::transport::{Endpoint, Uri};
 use tower::service_fn;
 use tracing::error;
 
-use super::ContainerRuntimeError;
+use super::{ContainerRuntimeError, ContainerSnapshot, extract_env_from_spec, snapshot};
 use crate::runtime::{ContainerInfo, ContainerRuntime, error::ContainerRuntimeResult};
 
 static CRIO_DEFAULT_SOCK_PATH: &str = "/host/run/crio/crio.sock";
@@ -16,18 +21,19 @@ pub(crate) struct CriOContainer {
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct ContainerStatus {
+    #[serde(default)]
     pid: u64,
+    runtime_spec: Option<Spec>,
 }
 
 impl CriOContainer {
     pub fn from_id(container_id: String) -> Self {
         CriOContainer { container_id }
     }
-}
 
-impl ContainerRuntime for CriOContainer {
-    async fn get_info(&self) -> ContainerRuntimeResult<ContainerInfo> {
+    async fn get_status(&self) -> ContainerRuntimeResult<ContainerStatusResponse> {
         let channel = Endpoint::try_from("http://localhost")
             .map_err(ContainerRuntimeError::crio)?
             .connect_with_connector(service_fn(move |_: Uri| async {
@@ -42,14 +48,53 @@ impl ContainerRuntime for CriOContainer {
 
         let mut client = RuntimeServiceClient::new(channel);
 
-        let status = client
+        client
             .container_status(ContainerStatusRequest {
                 container_id: self.container_id.clone(),
                 verbose: true,
             })
             .await
-            .map_err(ContainerRuntimeError::crio)?
-            .into_inner();
+            .map_err(ContainerRuntimeError::crio)
+            .map(tonic::Response::into_inner)
+    }
+}
+
+/// Extract the root filesystem layers, topmost first, of a container stored in the overlay driver
+/// of `containers/storage`.
+///
+/// The container root is `<overlay>/<layer>/merged`, which is only mounted while the container is
+/// running. The layer's own contents are in `<layer>/diff`, and `<layer>/lower` lists the layers
+/// below it, as links relative to `<overlay>`.
+fn layers_from_storage(root: &Path) -> ContainerRuntimeResult<Vec<PathBuf>> {
+    let layer = root
+        .parent()
+        .map(snapshot::host_path)
+        .ok_or_else(|| ContainerRuntimeError::snapshot(format!("invalid root path {root:?}")))?;
+    let overlay = layer
+        .parent()
+        .ok_or_else(|| ContainerRuntimeError::snapshot(format!("invalid root path {root:?}")))?;
+
+    let lower = match std::fs::read_to_string(layer.join("lower")) {
+        Ok(lower) => lower,
+        // Layer with no parents.
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(ContainerRuntimeError::snapshot(error)),
+    };
+
+    Ok(std::iter::once(layer.join("diff"))
+        .chain(
+            lower
+                .trim()
+                .split(':')
+                .filter(|link| !link.is_empty())
+                .map(|link| overlay.join(link)),
+        )
+        .collect())
+}
+
+impl ContainerRuntime for CriOContainer {
+    async fn get_info(&self) -> ContainerRuntimeResult<ContainerInfo> {
+        let status = self.get_status().await?;
 
         // Not sure if the `.get("pid")` logic works as on OpenShift
         // we observed that the `pid` exists in the `info` field which is JSON encoded.
@@ -68,6 +113,36 @@ impl ContainerRuntime for CriOContainer {
             }
         };
 
+        // Exited containers report pid 0.
+        if pid == 0 {
+            return Err(ContainerRuntimeError::NotRunning("cri-o"));
+        }
+
         Ok(ContainerInfo::new(pid, Default::default()))
     }
+
+    async fn get_snapshot(&self) -> ContainerRuntimeResult<ContainerSnapshot> {
+        let status = self.get_status().await?;
+
+        let info_json = status.info.get("info").ok_or_else(|| {
+            ContainerRuntimeError::crio("info not found in the runtime response status")
+        })?;
+        let spec = serde_json::from_str::<ContainerStatus>(info_json)
+            .map_err(ContainerRuntimeError::crio)?
+            .runtime_spec
+            .ok_or_else(|| {
+                ContainerRuntimeError::crio("runtime spec not found in the runtime response")
+            })?;
+
+        let root = spec
+            .root()
+            .as_ref()
+            .map(|root| root.path().clone())
+            .ok_or_else(|| ContainerRuntimeError::crio("root not found in the runtime spec"))?;
+
+        Ok(ContainerSnapshot {
+            layers: layers_from_storage(&root)?,
+            env: extract_env_from_spec(&spec).unwrap_or_default(),
+        })
+    }
 }
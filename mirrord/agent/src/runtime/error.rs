@@ -5,8 +5,15 @@ use thiserror::Error;
 pub(crate) enum ContainerRuntimeError {
     #[error("failed to get target container info: {} [{}]", .error, .runtime)]
     GetInfoError { runtime: String, error: String },
+    /// The container exists, but its process is not running (e.g. it's crash-looping).
+    #[error("target container process is not running [{0}]")]
+    NotRunning(&'static str),
     #[error("unknown container runtime `{0}`")]
     UnknownRuntimeName(String),
+    #[error("failed to access target container root filesystem snapshot: {0}")]
+    SnapshotError(String),
+    #[error("container runtime `{0}` does not support root filesystem snapshots")]
+    SnapshotUnsupported(&'static str),
 }
 
 impl ContainerRuntimeError {
@@ -31,6 +38,10 @@ impl ContainerRuntimeError {
         }
     }
 
+    pub(crate) fn snapshot<E: ToString>(error: E) -> Self {
+        Self::SnapshotError(error.to_string())
+    }
+
     pub(crate) fn unknown_runtime<N: ToString>(name: N) -> Self {
         Self::UnknownRuntimeName(name.to_string())
     }
@@ -0,0 +1,139 @@
+//! Access to the root filesystem of a target container that has no running process (e.g. it's
+//! crash-looping, or it belongs to a completed `Job`).
+//!
+//! In such case there's no `/proc/{pid}/root` to resolve paths against, so we get the overlay
+//! layers of the container's root filesystem from the container runtime, and mount them read-only
+//! in the agent.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use nix::mount::{MntFlags, MsFlags};
+use tracing::Level;
+
+use super::{ContainerRuntimeError, ContainerRuntimeResult};
+
+/// The host root filesystem is not mounted in the agent container, only `/run` and `/var` are,
+/// under this directory.
+const HOST_MOUNT_PREFIX: &str = "/host";
+
+/// Root filesystem of a container, as reported by the container runtime.
+#[derive(Debug)]
+pub(crate) struct ContainerSnapshot {
+    /// Layers of the root filesystem, topmost first, as seen from the agent.
+    pub(crate) layers: Vec<PathBuf>,
+    /// Environment variables from the container spec.
+    pub(crate) env: HashMap<String, String>,
+}
+
+/// Converts a path on the host to a path accessible from the agent container.
+pub(super) fn host_path(path: impl AsRef<Path>) -> PathBuf {
+    Path::new(HOST_MOUNT_PREFIX).join(path.as_ref().strip_prefix("/").unwrap_or(path.as_ref()))
+}
+
+/// Read-only mount of a [`ContainerSnapshot`].
+///
+/// Unmounted when dropped.
+#[derive(Debug)]
+pub(crate) struct SnapshotMount {
+    path: PathBuf,
+}
+
+impl SnapshotMount {
+    /// Mounts the layers of the given snapshot in a new temporary directory.
+    ///
+    /// Multiple layers are mounted as an overlay with no upper directory, which the kernel always
+    /// mounts read-only. A single layer is bind mounted and then remounted read-only.
+    #[tracing::instrument(level = Level::DEBUG, err)]
+    pub(crate) fn new(layers: &[PathBuf]) -> ContainerRuntimeResult<Self> {
+        let path = std::env::temp_dir().join(format!("mirrord-rootfs-{}", std::process::id()));
+        std::fs::create_dir_all(&path).map_err(ContainerRuntimeError::snapshot)?;
+
+        let result = match layers {
+            [] => Err(ContainerRuntimeError::snapshot(
+                "container runtime returned no root filesystem layers",
+            )),
+            [layer] => Self::bind_read_only(layer, &path),
+            layers => {
+                let lower_dirs = layers
+                    .iter()
+                    .map(|layer| layer.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(":");
+
+                // The kernel limits mount options to a single page, this may fail for images with
+                // a lot of layers.
+                nix::mount::mount(
+                    Some("overlay"),
+                    &path,
+                    Some("overlay"),
+                    MsFlags::MS_RDONLY,
+                    Some(format!("lowerdir={lower_dirs}").as_str()),
+                )
+                .map_err(ContainerRuntimeError::snapshot)
+            }
+        };
+
+        match result {
+            Ok(()) => Ok(Self { path }),
+            Err(error) => {
+                let _ = std::fs::remove_dir(&path);
+                Err(error)
+            }
+        }
+    }
+
+    fn bind_read_only(source: &Path, target: &Path) -> ContainerRuntimeResult<()> {
+        nix::mount::mount(
+            Some(source),
+            target,
+            None::<&str>,
+            MsFlags::MS_BIND | MsFlags::MS_REC,
+            None::<&str>,
+        )
+        .map_err(ContainerRuntimeError::snapshot)?;
+
+        nix::mount::mount(
+            None::<&str>,
+            target,
+            None::<&str>,
+            MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY,
+            None::<&str>,
+        )
+        .map_err(ContainerRuntimeError::snapshot)
+    }
+
+    /// Path where the root filesystem is mounted.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SnapshotMount {
+    fn drop(&mut self) {
+        if let Err(error) = nix::mount::umount2(&self.path, MntFlags::MNT_DETACH) {
+            tracing::warn!(%error, path = ?self.path, "Failed to unmount the target root filesystem");
+        }
+
+        let _ = std::fs::remove_dir(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn host_path_prefix() {
+        assert_eq!(
+            host_path("/var/lib/containerd/snapshots/12/fs"),
+            PathBuf::from("/host/var/lib/containerd/snapshots/12/fs")
+        );
+        assert_eq!(
+            host_path("var/lib/containers"),
+            PathBuf::from("/host/var/lib/containers")
+        );
+    }
+}
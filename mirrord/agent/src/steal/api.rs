@@ -15,10 +15,10 @@ use mirrord_protocol::{
     tcp::{
         ChunkedRequest, ChunkedRequestBodyV1, ChunkedRequestStartV2, ChunkedResponse, DaemonTcp,
         HTTP_CHUNKED_REQUEST_V2_VERSION, HTTP_CHUNKED_REQUEST_VERSION, HTTP_FRAMED_VERSION,
-        HttpRequest, HttpRequestMetadata, HttpResponse, IncomingTrafficTransportType,
-        InternalHttpBody, InternalHttpBodyFrame, InternalHttpBodyNew, InternalHttpRequest,
-        LayerTcpSteal, MODE_AGNOSTIC_HTTP_REQUESTS, NewTcpConnectionV1, NewTcpConnectionV2,
-        StealType, TcpClose, TcpData,
+        HttpRequest, HttpResponse, IncomingTrafficTransportType, InternalHttpBody,
+        InternalHttpBodyFrame, InternalHttpBodyNew, InternalHttpRequest, LayerTcpSteal,
+        MODE_AGNOSTIC_HTTP_REQUESTS, NewTcpConnectionV1, NewTcpConnectionV2, StealType, TcpClose,
+        TcpData,
     },
 };
 use tokio::sync::mpsc::{self, Receiver, Sender};
@@ -34,6 +34,7 @@ use crate::{
         ConnError, IncomingStream, IncomingStreamItem, RedirectorTaskConfig, ResponseBodyProvider,
         ResponseProvider, StolenHttp, StolenTcp,
     },
+    otel,
     steal::api::wait_body::WaitForFullBody,
     task::status::BgTaskStatus,
     util::{ClientId, protocol_version::ClientProtocolVersion},
@@ -211,6 +212,13 @@ impl TcpStealerApi {
             .protocol_version
             .matches(&HTTP_CHUNKED_REQUEST_V2_VERSION)
         {
+            let metadata = otel::http_request_metadata(
+                &self.protocol_version,
+                info.peer_addr,
+                info.original_destination,
+                &request_head.parts,
+                true,
+            );
             let message = DaemonMessage::TcpSteal(DaemonTcp::HttpRequestChunked(
                 ChunkedRequest::StartV2(ChunkedRequestStartV2 {
                     connection_id,
@@ -225,10 +233,7 @@ impl TcpStealerApi {
                             is_last: request_head.body_finished,
                         },
                     },
-                    metadata: HttpRequestMetadata::V1 {
-                        source: info.peer_addr,
-                        destination: info.original_destination,
-                    },
+                    metadata,
                     transport: info
                         .tls_connector
                         .as_ref()
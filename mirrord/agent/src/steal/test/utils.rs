@@ -723,8 +723,10 @@ impl StealingClient {
                     self.protocol_version
                         .matches(&HTTP_CHUNKED_REQUEST_V2_VERSION)
                 );
-                let HttpRequestMetadata::V1 { destination, .. } = &request.metadata;
-                assert_eq!(destination.port(), self.steal_type.get_port(),);
+                assert_eq!(
+                    request.metadata.destination().port(),
+                    self.steal_type.get_port(),
+                );
                 request
             }
             other => panic!(
@@ -24,6 +24,14 @@ impl InTargetPathResolver {
         }
     }
 
+    /// Constructs a new resolver with the given root path.
+    ///
+    /// Used when the target root filesystem is mounted in the agent, see
+    /// [`crate::runtime::SnapshotMount`]. Also makes it easy to test with [`tempfile::tempdir`].
+    pub fn with_root_path(root: PathBuf) -> Self {
+        Self { root }
+    }
+
     pub fn root_path(&self) -> &Path {
         &self.root
     }
@@ -73,13 +81,3 @@ impl InTargetPathResolver {
         Ok(self.root.join(temp_path))
     }
 }
-
-#[cfg(test)]
-impl InTargetPathResolver {
-    /// Constructs a new resolver with the given root path.
-    ///
-    /// Makes it easy to test with [`tempfile::tempdir`].
-    pub fn with_root_path(root: PathBuf) -> Self {
-        Self { root }
-    }
-}
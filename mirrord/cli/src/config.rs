@@ -174,6 +174,12 @@ pub(super) enum Commands {
     #[command(hide = true)]
     VerifyConfig(VerifyConfigArgs),
 
+    /// Inspect the mirrord config.
+    Config(Box<ConfigArgs>),
+
+    /// Inspect the mirrord policies that apply to your sessions.
+    Policy(Box<PolicyArgs>),
+
     /// Try out mirrord for Teams.
     #[cfg_attr(target_os = "windows", command(hide = true))]
     Teams,
@@ -192,6 +198,10 @@ pub(super) enum Commands {
     #[cfg_attr(target_os = "windows", command(hide = true))]
     Ci(Box<CiArgs>),
 
+    /// Manage local sessions, which keep the agent connection alive for multiple processes.
+    #[cfg_attr(target_os = "windows", command(hide = true))]
+    Session(Box<SessionArgs>),
+
     /// Launch the config wizard.
     ///
     /// The config wizard is a web app that allows the user to create a mirrord config file by
@@ -432,6 +442,31 @@ pub(super) struct ExecArgs {
 
     /// Arguments to pass to the binary.
     pub(super) binary_args: Vec<String>,
+
+    /// Restart the binary whenever files matching this glob change, e.g. `--watch 'src/**/*.rs'`.
+    /// Can be specified multiple times.
+    ///
+    /// The agent connection, port subscriptions and remote environment are kept between restarts.
+    /// Paths are relative to the working directory, hidden directories, `node_modules` and
+    /// `target` are not scanned.
+    #[cfg(not(target_os = "windows"))]
+    #[arg(long, value_name = "GLOB")]
+    pub watch: Vec<String>,
+
+    /// Write the traffic of all outgoing connections made through the agent to this pcap-ng file,
+    /// to be opened with tools like Wireshark.
+    ///
+    /// Same as setting `internal_proxy.capture_outgoing` in the mirrord config.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub capture_outgoing: Option<PathBuf>,
+
+    /// Run the binary in a local session started with `mirrord session start`, instead of
+    /// connecting to the cluster.
+    ///
+    /// The session's config is used, mirrord config options passed here are ignored.
+    #[cfg(not(target_os = "windows"))]
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["watch", "capture_outgoing"])]
+    pub session: Option<String>,
 }
 
 // `mirrord dump` command
@@ -486,6 +521,7 @@ pub(super) struct TargetParams {
     /// - `statefulset/{statefulset-name}[/container/{container-name}]`
     /// - `service/{service-name}[/container/{container-name}]`
     /// - `replicaset/{replicaset-name}[/container/{container-name}]`
+    /// - `daemonset/{daemonset-name}[/container/{container-name}][/node/{node-name}]`
     ///
     /// E.g `pod/my-pod/container/my-container`.
     #[arg(short = 't', long)]
@@ -973,10 +1009,87 @@ pub(super) struct VerifyConfigArgs {
     #[arg(long)]
     pub(super) ide: bool,
 
+    /// Also print the config merged with the files it `extends`, and the file that each value
+    /// comes from.
+    #[arg(long)]
+    pub(super) show_merged: bool,
+
     /// Config file path.
     pub(super) path: PathBuf,
 }
 
+#[derive(Args, Debug)]
+pub(super) struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum ConfigCommand {
+    /// Resolve the config like `mirrord exec` would, and print every value with its source: the
+    /// config file, an environment variable, a CLI flag, a mirrord profile or the default.
+    Explain(Box<ConfigExplainArgs>),
+}
+
+/// Args for the [`mod@super::config_explain`] mirrord-cli command.
+#[derive(Args, Debug)]
+pub(super) struct ConfigExplainArgs {
+    /// Specify the format of the output.
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = ExplainFormat::Text
+    )]
+    pub output: ExplainFormat,
+
+    /// Same parameters as for `mirrord exec`.
+    #[clap(flatten)]
+    pub params: Box<ExecParams>,
+}
+
+#[derive(Args, Debug)]
+pub(super) struct PolicyArgs {
+    #[command(subcommand)]
+    pub command: PolicyCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub(super) enum PolicyCommand {
+    /// Resolve the config like `mirrord exec` would, find the mirrord policies that apply to the
+    /// target, and print what they will block or change in the session, without starting it.
+    ///
+    /// Exits with an error if any of the policies would block the session.
+    Check(Box<PolicyCheckArgs>),
+}
+
+/// Args for the [`mod@super::policy`] mirrord-cli command.
+#[derive(Args, Debug)]
+pub(super) struct PolicyCheckArgs {
+    /// Specify the format of the output.
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = ExplainFormat::Text
+    )]
+    pub output: ExplainFormat,
+
+    /// Same parameters as for `mirrord exec`.
+    #[clap(flatten)]
+    pub params: Box<ExecParams>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExplainFormat {
+    /// One line per value.
+    Text,
+    /// For the IDE extensions.
+    Json,
+}
+
 #[derive(Args, Debug)]
 pub(super) struct CompletionsArgs {
     pub(super) shell: Shell,
@@ -1143,6 +1256,54 @@ pub(super) enum CiCommand {
     Stop,
 }
 
+#[derive(Args, Debug)]
+pub(super) struct SessionArgs {
+    /// Command to use with `mirrord session`.
+    #[command(subcommand)]
+    pub command: SessionCommand,
+}
+
+/// `mirrord session` commands.
+#[derive(Subcommand, Debug)]
+pub(super) enum SessionCommand {
+    /// Starts a named session in the background. Takes the same mirrord arguments as `mirrord
+    /// exec`.
+    ///
+    /// The session holds the agent connection until it's stopped with `mirrord session stop`.
+    Start(Box<SessionStartArgs>),
+
+    /// Runs a binary in a session, same as `mirrord exec --session <NAME> -- <BINARY>`.
+    Attach {
+        /// Name of the session.
+        name: String,
+
+        /// Binary to execute.
+        binary: String,
+
+        /// Arguments to pass to the binary.
+        binary_args: Vec<String>,
+    },
+
+    /// Stops a session, the processes running in it lose their connection to the cluster.
+    Stop {
+        /// Name of the session.
+        name: String,
+    },
+
+    /// Lists the sessions and the processes running in them.
+    List,
+}
+
+// `mirrord session start` command
+#[derive(Args, Debug)]
+pub(super) struct SessionStartArgs {
+    /// Name of the session, used to attach processes to it.
+    pub name: String,
+
+    #[clap(flatten)]
+    pub params: Box<ExecParams>,
+}
+
 #[derive(Args, Debug)]
 pub(super) struct DbBranchesArgs {
     /// Specify the namespace to operate on
@@ -0,0 +1,481 @@
+//! `mirrord config explain` resolves the [`LayerConfig`] like `mirrord exec` does, and prints
+//! every value along with its source.
+//!
+//! The config is resolved from the sources in this order of precedence:
+//! 1. mirrord profile (see [`crate::profile`]),
+//! 2. CLI flags, which are passed to the config as environment variables,
+//! 3. `MIRRORD_*` environment variables,
+//! 4. config file (and the files it extends),
+//! 5. defaults.
+//!
+//! The config derive does not keep track of where the values come from, so we find out by
+//! resolving the config again without each of the sources, and comparing the results.
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    fmt,
+    path::PathBuf,
+};
+
+use mirrord_config::{
+    LayerConfig, LayerFileConfig,
+    config::{ConfigContext, ConfigError, MirrordConfig},
+    env_key::MIRRORD_ENV_KEY,
+    extends::MergedConfigFile,
+};
+use mirrord_progress::NullProgress;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    CliResult,
+    config::{ConfigArgs, ConfigCommand, ConfigExplainArgs, ExplainFormat},
+};
+
+/// Where a config value comes from.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ValueSource {
+    /// A mirrord profile, `change` is the name of the
+    /// [`FeatureChange`](mirrord_operator::crd::profile::FeatureChange), or the target namespace.
+    Profile {
+        profile: String,
+        change: String,
+    },
+    /// A CLI flag, passed to the config in the `env` variable.
+    Cli {
+        env: String,
+    },
+    /// An environment variable.
+    Env {
+        name: String,
+    },
+    /// The config file, or a file that it extends.
+    File {
+        path: PathBuf,
+        line: Option<usize>,
+    },
+    Default,
+}
+
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Profile { profile, change } => write!(f, "profile `{profile}` ({change})"),
+            Self::Cli { env } => write!(f, "CLI flag ({env})"),
+            Self::Env { name } => write!(f, "env {name}"),
+            Self::File {
+                path,
+                line: Some(line),
+            } => write!(f, "file {}:{line}", path.display()),
+            Self::File { path, line: None } => write!(f, "file {}", path.display()),
+            Self::Default => f.write_str("default"),
+        }
+    }
+}
+
+/// A single value of the resolved config.
+#[derive(Serialize, Debug)]
+struct ExplainedValue {
+    /// JSON pointer of the value in the resolved config.
+    pointer: String,
+    value: Value,
+    source: ValueSource,
+}
+
+/// Output of `mirrord config explain --output json`.
+#[derive(Serialize, Debug)]
+struct ExplainedConfig {
+    values: Vec<ExplainedValue>,
+    warnings: Vec<String>,
+}
+
+pub(super) async fn config_command(args: ConfigArgs) -> CliResult<()> {
+    match args.command {
+        ConfigCommand::Explain(args) => config_explain(*args).await,
+    }
+}
+
+/// Resolves the config from the given environment only, without looking at the process
+/// environment.
+fn resolve(envs: &HashMap<OsString, OsString>) -> CliResult<(LayerConfig, ConfigContext)> {
+    let mut context = ConfigContext::default()
+        .strict_env(true)
+        .override_envs(envs);
+    let config = LayerConfig::resolve(&mut context)?;
+
+    Ok((config, context))
+}
+
+async fn config_explain(args: ConfigExplainArgs) -> CliResult<()> {
+    let cli_envs = args
+        .params
+        .as_env_vars()
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.into_owned()))
+        .collect::<HashMap<_, _>>();
+
+    // Same as `ConfigContext::get_env`: CLI flags override the process environment.
+    let mut envs = std::env::vars_os()
+        .filter(|(key, _)| key.to_str().is_some_and(|key| key.starts_with("MIRRORD_")))
+        .collect::<HashMap<_, _>>();
+    envs.extend(cli_envs.clone());
+
+    let (config, mut context) = resolve(&envs)?;
+    config.verify(&mut context)?;
+
+    // Without this, every resolution would generate a new key.
+    let user_key = envs.contains_key(OsStr::new(MIRRORD_ENV_KEY));
+    if let Ok(key) = context.get_env(MIRRORD_ENV_KEY) {
+        envs.insert(MIRRORD_ENV_KEY.into(), key.into());
+    }
+    let config_file = context
+        .get_env(LayerConfig::FILE_PATH_ENV)
+        .ok()
+        .map(PathBuf::from);
+    let warnings = context.into_warnings();
+
+    let mut sources = BTreeMap::<String, ValueSource>::new();
+
+    let mut profiled = config.clone();
+    let mut previous = leaves(&profiled);
+    let profile = config.profile.clone().unwrap_or_default();
+    // In case the profile has a config patch, the config is resolved again.
+    let mut profile_context = ConfigContext::default()
+        .strict_env(true)
+        .override_envs(&envs);
+    crate::profile::apply_profile_if_configured_with(
+        &mut profiled,
+        &mut profile_context,
+        &NullProgress,
+        |change, config| {
+            let current = leaves(config);
+            for (pointer, value) in &current {
+                if previous.get(pointer) != Some(value) {
+                    sources.insert(
+                        pointer.clone(),
+                        ValueSource::Profile {
+                            profile: profile.clone(),
+                            change: change.to_string(),
+                        },
+                    );
+                }
+            }
+            previous = current;
+        },
+    )
+    .await?;
+
+    let resolved = leaves(&config);
+    for name in envs.keys() {
+        let Some(name_str) = name.to_str() else {
+            continue;
+        };
+        if name_str == LayerConfig::FILE_PATH_ENV || (name_str == MIRRORD_ENV_KEY && !user_key) {
+            continue;
+        }
+
+        let mut without = envs.clone();
+        without.remove(name);
+        // The config might not be valid without this variable, e.g. a target namespace without
+        // the target.
+        let Ok((config, _)) = resolve(&without) else {
+            continue;
+        };
+
+        let source = if cli_envs.contains_key(name) {
+            ValueSource::Cli {
+                env: name_str.to_string(),
+            }
+        } else {
+            ValueSource::Env {
+                name: name_str.to_string(),
+            }
+        };
+        let without = leaves(&config);
+        for (pointer, value) in &resolved {
+            if without.get(pointer) != Some(value) {
+                sources
+                    .entry(pointer.clone())
+                    .or_insert_with(|| source.clone());
+            }
+        }
+    }
+
+    if let Some(config_file) = config_file {
+        let mut context = ConfigContext::default()
+            .strict_env(true)
+            .override_envs(&envs);
+        let merged = LayerFileConfig::merged_from_path(&config_file, &mut context)
+            .map_err(ConfigError::from)?;
+
+        let default_envs = envs
+            .get(OsStr::new(MIRRORD_ENV_KEY))
+            .map(|key| HashMap::from([(OsString::from(MIRRORD_ENV_KEY), key.clone())]))
+            .unwrap_or_default();
+        let defaults = resolve(&default_envs)
+            .map(|(config, _)| leaves(&config))
+            .ok();
+
+        for (pointer, value) in &resolved {
+            if sources.contains_key(pointer) {
+                continue;
+            }
+
+            let source =
+                file_source(&merged, pointer, &default_envs, defaults.as_ref()).or_else(|| {
+                    defaults
+                        .as_ref()
+                        .is_some_and(|defaults| defaults.get(pointer) != Some(value))
+                        .then(|| ValueSource::File {
+                            path: config_file.clone(),
+                            line: None,
+                        })
+                });
+            if let Some(source) = source {
+                sources.insert(pointer.clone(), source);
+            }
+        }
+    }
+
+    let values = leaves(&profiled)
+        .into_iter()
+        .map(|(pointer, value)| ExplainedValue {
+            source: sources.remove(&pointer).unwrap_or(ValueSource::Default),
+            pointer,
+            value,
+        })
+        .collect::<Vec<_>>();
+
+    match args.output {
+        ExplainFormat::Json => {
+            let explained = ExplainedConfig { values, warnings };
+            println!("{}", serde_json::to_string_pretty(&explained)?);
+        }
+        ExplainFormat::Text => {
+            for ExplainedValue {
+                pointer,
+                value,
+                source,
+            } in values
+            {
+                let path = pointer.trim_start_matches('/').replace('/', ".");
+                println!("{path} = {value}  # {source}");
+            }
+            for warning in warnings {
+                println!("warning: {warning}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the file that sets the value under `pointer`, using the provenance of the merged config.
+///
+/// The config file may use a shorthand for the value (e.g. `"fs": "write"` for `fs.mode`), so we
+/// also look at the parents and children of `pointer`. A parent is a source only if its shorthand
+/// sets the value, see [`shorthand_sets`].
+///
+/// `envs` and `defaults` are the environment and the leaves of the config resolved without the
+/// config file.
+fn file_source(
+    merged: &MergedConfigFile,
+    pointer: &str,
+    envs: &HashMap<OsString, OsString>,
+    defaults: Option<&BTreeMap<String, Value>>,
+) -> Option<ValueSource> {
+    let (file_pointer, path) = merged
+        .provenance
+        .iter()
+        .filter(|(file_pointer, _)| {
+            is_same_or_nested(pointer, file_pointer) || is_same_or_nested(file_pointer, pointer)
+        })
+        .max_by_key(|(file_pointer, _)| file_pointer.len())?;
+
+    if file_pointer.len() < pointer.len()
+        && !shorthand_sets(merged, file_pointer, pointer, envs, defaults?)
+    {
+        return None;
+    }
+
+    Some(ValueSource::File {
+        path: path.clone(),
+        line: std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| find_line(&contents, file_pointer)),
+    })
+}
+
+/// Whether the shorthand under `file_pointer` in the merged config sets the value under `pointer`,
+/// e.g. `"fs": "write"` sets `/feature/fs/mode`, but not `/feature/fs/read_only`.
+///
+/// Resolves a config with the shorthand only, and compares the value with the default, so a
+/// shorthand that sets a value to its default is not a source.
+fn shorthand_sets(
+    merged: &MergedConfigFile,
+    file_pointer: &str,
+    pointer: &str,
+    envs: &HashMap<OsString, OsString>,
+    defaults: &BTreeMap<String, Value>,
+) -> bool {
+    let Some(shorthand) = merged.config.pointer(file_pointer) else {
+        return false;
+    };
+
+    let file = file_pointer
+        .split('/')
+        .skip(1)
+        .rev()
+        .fold(shorthand.clone(), |value, key| {
+            let key = key.replace("~1", "/").replace("~0", "~");
+            Value::Object([(key, value)].into_iter().collect())
+        });
+
+    let mut context = ConfigContext::default()
+        .strict_env(true)
+        .override_envs(envs);
+    serde_json::from_value::<LayerFileConfig>(file)
+        .ok()
+        .and_then(|file| file.generate_config(&mut context).ok())
+        .is_some_and(|config| leaves(&config).get(pointer) != defaults.get(pointer))
+}
+
+fn is_same_or_nested(pointer: &str, parent: &str) -> bool {
+    pointer
+        .strip_prefix(parent)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Best effort search for the line of the value under `pointer` in the file `contents`: finds the
+/// keys of the pointer one after another.
+fn find_line(contents: &str, pointer: &str) -> Option<usize> {
+    let lines = contents.lines().collect::<Vec<_>>();
+
+    let mut line = 0;
+    for key in pointer.split('/').skip(1) {
+        let key = key.replace("~1", "/").replace("~0", "~");
+        line += lines
+            .get(line..)?
+            .iter()
+            .position(|text| contains_key(text, &key))?;
+    }
+
+    Some(line + 1)
+}
+
+/// Whether the line contains `key` as a whole word.
+fn contains_key(line: &str, key: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+
+    line.match_indices(key).any(|(start, _)| {
+        let before = line.get(..start).and_then(|text| text.chars().next_back());
+        let after = line
+            .get(start + key.len()..)
+            .and_then(|text| text.chars().next());
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
+}
+
+/// Flattens the config into its leaves, by JSON pointer, see [`MergedConfigFile::provenance`].
+fn leaves(config: &LayerConfig) -> BTreeMap<String, Value> {
+    fn collect(value: Value, pointer: String, leaves: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Object(object) if !object.is_empty() => {
+                for (key, value) in object {
+                    let key = key.replace('~', "~0").replace('/', "~1");
+                    collect(value, format!("{pointer}/{key}"), leaves);
+                }
+            }
+            value => {
+                leaves.insert(pointer, value);
+            }
+        }
+    }
+
+    let mut leaves = BTreeMap::new();
+    if let Ok(value) = serde_json::to_value(config) {
+        collect(value, String::new(), &mut leaves);
+    }
+
+    leaves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_of_nested_key() {
+        let contents = r#"{
+  "agent": { "ttl": 30 },
+  "feature": {
+    "network": {
+      "incoming": {
+        "mode": "steal"
+      }
+    },
+    "fs": "read"
+  }
+}"#;
+
+        assert_eq!(
+            find_line(contents, "/feature/network/incoming/mode"),
+            Some(6)
+        );
+        assert_eq!(find_line(contents, "/feature/fs"), Some(9));
+        assert_eq!(find_line(contents, "/agent/ttl"), Some(2));
+        assert_eq!(find_line(contents, "/feature/env"), None);
+    }
+
+    #[test]
+    fn file_source_matches_shorthands() {
+        let merged = MergedConfigFile {
+            config: serde_json::json!({
+                "feature": { "fs": "write", "env": { "include": "FOO" } },
+                "target": "deployment/app",
+            }),
+            provenance: BTreeMap::from([
+                (
+                    "/feature/fs".to_string(),
+                    PathBuf::from("/nonexistent/base.json"),
+                ),
+                (
+                    "/feature/env/include".to_string(),
+                    PathBuf::from("/nonexistent/base.json"),
+                ),
+                (
+                    "/target".to_string(),
+                    PathBuf::from("/nonexistent/main.json"),
+                ),
+            ]),
+            ..Default::default()
+        };
+        let envs = HashMap::new();
+        let (defaults, _) = resolve(&envs).unwrap();
+        let defaults = leaves(&defaults);
+        let source = |pointer| file_source(&merged, pointer, &envs, Some(&defaults));
+
+        assert_eq!(
+            source("/feature/fs/mode"),
+            Some(ValueSource::File {
+                path: "/nonexistent/base.json".into(),
+                line: None
+            })
+        );
+        assert_eq!(
+            source("/target/path/deployment"),
+            Some(ValueSource::File {
+                path: "/nonexistent/main.json".into(),
+                line: None
+            })
+        );
+        assert_eq!(source("/feature/fsync"), None);
+        assert_eq!(source("/feature/env/exclude"), None);
+
+        // Not set by the shorthands.
+        assert_eq!(source("/feature/fs/read_only"), None);
+        assert_eq!(source("/feature/fs/readonly_file_buffer"), None);
+        assert_eq!(source("/target/namespace"), None);
+    }
+}
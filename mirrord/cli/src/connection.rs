@@ -3,11 +3,13 @@ use std::{collections::HashSet, time::Duration};
 use mirrord_analytics::Reporter;
 use mirrord_config::{
     LayerConfig,
-    target::{Target, TargetDisplay},
+    target::{Target, TargetDisplay, daemon_set::DaemonSetTarget},
 };
 use mirrord_intproxy::agent_conn::AgentConnectInfo;
 use mirrord_kube::{
-    api::{container::ContainerConfig, kubernetes::KubernetesAPI},
+    api::{
+        container::ContainerConfig, kubernetes::KubernetesAPI, runtime::resolve_target_selector,
+    },
     error::KubeApiError,
     resolved::ResolvedTarget,
 };
@@ -23,6 +25,11 @@ use crate::{CliError, CliResult, MirrordCi, ci::error::CiError};
 
 pub const AGENT_CONNECT_INFO_ENV_KEY: &str = "MIRRORD_AGENT_CONNECT_INFO";
 
+/// Used to pass [`AgentConnectInfo`]s of the additional targets (see
+/// [`TargetConfig::additional`](mirrord_config::target::TargetConfig::additional)) to the
+/// internal proxy, as a JSON array.
+pub const ADDITIONAL_AGENT_CONNECT_INFO_ENV_KEY: &str = "MIRRORD_ADDITIONAL_AGENT_CONNECT_INFO";
+
 /// 1. If mirrord-operator is explicitly enabled in the given [`LayerConfig`], makes a connection
 ///    with the target using the mirrord-operator.
 /// 2. If mirrord-operator is explicitly disabled in the given [`LayerConfig`], returns [`None`].
@@ -71,6 +78,16 @@ where
         }
     }
 
+    // The operator finds the target by its name only, see `TargetCrd::urlfied_name`.
+    if let Some(Target::DaemonSet(DaemonSetTarget { node: Some(..), .. })) =
+        &layer_config.target.path
+    {
+        operator_subtask.failure(Some("DaemonSet node is not supported"));
+        return Err(CliError::FeatureNotSupportedWithOperatorError(
+            "DaemonSet target node".into(),
+        ));
+    }
+
     let mut user_cert_subtask = operator_subtask.subtask("preparing user credentials");
     let api = match mirrord_for_ci {
         Some(mirrord_for_ci) => {
@@ -136,6 +153,8 @@ pub(crate) async fn create_and_connect<P: Progress, R: Reporter>(
     branch_name: Option<String>,
     mirrord_for_ci: Option<&MirrordCi>,
 ) -> CliResult<(AgentConnectInfo, Connection<Client>)> {
+    resolve_selector_to_path(config, progress).await?;
+
     if let Some(connection) =
         try_connect_using_operator(config, progress, analytics, branch_name, mirrord_for_ci).await?
     {
@@ -188,6 +207,85 @@ pub(crate) async fn create_and_connect<P: Progress, R: Reporter>(
     Ok((AgentConnectInfo::DirectKubernetes(agent_connect_info), conn))
 }
 
+/// Replaces [`TargetConfig::selector`](mirrord_config::target::TargetConfig::selector) with the
+/// [`Target::Pod`] it resolves to, so that the rest of the flow (and the layer, which gets the
+/// resolved config) sees a regular target path.
+pub(crate) async fn resolve_selector_to_path<P: Progress>(
+    config: &mut LayerConfig,
+    progress: &mut P,
+) -> CliResult<()> {
+    let Some(selector) = config.target.selector.take() else {
+        return Ok(());
+    };
+
+    let mut subtask = progress.subtask("resolving target selector");
+
+    let k8s_api = KubernetesAPI::create(config, &subtask)
+        .await
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
+    let agent_namespace = config
+        .agent
+        .namespace
+        .as_deref()
+        .or(config.target.namespace.as_deref());
+
+    let target = resolve_target_selector(
+        k8s_api.client(),
+        &selector,
+        config.target.namespace.as_deref(),
+        agent_namespace,
+    )
+    .await
+    .map_err(|error| {
+        CliError::friendlier_error_or_else(error, CliError::TargetSelectorResolution)
+    })?;
+
+    subtask.success(Some(&format!("selected pod/{}", target.pod)));
+    config.target.path = Some(Target::Pod(target));
+
+    Ok(())
+}
+
+/// Starts sessions with all additional targets of the given [`LayerConfig`], in the order of the
+/// targets in the config.
+///
+/// Each target goes through [`create_and_connect`] with a copy of the config, where only the
+/// target is replaced (see
+/// [`TargetConfig::for_additional`](mirrord_config::target::TargetConfig::for_additional)).
+#[tracing::instrument(level = Level::TRACE, skip_all, err)]
+pub(crate) async fn create_and_connect_additional<P: Progress, R: Reporter>(
+    config: &LayerConfig,
+    progress: &mut P,
+    analytics: &mut R,
+    branch_name: Option<String>,
+    mirrord_for_ci: Option<&MirrordCi>,
+) -> CliResult<Vec<(AgentConnectInfo, Connection<Client>)>> {
+    let mut connections = Vec::with_capacity(config.target.additional.len());
+
+    for additional in &config.target.additional {
+        let mut subtask = progress.subtask(&format!("connecting to {}", additional.path));
+
+        let mut additional_config = config.clone();
+        additional_config.target = config.target.for_additional(additional);
+
+        let connection = create_and_connect(
+            &mut additional_config,
+            &mut subtask,
+            analytics,
+            branch_name.clone(),
+            mirrord_for_ci,
+        )
+        .await?;
+        connections.push(connection);
+
+        subtask.success(Some("connected to additional target"));
+    }
+
+    Ok(connections)
+}
+
 /// Verifies and adjusts the [`LayerConfig`] after we've determined that this run does not use the
 /// operator.
 fn process_config_oss<P: Progress>(config: &mut LayerConfig, progress: &mut P) -> CliResult<()> {
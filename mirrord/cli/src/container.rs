@@ -1,6 +1,11 @@
 #[cfg(not(target_os = "windows"))]
 use std::os::unix::process::ExitStatusExt;
-use std::{net::SocketAddr, ops::Not, path::PathBuf, process::Stdio};
+use std::{
+    net::SocketAddr,
+    ops::Not,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
 
 use clap::ValueEnum;
 pub use command_display::CommandDisplay;
@@ -21,7 +26,7 @@ use crate::{
     container::{command_builder::RuntimeCommandBuilder, sidecar::IntproxySidecar},
     ensure_not_nested,
     error::{CliResult, ContainerError},
-    execution::{LINUX_INJECTION_ENV_VAR, MirrordExecution},
+    execution::{LINUX_INJECTION_ENV_VAR, MirrordExecution, new_fs_overlay_dir},
     logging::pipe_intproxy_sidecar_logs,
     user_data::UserData,
     util::MIRRORD_CONSOLE_ADDR_ENV,
@@ -104,7 +109,7 @@ async fn create_config_and_analytics<P: Progress>(
     user_data: &UserData,
 ) -> CliResult<(LayerConfig, AnalyticsReporter)> {
     let mut config = LayerConfig::resolve(&mut cfg_context)?;
-    crate::profile::apply_profile_if_configured(&mut config, progress).await?;
+    crate::profile::apply_profile_if_configured(&mut config, &mut cfg_context, progress).await?;
 
     // Initialize only error analytics, extproxy will be the full AnalyticsReporter.
     let analytics = AnalyticsReporter::only_error(
@@ -163,6 +168,22 @@ async fn prepare_proxies<P: Progress>(
     let sidecar =
         IntproxySidecar::create(config, runtime, extproxy_addr, tls_setup.as_ref()).await?;
 
+    // The layer creates the fs overlay inside of the user container, where the sidecar can't
+    // reach it. That's why this is done after the sidecar is created.
+    if config.feature.fs.uses_overlay() {
+        if config.feature.fs.overlay_export.take().is_some() {
+            progress.warning(
+                "`feature.fs.overlay_export` is not supported in `mirrord container`, \
+                the fs overlay stays in the user container",
+            );
+        }
+        config
+            .feature
+            .fs
+            .overlay_dir
+            .get_or_insert_with(|| new_fs_overlay_dir(Path::new("/tmp")));
+    }
+
     let mut runtime_command = RuntimeCommandBuilder::new(runtime);
     // Provide remote environment to the user application.
     runtime_command.add_envs(
@@ -15,6 +15,7 @@ use tower::{buffer::BufferLayer, retry::RetryLayer};
 use crate::{
     CliError, CliResult,
     config::{DbBranchesArgs, DbBranchesCommand},
+    local_mongodb::{self, LocalMongodbContainer},
 };
 
 #[derive(Debug)]
@@ -22,7 +23,8 @@ struct BranchInfo {
     name: String,
     db_type: &'static str,
     phase: Option<String>,
-    ttl: u64,
+    /// Not set for local branches, which live until they're destroyed.
+    ttl: Option<u64>,
     database: Option<String>,
     users: Option<String>,
     expire_time: Option<String>,
@@ -34,7 +36,7 @@ impl From<&MysqlBranchDatabase> for BranchInfo {
             name: branch.metadata.name.clone().unwrap_or_default(),
             db_type: "MySQL",
             phase: branch.status.as_ref().map(|s| s.phase.to_string()),
-            ttl: branch.spec.ttl_secs,
+            ttl: Some(branch.spec.ttl_secs),
             database: branch.spec.database_name.clone(),
             users: branch.status.as_ref().and_then(|s| {
                 if s.session_info.is_empty() {
@@ -60,7 +62,7 @@ impl From<&PgBranchDatabase> for BranchInfo {
             name: branch.metadata.name.clone().unwrap_or_default(),
             db_type: "PostgreSQL",
             phase: branch.status.as_ref().map(|s| s.phase.to_string()),
-            ttl: branch.spec.ttl_secs,
+            ttl: Some(branch.spec.ttl_secs),
             database: branch.spec.database_name.clone(),
             users: branch.status.as_ref().and_then(|s| {
                 if s.session_info.is_empty() {
@@ -80,6 +82,20 @@ impl From<&PgBranchDatabase> for BranchInfo {
     }
 }
 
+impl From<&LocalMongodbContainer> for BranchInfo {
+    fn from(container: &LocalMongodbContainer) -> Self {
+        Self {
+            name: container.name.clone(),
+            db_type: "MongoDB (local)",
+            phase: Some(container.status.clone()),
+            ttl: None,
+            database: None,
+            users: None,
+            expire_time: None,
+        }
+    }
+}
+
 pub async fn db_branches_command(args: DbBranchesArgs) -> CliResult<()> {
     match &args.command {
         DbBranchesCommand::Status { names } => status_command(&args, names.as_slice()).await,
@@ -169,6 +185,13 @@ async fn status_command(args: &DbBranchesArgs, names: &[String]) -> CliResult<()
         }
     }
 
+    // Local MongoDB branches run in containers on this machine.
+    for (_, container) in local_mongodb::list_containers(&layer_config) {
+        if names.is_empty() || names.contains(&container.name) {
+            all_branches.push(BranchInfo::from(&container));
+        }
+    }
+
     if all_branches.is_empty() {
         progress.success(Some("No active DB branch found"));
         return Ok(());
@@ -193,7 +216,10 @@ async fn status_command(args: &DbBranchesArgs, names: &[String]) -> CliResult<()
             branch.name,
             branch.db_type,
             branch.phase.unwrap_or_else(|| "Unknown".to_string()),
-            branch.ttl,
+            branch
+                .ttl
+                .map(|ttl| ttl.to_string())
+                .unwrap_or_else(|| "-".to_string()),
             branch.database.unwrap_or_else(|| "<none>".to_string()),
             branch.users.unwrap_or_else(|| "none".to_string()),
             branch.expire_time.unwrap_or_else(|| "Unknown".to_string())
@@ -261,7 +287,12 @@ async fn destroy_command(args: &DbBranchesArgs, all: bool, names: &Vec<String>)
             crate::CliError::ListTargetsFailed(mirrord_kube::error::KubeApiError::KubeError(e))
         })?;
 
-        if mysql_branches.items.is_empty() && pg_branches.items.is_empty() {
+        let mongodb_containers = local_mongodb::list_containers(&layer_config);
+
+        if mysql_branches.items.is_empty()
+            && pg_branches.items.is_empty()
+            && mongodb_containers.is_empty()
+        {
             destroy_progress.success(Some("No active DB branch found."));
         }
 
@@ -289,6 +320,17 @@ async fn destroy_command(args: &DbBranchesArgs, all: bool, names: &Vec<String>)
                 }
             }
         }
+
+        // Remove all local MongoDB branches
+        for (runtime_cmd, container) in mongodb_containers {
+            let name = container.name;
+            let mut branch_progress =
+                destroy_progress.subtask(&format!("destroying MongoDB branch {name}"));
+            match local_mongodb::remove_container(&runtime_cmd, &name) {
+                Ok(()) => branch_progress.success(Some(&format!("destroyed {name}"))),
+                Err(e) => branch_progress.failure(Some(&format!("failed: {e}"))),
+            }
+        }
         destroy_progress.success(None);
     } else {
         // First, list all branches to determine their types
@@ -310,6 +352,8 @@ async fn destroy_command(args: &DbBranchesArgs, all: bool, names: &Vec<String>)
             .filter_map(|b| b.metadata.name)
             .collect();
 
+        let mongodb_containers = local_mongodb::list_containers(&layer_config);
+
         let delete_params = DeleteParams::default();
 
         for name in names {
@@ -339,6 +383,21 @@ async fn destroy_command(args: &DbBranchesArgs, all: bool, names: &Vec<String>)
                 }
             }
 
+            if let Some((runtime_cmd, _)) = mongodb_containers
+                .iter()
+                .find(|(_, container)| &container.name == name)
+            {
+                found = true;
+                match local_mongodb::remove_container(runtime_cmd, name) {
+                    Ok(()) => {
+                        branch_progress.success(Some(&format!("destroyed MongoDB branch: {name}")))
+                    }
+                    Err(e) => branch_progress.failure(Some(&format!(
+                        "failed to destroy MongoDB branch {name}: {e}"
+                    ))),
+                }
+            }
+
             if !found {
                 branch_progress.failure(Some(&format!("branch not found: {name}")));
             }
@@ -11,9 +11,8 @@ use mirrord_progress::{Progress, ProgressTracker};
 use mirrord_protocol::{
     ClientMessage, ConnectionId, DaemonMessage, LogLevel, LogMessage, RequestId, ResponseError,
     tcp::{
-        ChunkedRequest, DaemonTcp, HttpRequestMetadata, IncomingTrafficTransportType,
-        InternalHttpBodyFrame, InternalHttpRequest, LayerTcp, NewTcpConnectionV1,
-        NewTcpConnectionV2, TcpData,
+        ChunkedRequest, DaemonTcp, IncomingTrafficTransportType, InternalHttpBodyFrame,
+        InternalHttpRequest, LayerTcp, NewTcpConnectionV1, NewTcpConnectionV2, TcpData,
     },
 };
 use mirrord_protocol_io::{Client, Connection};
@@ -291,10 +290,8 @@ impl DumpSession {
                         .entry(req.connection_id)
                         .or_default()
                         .insert(req.request_id);
-                    let HttpRequestMetadata::V1 {
-                        source,
-                        destination,
-                    } = &req.metadata;
+                    let source = req.metadata.source();
+                    let destination = req.metadata.destination();
                     println!(
                         "## New {} request received: Request ID [{}:{}] from {source} to {destination}",
                         match &req.transport {
@@ -437,7 +434,9 @@ impl DumpSession {
                 | DaemonMessage::UdpOutgoing(..)
                 | DaemonMessage::Vpn(..)
                 | DaemonMessage::TcpSteal(..)
-                | DaemonMessage::ReverseDnsLookup(..)) => {
+                | DaemonMessage::ReverseDnsLookup(..)
+                | DaemonMessage::IdentifiedFile(..)
+                | DaemonMessage::SwitchCompressionResponse(..)) => {
                     return Err(DumpSessionError::UnexpectedAgentMessage(Box::new(message)));
                 }
             }
@@ -142,6 +142,18 @@ pub(crate) enum InternalProxyError {
     #[diagnostic(help("{GENERAL_HELP}"))]
     OpenLogFile(String, std::io::Error),
 
+    #[error("Failed to set up trace export to `{0}`: {1}")]
+    #[diagnostic(help(
+        "Please check the value of `internal_proxy.otlp_endpoint` in your config.{GENERAL_HELP}"
+    ))]
+    OtlpExport(String, opentelemetry::trace::TraceError),
+
+    #[error("Failed to create the outgoing traffic capture at `{}`: {1}", .0.display())]
+    #[diagnostic(help(
+        "Please check the value of `internal_proxy.capture_outgoing` in your config.{GENERAL_HELP}"
+    ))]
+    OutgoingCapture(PathBuf, std::io::Error),
+
     #[error("Missing connect info environment variable")]
     MissingConnectInfo,
 
@@ -257,6 +269,12 @@ pub(crate) enum CliError {
     ))]
     EnvFileAccessError(PathBuf, dotenvy::Error),
 
+    #[error("Failed to resolve environment variables with the Kubernetes API: {0}")]
+    #[diagnostic(help(
+        "Please check that your Kubernetes user can get the target and the Secrets and ConfigMaps referenced in `feature.env`, or disable `feature.env.from_spec` and `feature.env.sources`.{GENERAL_HELP}"
+    ))]
+    KubeEnvResolution(KubeApiError),
+
     #[cfg(target_os = "macos")]
     #[error("SIP Error: `{0:#?}`")]
     #[diagnostic(help(
@@ -335,6 +353,12 @@ pub(crate) enum CliError {
         operator_version: String,
     },
 
+    #[error("Feature `{0}` is not supported when using mirrord operator.")]
+    #[diagnostic(help(
+        "If you want to run without the operator, please set `\"operator\": false` in the mirrord configuration file.{GENERAL_HELP}"
+    ))]
+    FeatureNotSupportedWithOperatorError(String),
+
     #[error("mirrord operator API failed: {0} failed with {1}")]
     #[diagnostic(help(
     "Please check the following:
@@ -422,6 +446,34 @@ pub(crate) enum CliError {
     ))]
     OperatorTargetResolution(KubeApiError),
 
+    #[error("Failed to resolve the target label selector: {0}")]
+    #[diagnostic(help(
+        "
+        mirrord failed to find a ready pod matching `target.selector`.
+        Please check that the label selector is correct, that the pods live in `target.namespace`,
+        and that your Kubernetes user can list pods there.{GENERAL_HELP}
+    "
+    ))]
+    TargetSelectorResolution(KubeApiError),
+
+    #[error("Failed to fetch mirrord policies: {0}")]
+    #[diagnostic(help(
+        "Please check that your Kubernetes user can list `mirrordpolicies` in the target namespace and `mirrordclusterpolicies`.{GENERAL_HELP}"
+    ))]
+    PolicyFetch(KubeApiError),
+
+    #[error("{0} mirrord policy rule(s) would block this session")]
+    #[diagnostic(help(
+        "Change your mirrord config, or ask your cluster admin about the policies listed above."
+    ))]
+    PolicyCheckBlocked(usize),
+
+    #[error("Failed to prepare the fs overlay directory `{}`: {1}", .0.display())]
+    #[diagnostic(help(
+        "`feature.fs.overlay_dir` must be an empty or non-existent directory, remove it or pick another one.{GENERAL_HELP}"
+    ))]
+    FsOverlaySetup(PathBuf, io::Error),
+
     #[error("A null byte was found when trying to execute process: {0}")]
     ExecNulError(#[from] NulError),
 
@@ -445,6 +497,27 @@ pub(crate) enum CliError {
     ))]
     ExecveE2Big,
 
+    #[cfg(target_os = "linux")]
+    #[error("Failed to run the binary with seccomp interception: {0}")]
+    #[diagnostic(help(
+        "`experimental.seccomp_interception` requires Linux 5.14 or newer, and seccomp must not be \
+        disabled in the kernel or by the container runtime.{GENERAL_HELP}"
+    ))]
+    SeccompSupervisor(#[from] crate::seccomp::SeccompError),
+
+    /// Not a failure of mirrord, see [`CliError::process_exit_code`].
+    #[cfg(target_os = "linux")]
+    #[error("The process exited with code {0}")]
+    ProcessExit(i32),
+
+    #[cfg(not(target_os = "windows"))]
+    #[error("`mirrord exec --watch` failed: {0}")]
+    Watch(#[from] crate::watch::WatchError),
+
+    #[cfg(not(target_os = "windows"))]
+    #[error("mirrord session failed: {0}")]
+    Session(#[from] crate::session::SessionError),
+
     #[error("mirrord dump session failed: {0}")]
     DumpError(#[from] DumpSessionError),
 
@@ -489,9 +562,28 @@ pub(crate) enum CliError {
     #[error("Local Redis error: {0}")]
     #[diagnostic(help("Install redis-server with"))]
     LocalRedisError(String),
+
+    #[error("Local database error: {0}")]
+    #[diagnostic(help(
+        "Local PostgreSQL and MySQL branches need a container runtime, and `pg_dump`/`psql` or \
+        `mysqldump` installed locally.{GENERAL_HELP}"
+    ))]
+    LocalDatabaseError(String),
 }
 
 impl CliError {
+    /// Exit code of the user application that we ran as a child process, when it exited with
+    /// a non-zero code.
+    ///
+    /// mirrord should exit with the same code, without reporting an error.
+    pub fn process_exit_code(&self) -> Option<i32> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::ProcessExit(code) => Some(*code),
+            _ => None,
+        }
+    }
+
     /// Here we give more meaning to some errors, instead of just letting them pass as
     /// whatever [`KubeApiError`] we're getting.
     ///
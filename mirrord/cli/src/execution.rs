@@ -3,15 +3,23 @@ use std::ffi::OsString;
 use std::{
     collections::{HashMap, HashSet},
     net::SocketAddr,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
+use kube::client::ClientBuilder;
+use mirrord_agent_env::target_env::EXCLUDED_ENV_VARS;
 use mirrord_analytics::{AnalyticsError, AnalyticsReporter, Reporter};
 use mirrord_config::{
     LayerConfig, MIRRORD_LAYER_INTPROXY_ADDR, config::ConfigError,
     external_proxy::MIRRORD_EXTPROXY_TLS_SETUP_PEM, feature::env::mapper::EnvVarsRemapper,
+    target::Target,
 };
 use mirrord_intproxy::agent_conn::AgentConnectInfo;
+use mirrord_kube::{
+    api::kubernetes::create_kube_config, env::load_env_sources, resolved::ResolvedTarget,
+    retry::RetryKube,
+};
 use mirrord_progress::Progress;
 use mirrord_protocol::{ClientMessage, DaemonMessage, EnvVars, GetEnvVarsRequest, LogLevel};
 use mirrord_protocol_io::{Client, Connection};
@@ -27,7 +35,10 @@ use tokio::{
     sync::mpsc::{self, UnboundedReceiver},
 };
 use tokio_util::sync::CancellationToken;
+use tower::{buffer::BufferLayer, retry::RetryLayer};
 use tracing::{Level, debug, error, info, trace, warn};
+use uuid::Uuid;
+use wildmatch::WildMatch;
 
 #[cfg(target_os = "macos")]
 use crate::extract::extract_arm64;
@@ -35,7 +46,10 @@ use crate::extract::extract_arm64;
 use crate::util::reparent_to_init;
 use crate::{
     CliResult, MirrordCi,
-    connection::{AGENT_CONNECT_INFO_ENV_KEY, create_and_connect},
+    connection::{
+        ADDITIONAL_AGENT_CONNECT_INFO_ENV_KEY, AGENT_CONNECT_INFO_ENV_KEY, create_and_connect,
+        create_and_connect_additional,
+    },
     error::CliError,
     extract::extract_library,
     util::{get_user_git_branch, remove_proxy_env},
@@ -54,6 +68,10 @@ pub(crate) const INJECTION_ENV_VAR: &str = LINUX_INJECTION_ENV_VAR;
 #[cfg(target_os = "macos")]
 pub(crate) const INJECTION_ENV_VAR: &str = "DYLD_INSERT_LIBRARIES";
 
+/// Go runtime settings, used to make Go programs resolve DNS with `getaddrinfo`.
+#[cfg(not(target_os = "windows"))]
+const GODEBUG_ENV_VAR: &str = "GODEBUG";
+
 /// A handle to a running mirrord proxy (either internal proxy or external proxy).
 #[derive(Debug, Serialize)]
 pub(crate) struct MirrordExecution {
@@ -99,6 +117,42 @@ where
     }
 }
 
+/// Unique directory under `base` for the local fs overlay of a single run.
+pub(crate) fn new_fs_overlay_dir(base: &Path) -> PathBuf {
+    base.join(format!("mirrord-fs-overlay-{}", Uuid::new_v4()))
+}
+
+/// Creates the directory for the local fs overlay, see
+/// [`FsConfig::overlay`](mirrord_config::feature::fs::FsConfig::overlay).
+///
+/// When `feature.fs.overlay_dir` is not set, a fresh temporary directory is used, and stored in the
+/// config so that the layer and the internal proxy agree on it.
+fn prepare_fs_overlay(config: &mut LayerConfig) -> CliResult<()> {
+    let dir = config
+        .feature
+        .fs
+        .overlay_dir
+        .get_or_insert_with(|| new_fs_overlay_dir(&std::env::temp_dir()))
+        .clone();
+
+    let not_empty = match std::fs::read_dir(&dir) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => false,
+        Err(error) => return Err(CliError::FsOverlaySetup(dir, error)),
+    };
+    if not_empty {
+        return Err(CliError::FsOverlaySetup(
+            dir,
+            std::io::Error::from(std::io::ErrorKind::DirectoryNotEmpty),
+        ));
+    }
+
+    std::fs::create_dir_all(&dir).map_err(|error| CliError::FsOverlaySetup(dir.clone(), error))?;
+    debug!(?dir, "Prepared the fs overlay directory");
+
+    Ok(())
+}
+
 /// Creates a task that reads stderr and returns a vector of warnings at the end.
 /// Caller should cancel the token and wait on join handle.
 async fn watch_stderr<P>(stderr: ChildStderr, progress: &P) -> DropProgress<'_, P>
@@ -213,10 +267,24 @@ impl MirrordExecution {
 
         let branch_name = get_user_git_branch().await;
 
-        let (connect_info, mut connection) =
-            create_and_connect(config, progress, analytics, branch_name, mirrord_for_ci)
+        let (connect_info, mut connection) = create_and_connect(
+            config,
+            progress,
+            analytics,
+            branch_name.clone(),
+            mirrord_for_ci,
+        )
+        .await
+        .inspect_err(|_| analytics.set_error(AnalyticsError::AgentConnection))?;
+
+        // The connections are kept until the internal proxy makes its own, so that the agents
+        // don't exit prematurely.
+        let (additional_connect_infos, _additional_connections): (Vec<_>, Vec<_>) =
+            create_and_connect_additional(config, progress, analytics, branch_name, mirrord_for_ci)
                 .await
-                .inspect_err(|_| analytics.set_error(AnalyticsError::AgentConnection))?;
+                .inspect_err(|_| analytics.set_error(AnalyticsError::AgentConnection))?
+                .into_iter()
+                .unzip();
 
         let agent_protocol_version = match &connect_info {
             AgentConnectInfo::Operator(session) => session.operator_protocol_version.clone(),
@@ -272,6 +340,27 @@ impl MirrordExecution {
             unsafe { std::env::set_var("MIRRORD_LAYER_FILE", lib_path) };
         }
 
+        // The pure Go resolver sends raw DNS queries, resolving through `getaddrinfo` instead lets
+        // the layer apply the DNS overrides. Programs that can't use `getaddrinfo` get their raw
+        // queries answered by the internal proxy.
+        #[cfg(not(target_os = "windows"))]
+        if config.feature.network.dns.enabled && !config.feature.network.dns.overrides.is_empty() {
+            let godebug = env_vars
+                .get(GODEBUG_ENV_VAR)
+                .cloned()
+                .or_else(|| std::env::var(GODEBUG_ENV_VAR).ok())
+                .filter(|godebug| !godebug.is_empty());
+            let godebug = match godebug {
+                Some(godebug) => format!("{godebug},netdns=cgo"),
+                None => "netdns=cgo".to_string(),
+            };
+            env_vars.insert(GODEBUG_ENV_VAR.to_string(), godebug);
+        }
+
+        if config.feature.fs.uses_overlay() {
+            prepare_fs_overlay(config)?;
+        }
+
         let encoded_config = config.encode()?;
 
         let mut proxy_command =
@@ -297,6 +386,13 @@ impl MirrordExecution {
             )
             .env(LayerConfig::RESOLVED_CONFIG_ENV, &encoded_config);
 
+        if !additional_connect_infos.is_empty() {
+            proxy_command.env(
+                ADDITIONAL_AGENT_CONNECT_INFO_ENV_KEY,
+                serde_json::to_string(&additional_connect_infos)?,
+            );
+        }
+
         #[cfg(unix)]
         unsafe {
             proxy_command.pre_exec(|| reparent_to_init().map_err(Into::into));
@@ -348,42 +444,11 @@ impl MirrordExecution {
             intproxy_address.to_string(),
         );
 
-        #[cfg(target_os = "macos")]
-        let log_info = config
-            .experimental
-            .sip_log_destination
-            .as_ref()
-            .map(|log_destination| mirrord_sip::SipLogInfo {
-                log_destination,
-                args,
-                load_type: None,
-            });
-
         #[cfg(target_os = "macos")]
         let patched_path = executable
-            .and_then(|exe| {
-                sip_patch(
-                    exe,
-                    SipPatchOptions {
-                        patch: &config
-                            .sip_binaries
-                            .clone()
-                            .map(|x| x.to_vec())
-                            .unwrap_or_default(),
-                        skip: &config.skip_sip,
-                    },
-                    log_info,
-                )
-                .transpose() // We transpose twice to propagate a possible error out of this
-                // closure.
-            })
-            .transpose()
-            .inspect_err(|sip_error| {
-                // we can't recover from hitting the fd limit, so we have to exit fully
-                if let SipError::TooManyFilesOpen(..) = sip_error {
-                    panic!("mirrord failed to patch SIP with: {}", sip_error);
-                }
-            })?;
+            .map(|exe| Self::sip_patch_executable(config, exe, args))
+            .transpose()?
+            .flatten();
 
         #[cfg(not(target_os = "macos"))]
         let patched_path = None;
@@ -403,6 +468,44 @@ impl MirrordExecution {
         })
     }
 
+    /// Patches the executable if it's SIP protected, returns the path to the patched copy.
+    #[cfg(target_os = "macos")]
+    pub(crate) fn sip_patch_executable(
+        config: &LayerConfig,
+        executable: &str,
+        args: Option<&[OsString]>,
+    ) -> CliResult<Option<String>> {
+        let log_info = config
+            .experimental
+            .sip_log_destination
+            .as_ref()
+            .map(|log_destination| mirrord_sip::SipLogInfo {
+                log_destination,
+                args,
+                load_type: None,
+            });
+
+        sip_patch(
+            executable,
+            SipPatchOptions {
+                patch: &config
+                    .sip_binaries
+                    .clone()
+                    .map(|x| x.to_vec())
+                    .unwrap_or_default(),
+                skip: &config.skip_sip,
+            },
+            log_info,
+        )
+        .inspect_err(|sip_error| {
+            // we can't recover from hitting the fd limit, so we have to exit fully
+            if let SipError::TooManyFilesOpen(..) = sip_error {
+                panic!("mirrord failed to patch SIP with: {}", sip_error);
+            }
+        })
+        .map_err(From::from)
+    }
+
     async fn get_agent_version(connection: &mut Connection<Client>) -> CliResult<Version> {
         connection
             .send(ClientMessage::SwitchProtocolVersion(
@@ -445,6 +548,13 @@ impl MirrordExecution {
             remove_proxy_env();
         }
 
+        if !config.target.additional.is_empty() {
+            progress.warning(
+                "Additional targets are not supported in mirrord container, \
+                only the primary target will be used.",
+            );
+        }
+
         let branch_name = get_user_git_branch().await;
 
         let (connect_info, mut connection) =
@@ -575,6 +685,26 @@ impl MirrordExecution {
             Default::default()
         };
 
+        if config.feature.env.from_spec || config.feature.env.sources.is_some() {
+            let include = env_vars_include
+                .iter()
+                .map(|pattern| WildMatch::new(pattern))
+                .collect::<Vec<_>>();
+            // Same as the agent does with the environment of the target process.
+            let exclude = EXCLUDED_ENV_VARS
+                .iter()
+                .copied()
+                .chain(env_vars_exclude.iter().map(String::as_str))
+                .map(WildMatch::new)
+                .collect::<Vec<_>>();
+
+            let kube_env_vars = Self::fetch_kube_env_vars(config).await?;
+            env_vars.extend(kube_env_vars.into_iter().filter(|(key, _)| {
+                (include.is_empty() || include.iter().any(|pattern| pattern.matches(key)))
+                    && !exclude.iter().any(|pattern| pattern.matches(key))
+            }));
+        }
+
         if let Some(file) = &config.feature.env.env_file {
             let envs_from_file = dotenvy::from_path_iter(file)
                 .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
@@ -596,6 +726,59 @@ impl MirrordExecution {
         Ok(env_vars)
     }
 
+    /// Resolve environment variables with the Kubernetes API, as configured with
+    /// [`EnvConfig::from_spec`](mirrord_config::feature::env::EnvConfig::from_spec) and
+    /// [`EnvConfig::sources`](mirrord_config::feature::env::EnvConfig::sources).
+    ///
+    /// Uses the user's own Kubernetes client, not the operator's.
+    #[tracing::instrument(level = Level::TRACE, skip_all, err)]
+    async fn fetch_kube_env_vars(config: &LayerConfig) -> CliResult<HashMap<String, String>> {
+        let client = create_kube_config(
+            config.accept_invalid_certificates,
+            config.kubeconfig.clone(),
+            config.kube_context.clone(),
+        )
+        .await
+        .and_then(|kube_config| {
+            Ok(ClientBuilder::try_from(kube_config)?
+                .with_layer(&BufferLayer::new(1024))
+                .with_layer(&RetryLayer::new(RetryKube::try_from(
+                    &config.startup_retry,
+                )?))
+                .build())
+        })
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
+
+        let namespace = config.target.namespace.as_deref();
+        let mut env_vars = HashMap::new();
+
+        if config.feature.env.from_spec
+            && let Some(target) = config
+                .target
+                .path
+                .as_ref()
+                .filter(|target| !matches!(target, Target::Targetless))
+        {
+            env_vars = ResolvedTarget::new(&client, target, namespace)
+                .await
+                .map_err(CliError::KubeEnvResolution)?
+                .resolve_env(&client)
+                .await
+                .map_err(CliError::KubeEnvResolution)?;
+        }
+
+        if let Some(sources) = &config.feature.env.sources {
+            let loaded = load_env_sources(&client, namespace, sources)
+                .await
+                .map_err(CliError::KubeEnvResolution)?;
+            env_vars.extend(loaded);
+        }
+
+        Ok(env_vars)
+    }
+
     /// Retrieve remote environment from the connected agent.
     #[tracing::instrument(level = Level::TRACE, skip_all)]
     async fn get_remote_env(
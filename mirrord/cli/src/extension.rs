@@ -68,7 +68,7 @@ pub(crate) async fn extension_exec(
         .override_env_opt("MIRRORD_IMPERSONATED_TARGET", args.target);
 
     let mut config = LayerConfig::resolve(&mut cfg_context)?;
-    crate::profile::apply_profile_if_configured(&mut config, &progress).await?;
+    crate::profile::apply_profile_if_configured(&mut config, &mut cfg_context, &progress).await?;
 
     let mut analytics = AnalyticsReporter::only_error(
         config.telemetry,
@@ -37,7 +37,8 @@ use local_ip_address::local_ip;
 use mirrord_analytics::{AnalyticsReporter, CollectAnalytics, Reporter};
 use mirrord_config::{LayerConfig, external_proxy::MIRRORD_EXTPROXY_TLS_SETUP_PEM};
 use mirrord_intproxy::agent_conn::{AgentConnectInfo, AgentConnection};
-use mirrord_protocol::{ClientMessage, DaemonCodec, DaemonMessage, LogLevel, LogMessage};
+use mirrord_protocol::{ClientMessage, DaemonMessage, LogLevel, LogMessage};
+use mirrord_protocol_io::compression::CompressionCodec;
 use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::server::TlsStream;
 use tokio_util::{either::Either, sync::CancellationToken};
@@ -221,7 +222,9 @@ pub async fn proxy(
                     | message @ Some(DaemonMessage::PauseTarget(_))
                     | message @ Some(DaemonMessage::SwitchProtocolVersionResponse(_))
                     | message @ Some(DaemonMessage::Vpn(_))
-                    | message @ Some(DaemonMessage::ReverseDnsLookup(_)) => {
+                    | message @ Some(DaemonMessage::ReverseDnsLookup(_))
+                    | message @ Some(DaemonMessage::IdentifiedFile(_))
+                    | message @ Some(DaemonMessage::SwitchCompressionResponse(_)) => {
                         return Err(
                             ExternalProxyError::PingPongFailed(format!(
                                 "agent sent an unexpected message: {message:?}"
@@ -268,12 +271,21 @@ async fn handle_connection(
     mut agent_conn: AgentConnection,
     cancellation_token: CancellationToken,
 ) {
-    let mut stream = actix_codec::Framed::new(stream, DaemonCodec::default());
+    // The intproxy may switch on compression, the codec follows the switching messages in both
+    // directions.
+    let mut stream = actix_codec::Framed::new(
+        stream,
+        CompressionCodec::<ClientMessage, DaemonMessage>::default(),
+    );
 
     loop {
         tokio::select! {
             client_message = stream.next() => {
                 match client_message {
+                    Some(Ok(ClientMessage::SwitchCompression(algorithm))) if !agent_conn.supports_compression => {
+                        // The intproxy's side is already compressed, we just never confirm it.
+                        tracing::debug!(?peer_addr, ?algorithm, "operator doesn't support compression, not switching it on");
+                    }
                     Some(Ok(client_message)) => {
                         agent_conn.connection.send(client_message).await;
                     }
@@ -13,20 +13,23 @@
 use std::{
     env, io,
     net::{Ipv4Addr, SocketAddr},
+    path::Path,
     time::Duration,
 };
 #[cfg(not(target_os = "windows"))]
 use std::{ops::Not, os::unix::ffi::OsStrExt};
 
 use mirrord_analytics::{AnalyticsReporter, CollectAnalytics, Reporter};
-use mirrord_config::LayerConfig;
+use mirrord_config::{LayerConfig, feature::network::dns::DnsOverrides};
 use mirrord_intproxy::{
     IntProxy,
-    agent_conn::{AgentConnectInfo, AgentConnection},
+    agent_conn::{AdditionalAgentConnection, AgentConnectInfo, AgentConnection},
+    proxies::outgoing::capture::OutgoingCapture,
 };
 use mirrord_protocol::{ClientMessage, DaemonMessage, LogLevel, LogMessage};
 #[cfg(not(target_os = "windows"))]
 use nix::sys::resource::{Resource, setrlimit};
+use serde::de::DeserializeOwned;
 use tokio::net::TcpListener;
 use tracing::Level;
 #[cfg(not(target_os = "windows"))]
@@ -35,7 +38,7 @@ use tracing::warn;
 #[cfg(not(target_os = "windows"))]
 use crate::util::detach_io;
 use crate::{
-    connection::AGENT_CONNECT_INFO_ENV_KEY,
+    connection::{ADDITIONAL_AGENT_CONNECT_INFO_ENV_KEY, AGENT_CONNECT_INFO_ENV_KEY},
     error::{CliResult, InternalProxyError},
     execution::MIRRORD_EXECUTION_KIND_ENV,
     user_data::UserData,
@@ -50,6 +53,24 @@ fn print_addr(listener: &TcpListener) -> io::Result<()> {
     Ok(())
 }
 
+/// Reads the JSON encoded connect info from the given environment variable.
+fn connect_info_from_env<T: DeserializeOwned>(key: &str) -> Result<Option<T>, InternalProxyError> {
+    let Some(var) = env::var_os(key) else {
+        return Ok(None);
+    };
+
+    #[cfg(target_os = "windows")]
+    let var = var.to_string_lossy();
+    serde_json::from_slice(var.as_bytes())
+        .map(Some)
+        .map_err(|error| {
+            InternalProxyError::DeseralizeConnectInfo(
+                String::from_utf8_lossy(var.as_bytes()).into_owned(),
+                error,
+            )
+        })
+}
+
 /// Main entry point for the internal proxy.
 /// It listens for inbound layer connect and forwards to agent.
 #[tracing::instrument(level = Level::INFO, skip_all, err)]
@@ -73,18 +94,10 @@ pub(crate) async fn proxy(
         warn!(%error, "Failed to set the file descriptor limit");
     }
 
-    let agent_connect_info = env::var_os(AGENT_CONNECT_INFO_ENV_KEY)
-        .ok_or(InternalProxyError::MissingConnectInfo)
-        .and_then(|var| {
-            #[cfg(target_os = "windows")]
-            let var = var.to_string_lossy();
-            serde_json::from_slice(var.as_bytes()).map_err(|error| {
-                InternalProxyError::DeseralizeConnectInfo(
-                    String::from_utf8_lossy(var.as_bytes()).into_owned(),
-                    error,
-                )
-            })
-        })?;
+    let agent_connect_info: AgentConnectInfo = connect_info_from_env(AGENT_CONNECT_INFO_ENV_KEY)?
+        .ok_or(InternalProxyError::MissingConnectInfo)?;
+    let additional_connect_infos: Vec<AgentConnectInfo> =
+        connect_info_from_env(ADDITIONAL_AGENT_CONNECT_INFO_ENV_KEY)?.unwrap_or_default();
 
     let execution_kind = std::env::var(MIRRORD_EXECUTION_KIND_ENV)
         .ok()
@@ -116,6 +129,37 @@ pub(crate) async fn proxy(
     // We also perform initial ping pong round to ensure that k8s runtime actually made connection
     // with the agent (it's a must, because port forwarding may be done lazily).
     let agent_conn = connect_and_ping(&config, agent_connect_info, &mut analytics).await?;
+    let mut additional_conns = Vec::with_capacity(additional_connect_infos.len());
+    for (additional, connect_info) in config
+        .target
+        .additional
+        .iter()
+        .zip(additional_connect_infos)
+    {
+        let mut additional_config = config.clone();
+        additional_config.target = config.target.for_additional(additional);
+
+        additional_conns.push(AdditionalAgentConnection {
+            connection: connect_and_ping(&additional_config, connect_info, &mut analytics).await?,
+            ports: additional.ports.clone(),
+        });
+    }
+
+    let outgoing_capture = config
+        .internal_proxy
+        .capture_outgoing
+        .as_deref()
+        .map(|path| {
+            OutgoingCapture::create(path)
+                .map_err(|error| InternalProxyError::OutgoingCapture(path.to_path_buf(), error))
+        })
+        .transpose()?;
+
+    let dns_overrides = if config.feature.network.dns.enabled {
+        DnsOverrides::new(&config.feature.network.dns.overrides)?
+    } else {
+        Default::default()
+    };
 
     // Let it assign address for us then print it for the user.
     let listener = create_listen_socket(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), listen_port))
@@ -131,24 +175,78 @@ pub(crate) async fn proxy(
     let consecutive_connection_timeout = Duration::from_secs(config.internal_proxy.idle_timeout);
     let process_logging_interval =
         Duration::from_secs(config.internal_proxy.process_logging_interval);
+    let port_subscription_retention =
+        Duration::from_secs(config.internal_proxy.port_subscription_retention);
+    let https_delivery = config
+        .feature
+        .network
+        .incoming
+        .tls_delivery
+        .clone()
+        .or(config.feature.network.incoming.https_delivery.clone())
+        .unwrap_or_default();
 
-    IntProxy::new_with_connection(
+    let result = IntProxy::new_with_connection(
         agent_conn,
         listener,
         config.feature.fs.readonly_file_buffer,
-        config
-            .feature
-            .network
-            .incoming
-            .tls_delivery
-            .or(config.feature.network.incoming.https_delivery)
-            .unwrap_or_default(),
+        https_delivery.clone(),
         process_logging_interval,
+        port_subscription_retention,
+        outgoing_capture,
+        dns_overrides,
         &config.experimental,
     )
+    .with_additional_connections(additional_conns, https_delivery, &config.experimental)
     .run(first_connection_timeout, consecutive_connection_timeout)
-    .await
-    .map_err(From::from)
+    .await;
+
+    if config.feature.fs.uses_overlay()
+        && let Some(overlay_dir) = config.feature.fs.overlay_dir.as_deref()
+    {
+        finish_fs_overlay(overlay_dir, config.feature.fs.overlay_export.as_deref());
+    }
+
+    result.map_err(From::from)
+}
+
+/// Exports the local fs overlay to `export`, or discards it when there's nowhere to export it.
+///
+/// Called when all layers are gone, so that nothing writes to the overlay anymore.
+fn finish_fs_overlay(overlay_dir: &Path, export: Option<&Path>) {
+    if let Some(export) = export {
+        let result = std::fs::rename(overlay_dir, export).or_else(|_| {
+            // Probably a different filesystem.
+            copy_dir_all(overlay_dir, export)?;
+            std::fs::remove_dir_all(overlay_dir)
+        });
+
+        match result {
+            Ok(()) => tracing::info!(?overlay_dir, ?export, "Exported the fs overlay"),
+            Err(error) => {
+                tracing::warn!(?overlay_dir, ?export, %error, "Failed to export the fs overlay")
+            }
+        }
+    } else if let Err(error) = std::fs::remove_dir_all(overlay_dir) {
+        tracing::warn!(?overlay_dir, %error, "Failed to remove the fs overlay");
+    }
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
 }
 
 /// Creates a connection with the agent and handles one round of ping pong.
@@ -200,7 +298,9 @@ pub(crate) async fn connect_and_ping(
             | message @ Some(DaemonMessage::PauseTarget(_))
             | message @ Some(DaemonMessage::SwitchProtocolVersionResponse(_))
             | message @ Some(DaemonMessage::Vpn(_))
-            | message @ Some(DaemonMessage::ReverseDnsLookup(_)) => {
+            | message @ Some(DaemonMessage::ReverseDnsLookup(_))
+            | message @ Some(DaemonMessage::IdentifiedFile(_))
+            | message @ Some(DaemonMessage::SwitchCompressionResponse(_)) => {
                 break Err(InternalProxyError::InitialPingPongFailed(format!(
                     "agent sent an unexpected message: {message:?}"
                 )));
@@ -213,3 +313,58 @@ pub(crate) async fn connect_and_ping(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// The overlay is moved to the export directory with its whiteouts, or discarded.
+    #[test]
+    fn fs_overlay_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let overlay_dir = dir.path().join("overlay");
+        let export = dir.path().join("export");
+
+        fs::create_dir_all(overlay_dir.join("app/data")).unwrap();
+        fs::write(overlay_dir.join("app/config.yaml"), "changed").unwrap();
+        fs::write(overlay_dir.join("app/data/.wh.file.txt"), "").unwrap();
+
+        finish_fs_overlay(&overlay_dir, Some(&export));
+
+        assert!(!overlay_dir.exists());
+        assert_eq!(
+            fs::read_to_string(export.join("app/config.yaml")).unwrap(),
+            "changed"
+        );
+        assert!(export.join("app/data/.wh.file.txt").exists());
+
+        fs::create_dir_all(overlay_dir.join("app")).unwrap();
+        fs::write(overlay_dir.join("app/config.yaml"), "changed").unwrap();
+
+        finish_fs_overlay(&overlay_dir, None);
+
+        assert!(!overlay_dir.exists());
+    }
+
+    #[test]
+    fn fs_overlay_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+
+        fs::create_dir_all(from.join("app/empty")).unwrap();
+        fs::write(from.join("app/.wh.removed"), "").unwrap();
+        fs::write(from.join("app/config.yaml"), "changed").unwrap();
+
+        copy_dir_all(&from, &to).unwrap();
+
+        assert!(to.join("app/empty").is_dir());
+        assert!(to.join("app/.wh.removed").is_file());
+        assert_eq!(
+            fs::read_to_string(to.join("app/config.yaml")).unwrap(),
+            "changed"
+        );
+    }
+}
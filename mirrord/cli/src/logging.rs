@@ -2,9 +2,21 @@ use std::{fs::OpenOptions, future::Future, ops::Not, path::Path};
 
 use futures::StreamExt;
 use mirrord_config::LayerConfig;
+use opentelemetry::{
+    KeyValue,
+    trace::{TraceError, TracerProvider as _},
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    Resource,
+    propagation::TraceContextPropagator,
+    runtime::TokioCurrentThread,
+    trace::{Tracer, TracerProvider},
+};
 use tokio::io::AsyncWriteExt;
 use tokio_stream::Stream;
-use tracing_subscriber::{EnvFilter, prelude::*};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::{EnvFilter, Registry, prelude::*};
 
 use crate::{
     config::Commands,
@@ -60,6 +72,43 @@ pub async fn init_tracing_registry(
     Ok(())
 }
 
+/// Name of the intproxy in exported traces.
+const INTPROXY_SERVICE_NAME: &str = "mirrord-intproxy";
+
+/// Prepares a layer that exports spans to the OpenTelemetry collector at the given endpoint, using
+/// OTLP over gRPC.
+///
+/// Also installs the W3C trace context propagator, which the intproxy uses to continue traces of
+/// HTTP requests received from the agent.
+fn otlp_layer(endpoint: &str) -> Result<OpenTelemetryLayer<Registry, Tracer>, TraceError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    // The CLI runs on a current thread runtime, so the exporter needs its own thread.
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, TokioCurrentThread)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            INTPROXY_SERVICE_NAME,
+        )]))
+        .build();
+    let tracer = provider.tracer(INTPROXY_SERVICE_NAME);
+
+    opentelemetry::global::set_tracer_provider(provider);
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Exports the remaining spans, if [`otlp_layer`] was installed.
+///
+/// Should be called right before the intproxy exits.
+pub fn shutdown_otlp() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
 /// Initializes mirrord intproxy/extproxy tracing registry.
 ///
 /// Fails if the specified log file cannot be opened/created for writing.
@@ -72,6 +121,7 @@ async fn init_proxy_tracing_registry(
     log_destination: &Path,
     log_level: &str,
     json_log: bool,
+    otlp_layer: Option<OpenTelemetryLayer<Registry, Tracer>>,
 ) -> std::io::Result<()> {
     if std::env::var("MIRRORD_CONSOLE_ADDR").is_ok() {
         return Ok(());
@@ -100,6 +150,7 @@ async fn init_proxy_tracing_registry(
     };
 
     tracing_subscriber::registry()
+        .with(otlp_layer)
         .with(fmt_layer)
         .with(env_filter)
         .init();
@@ -110,11 +161,22 @@ async fn init_proxy_tracing_registry(
 pub async fn init_intproxy_tracing_registry(
     config: &LayerConfig,
 ) -> Result<(), InternalProxyError> {
+    let otlp_layer = config
+        .internal_proxy
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| {
+            otlp_layer(endpoint)
+                .map_err(|error| InternalProxyError::OtlpExport(endpoint.to_string(), error))
+        })
+        .transpose()?;
+
     if crate::util::intproxy_container_mode().not() {
         init_proxy_tracing_registry(
             &config.internal_proxy.log_destination,
             &config.internal_proxy.log_level,
             config.internal_proxy.json_log,
+            otlp_layer,
         )
         .await
         .map_err(|fail| {
@@ -145,6 +207,7 @@ pub async fn init_intproxy_tracing_registry(
         };
 
         tracing_subscriber::registry()
+            .with(otlp_layer)
             .with(fmt_layer)
             .with(env_filter)
             .init();
@@ -161,6 +224,7 @@ pub async fn init_extproxy_tracing_registry(
         log_destination,
         &config.external_proxy.log_level,
         config.external_proxy.json_log,
+        None,
     )
     .await
     .map_err(|fail| {
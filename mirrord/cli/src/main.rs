@@ -138,6 +138,18 @@
 //! The command itself is not really used anywhere. Other commands that are related to starting a
 //! mirrord instance use the [`extract_library`] function directly
 //!
+//! ### `mirrord session <COMMAND>`
+//!
+//! - `session::session_command`
+//!
+//! > Agent connections shared by many short-lived processes.
+//!
+//! `mirrord session start <NAME>` does what `mirrord exec` does up to starting the intproxy, and
+//! then leaves it running in the background, saving its address and the layer environment in
+//! `~/.mirrord/sessions`. Processes started with `mirrord exec --session <NAME>` skip target
+//! resolution and connect to that intproxy. `mirrord session list` and `mirrord session stop`
+//! talk to the intproxy with `SessionControlRequest`s.
+//!
 //! ### `mirrord verify-config [OPTIONS] <PATH>`
 //!
 //! - [`verify_config()`]
@@ -151,6 +163,16 @@
 //! special handling that allows the omission of a target, since in the IDE, a pop-up is shown
 //! for target selection if it was missing from the [`LayerConfig`].
 //!
+//! ### `mirrord config explain [OPTIONS]`
+//!
+//! - [`config_explain`]
+//!
+//! > Config provenance.
+//!
+//! Resolves the [`LayerConfig`] like `mirrord exec` would (accepting the same parameters), and
+//! prints every value with its source: a profile, a CLI flag, an env var, the config file, or the
+//! default. Supports text and JSON (for the IDE extensions) outputs.
+//!
 //! ### `mirrord operator <COMMAND>`
 //!
 //! - [`operator_command`]
@@ -300,6 +322,7 @@ use which::which;
 mod browser;
 mod ci;
 mod config;
+mod config_explain;
 mod connection;
 mod container;
 mod db_branches;
@@ -314,17 +337,26 @@ mod internal_proxy;
 #[cfg(target_os = "linux")]
 mod is_static;
 mod list;
+mod local_database;
+mod local_mongodb;
 mod local_redis;
 mod logging;
 mod newsletter;
 mod operator;
+mod policy;
 mod port_forward;
 mod profile;
+#[cfg(target_os = "linux")]
+mod seccomp;
+#[cfg(not(target_os = "windows"))]
+mod session;
 mod teams;
 mod user_data;
 mod util;
 mod verify_config;
 mod vpn;
+#[cfg(not(target_os = "windows"))]
+mod watch;
 mod wsl;
 
 #[cfg(feature = "wizard")]
@@ -340,6 +372,7 @@ use crate::{
     util::get_user_git_branch,
 };
 
+#[allow(clippy::too_many_arguments)]
 async fn exec_process<P>(
     mut config: LayerConfig,
     config_file_path: Option<&str>,
@@ -348,6 +381,7 @@ async fn exec_process<P>(
     analytics: &mut AnalyticsReporter,
     user_data: &mut UserData,
     mirrord_for_ci: Option<MirrordCi>,
+    local_databases: &local_database::LocalDatabases,
 ) -> CliResult<()>
 where
     P: Progress,
@@ -361,14 +395,47 @@ where
         let mut sub_progress =
             sub_progress.subtask("checking if target binary is dynamically linked");
         if is_static::is_binary_static(Path::new(&args.binary)) {
-            sub_progress.failure(Some(
-                "target binary might not be dynamically linked, mirrord might not work!",
-            ));
+            if config.experimental.seccomp_interception {
+                sub_progress.success(Some(
+                    "target binary is statically linked, mirrord will intercept its syscalls with seccomp",
+                ));
+            } else {
+                sub_progress.failure(Some(
+                    "target binary might not be dynamically linked, mirrord might not work!",
+                ));
+            }
         } else {
             sub_progress.success(Some("target binary is dynamically linked"));
         }
     }
 
+    // Checked before connecting, so that a bad pattern fails fast.
+    #[cfg(not(target_os = "windows"))]
+    let watcher = match (args.watch.is_empty(), &mirrord_for_ci) {
+        (true, _) => None,
+        (false, Some(..)) => {
+            sub_progress.warning("`--watch` is ignored when running in mirrord for CI");
+            None
+        }
+        (false, None) => {
+            let watcher = watch::FileWatcher::new(&args.watch)?;
+            config.internal_proxy.port_subscription_retention = config
+                .internal_proxy
+                .port_subscription_retention
+                .max(watch::PORT_SUBSCRIPTION_RETENTION);
+            Some(watcher)
+        }
+    };
+
+    if let Some(path) = &args.capture_outgoing {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+        sub_progress.info(&format!(
+            "outgoing traffic will be captured to {}",
+            path.display()
+        ));
+        config.internal_proxy.capture_outgoing = Some(path);
+    }
+
     #[cfg(target_os = "macos")]
     let binary_args = args
         .binary_args
@@ -413,6 +480,11 @@ where
         env_vars.remove(key);
     }
 
+    // Seeding needs the remote connection URL and the layer environment, so it can only happen
+    // once the internal proxy is running.
+    let local_database_env = local_databases.seed(&sub_progress, &env_vars).await?;
+    env_vars.extend(local_database_env);
+
     // Put original executable in argv[0] even if actually running patched version.
     let binary_args = std::iter::once(&args.binary)
         .chain(args.binary_args.iter())
@@ -442,6 +514,19 @@ where
     // print an invitation to the newsletter on certain run count numbers
     suggest_newsletter_signup(user_data, progress).await;
 
+    #[cfg(not(target_os = "windows"))]
+    if let Some(watcher) = watcher {
+        let mut sub_progress = progress.subtask("running process in watch mode");
+        let binary_path = process_which(&binary)?;
+        sub_progress.success(Some("Ready! Watching for changes"));
+
+        // The execution is kept alive, dropping it stops the internal proxy.
+        let _execution_info = execution_info;
+        return watch::run_watched(watcher, &binary_path, &binary_args, &env_vars, &*progress)
+            .await
+            .map_err(From::from);
+    }
+
     let sub_progress = progress.subtask("running process");
 
     run_process_with_mirrord(
@@ -506,6 +591,15 @@ async fn run_process_with_mirrord<P: Progress>(
             )
             .await
             .map_err(From::from),
+        #[cfg(target_os = "linux")]
+        None if config.experimental.seccomp_interception
+            && is_static::is_binary_static(&binary_path) =>
+        {
+            match seccomp::run_supervised(&binary_path, &binary_args, &env_vars, config)? {
+                0 => Ok(()),
+                code => Err(CliError::ProcessExit(code)),
+            }
+        }
         None => {
             // The execve hook is not yet active and does not hijack this call.
             let errno = nix::unistd::execve(&path, args.as_slice(), env.as_slice())
@@ -636,6 +730,7 @@ pub(crate) fn print_config<P>(
     let fs_info = match config.feature.fs.mode {
         FsModeConfig::Read => "read from the remote",
         FsModeConfig::Write => "read from and write to the remote",
+        FsModeConfig::Overlay => "read from the remote and write to a local overlay",
         _ => "read and write locally",
     };
     progress.info(&format!("fs: file operations will default to {}", fs_info));
@@ -723,25 +818,36 @@ pub(crate) fn print_config<P>(
         DnsConfig {
             enabled: true,
             filter: None,
+            ..
         } => "remotely",
         DnsConfig {
             enabled: true,
             filter: Some(DnsFilterConfig::Remote(filters)),
+            ..
         } if filters.is_empty() => "locally",
         DnsConfig {
             enabled: true,
             filter: Some(DnsFilterConfig::Local(filters)),
+            ..
         } if filters.is_empty() => "remotely",
         DnsConfig {
             enabled: true,
             filter: Some(DnsFilterConfig::Remote(..)),
+            ..
         } => "locally with exceptions",
         DnsConfig {
             enabled: true,
             filter: Some(DnsFilterConfig::Local(..)),
+            ..
         } => "remotely with exceptions",
     };
     progress.info(&format!("dns: DNS will be resolved {}", dns_info));
+    if config.feature.network.dns.enabled && !config.feature.network.dns.overrides.is_empty() {
+        progress.info(&format!(
+            "dns: {} hostname overrides will be applied",
+            config.feature.network.dns.overrides.len()
+        ));
+    }
 
     progress.info(&format!(
         "internal proxy: logs will be written to {}",
@@ -760,6 +866,19 @@ async fn exec(
 ) -> CliResult<()> {
     ensure_not_nested()?;
 
+    #[cfg(not(target_os = "windows"))]
+    if let Some(name) = &args.session {
+        return session::exec_in_session(
+            name,
+            args.binary.clone(),
+            args.binary_args.clone(),
+            watch,
+            user_data,
+            progress,
+        )
+        .await;
+    }
+
     if !args.params.disable_version_check {
         prompt_outdated_version(progress).await;
     }
@@ -784,7 +903,7 @@ async fn exec(
     let config_file_path = cfg_context.get_env(LayerConfig::FILE_PATH_ENV).ok();
     let mut config = LayerConfig::resolve(&mut cfg_context)?;
 
-    crate::profile::apply_profile_if_configured(&mut config, progress).await?;
+    crate::profile::apply_profile_if_configured(&mut config, &mut cfg_context, progress).await?;
 
     let _local_redis: Option<local_redis::LocalRedis> = if let Some(redis_config) =
         config.feature.db_branches.iter().find_map(|branch| {
@@ -817,6 +936,8 @@ async fn exec(
         None
     };
 
+    let local_databases = local_database::LocalDatabases::start(progress, &mut config).await?;
+
     let mut analytics = AnalyticsReporter::only_error(
         config.telemetry,
         Default::default(),
@@ -843,10 +964,15 @@ async fn exec(
         &mut analytics,
         user_data,
         mirrord_for_ci,
+        &local_databases,
     )
     .await;
 
-    if res.is_err() && !analytics.has_error() {
+    if res
+        .as_ref()
+        .is_err_and(|error| error.process_exit_code().is_none())
+        && !analytics.has_error()
+    {
         analytics.set_error(AnalyticsError::Unknown);
     }
     res
@@ -922,7 +1048,7 @@ async fn port_forward(
         .override_env_opt("MIRRORD_KUBE_CONTEXT", args.context.as_ref())
         .override_env_opt(LayerConfig::FILE_PATH_ENV, args.config_file.as_ref());
     let mut config = LayerConfig::resolve(&mut cfg_context)?;
-    crate::profile::apply_profile_if_configured(&mut config, &progress).await?;
+    crate::profile::apply_profile_if_configured(&mut config, &mut cfg_context, &progress).await?;
 
     let mut analytics = AnalyticsReporter::new(
         config.telemetry,
@@ -1064,9 +1190,13 @@ fn main() -> miette::Result<()> {
                 }
 
                 logging::init_intproxy_tracing_registry(&config).await?;
-                internal_proxy::proxy(config, port, watch, &user_data).await?
+                let result = internal_proxy::proxy(config, port, watch, &user_data).await;
+                logging::shutdown_otlp();
+                result?
             }
             Commands::VerifyConfig(args) => verify_config(args).await?,
+            Commands::Config(args) => config_explain::config_command(*args).await?,
+            Commands::Policy(args) => policy::policy_command(*args).await?,
             Commands::Completions(args) => {
                 let mut cmd: clap::Command = Cli::command();
                 generate(args.shell, &mut cmd, "mirrord", &mut std::io::stdout());
@@ -1102,6 +1232,10 @@ fn main() -> miette::Result<()> {
             Commands::Ci(args) => windows_unsupported!(args, "ci", {
                 ci::ci_command(*args, watch, &mut user_data).await?
             }),
+            Commands::Session(args) => windows_unsupported!(args, "session", {
+                #[cfg(not(target_os = "windows"))]
+                session::session_command(*args, watch, &mut user_data).await?
+            }),
             Commands::DbBranches(args) => db_branches_command(*args).await?,
             #[cfg(feature = "wizard")]
             Commands::Wizard(args) => {
@@ -1127,6 +1261,11 @@ fn main() -> miette::Result<()> {
             });
     });
 
+    // Everything is cleaned up by now, so we can exit with the code of the user application.
+    if let Some(code) = res.as_ref().err().and_then(CliError::process_exit_code) {
+        std::process::exit(code);
+    }
+
     res.map_err(Into::into)
 }
 
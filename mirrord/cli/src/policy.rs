@@ -0,0 +1,747 @@
+//! `mirrord policy check` resolves the [`LayerConfig`] like `mirrord exec` does, finds the
+//! [`MirrordPolicy`]s and [`MirrordClusterPolicy`]s that apply to the target, and prints what
+//! they will block or change in the session.
+//!
+//! Policies are enforced by the operator, this is only a best-effort preview that lets the user
+//! fix their config before starting a session. The policies are fetched with the user's
+//! Kubernetes client, so the user needs RBAC permissions to list them.
+use std::{collections::BTreeMap, fmt};
+
+use kube::{Api, Client, ResourceExt, api::ListParams};
+use mirrord_config::{
+    LayerConfig,
+    config::ConfigContext,
+    feature::network::incoming::{IncomingMode, http_filter::InnerFilter},
+    target::{Target, TargetDisplay},
+};
+use mirrord_kube::{api::kubernetes::KubernetesAPI, error::KubeApiError, resolved::ResolvedTarget};
+use mirrord_operator::crd::{
+    TargetCrd,
+    label_selector::LabelSelector,
+    policy::{
+        BlockedFeature, EnvPolicy, FsPolicy, MirrordClusterPolicy, MirrordClusterPolicySpec,
+        MirrordPolicy, MirrordPolicySpec, NetworkPolicy, OutgoingRule,
+    },
+};
+use mirrord_progress::NullProgress;
+use regex::Regex;
+use serde::Serialize;
+use wildmatch::WildMatch;
+
+use crate::{
+    CliError, CliResult,
+    config::{ExplainFormat, PolicyArgs, PolicyCheckArgs, PolicyCommand},
+    connection::resolve_selector_to_path,
+};
+
+/// Fields shared by [`MirrordPolicySpec`] and [`MirrordClusterPolicySpec`].
+#[derive(Debug)]
+struct PolicyRules<'a> {
+    target_path: Option<&'a str>,
+    selector: Option<&'a LabelSelector>,
+    block: &'a [BlockedFeature],
+    env: &'a EnvPolicy,
+    fs: &'a FsPolicy,
+    network: &'a NetworkPolicy,
+    require_profile: bool,
+    profile_allowlist: Option<&'a [String]>,
+    applies_to_copy_targets: bool,
+}
+
+impl<'a> From<&'a MirrordPolicySpec> for PolicyRules<'a> {
+    fn from(spec: &'a MirrordPolicySpec) -> Self {
+        Self {
+            target_path: spec.target_path.as_deref(),
+            selector: spec.selector.as_ref(),
+            block: &spec.block,
+            env: &spec.env,
+            fs: &spec.fs,
+            network: &spec.network,
+            require_profile: spec.require_profile,
+            profile_allowlist: spec.profile_allowlist.as_deref(),
+            applies_to_copy_targets: spec.applies_to_copy_targets,
+        }
+    }
+}
+
+impl<'a> From<&'a MirrordClusterPolicySpec> for PolicyRules<'a> {
+    fn from(spec: &'a MirrordClusterPolicySpec) -> Self {
+        Self {
+            target_path: spec.target_path.as_deref(),
+            selector: spec.selector.as_ref(),
+            block: &spec.block,
+            env: &spec.env,
+            fs: &spec.fs,
+            network: &spec.network,
+            require_profile: spec.require_profile,
+            profile_allowlist: spec.profile_allowlist.as_deref(),
+            applies_to_copy_targets: spec.applies_to_copy_targets,
+        }
+    }
+}
+
+enum PolicyFetchResult {
+    Cluster(MirrordClusterPolicy),
+    Namespaced(MirrordPolicy),
+}
+
+impl PolicyFetchResult {
+    fn rules(&self) -> PolicyRules<'_> {
+        match self {
+            PolicyFetchResult::Cluster(policy) => (&policy.spec).into(),
+            PolicyFetchResult::Namespaced(policy) => (&policy.spec).into(),
+        }
+    }
+
+    /// `<name>` for cluster-wide policies, `<namespace>/<name>` for namespaced ones.
+    fn name(&self) -> String {
+        match self {
+            PolicyFetchResult::Cluster(policy) => policy.name_any(),
+            PolicyFetchResult::Namespaced(policy) => format!(
+                "{}/{}",
+                policy.namespace().unwrap_or_default(),
+                policy.name_any()
+            ),
+        }
+    }
+}
+
+/// The session target, as seen by the policies.
+#[derive(Debug, Default)]
+struct PolicyTarget {
+    /// Paths that [`PolicyRules::target_path`] is matched against, in the `deploy/my-deploy`
+    /// notation, e.g. `deploy/my-deploy` and `deploy/my-deploy/container/my-container`.
+    ///
+    /// Empty for targetless sessions.
+    paths: Vec<String>,
+
+    /// Labels of the target resource.
+    labels: Option<BTreeMap<String, String>>,
+}
+
+impl PolicyTarget {
+    fn new(target: &Target, labels: Option<BTreeMap<String, String>>) -> Self {
+        if matches!(target, Target::Targetless) {
+            return Self {
+                paths: Vec::new(),
+                labels,
+            };
+        }
+
+        // Policies use the same short type names as the operator urls, e.g. `deploy`.
+        let urlfied = TargetCrd::urlfied_name(target);
+        let type_name = urlfied.split('.').next().unwrap_or_default();
+        let path = format!("{type_name}/{}", target.name());
+
+        let paths = match target.container() {
+            Some(container) => vec![format!("{path}/container/{container}"), path],
+            None => vec![path],
+        };
+
+        Self { paths, labels }
+    }
+}
+
+impl PolicyRules<'_> {
+    /// Whether this policy applies to a session with the given target and config.
+    ///
+    /// A `target_path` pattern matches if it matches the path of the target with or without the
+    /// container, so policies that specify one never apply to targetless sessions.
+    ///
+    /// A `selector` is matched against the labels of the target. Targetless sessions have no
+    /// labels, so only selectors made of negative rules (`NotIn` and `DoesNotExist`) apply to
+    /// them, like in the operator.
+    fn applies_to(&self, target: &PolicyTarget, config: &LayerConfig) -> bool {
+        if config.feature.copy_target.enabled && !self.applies_to_copy_targets {
+            return false;
+        }
+
+        if let Some(pattern) = self.target_path {
+            let pattern = WildMatch::new(pattern);
+            if !target.paths.iter().any(|path| pattern.matches(path)) {
+                return false;
+            }
+        }
+
+        self.selector
+            .is_none_or(|selector| selector.matches_optional(&target.labels))
+    }
+
+    /// Evaluates this policy against the config.
+    fn evaluate(&self, config: &LayerConfig) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let feature = &config.feature;
+        let incoming = &feature.network.incoming;
+
+        let blocks = |blocked: BlockedFeature| self.block.contains(&blocked);
+
+        if incoming.is_steal() {
+            if blocks(BlockedFeature::Steal) {
+                findings.push(Finding::blocked("stealing incoming traffic is not allowed"));
+            } else if !incoming.http_filter.is_filter_set()
+                && (blocks(BlockedFeature::StealWithoutFilter)
+                    || self.network.incoming.http_filter.header_filter.is_some())
+            {
+                findings.push(Finding::blocked(
+                    "stealing incoming traffic without an HTTP filter is not allowed",
+                ));
+            } else if let Some(pattern) = &self.network.incoming.http_filter.header_filter {
+                findings.extend(check_header_filter(pattern, config));
+            }
+        }
+
+        if incoming.mode == IncomingMode::Mirror && blocks(BlockedFeature::Mirror) {
+            findings.push(Finding::blocked(
+                "mirroring incoming traffic is not allowed",
+            ));
+        }
+
+        if feature.copy_target.enabled
+            && feature.copy_target.scale_down
+            && blocks(BlockedFeature::CopyTargetScaleDown)
+        {
+            findings.push(Finding::blocked(
+                "scaling down the target with `copy_target` is not allowed",
+            ));
+        }
+
+        match (config.profile.as_deref(), self.profile_allowlist) {
+            (None, _) if self.require_profile => {
+                findings.push(Finding::blocked("a mirrord profile is required"));
+            }
+            (Some(profile), Some(allowlist))
+                if !allowlist.iter().any(|allowed| allowed == profile) =>
+            {
+                findings.push(Finding::blocked(format!(
+                    "mirrord profile `{profile}` is not allowed, allowed profiles: {}",
+                    allowlist.join(", ")
+                )));
+            }
+            (None, Some(allowlist)) => {
+                findings.push(Finding::blocked(format!(
+                    "one of the allowed mirrord profiles is required: {}",
+                    allowlist.join(", ")
+                )));
+            }
+            _ => {}
+        }
+
+        if !self.env.exclude.is_empty() {
+            findings.push(Finding::changed(format!(
+                "environment variables matching {} will not be fetched from the target",
+                sorted_list(&self.env.exclude)
+            )));
+        }
+
+        if !feature.fs.mode.is_local() {
+            let fs_rules = [
+                (&self.fs.local, "will be opened locally"),
+                (&self.fs.not_found, "will not be found"),
+                (&self.fs.read_only, "can only be opened for reading"),
+            ];
+            for (patterns, effect) in fs_rules {
+                if !patterns.is_empty() {
+                    findings.push(Finding::changed(format!(
+                        "remote files matching {} {effect}",
+                        sorted_list(patterns)
+                    )));
+                }
+            }
+        }
+
+        let outgoing = &feature.network.outgoing;
+        if outgoing.tcp || outgoing.udp {
+            let rules = &self.network.outgoing;
+            if !rules.allow.is_empty() {
+                findings.push(Finding::changed(format!(
+                    "only outgoing connections to {} are allowed",
+                    describe_rules(&rules.allow)
+                )));
+            }
+            if !rules.block.is_empty() {
+                findings.push(Finding::changed(format!(
+                    "outgoing connections to {} are blocked",
+                    describe_rules(&rules.block)
+                )));
+            }
+        }
+
+        findings
+    }
+}
+
+/// Checks the user's HTTP header filters against the `header_filter` regex required by the
+/// policy, see [`HttpFilterPolicy::header_filter`].
+///
+/// [`HttpFilterPolicy::header_filter`]: mirrord_operator::crd::policy::HttpFilterPolicy::header_filter
+fn check_header_filter(pattern: &str, config: &LayerConfig) -> Option<Finding> {
+    let regex = match Regex::new(pattern) {
+        Ok(regex) => regex,
+        Err(error) => {
+            return Some(Finding::blocked(format!(
+                "the policy requires a header filter matching an invalid regex `{pattern}`: {error}"
+            )));
+        }
+    };
+
+    let http_filter = &config.feature.network.incoming.http_filter;
+    let nested_headers = |filters: &[InnerFilter]| {
+        filters
+            .iter()
+            .filter_map(|filter| match filter {
+                InnerFilter::Header { header } => Some(header.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let allowed = if let Some(header) = &http_filter.header_filter {
+        regex.is_match(header)
+    } else if let Some(all_of) = &http_filter.all_of {
+        nested_headers(all_of)
+            .into_iter()
+            .any(|header| regex.is_match(header))
+    } else if let Some(any_of) = &http_filter.any_of {
+        let headers = nested_headers(any_of);
+        !headers.is_empty() && headers.into_iter().all(|header| regex.is_match(header))
+    } else {
+        false
+    };
+
+    (!allowed).then(|| {
+        Finding::blocked(format!(
+            "stealing incoming traffic requires a header filter matching `{pattern}`"
+        ))
+    })
+}
+
+/// Joins the patterns in a stable order.
+fn sorted_list<'a, I: IntoIterator<Item = &'a String>>(patterns: I) -> String {
+    let mut patterns = patterns
+        .into_iter()
+        .map(|pattern| format!("`{pattern}`"))
+        .collect::<Vec<_>>();
+    patterns.sort();
+    patterns.join(", ")
+}
+
+/// Human-readable description of [`OutgoingRule`]s, e.g. `10.0.0.0/16 on TCP/5432`.
+fn describe_rules(rules: &[OutgoingRule]) -> String {
+    rules
+        .iter()
+        .map(|rule| {
+            let mut destinations = Vec::new();
+            if let Some(ip_block) = &rule.ip_block {
+                if ip_block.except.is_empty() {
+                    destinations.push(ip_block.cidr.clone());
+                } else {
+                    destinations.push(format!(
+                        "{} (except {})",
+                        ip_block.cidr,
+                        sorted_list(&ip_block.except)
+                    ));
+                }
+            }
+            if let Some(hostname) = &rule.hostname {
+                destinations.push(format!("hostnames matching `{hostname}`"));
+            }
+            if destinations.is_empty() {
+                destinations.push("any host".to_string());
+            }
+
+            let mut description = destinations.join(" and ");
+            if !rule.ports.is_empty() {
+                let mut ports = rule
+                    .ports
+                    .iter()
+                    .map(|port| format!("{:?}/{}", port.protocol, port.port))
+                    .collect::<Vec<_>>();
+                ports.sort();
+                description.push_str(&format!(" on {}", ports.join(", ")));
+            }
+
+            description
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// What a policy does with the session.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Effect {
+    /// The operator will refuse to start the session.
+    Blocked,
+    /// The session will start, but will behave differently than the config says.
+    Changed,
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Blocked => f.write_str("blocked"),
+            Self::Changed => f.write_str("changed"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Finding {
+    effect: Effect,
+    message: String,
+}
+
+impl Finding {
+    fn blocked<S: Into<String>>(message: S) -> Self {
+        Self {
+            effect: Effect::Blocked,
+            message: message.into(),
+        }
+    }
+
+    fn changed<S: Into<String>>(message: S) -> Self {
+        Self {
+            effect: Effect::Changed,
+            message: message.into(),
+        }
+    }
+}
+
+/// A policy that applies to the session.
+#[derive(Serialize, Debug)]
+struct CheckedPolicy {
+    name: String,
+    cluster_wide: bool,
+    findings: Vec<Finding>,
+}
+
+/// Output of `mirrord policy check --output json`.
+#[derive(Serialize, Debug)]
+struct PolicyCheck {
+    target: String,
+    namespace: String,
+    policies: Vec<CheckedPolicy>,
+}
+
+impl PolicyCheck {
+    fn blocked_count(&self) -> usize {
+        self.policies
+            .iter()
+            .flat_map(|policy| &policy.findings)
+            .filter(|finding| finding.effect == Effect::Blocked)
+            .count()
+    }
+}
+
+impl fmt::Display for PolicyCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "target: {} in namespace {}", self.target, self.namespace)?;
+
+        if self.policies.is_empty() {
+            return writeln!(f, "No mirrord policies apply to this session.");
+        }
+
+        for policy in &self.policies {
+            let kind = if policy.cluster_wide {
+                "mirrordclusterpolicy"
+            } else {
+                "mirrordpolicy"
+            };
+
+            if policy.findings.is_empty() {
+                writeln!(f, "{kind} `{}`: no effect on this session", policy.name)?;
+                continue;
+            }
+
+            writeln!(f, "{kind} `{}`:", policy.name)?;
+            for finding in &policy.findings {
+                writeln!(f, "  {}: {}", finding.effect, finding.message)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) async fn policy_command(args: PolicyArgs) -> CliResult<()> {
+    match args.command {
+        PolicyCommand::Check(args) => policy_check(*args).await,
+    }
+}
+
+/// Lists the namespaced policies from the target namespace, and all cluster-wide policies.
+async fn list_policies(
+    client: &Client,
+    namespace: &str,
+) -> Result<Vec<PolicyFetchResult>, KubeApiError> {
+    let namespaced = Api::<MirrordPolicy>::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?;
+    let cluster = Api::<MirrordClusterPolicy>::all(client.clone())
+        .list(&ListParams::default())
+        .await?;
+
+    Ok(namespaced
+        .into_iter()
+        .map(PolicyFetchResult::Namespaced)
+        .chain(cluster.into_iter().map(PolicyFetchResult::Cluster))
+        .collect())
+}
+
+async fn policy_check(args: PolicyCheckArgs) -> CliResult<()> {
+    let mut context = ConfigContext::default().override_envs(args.params.as_env_vars());
+    let mut config = LayerConfig::resolve(&mut context)?;
+    crate::profile::apply_profile_if_configured(&mut config, &mut context, &NullProgress).await?;
+    config.verify(&mut context)?;
+    resolve_selector_to_path(&mut config, &mut NullProgress).await?;
+
+    let k8s_api = KubernetesAPI::create(&config, &NullProgress)
+        .await
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::CreateKubeApiFailed)
+        })?;
+    let client = k8s_api.client();
+
+    let namespace = config
+        .target
+        .namespace
+        .clone()
+        .unwrap_or_else(|| client.default_namespace().to_string());
+    let target = config.target.path.clone().unwrap_or(Target::Targetless);
+
+    let labels = ResolvedTarget::new(client, &target, Some(&namespace))
+        .await
+        .map_err(|error| {
+            CliError::friendlier_error_or_else(error, CliError::OperatorTargetResolution)
+        })?
+        .into_labels();
+    let policy_target = PolicyTarget::new(&target, labels);
+
+    let policies = list_policies(client, &namespace)
+        .await
+        .map_err(|error| CliError::friendlier_error_or_else(error, CliError::PolicyFetch))?
+        .into_iter()
+        .filter(|policy| policy.rules().applies_to(&policy_target, &config))
+        .map(|policy| CheckedPolicy {
+            name: policy.name(),
+            cluster_wide: matches!(policy, PolicyFetchResult::Cluster(..)),
+            findings: policy.rules().evaluate(&config),
+        })
+        .collect();
+
+    let check = PolicyCheck {
+        target: target.to_string(),
+        namespace,
+        policies,
+    };
+
+    match args.output {
+        ExplainFormat::Text => print!("{check}"),
+        ExplainFormat::Json => println!("{}", serde_json::to_string_pretty(&check)?),
+    }
+
+    match check.blocked_count() {
+        0 => Ok(()),
+        blocked => Err(CliError::PolicyCheckBlocked(blocked)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mirrord_config::target::{deployment::DeploymentTarget, pod::PodTarget};
+    use mirrord_operator::crd::{
+        label_selector::{LabelSelector, LabelSelectorRequirement, MatchExpressionOperator},
+        policy::{IpBlock, PortRule, Protocol},
+    };
+
+    use super::*;
+
+    fn config(envs: &[(&str, &str)]) -> LayerConfig {
+        let mut context = ConfigContext::default().strict_env(true);
+        for (key, value) in envs {
+            context = context.override_env(*key, *value);
+        }
+        LayerConfig::resolve(&mut context).unwrap()
+    }
+
+    fn spec() -> MirrordPolicySpec {
+        MirrordPolicySpec {
+            target_path: None,
+            selector: None,
+            block: Vec::new(),
+            env: Default::default(),
+            fs: Default::default(),
+            network: Default::default(),
+            require_profile: false,
+            profile_allowlist: None,
+            applies_to_copy_targets: false,
+        }
+    }
+
+    fn blocked(findings: &[Finding]) -> usize {
+        findings
+            .iter()
+            .filter(|finding| finding.effect == Effect::Blocked)
+            .count()
+    }
+
+    #[test]
+    fn target_matching() {
+        let target = PolicyTarget::new(
+            &Target::Deployment(DeploymentTarget {
+                deployment: "my-deploy".into(),
+                container: Some("app".into()),
+            }),
+            Some([("app".to_string(), "web".to_string())].into()),
+        );
+        assert_eq!(
+            target.paths,
+            ["deploy/my-deploy/container/app", "deploy/my-deploy"]
+        );
+
+        let config = config(&[]);
+        let mut spec = spec();
+        assert!(PolicyRules::from(&spec).applies_to(&target, &config));
+
+        spec.target_path = Some("deploy/my-*".into());
+        assert!(PolicyRules::from(&spec).applies_to(&target, &config));
+
+        spec.target_path = Some("pod/*".into());
+        assert!(!PolicyRules::from(&spec).applies_to(&target, &config));
+
+        let pod = PolicyTarget::new(
+            &Target::Pod(PodTarget {
+                pod: "my-pod".into(),
+                container: None,
+            }),
+            None,
+        );
+        assert!(PolicyRules::from(&spec).applies_to(&pod, &config));
+        assert!(!PolicyRules::from(&spec).applies_to(&PolicyTarget::default(), &config));
+
+        let mut copy_target = config.clone();
+        copy_target.feature.copy_target.enabled = true;
+        assert!(!PolicyRules::from(&spec).applies_to(&pod, &copy_target));
+        spec.applies_to_copy_targets = true;
+        assert!(PolicyRules::from(&spec).applies_to(&pod, &copy_target));
+    }
+
+    #[test]
+    fn selector_matching() {
+        let labelled = PolicyTarget::new(
+            &Target::Pod(PodTarget {
+                pod: "my-pod".into(),
+                container: None,
+            }),
+            Some([("app".to_string(), "web".to_string())].into()),
+        );
+        let targetless = PolicyTarget::new(&Target::Targetless, None);
+        let config = config(&[]);
+
+        let selector = |operator, values: Option<&[&str]>| LabelSelector {
+            match_expressions: Some(vec![LabelSelectorRequirement {
+                key: "app".into(),
+                operator,
+                values: values.map(|values| values.iter().map(ToString::to_string).collect()),
+            }]),
+            match_labels: None,
+        };
+        let mut spec = spec();
+
+        spec.selector = Some(selector(MatchExpressionOperator::In, Some(&["web"])));
+        assert!(PolicyRules::from(&spec).applies_to(&labelled, &config));
+        assert!(!PolicyRules::from(&spec).applies_to(&targetless, &config));
+
+        spec.selector = Some(selector(MatchExpressionOperator::Exists, None));
+        assert!(!PolicyRules::from(&spec).applies_to(&targetless, &config));
+
+        spec.selector = Some(selector(MatchExpressionOperator::NotIn, Some(&["web"])));
+        assert!(!PolicyRules::from(&spec).applies_to(&labelled, &config));
+        assert!(PolicyRules::from(&spec).applies_to(&targetless, &config));
+
+        spec.selector = Some(selector(MatchExpressionOperator::DoesNotExist, None));
+        assert!(PolicyRules::from(&spec).applies_to(&targetless, &config));
+    }
+
+    #[test]
+    fn steal_rules() {
+        let steal = config(&[("MIRRORD_AGENT_TCP_STEAL_TRAFFIC", "true")]);
+        let filtered = config(&[
+            ("MIRRORD_AGENT_TCP_STEAL_TRAFFIC", "true"),
+            ("MIRRORD_HTTP_HEADER_FILTER", "x-user: alice"),
+        ]);
+
+        let mut spec = spec();
+        spec.block = vec![BlockedFeature::StealWithoutFilter];
+        assert_eq!(blocked(&PolicyRules::from(&spec).evaluate(&steal)), 1);
+        assert_eq!(blocked(&PolicyRules::from(&spec).evaluate(&filtered)), 0);
+
+        spec.block = vec![BlockedFeature::Steal];
+        assert_eq!(blocked(&PolicyRules::from(&spec).evaluate(&filtered)), 1);
+
+        spec.block = Vec::new();
+        spec.network.incoming.http_filter.header_filter = Some("^x-user: .+".into());
+        assert_eq!(blocked(&PolicyRules::from(&spec).evaluate(&steal)), 1);
+        assert_eq!(blocked(&PolicyRules::from(&spec).evaluate(&filtered)), 0);
+
+        spec.network.incoming.http_filter.header_filter = Some("^x-tenant: .+".into());
+        assert_eq!(blocked(&PolicyRules::from(&spec).evaluate(&filtered)), 1);
+    }
+
+    #[test]
+    fn profile_rules() {
+        let mut spec = spec();
+        spec.require_profile = true;
+        assert_eq!(blocked(&PolicyRules::from(&spec).evaluate(&config(&[]))), 1);
+
+        let mut with_profile = config(&[]);
+        with_profile.profile = Some("safe".into());
+        assert_eq!(
+            blocked(&PolicyRules::from(&spec).evaluate(&with_profile)),
+            0
+        );
+
+        spec.profile_allowlist = Some(vec!["other".into()]);
+        assert_eq!(
+            blocked(&PolicyRules::from(&spec).evaluate(&with_profile)),
+            1
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let mut spec = spec();
+        spec.env.exclude = ["DB_*".to_string()].into();
+        spec.fs.read_only = [".*".to_string()].into();
+        spec.network.outgoing.block = vec![OutgoingRule {
+            ip_block: Some(IpBlock {
+                cidr: "10.0.0.0/16".into(),
+                except: Default::default(),
+            }),
+            hostname: None,
+            ports: [PortRule {
+                protocol: Protocol::TCP,
+                port: 5432,
+            }]
+            .into(),
+        }];
+
+        let findings = PolicyRules::from(&spec).evaluate(&config(&[]));
+        assert_eq!(blocked(&findings), 0);
+        let messages = findings
+            .iter()
+            .map(|finding| finding.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "environment variables matching `DB_*` will not be fetched from the target",
+                "remote files matching `.*` can only be opened for reading",
+                "outgoing connections to 10.0.0.0/16 on TCP/5432 are blocked",
+            ]
+        );
+
+        let local_fs = config(&[("MIRRORD_FILE_MODE", "local")]);
+        assert_eq!(PolicyRules::from(&spec).evaluate(&local_fs).len(), 2);
+    }
+}
@@ -359,7 +359,9 @@ impl PortForwarder {
             | DaemonMessage::UdpOutgoing(..)
             | DaemonMessage::Vpn(..)
             | DaemonMessage::TcpSteal(..)
-            | DaemonMessage::ReverseDnsLookup(..)) => {
+            | DaemonMessage::ReverseDnsLookup(..)
+            | DaemonMessage::IdentifiedFile(..)
+            | DaemonMessage::SwitchCompressionResponse(..)) => {
                 // includes unexpected DaemonMessage::Pong
                 return Err(PortForwardError::AgentError(format!(
                     "unexpected message from agent: {message:?}"
@@ -625,7 +627,9 @@ impl ReversePortForwarder {
             | message @ DaemonMessage::SwitchProtocolVersionResponse(_)
             | message @ DaemonMessage::Vpn(_)
             | message @ DaemonMessage::Pong
-            | message @ DaemonMessage::ReverseDnsLookup(_) => {
+            | message @ DaemonMessage::ReverseDnsLookup(_)
+            | message @ DaemonMessage::IdentifiedFile(_)
+            | message @ DaemonMessage::SwitchCompressionResponse(_) => {
                 return Err(PortForwardError::AgentError(format!(
                     "unexpected message from agent: {message:?}"
                 )));
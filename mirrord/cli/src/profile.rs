@@ -7,6 +7,7 @@ use kube::{Api, client::ClientBuilder};
 use miette::Diagnostic;
 use mirrord_config::{
     LayerConfig,
+    config::{ConfigContext, ConfigError},
     feature::{FeatureConfig, network::incoming::IncomingMode},
     util::VecOrSingle,
 };
@@ -49,6 +50,22 @@ pub enum ProfileError {
 
     #[error("mirrord profile must be cluster-wide or in the same namespace as the target ({0})")]
     NamespaceConflict(String),
+
+    #[error("failed to apply the config patch from the mirrord profile: {0}")]
+    #[diagnostic(help(
+        "The `configPatch` of the profile, merged into your mirrord config, \
+        must produce a valid config."
+    ))]
+    ConfigPatch(ConfigError),
+
+    #[error(
+        "mirrord config is invalid, so the config patch from the mirrord profile can't be applied: {0}"
+    )]
+    #[diagnostic(help(
+        "Your mirrord config must be valid before the `configPatch` of the profile is merged \
+        into it."
+    ))]
+    InvalidConfig(ConfigError),
 }
 
 /// Identifier of [`MirrordClusterProfile`] and [`MirrordProfile`].
@@ -95,6 +112,13 @@ impl ProfileFetchResult {
         }
     }
 
+    fn config_patch(&self) -> Option<&serde_json::Value> {
+        match self {
+            ProfileFetchResult::Cluster(profile) => profile.spec.config_patch.as_ref(),
+            ProfileFetchResult::Namespaced(profile) => profile.spec.config_patch.as_ref(),
+        }
+    }
+
     fn unknown_fields(&self) -> impl Iterator<Item = (&String, &serde_json::Value)> {
         match self {
             ProfileFetchResult::Cluster(profile) => profile.spec.unknown_fields.iter(),
@@ -156,16 +180,35 @@ impl FeatureAdjustmentExt for FeatureAdjustment {
 }
 
 /// Applies the given profile to the given [`LayerConfig`].
-fn apply_profile<P: Progress>(
+///
+/// If the profile has a config patch, the config is generated again with
+/// [`LayerConfig::resolve_with_patch`], using the given [`ConfigContext`].
+///
+/// Calls `on_change` after every change made to the config, see
+/// [`apply_profile_if_configured_with`].
+fn apply_profile<P: Progress, F: FnMut(&str, &LayerConfig)>(
     config: &mut LayerConfig,
+    context: &mut ConfigContext,
     profile: &ProfileFetchResult,
     profile_identifier: &ProfileIdentifier,
     subtask: &mut P,
+    on_change: &mut F,
 ) -> Result<(), ProfileError> {
     if let Some((field, _)) = profile.unknown_fields().next() {
         return Err(ProfileError::UnknownField(field.to_string()));
     }
 
+    if let Some(patch) = profile.config_patch() {
+        subtask.info("applying config patch");
+        *config = LayerConfig::resolve_with_patch(context, patch).map_err(|error| match error {
+            ConfigError::InvalidConfigFile(..) | ConfigError::FromFileError(..) => {
+                ProfileError::InvalidConfig(error)
+            }
+            error => ProfileError::ConfigPatch(error),
+        })?;
+        on_change("config patch", config);
+    }
+
     if let ProfileIdentifier::Namespaced {
         namespace: profile_ns,
         profile: _,
@@ -178,11 +221,18 @@ fn apply_profile<P: Progress>(
         } else {
             subtask.info(&format!("setting target namespace to {profile_ns}"));
             config.target.namespace = Some(profile_ns.to_string());
+            on_change("target namespace", config);
         }
     }
 
     for adjustment in profile.feature_adjustments() {
         adjustment.apply_to(&mut config.feature)?;
+
+        let change = serde_json::to_value(adjustment.change)
+            .ok()
+            .and_then(|change| change.as_str().map(ToString::to_string))
+            .unwrap_or_else(|| format!("{:?}", adjustment.change));
+        on_change(&change, config);
     }
 
     Ok(())
@@ -193,9 +243,26 @@ fn apply_profile<P: Progress>(
 /// to the config.
 ///
 /// Verifies that the fetched profile does not contain any unknown fields or values.
+///
+/// `context` should be the one that was used to resolve the `config`, see [`apply_profile`].
 pub async fn apply_profile_if_configured<P: Progress>(
     config: &mut LayerConfig,
+    context: &mut ConfigContext,
+    progress: &P,
+) -> Result<(), CliError> {
+    apply_profile_if_configured_with(config, context, progress, |_, _| {}).await
+}
+
+/// Same as [`apply_profile_if_configured`], but calls `on_change` after every change that the
+/// profile makes to the config, with a short description of the change (the config patch, the
+/// target namespace, or the name of the [`FeatureChange`]).
+///
+/// Used by `mirrord config explain` to find the values that come from the profile.
+pub async fn apply_profile_if_configured_with<P: Progress, F: FnMut(&str, &LayerConfig)>(
+    config: &mut LayerConfig,
+    context: &mut ConfigContext,
     progress: &P,
+    mut on_change: F,
 ) -> Result<(), CliError> {
     let Some(name) = config.profile.as_deref() else {
         return Ok(());
@@ -211,7 +278,14 @@ pub async fn apply_profile_if_configured<P: Progress>(
             )));
             let mut subtask =
                 progress.subtask(&format!("applying mirrord profile `{profile_identifier}`"));
-            apply_profile(config, &profile, &profile_identifier, &mut subtask)?;
+            apply_profile(
+                config,
+                context,
+                &profile,
+                &profile_identifier,
+                &mut subtask,
+                &mut on_change,
+            )?;
             subtask.success(Some(&format!(
                 "mirrord profile `{profile_identifier}` applied"
             )));
@@ -0,0 +1,949 @@
+//! Syscall interception for statically linked binaries, enabled with
+//! [`ExperimentalConfig::seccomp_interception`](mirrord_config::experimental::ExperimentalConfig::seccomp_interception).
+//!
+//! The layer is loaded with `LD_PRELOAD`, so it never makes it into statically linked binaries
+//! (musl, Rust, Zig), and it doesn't see raw `syscall()`s. In this mode, instead of `execve`ing the
+//! user binary, we fork, and the child installs a seccomp filter that returns
+//! `SECCOMP_RET_USER_NOTIF` for the syscalls we care about, before `execve`ing the binary. The
+//! parent stays around as the [`Supervisor`], and handles the notifications with the same intproxy
+//! requests the layer would make:
+//!
+//! - `open`/`openat` of remote files: the file is read through the intproxy into a memfd, which is
+//!   then installed in the target process (remote files can be opened only for reading);
+//! - `connect` of TCP sockets: the connection is made through the intproxy, and the syscall is
+//!   emulated: the [`Supervisor`] connects a duplicate of the target's socket to the intproxy
+//!   socket, and returns the result to the target process.
+//!
+//! All other syscalls (and the ones we decide not to handle) continue in the kernel as usual. The
+//! filter is inherited by child processes of the binary, so they're supervised as well.
+//!
+//! Requires Linux 5.14 (`SECCOMP_ADDFD_FLAG_SEND`).
+
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    fs::File,
+    io::{self, Read, Seek, Write},
+    net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs},
+    ops::Not,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::fs::FileExt,
+    },
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use mirrord_config::{
+    LayerConfig, MIRRORD_LAYER_INTPROXY_ADDR,
+    feature::{fs::FsModeConfig, network::outgoing::OutgoingConfig},
+};
+use mirrord_intproxy_protocol::{
+    NetProtocol, NewSessionRequest, OutgoingConnectRequest, ProcessInfo,
+};
+use mirrord_layer_lib::{
+    HookError,
+    error::get_platform_errno,
+    file::{
+        filter::{FileFilter, FileMode},
+        mapper::FileRemapper,
+    },
+    proxy_connection::{
+        PROXY_CONNECTION, make_proxy_request_no_response, make_proxy_request_with_response,
+    },
+    socket::{
+        ConnectionThrough, DnsResolver, OutgoingSelector, hostname::remote_dns_resolve_via_proxy,
+    },
+};
+use mirrord_protocol::file::{
+    CloseFileRequest, OpenFileRequest, OpenOptionsInternal, ReadFileRequest,
+};
+use nix::{
+    errno::Errno,
+    sys::{
+        memfd::{MemFdCreateFlag, memfd_create},
+        signal::{SigHandler, Signal, kill, signal},
+        wait::{WaitStatus, waitpid},
+    },
+    unistd::{ForkResult, Pid, fork, pipe},
+};
+use thiserror::Error;
+
+use crate::execution::INJECTION_ENV_VAR;
+
+/// `AUDIT_ARCH_*` value of the current architecture, from `linux/audit.h`.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xC000_003E;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+/// Syscalls handled by the [`Supervisor`].
+#[cfg(target_arch = "x86_64")]
+const NOTIFIED_SYSCALLS: &[libc::c_long] = &[libc::SYS_open, libc::SYS_openat, libc::SYS_connect];
+#[cfg(target_arch = "aarch64")]
+const NOTIFIED_SYSCALLS: &[libc::c_long] = &[libc::SYS_openat, libc::SYS_connect];
+
+/// Size of reads when copying a remote file to a memfd.
+const READ_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Upper bound for paths read from the target process memory.
+const PATH_MAX: usize = libc::PATH_MAX as usize;
+
+/// Sent to the child once the [`Supervisor`] is ready, the child exits on anything else.
+const SUPERVISOR_READY: u8 = 1;
+
+nix::ioctl_readwrite!(notif_recv, b'!', 0, libc::seccomp_notif);
+nix::ioctl_readwrite!(notif_send, b'!', 1, libc::seccomp_notif_resp);
+nix::ioctl_write_ptr!(notif_id_valid, b'!', 2, u64);
+nix::ioctl_write_ptr!(notif_addfd, b'!', 3, libc::seccomp_notif_addfd);
+
+/// Errors that prevent the [`Supervisor`] from starting.
+#[derive(Debug, Error)]
+pub(crate) enum SeccompError {
+    #[error("failed to start the supervised process: {0}")]
+    Spawn(#[source] Errno),
+
+    #[error("failed to install the seccomp filter in the supervised process: {0}")]
+    InstallFilter(Errno),
+
+    #[error("failed to take the seccomp listener from the supervised process: {0}")]
+    Listener(#[source] io::Error),
+
+    #[error("failed to connect to the internal proxy: {0}")]
+    ProxyConnection(String),
+}
+
+/// Outcome of a single notification, sent back to the kernel.
+#[derive(Debug)]
+enum Reply {
+    /// Let the syscall continue in the kernel.
+    Continue,
+    /// Complete the syscall with the given return value, without running it.
+    Return(i64),
+    /// Fail the syscall with the given errno.
+    Error(i32),
+    /// Complete the syscall by installing the given fd in the target process.
+    InstallFd { fd: OwnedFd, cloexec: bool },
+}
+
+/// What to do with an `open` of a path, decided with the [`FileFilter`].
+#[derive(Debug, PartialEq, Eq)]
+enum OpenAction {
+    Local,
+    NotFound,
+    Remote,
+}
+
+/// Resolves hostnames in outgoing filters, the same way the layer does.
+struct SupervisorDnsResolver;
+
+/// Set from [`LayerConfig`] when the [`Supervisor`] starts.
+static REMOTE_DNS: OnceLock<bool> = OnceLock::new();
+
+impl DnsResolver for SupervisorDnsResolver {
+    type Error = HookError;
+
+    fn resolve_hostname(
+        hostname: &str,
+        _port: u16,
+        _family: i32,
+        _protocol: i32,
+    ) -> Result<Vec<IpAddr>, Self::Error> {
+        if Self::remote_dns_enabled() {
+            Ok(remote_dns_resolve_via_proxy(hostname)?
+                .into_iter()
+                .map(|(_, ip)| ip)
+                .collect())
+        } else {
+            Ok((hostname, 0)
+                .to_socket_addrs()?
+                .map(|address| address.ip())
+                .collect())
+        }
+    }
+
+    fn remote_dns_enabled() -> bool {
+        REMOTE_DNS.get().copied().unwrap_or_default()
+    }
+}
+
+/// Runs the given binary under the [`Supervisor`], returning its exit code once it's done.
+///
+/// Blocks the calling thread for the whole lifetime of the binary, much like `execve` would.
+pub(crate) fn run_supervised(
+    binary_path: &Path,
+    binary_args: &[String],
+    env_vars: &HashMap<String, String>,
+    config: &LayerConfig,
+) -> Result<i32, SeccompError> {
+    let path = CString::new(binary_path.as_os_str().as_encoded_bytes())
+        .map_err(|_| SeccompError::Spawn(Errno::EINVAL))?;
+    let args = binary_args
+        .iter()
+        .map(|arg| CString::new(arg.as_str()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| SeccompError::Spawn(Errno::EINVAL))?;
+    // The layer would only intercept the same calls again, in the dynamically linked children.
+    let env = env_vars
+        .iter()
+        .filter(|(key, _)| key.as_str() != INJECTION_ENV_VAR)
+        .map(|(key, value)| CString::new(format!("{key}={value}")))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| SeccompError::Spawn(Errno::EINVAL))?;
+
+    let proxy_addr = env_vars
+        .get(MIRRORD_LAYER_INTPROXY_ADDR)
+        .and_then(|address| address.parse::<SocketAddr>().ok())
+        .ok_or_else(|| {
+            SeccompError::ProxyConnection(format!("missing {MIRRORD_LAYER_INTPROXY_ADDR}"))
+        })?;
+
+    // Everything the child needs is prepared before the fork, the child may only make raw
+    // syscalls until it calls `execve`.
+    let filter = bpf_filter(NOTIFIED_SYSCALLS);
+    let argv = null_terminated(&args);
+    let envp = null_terminated(&env);
+    let (listener_rx, listener_tx) = pipe().map_err(SeccompError::Spawn)?;
+    let (ack_rx, ack_tx) = pipe().map_err(SeccompError::Spawn)?;
+
+    // SAFETY: the child only makes async-signal-safe calls, see `install_and_exec`.
+    let child = match unsafe { fork() }.map_err(SeccompError::Spawn)? {
+        ForkResult::Child => unsafe {
+            install_and_exec(
+                &filter,
+                listener_tx.as_raw_fd(),
+                ack_rx.as_raw_fd(),
+                &path,
+                &argv,
+                &envp,
+            )
+        },
+        ForkResult::Parent { child } => child,
+    };
+    drop(listener_tx);
+    drop(ack_rx);
+
+    // Signals from the terminal reach the whole process group, the supervised process decides
+    // whether to exit.
+    for sig in [Signal::SIGINT, Signal::SIGQUIT] {
+        let _ = unsafe { signal(sig, SigHandler::SigIgn) };
+    }
+
+    let setup = (|| -> Result<Supervisor, SeccompError> {
+        let listener = take_listener(child, listener_rx)?;
+        let supervisor = Supervisor::new(listener, child, binary_args, proxy_addr, config)?;
+        // The child can `execve` now.
+        File::from(ack_tx)
+            .write_all(&[SUPERVISOR_READY])
+            .map_err(SeccompError::Listener)?;
+        Ok(supervisor)
+    })();
+    let supervisor = match setup {
+        Ok(supervisor) => supervisor,
+        Err(error) => {
+            // Without the supervisor, the notified syscalls would fail in the child.
+            let _ = kill(child, Signal::SIGKILL);
+            let _ = waitpid(child, None);
+            return Err(error);
+        }
+    };
+    supervisor.run();
+
+    Ok(wait_for_exit(child))
+}
+
+/// Runs in the forked child: installs the seccomp filter, hands the listener fd over to the
+/// parent and `execve`s the binary.
+///
+/// # Safety
+///
+/// Must be called right after `fork`, and only makes async-signal-safe calls.
+unsafe fn install_and_exec(
+    filter: &[libc::sock_filter],
+    listener_tx: libc::c_int,
+    ack_rx: libc::c_int,
+    path: &CStr,
+    argv: &[*const libc::c_char],
+    envp: &[*const libc::c_char],
+) -> ! {
+    unsafe {
+        let program = libc::sock_fprog {
+            len: filter.len() as libc::c_ushort,
+            filter: filter.as_ptr().cast_mut(),
+        };
+
+        let listener = if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0 {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                &program as *const libc::sock_fprog,
+            ) as libc::c_int
+        } else {
+            -1
+        };
+
+        // Negative errno tells the parent that we failed.
+        let message = if listener < 0 {
+            -*libc::__errno_location()
+        } else {
+            listener
+        };
+        libc::write(
+            listener_tx,
+            (&message as *const libc::c_int).cast(),
+            size_of::<libc::c_int>(),
+        );
+        if listener < 0 {
+            libc::_exit(127);
+        }
+
+        // Wait until the parent takes the listener and starts the supervisor. The listener is
+        // closed on `execve`. Anything other than the ack means that the parent failed, and the
+        // binary must not run with the filter, but without the supervisor.
+        let mut ack = 0u8;
+        let read = loop {
+            let read = libc::read(ack_rx, (&mut ack as *mut u8).cast(), 1);
+            if read >= 0 || *libc::__errno_location() != libc::EINTR {
+                break read;
+            }
+        };
+        if read != 1 || ack != SUPERVISOR_READY {
+            libc::_exit(127);
+        }
+        libc::close(listener);
+
+        libc::execve(path.as_ptr(), argv.as_ptr(), envp.as_ptr());
+        libc::_exit(127)
+    }
+}
+
+/// Receives the number of the listener fd from the child, and duplicates it from the child's fd
+/// table.
+fn take_listener(child: Pid, listener_rx: OwnedFd) -> Result<OwnedFd, SeccompError> {
+    let mut message = [0; size_of::<libc::c_int>()];
+    File::from(listener_rx)
+        .read_exact(&mut message)
+        .map_err(SeccompError::Listener)?;
+    let remote_fd = libc::c_int::from_ne_bytes(message);
+    if remote_fd < 0 {
+        return Err(SeccompError::InstallFilter(Errno::from_raw(-remote_fd)));
+    }
+
+    let pidfd = pidfd_open(child).map_err(SeccompError::Listener)?;
+    pidfd_getfd(&pidfd, remote_fd).map_err(SeccompError::Listener)
+}
+
+/// Waits for the supervised process, and converts its exit status to our exit code.
+fn wait_for_exit(child: Pid) -> i32 {
+    loop {
+        match waitpid(child, None) {
+            Ok(WaitStatus::Exited(_, code)) => break code,
+            Ok(WaitStatus::Signaled(_, signal, _)) => break 128 + signal as i32,
+            Ok(..) | Err(Errno::EINTR) => continue,
+            Err(error) => {
+                tracing::error!(%error, "Failed to wait for the supervised process");
+                break 1;
+            }
+        }
+    }
+}
+
+/// Builds the classic BPF program of the seccomp filter: notifies the [`Supervisor`] about the
+/// given syscalls, and allows everything else.
+///
+/// Syscalls made with a foreign architecture's calling convention are always allowed.
+fn bpf_filter(syscalls: &[libc::c_long]) -> Vec<libc::sock_filter> {
+    const fn statement(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    const fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    // Offsets in `seccomp_data`.
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+
+    let mut filter = vec![
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, ARCH_OFFSET),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            AUDIT_ARCH,
+            1,
+            0,
+        ),
+        statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+        statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, NR_OFFSET),
+    ];
+
+    // Each match jumps over the remaining checks and the final `ALLOW`.
+    for (i, syscall) in syscalls.iter().enumerate() {
+        filter.push(jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            *syscall as u32,
+            (syscalls.len() - i) as u8,
+            0,
+        ));
+    }
+
+    filter.push(statement(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ALLOW,
+    ));
+    filter.push(statement(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_USER_NOTIF,
+    ));
+
+    filter
+}
+
+fn null_terminated(strings: &[CString]) -> Vec<*const libc::c_char> {
+    strings
+        .iter()
+        .map(|string| string.as_ptr())
+        .chain(std::iter::once(std::ptr::null()))
+        .collect()
+}
+
+fn pidfd_open(pid: Pid) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+    if fd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        // SAFETY: the fd was just created.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) })
+    }
+}
+
+/// Duplicates `remote_fd` from the fd table of the process referred to by `pidfd`.
+fn pidfd_getfd(pidfd: &OwnedFd, remote_fd: libc::c_int) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), remote_fd, 0) };
+    if fd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        // SAFETY: the fd was just created.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) })
+    }
+}
+
+/// Handles seccomp notifications of the supervised process (and its children).
+///
+/// Notifications are handled one at a time, so a slow intproxy request blocks the other
+/// supervised threads only when they make one of the [`NOTIFIED_SYSCALLS`].
+struct Supervisor {
+    listener: OwnedFd,
+    file_filter: FileFilter,
+    file_remapper: FileRemapper,
+    outgoing: OutgoingConfig,
+    outgoing_selector: OutgoingSelector,
+    proxy_addr: SocketAddr,
+}
+
+impl Supervisor {
+    /// Starts a new intproxy session on behalf of the supervised process.
+    fn new(
+        listener: OwnedFd,
+        child: Pid,
+        binary_args: &[String],
+        proxy_addr: SocketAddr,
+        config: &LayerConfig,
+    ) -> Result<Self, SeccompError> {
+        let session = NewSessionRequest {
+            process_info: ProcessInfo {
+                pid: child.as_raw(),
+                parent_pid: std::process::id() as i32,
+                name: binary_args
+                    .first()
+                    .and_then(|binary| Path::new(binary).file_name())
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                cmdline: binary_args.to_vec(),
+                loaded: true,
+            },
+            parent_layer: None,
+        };
+        let connection = mirrord_layer_lib::ProxyConnection::new(
+            proxy_addr,
+            session,
+            Duration::from_secs(config.internal_proxy.socket_timeout),
+        )
+        .map_err(|error| SeccompError::ProxyConnection(error.to_string()))?;
+        let _ = PROXY_CONNECTION.set(connection);
+        let _ = REMOTE_DNS.set(config.feature.network.dns.enabled);
+
+        Ok(Self {
+            listener,
+            file_filter: FileFilter::new(config.feature.fs.clone()),
+            file_remapper: FileRemapper::new(config.feature.fs.mapping.clone().unwrap_or_default()),
+            outgoing: config.feature.network.outgoing.clone(),
+            outgoing_selector: OutgoingSelector::new(&config.feature.network.outgoing),
+            proxy_addr,
+        })
+    }
+
+    /// Handles notifications until no process uses the filter anymore.
+    fn run(&self) {
+        loop {
+            let mut poll_fd = libc::pollfd {
+                fd: self.listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut poll_fd, 1, -1) } < 0 {
+                match Errno::last() {
+                    Errno::EINTR => continue,
+                    error => {
+                        tracing::error!(%error, "Failed to poll the seccomp listener");
+                        break;
+                    }
+                }
+            }
+
+            if poll_fd.revents & libc::POLLIN != 0 {
+                self.handle_next();
+            } else if poll_fd.revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+                break;
+            }
+        }
+    }
+
+    fn handle_next(&self) {
+        // SAFETY: all fields are plain integers.
+        let mut notification: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+        match unsafe { notif_recv(self.listener.as_raw_fd(), &mut notification) } {
+            Ok(..) => {}
+            // The process was killed before we received the notification.
+            Err(Errno::ENOENT | Errno::EINTR) => return,
+            Err(error) => {
+                tracing::error!(%error, "Failed to receive a seccomp notification");
+                return;
+            }
+        }
+
+        let reply = self.handle(&notification).unwrap_or_else(|error| {
+            tracing::debug!(
+                %error,
+                nr = notification.data.nr,
+                "Failed to handle a seccomp notification, letting the syscall continue"
+            );
+            Reply::Continue
+        });
+
+        if let Err(error) = self.reply(notification.id, reply) {
+            // The process is gone, or the syscall was interrupted.
+            tracing::debug!(%error, "Failed to reply to a seccomp notification");
+        }
+    }
+
+    fn handle(&self, notification: &libc::seccomp_notif) -> io::Result<Reply> {
+        let data = &notification.data;
+        let args = data.args;
+        let pid = notification.pid;
+
+        match libc::c_long::from(data.nr) {
+            #[cfg(target_arch = "x86_64")]
+            libc::SYS_open => self.handle_open(notification.id, pid, args[0], args[1]),
+            libc::SYS_openat => self.handle_open(notification.id, pid, args[1], args[2]),
+            libc::SYS_connect => {
+                self.handle_connect(notification.id, pid, args[0], args[1], args[2])
+            }
+            _ => Ok(Reply::Continue),
+        }
+    }
+
+    fn handle_open(&self, id: u64, pid: u32, path_address: u64, flags: u64) -> io::Result<Reply> {
+        let memory = File::open(format!("/proc/{pid}/mem"))?;
+        let path = read_c_string(&memory, path_address)?;
+        // The memory could have been reused by another process.
+        self.check_valid(id)?;
+
+        let flags = flags as libc::c_int;
+        // Relative paths are local, like in the layer, whether they're relative to the working
+        // directory or to the directory fd of `openat`. Absolute paths ignore the directory fd.
+        if path.is_relative() {
+            return Ok(Reply::Continue);
+        }
+        let path = self.file_remapper.change_path(path);
+
+        match open_action(&self.file_filter, &path, is_write(flags)) {
+            OpenAction::Local => Ok(Reply::Continue),
+            OpenAction::NotFound => Ok(Reply::Error(libc::ENOENT)),
+            OpenAction::Remote => match read_remote_file(&path) {
+                Ok(fd) => Ok(Reply::InstallFd {
+                    fd,
+                    cloexec: flags & libc::O_CLOEXEC != 0,
+                }),
+                Err(error) => Ok(Reply::Error(get_platform_errno(error))),
+            },
+        }
+    }
+
+    fn handle_connect(
+        &self,
+        id: u64,
+        pid: u32,
+        sockfd: u64,
+        address: u64,
+        address_len: u64,
+    ) -> io::Result<Reply> {
+        let mut raw_address = vec![0; (address_len as usize).min(size_of::<libc::sockaddr_in6>())];
+        let memory = File::open(format!("/proc/{pid}/mem"))?;
+        memory.read_exact_at(&mut raw_address, address)?;
+        self.check_valid(id)?;
+
+        let Some(remote_address) = parse_socket_address(&raw_address) else {
+            return Ok(Reply::Continue);
+        };
+        if self.outgoing.tcp.not() {
+            return Ok(Reply::Continue);
+        }
+        // Shares the open file description with the target's socket.
+        let socket = pidfd_getfd(
+            &pidfd_open(Pid::from_raw(pid as i32))?,
+            sockfd as libc::c_int,
+        )?;
+        if is_stream_socket(&socket)?.not() {
+            return Ok(Reply::Continue);
+        }
+        if remote_address == self.proxy_addr
+            || (self.outgoing.ignore_localhost && remote_address.ip().is_loopback())
+        {
+            return Ok(Reply::Continue);
+        }
+
+        let through = self
+            .outgoing_selector
+            .get_connection_through_with_resolver::<SupervisorDnsResolver>(
+                remote_address,
+                NetProtocol::Stream,
+            )
+            .map_err(io::Error::other)?;
+        if matches!(through, ConnectionThrough::Local(..)) {
+            return Ok(Reply::Continue);
+        }
+
+        let response = match make_proxy_request_with_response(OutgoingConnectRequest {
+            remote_address: remote_address.into(),
+            protocol: NetProtocol::Stream,
+            inspect: None,
+        }) {
+            Ok(Ok(response)) => response,
+            Ok(Err(error)) => {
+                return Ok(Reply::Error(get_platform_errno(HookError::ResponseError(
+                    error,
+                ))));
+            }
+            Err(error) => {
+                return Ok(Reply::Error(get_platform_errno(HookError::ProxyError(
+                    error,
+                ))));
+            }
+        };
+
+        let layer_address = SocketAddr::try_from(response.layer_address)
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        let layer_address = encode_socket_address(layer_address, remote_address.is_ipv6());
+
+        // We connect the socket ourselves, instead of replacing the address in the target process
+        // memory, which the target could change again before the kernel reads it. A non-blocking
+        // socket gets `EINPROGRESS`, just like it would from the kernel.
+        let result = unsafe {
+            libc::connect(
+                socket.as_raw_fd(),
+                layer_address.as_ptr().cast(),
+                layer_address.len() as libc::socklen_t,
+            )
+        };
+        if result == 0 {
+            Ok(Reply::Return(0))
+        } else {
+            Ok(Reply::Error(
+                io::Error::last_os_error()
+                    .raw_os_error()
+                    .unwrap_or(libc::ECONNREFUSED),
+            ))
+        }
+    }
+
+    fn check_valid(&self, id: u64) -> io::Result<()> {
+        unsafe { notif_id_valid(self.listener.as_raw_fd(), &id) }?;
+        Ok(())
+    }
+
+    fn reply(&self, id: u64, reply: Reply) -> nix::Result<()> {
+        if let Reply::InstallFd { fd, cloexec } = reply {
+            let addfd = libc::seccomp_notif_addfd {
+                id,
+                flags: libc::SECCOMP_ADDFD_FLAG_SEND as _,
+                srcfd: fd.as_raw_fd() as u32,
+                newfd: 0,
+                newfd_flags: if cloexec { libc::O_CLOEXEC as u32 } else { 0 },
+            };
+            // Installs the fd, and completes the syscall with its number.
+            unsafe { notif_addfd(self.listener.as_raw_fd(), &addfd) }?;
+            return Ok(());
+        }
+
+        let mut response = libc::seccomp_notif_resp {
+            id,
+            val: 0,
+            error: 0,
+            flags: 0,
+        };
+        match reply {
+            Reply::Continue => response.flags = libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as _,
+            Reply::Return(value) => response.val = value,
+            Reply::Error(errno) => response.error = -errno,
+            Reply::InstallFd { .. } => unreachable!("handled above"),
+        }
+
+        unsafe { notif_send(self.listener.as_raw_fd(), &mut response) }?;
+        Ok(())
+    }
+}
+
+/// Decides what to do with an `open` of the given path.
+///
+/// Remote files are copied to a memfd, so they can be opened only for reading.
+fn open_action(filter: &FileFilter, path: &Path, write: bool) -> OpenAction {
+    match filter.check(path.to_str().unwrap_or_default()) {
+        Some(FileMode::NotFound(..)) => OpenAction::NotFound,
+        Some(FileMode::Local(..)) => OpenAction::Local,
+        _ if write => OpenAction::Local,
+        Some(FileMode::ReadOnly(..) | FileMode::ReadWrite(..)) => OpenAction::Remote,
+        None => match filter.mode {
+            FsModeConfig::Read | FsModeConfig::Write | FsModeConfig::Overlay => OpenAction::Remote,
+            FsModeConfig::Local | FsModeConfig::LocalWithOverrides => OpenAction::Local,
+        },
+    }
+}
+
+fn is_write(flags: libc::c_int) -> bool {
+    flags & libc::O_ACCMODE != libc::O_RDONLY
+        || flags & (libc::O_CREAT | libc::O_TRUNC | libc::O_APPEND | libc::O_PATH) != 0
+}
+
+/// Reads the whole remote file through the intproxy into a new memfd.
+fn read_remote_file(path: &Path) -> Result<OwnedFd, HookError> {
+    let remote_fd = make_proxy_request_with_response(OpenFileRequest {
+        path: path.to_path_buf(),
+        open_options: OpenOptionsInternal {
+            read: true,
+            ..Default::default()
+        },
+    })??
+    .fd;
+
+    let result = (|| {
+        let memfd = memfd_create(c"mirrord-remote", MemFdCreateFlag::MFD_CLOEXEC)
+            .map_err(io::Error::from)?;
+        let mut file = File::from(memfd);
+        loop {
+            let response = make_proxy_request_with_response(ReadFileRequest {
+                remote_fd,
+                buffer_size: READ_CHUNK_SIZE,
+            })??;
+            if response.read_amount == 0 {
+                break;
+            }
+            file.write_all(&response.bytes)?;
+        }
+        file.rewind()?;
+
+        Ok(OwnedFd::from(file))
+    })();
+
+    let _ = make_proxy_request_no_response(CloseFileRequest { fd: remote_fd });
+    result
+}
+
+/// Reads a NUL-terminated path from the target process memory.
+fn read_c_string(memory: &File, address: u64) -> io::Result<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut path = Vec::new();
+    let mut chunk = [0u8; 256];
+    while path.len() < PATH_MAX {
+        let offset = address + path.len() as u64;
+        // Don't cross a page boundary, the next page might not be mapped.
+        let to_page_end = 4096 - (offset % 4096) as usize;
+        let read = memory.read_at(&mut chunk[..to_page_end.min(chunk.len())], offset)?;
+        if read == 0 {
+            break;
+        }
+
+        match chunk[..read].iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                path.extend_from_slice(&chunk[..end]);
+                return Ok(PathBuf::from(std::ffi::OsString::from_vec(path)));
+            }
+            None => path.extend_from_slice(&chunk[..read]),
+        }
+    }
+
+    Err(io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Parses an IPv4 or IPv6 `sockaddr`.
+fn parse_socket_address(raw: &[u8]) -> Option<SocketAddr> {
+    let family = libc::sa_family_t::from_ne_bytes(raw.get(..2)?.try_into().ok()?);
+    let port = u16::from_be_bytes(raw.get(2..4)?.try_into().ok()?);
+
+    match libc::c_int::from(family) {
+        libc::AF_INET => {
+            let ip: [u8; 4] = raw.get(4..8)?.try_into().ok()?;
+            Some(SocketAddrV4::new(ip.into(), port).into())
+        }
+        libc::AF_INET6 => {
+            let ip: [u8; 16] = raw.get(8..24)?.try_into().ok()?;
+            Some(SocketAddrV6::new(ip.into(), port, 0, 0).into())
+        }
+        _ => None,
+    }
+}
+
+/// Encodes the address as a `sockaddr` of the socket's family, mapping IPv4 to IPv6 if needed.
+fn encode_socket_address(address: SocketAddr, ipv6: bool) -> Vec<u8> {
+    let port = address.port().to_be_bytes();
+
+    if ipv6 {
+        let ip = match address.ip() {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        };
+        let mut raw = vec![0; size_of::<libc::sockaddr_in6>()];
+        raw[..2].copy_from_slice(&(libc::AF_INET6 as libc::sa_family_t).to_ne_bytes());
+        raw[2..4].copy_from_slice(&port);
+        raw[8..24].copy_from_slice(&ip.octets());
+        raw
+    } else {
+        let IpAddr::V4(ip) = address.ip() else {
+            unreachable!("the intproxy listens on IPv4 addresses");
+        };
+        let mut raw = vec![0; size_of::<libc::sockaddr_in>()];
+        raw[..2].copy_from_slice(&(libc::AF_INET as libc::sa_family_t).to_ne_bytes());
+        raw[2..4].copy_from_slice(&port);
+        raw[4..8].copy_from_slice(&ip.octets());
+        raw
+    }
+}
+
+/// Checks the type of a socket duplicated from the target process.
+fn is_stream_socket(socket: &OwnedFd) -> io::Result<bool> {
+    let mut socket_type: libc::c_int = 0;
+    let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            (&mut socket_type as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(socket_type == libc::SOCK_STREAM)
+}
+
+#[cfg(test)]
+mod test {
+    use mirrord_config::feature::fs::FsConfig;
+
+    use super::*;
+
+    /// Runs the filter against a syscall, supporting only the instructions [`bpf_filter`] uses.
+    fn evaluate(filter: &[libc::sock_filter], arch: u32, nr: u32) -> u32 {
+        let mut pc = 0;
+        let mut accumulator = 0;
+        loop {
+            let instruction = filter[pc];
+            let code = u32::from(instruction.code);
+            pc += 1;
+
+            if code == libc::BPF_LD | libc::BPF_W | libc::BPF_ABS {
+                accumulator = if instruction.k == 0 { nr } else { arch };
+            } else if code == libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K {
+                pc += usize::from(if accumulator == instruction.k {
+                    instruction.jt
+                } else {
+                    instruction.jf
+                });
+            } else if code == libc::BPF_RET | libc::BPF_K {
+                return instruction.k;
+            } else {
+                panic!("unexpected instruction {code:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn filter_notifies_only_handled_syscalls() {
+        let filter = bpf_filter(NOTIFIED_SYSCALLS);
+
+        for syscall in NOTIFIED_SYSCALLS {
+            assert_eq!(
+                evaluate(&filter, AUDIT_ARCH, *syscall as u32),
+                libc::SECCOMP_RET_USER_NOTIF
+            );
+        }
+        assert_eq!(
+            evaluate(&filter, AUDIT_ARCH, libc::SYS_read as u32),
+            libc::SECCOMP_RET_ALLOW
+        );
+        assert_eq!(
+            evaluate(&filter, 0, libc::SYS_connect as u32),
+            libc::SECCOMP_RET_ALLOW
+        );
+    }
+
+    #[test]
+    fn open_follows_file_filter() {
+        let filter = FileFilter::new(FsConfig {
+            mode: FsModeConfig::Read,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            open_action(&filter, Path::new("/app/config.yaml"), false),
+            OpenAction::Remote
+        );
+        assert_eq!(
+            open_action(&filter, Path::new("/app/config.yaml"), true),
+            OpenAction::Local
+        );
+        assert!(is_write(libc::O_WRONLY));
+        assert!(is_write(libc::O_RDONLY | libc::O_CREAT));
+        assert!(is_write(libc::O_RDONLY | libc::O_CLOEXEC).not());
+    }
+
+    #[test]
+    fn socket_address_roundtrip() {
+        let address: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+
+        let raw = encode_socket_address(address, false);
+        assert_eq!(parse_socket_address(&raw), Some(address));
+
+        let raw = encode_socket_address(address, true);
+        assert_eq!(
+            parse_socket_address(&raw),
+            Some("[::ffff:127.0.0.1]:8080".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,591 @@
+//! `mirrord session`, local sessions that outlive the processes that use them.
+//!
+//! `mirrord session start <name>` connects to the cluster like `mirrord exec` does, but instead of
+//! running a process, it leaves the internal proxy running in the background (without an idle
+//! timeout), and saves what's needed to use it in a [`SessionRecord`]. Processes started later
+//! with `mirrord exec --session <name>` get the saved environment, and their layers connect to the
+//! same internal proxy. This way, short-lived scripts and tests don't deploy an agent each.
+//!
+//! The internal proxy is controlled with [`SessionControlRequest`]s, sent over a layer session of
+//! its own, see [`ProxyControl`].
+
+use std::{
+    collections::HashMap,
+    env::vars,
+    fs, io,
+    io::Write,
+    net::{SocketAddr, TcpStream},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    sync::LazyLock,
+    time::{Duration, SystemTime},
+};
+
+use drain::Watch;
+use mirrord_analytics::{AnalyticsReporter, CollectAnalytics};
+use mirrord_config::{LayerConfig, MIRRORD_LAYER_INTPROXY_ADDR, config::ConfigContext};
+use mirrord_intproxy_protocol::{
+    LayerToProxyMessage, LocalMessage, NewSessionRequest, ProcessInfo, ProxyToLayerMessage,
+    SessionControlRequest, SessionControlResponse,
+    codec::{self, CodecError, SyncDecoder, SyncEncoder},
+};
+use mirrord_progress::{Progress, ProgressTracker};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    CliResult, SessionArgs, SessionCommand, SessionStartArgs, execution::MirrordExecution,
+    user_data::UserData,
+};
+
+/// "~/.mirrord/sessions"
+static SESSIONS_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    home::home_dir()
+        .unwrap_or_else(|| PathBuf::from("~"))
+        .join(".mirrord")
+        .join("sessions")
+});
+
+/// Used as both
+/// [`InternalProxyConfig::start_idle_timeout`](mirrord_config::internal_proxy::InternalProxyConfig::start_idle_timeout)
+/// and [`InternalProxyConfig::idle_timeout`](mirrord_config::internal_proxy::InternalProxyConfig::idle_timeout)
+/// of session internal proxies (a year), they exit on `mirrord session stop`.
+const SESSION_IDLE_TIMEOUT: u64 = 365 * 24 * 60 * 60;
+
+/// Errors of the `mirrord session` commands and `mirrord exec --session`.
+#[derive(Debug, Error)]
+pub(crate) enum SessionError {
+    #[error("invalid session name `{0}`, only ASCII letters, digits, `-`, `_` and `.` are allowed")]
+    InvalidName(String),
+
+    #[error("session `{0}` is already running")]
+    AlreadyRunning(String),
+
+    #[error("session `{0}` does not exist, start it with `mirrord session start {0}`")]
+    NotFound(String),
+
+    #[error(
+        "session `{0}` is not running anymore, start it again with `mirrord session start {0}`"
+    )]
+    NotRunning(String),
+
+    #[error("failed to access the session file {}: {1}", .0.display())]
+    Store(PathBuf, #[source] io::Error),
+
+    #[error("invalid session file {}: {1}", .0.display())]
+    Record(PathBuf, #[source] serde_json::Error),
+
+    #[error(
+        "session `{0}` has no resolved config, start it again with `mirrord session start {0}`"
+    )]
+    MissingConfig(String),
+
+    #[error("session internal proxy did not report its address")]
+    MissingProxyAddress,
+
+    #[error("failed to communicate with the internal proxy: {0}")]
+    ProxyConnection(String),
+}
+
+impl From<CodecError> for SessionError {
+    fn from(error: CodecError) -> Self {
+        Self::ProxyConnection(error.to_string())
+    }
+}
+
+/// A started session, saved at `~/.mirrord/sessions/<name>.json`.
+///
+/// Contains the remote environment, so the file is only readable by the user.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct SessionRecord {
+    name: String,
+
+    /// Address of the internal proxy, that the layers connect to.
+    proxy_address: SocketAddr,
+
+    /// [`MirrordExecution::environment`] of the session.
+    environment: HashMap<String, String>,
+
+    /// [`MirrordExecution::env_to_unset`] of the session.
+    env_to_unset: Vec<String>,
+
+    /// [`TargetConfig::path`](mirrord_config::target::TargetConfig::path) of the session, for
+    /// display.
+    target: Option<String>,
+
+    /// Seconds since the UNIX epoch.
+    started_at: u64,
+
+    uses_operator: bool,
+}
+
+impl SessionRecord {
+    fn path(name: &str) -> PathBuf {
+        SESSIONS_DIR.join(format!("{name}.json"))
+    }
+
+    fn load(name: &str) -> Result<Self, SessionError> {
+        let path = Self::path(name);
+        let bytes = fs::read(&path).map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => SessionError::NotFound(name.to_string()),
+            _ => SessionError::Store(path.clone(), error),
+        })?;
+
+        serde_json::from_slice(&bytes).map_err(|error| SessionError::Record(path, error))
+    }
+
+    /// Loads all saved sessions, sorted by name.
+    fn load_all() -> Result<Vec<Self>, SessionError> {
+        let entries = match fs::read_dir(&*SESSIONS_DIR) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(SessionError::Store(SESSIONS_DIR.clone(), error)),
+        };
+
+        let mut records = entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                let name = name.strip_suffix(".json")?;
+                Some(Self::load(name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        records.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(records)
+    }
+
+    fn save(&self) -> Result<(), SessionError> {
+        let path = Self::path(&self.name);
+        let bytes = serde_json::to_vec_pretty(self)
+            .map_err(|error| SessionError::Record(path.clone(), error))?;
+
+        fs::create_dir_all(&*SESSIONS_DIR)
+            .and_then(|()| {
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .open(&path)
+            })
+            .and_then(|mut file| file.write_all(&bytes))
+            .map_err(|error| SessionError::Store(path, error))
+    }
+
+    fn remove(&self) -> Result<(), SessionError> {
+        let path = Self::path(&self.name);
+        match fs::remove_file(&path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                Err(SessionError::Store(path, error))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Connects to the session internal proxy, fails if it's not running.
+    fn connect(&self) -> Result<ProxyControl, SessionError> {
+        ProxyControl::connect(self.proxy_address)
+            .map_err(|_| SessionError::NotRunning(self.name.clone()))
+    }
+}
+
+/// Session names are used as file names, so they are restricted.
+fn validate_name(name: &str) -> Result<(), SessionError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(SessionError::InvalidName(name.to_string()))
+    }
+}
+
+/// A layer session with an internal proxy, opened by the CLI and not by a user process.
+///
+/// Keeps the internal proxy from exiting on idle timeout while it's open, and can send
+/// [`SessionControlRequest`]s.
+pub(crate) struct ProxyControl {
+    encoder: SyncEncoder<LocalMessage<LayerToProxyMessage>, TcpStream>,
+    decoder: SyncDecoder<LocalMessage<ProxyToLayerMessage>, TcpStream>,
+    next_message_id: u64,
+}
+
+impl ProxyControl {
+    pub(crate) fn connect(proxy_addr: SocketAddr) -> Result<Self, SessionError> {
+        let stream = TcpStream::connect(proxy_addr)
+            .map_err(|error| SessionError::ProxyConnection(error.to_string()))?;
+        let (encoder, decoder) = codec::make_sync_framed::<
+            LocalMessage<LayerToProxyMessage>,
+            LocalMessage<ProxyToLayerMessage>,
+        >(stream)?;
+
+        let mut control = Self {
+            encoder,
+            decoder,
+            next_message_id: 0,
+        };
+
+        match control.request(LayerToProxyMessage::NewSession(NewSessionRequest {
+            parent_layer: None,
+            process_info: ProcessInfo {
+                pid: std::process::id() as i32,
+                parent_pid: nix::unistd::getppid().as_raw(),
+                name: "mirrord".to_string(),
+                cmdline: std::env::args().collect(),
+                loaded: false,
+            },
+        }))? {
+            ProxyToLayerMessage::NewSession(..) => Ok(control),
+            other => Err(SessionError::ProxyConnection(format!(
+                "unexpected response to the new session request: {other:?}"
+            ))),
+        }
+    }
+
+    /// Sends a [`SessionControlRequest`] and waits for the response.
+    fn control(
+        &mut self,
+        request: SessionControlRequest,
+    ) -> Result<SessionControlResponse, SessionError> {
+        match self.request(LayerToProxyMessage::SessionControl(request))? {
+            ProxyToLayerMessage::SessionControl(response) => Ok(response),
+            other => Err(SessionError::ProxyConnection(format!(
+                "unexpected response to the {request:?} request: {other:?}"
+            ))),
+        }
+    }
+
+    fn request(
+        &mut self,
+        message: LayerToProxyMessage,
+    ) -> Result<ProxyToLayerMessage, SessionError> {
+        let message_id = self.next_message_id;
+        self.next_message_id += 1;
+
+        self.encoder.send(&LocalMessage {
+            message_id,
+            inner: message,
+        })?;
+        self.encoder.flush()?;
+
+        loop {
+            match self.decoder.receive()? {
+                Some(LocalMessage {
+                    inner: ProxyToLayerMessage::ProxyFailed(error),
+                    ..
+                }) => break Err(SessionError::ProxyConnection(error)),
+                Some(response) if response.message_id == message_id => break Ok(response.inner),
+                // Not a response to our request, skip it.
+                Some(..) => continue,
+                None => {
+                    break Err(SessionError::ProxyConnection(
+                        "connection closed by the internal proxy".to_string(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Handles `mirrord session ...` commands.
+pub(crate) async fn session_command(
+    args: SessionArgs,
+    watch: Watch,
+    user_data: &mut UserData,
+) -> CliResult<()> {
+    match args.command {
+        SessionCommand::Start(args) => start(*args, watch, user_data).await,
+        SessionCommand::Attach {
+            name,
+            binary,
+            binary_args,
+        } => {
+            let mut progress = ProgressTracker::from_env("mirrord session attach");
+            exec_in_session(&name, binary, binary_args, watch, user_data, &mut progress).await
+        }
+        SessionCommand::Stop { name } => stop(&name),
+        SessionCommand::List => list(),
+    }
+}
+
+async fn start(args: SessionStartArgs, watch: Watch, user_data: &UserData) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord session start");
+
+    validate_name(&args.name)?;
+    match SessionRecord::load(&args.name) {
+        Ok(record) if record.connect().is_ok() => {
+            return Err(SessionError::AlreadyRunning(args.name).into());
+        }
+        Ok(..) | Err(SessionError::NotFound(..)) => {}
+        Err(error) => return Err(error.into()),
+    }
+
+    let mut cfg_context = ConfigContext::default().override_envs(args.params.as_env_vars());
+    let mut config = LayerConfig::resolve(&mut cfg_context)?;
+    crate::profile::apply_profile_if_configured(&mut config, &mut cfg_context, &progress).await?;
+
+    config.internal_proxy.start_idle_timeout = SESSION_IDLE_TIMEOUT;
+    config.internal_proxy.idle_timeout = SESSION_IDLE_TIMEOUT;
+
+    let mut analytics = AnalyticsReporter::only_error(
+        config.telemetry,
+        Default::default(),
+        watch,
+        user_data.machine_id(),
+    );
+    (&config).collect_analytics(analytics.get_mut());
+
+    let result = config.verify(&mut cfg_context);
+    for warning in cfg_context.into_warnings() {
+        progress.warning(&warning);
+    }
+    result?;
+
+    let mut sub_progress = progress.subtask("starting session");
+    let execution = MirrordExecution::start_internal(
+        &mut config,
+        #[cfg(target_os = "macos")]
+        None,
+        #[cfg(target_os = "macos")]
+        None,
+        &mut sub_progress,
+        &mut analytics,
+        None,
+    )
+    .await?;
+
+    // See the comment in `exec_process`.
+    tokio::time::sleep(Duration::from_micros(1)).await;
+
+    let proxy_address = execution
+        .environment
+        .get(MIRRORD_LAYER_INTPROXY_ADDR)
+        .and_then(|address| address.parse().ok())
+        .ok_or(SessionError::MissingProxyAddress)?;
+
+    let record = SessionRecord {
+        name: args.name,
+        proxy_address,
+        environment: execution.environment,
+        env_to_unset: execution.env_to_unset,
+        target: config.target.path.as_ref().map(ToString::to_string),
+        started_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        uses_operator: execution.uses_operator,
+    };
+    record.save()?;
+
+    sub_progress.success(Some("session started"));
+    progress.success(Some(&format!(
+        "Session `{0}` is running, use it with `mirrord exec --session {0} -- <BINARY>`",
+        record.name
+    )));
+
+    Ok(())
+}
+
+/// Runs the binary in a running session, with the session's environment, see
+/// [`ExecArgs::session`](crate::ExecArgs::session).
+pub(crate) async fn exec_in_session(
+    name: &str,
+    binary: String,
+    binary_args: Vec<String>,
+    watch: Watch,
+    user_data: &UserData,
+    progress: &mut ProgressTracker,
+) -> CliResult<()> {
+    let mut sub_progress = progress.subtask("attaching to session");
+
+    validate_name(name)?;
+    let record = SessionRecord::load(name)?;
+    record.connect()?;
+
+    let config = record
+        .environment
+        .get(LayerConfig::RESOLVED_CONFIG_ENV)
+        .map(|encoded| LayerConfig::decode(encoded))
+        .transpose()?
+        .ok_or_else(|| SessionError::MissingConfig(name.to_string()))?;
+
+    let mut analytics = AnalyticsReporter::only_error(
+        config.telemetry,
+        Default::default(),
+        watch,
+        user_data.machine_id(),
+    );
+
+    let mut env_vars: HashMap<String, String> = vars().collect();
+    env_vars.extend(record.environment.clone());
+    env_vars.insert(mirrord_progress::MIRRORD_PROGRESS_ENV.into(), "off".into());
+    for key in &record.env_to_unset {
+        env_vars.remove(key);
+    }
+
+    #[cfg(target_os = "macos")]
+    let (did_sip_patch, binary_to_run) = {
+        let args = binary_args
+            .iter()
+            .map(std::ffi::OsString::from)
+            .collect::<Vec<_>>();
+        match MirrordExecution::sip_patch_executable(&config, &binary, Some(&args))? {
+            Some(patched) => (true, patched),
+            None => (false, binary.clone()),
+        }
+    };
+
+    #[cfg(not(target_os = "macos"))]
+    let (did_sip_patch, binary_to_run) = (false, binary.clone());
+
+    // Put original executable in argv[0] even if actually running patched version.
+    let binary_args = std::iter::once(binary)
+        .chain(binary_args)
+        .collect::<Vec<_>>();
+
+    sub_progress.success(Some(&format!("attached to session `{name}`")));
+    crate::print_config(
+        &*progress,
+        Some(&binary_args),
+        &config,
+        None,
+        record.uses_operator,
+    );
+
+    let sub_progress = progress.subtask("running process");
+
+    crate::run_process_with_mirrord(
+        binary_to_run,
+        binary_args,
+        env_vars,
+        did_sip_patch,
+        sub_progress,
+        &mut analytics,
+        &config,
+        None,
+    )
+    .await
+}
+
+fn stop(name: &str) -> CliResult<()> {
+    let mut progress = ProgressTracker::from_env("mirrord session stop");
+
+    validate_name(name)?;
+    let record = SessionRecord::load(name)?;
+
+    match record.connect() {
+        Ok(mut control) => {
+            control.control(SessionControlRequest::Stop)?;
+            progress.success(Some(&format!("session `{name}` stopped")));
+        }
+        Err(..) => {
+            progress.warning(&format!(
+                "session `{name}` was not running anymore, removing it"
+            ));
+            progress.success(None);
+        }
+    }
+
+    record.remove()?;
+
+    Ok(())
+}
+
+fn list() -> CliResult<()> {
+    let records = SessionRecord::load_all()?;
+    if records.is_empty() {
+        println!("No sessions, start one with `mirrord session start <NAME>`");
+        return Ok(());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    for record in records {
+        let target = record.target.as_deref().unwrap_or("targetless");
+        let uptime =
+            humantime::format_duration(Duration::from_secs(now.saturating_sub(record.started_at)));
+
+        let layers = match record
+            .connect()
+            .and_then(|mut control| control.control(SessionControlRequest::ListLayers))
+        {
+            Ok(SessionControlResponse::Layers(layers)) => layers,
+            Ok(other) => {
+                return Err(SessionError::ProxyConnection(format!(
+                    "unexpected response to the list request: {other:?}"
+                ))
+                .into());
+            }
+            Err(..) => {
+                println!("{} ({target}): not running", record.name);
+                continue;
+            }
+        };
+
+        // Sessions of the CLI itself (like ours) don't load the layer.
+        let mut layers = layers
+            .into_iter()
+            .filter(|layer| layer.process_info.loaded)
+            .collect::<Vec<_>>();
+        layers.sort_by_key(|layer| layer.id.0);
+
+        println!(
+            "{} ({target}): running for {uptime}, {} attached process(es)",
+            record.name,
+            layers.len()
+        );
+        for layer in layers {
+            println!(
+                "  [{}] pid {}: {}",
+                layer.id.0,
+                layer.process_info.pid,
+                layer.process_info.cmdline.join(" ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("tests", true)]
+    #[case("my-session_2.0", true)]
+    #[case("", false)]
+    #[case(".hidden", false)]
+    #[case("../escape", false)]
+    #[case("with space", false)]
+    fn session_name(#[case] name: &str, #[case] valid: bool) {
+        assert_eq!(validate_name(name).is_ok(), valid);
+    }
+
+    #[test]
+    fn record_roundtrip() {
+        let record = SessionRecord {
+            name: "tests".to_string(),
+            proxy_address: "127.0.0.1:40000".parse().unwrap(),
+            environment: HashMap::from([("KEY".to_string(), "value".to_string())]),
+            env_to_unset: vec!["UNSET".to_string()],
+            target: Some("deployment/checkout".to_string()),
+            started_at: 1_700_000_000,
+            uses_operator: false,
+        };
+
+        let encoded = serde_json::to_vec(&record).unwrap();
+        let decoded: SessionRecord = serde_json::from_slice(&encoded).unwrap();
+        assert_eq!(decoded, record);
+    }
+}
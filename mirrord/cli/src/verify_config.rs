@@ -1,15 +1,16 @@
-//! `mirrord verify-config [--ide] {path}` builds a
+//! `mirrord verify-config [--ide] [--show-merged] {path}` builds a
 //! [`VerifyConfig`](crate::Commands::VerifyConfig) enum after checking the config file passed in
 //! `path`. It's used by the IDE plugins to display errors/warnings quickly, without having to start
 //! mirrord-layer.
 use error::CliResult;
-use futures::TryFutureExt;
 use mirrord_config::{
-    LayerConfig,
-    config::ConfigContext,
+    LayerConfig, LayerFileConfig,
+    config::{ConfigContext, ConfigError},
+    extends::MergedConfigFile,
     target::{
-        Target, TargetConfig, TargetType, cron_job::CronJobTarget, deployment::DeploymentTarget,
-        job::JobTarget, pod::PodTarget, replica_set::ReplicaSetTarget, rollout::RolloutTarget,
+        Target, TargetConfig, TargetType, cron_job::CronJobTarget, daemon_set::DaemonSetTarget,
+        deployment::DeploymentTarget, job::JobTarget, pod::PodTarget,
+        replica_set::ReplicaSetTarget, rollout::RolloutTarget, selector::TargetSelector,
         service::ServiceTarget, stateful_set::StatefulSetTarget,
     },
 };
@@ -52,6 +53,9 @@ enum VerifiedTarget {
 
     #[serde(untagged)]
     ReplicaSet(ReplicaSetTarget),
+
+    #[serde(untagged)]
+    DaemonSet(DaemonSetTarget),
 }
 
 impl From<Target> for VerifiedTarget {
@@ -65,6 +69,7 @@ impl From<Target> for VerifiedTarget {
             Target::StatefulSet(target) => Self::StatefulSet(target),
             Target::Service(target) => Self::Service(target),
             Target::ReplicaSet(target) => Self::ReplicaSet(target),
+            Target::DaemonSet(target) => Self::DaemonSet(target),
             Target::Targetless => Self::Targetless,
         }
     }
@@ -82,6 +87,7 @@ impl From<VerifiedTarget> for TargetType {
             VerifiedTarget::StatefulSet(_) => TargetType::StatefulSet,
             VerifiedTarget::Service(_) => TargetType::Service,
             VerifiedTarget::ReplicaSet(_) => TargetType::ReplicaSet,
+            VerifiedTarget::DaemonSet(_) => TargetType::DaemonSet,
         }
     }
 }
@@ -90,6 +96,9 @@ impl From<VerifiedTarget> for TargetType {
 struct VerifiedTargetConfig {
     path: Option<VerifiedTarget>,
     namespace: Option<String>,
+    /// Lets the IDEs skip the target selection dialog when the target is picked by labels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selector: Option<TargetSelector>,
 }
 
 impl From<TargetConfig> for VerifiedTargetConfig {
@@ -97,6 +106,7 @@ impl From<TargetConfig> for VerifiedTargetConfig {
         Self {
             path: value.path.map(Into::into),
             namespace: value.namespace,
+            selector: value.selector,
         }
     }
 }
@@ -118,6 +128,9 @@ enum VerifiedConfig {
         /// Target types compatible with the source config.
         /// Meant to be used by IDE plugins for customizing target selection.
         compatible_target_types: Vec<TargetType>,
+        /// The config merged with the files it extends, only with `--show-merged`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        merged: Option<MergedConfigFile>,
     },
     /// Invalid config was detected, mirrord cannot run.
     ///
@@ -130,7 +143,7 @@ enum VerifiedConfig {
 /// ## Usage
 ///
 /// ```sh
-/// mirrord verify-config [path]
+/// mirrord verify-config [--show-merged] [path]
 /// ```
 ///
 /// - Example:
@@ -162,31 +175,43 @@ enum VerifiedConfig {
 /// }
 /// ```
 pub(super) async fn verify_config(
-    VerifyConfigArgs { ide, path }: VerifyConfigArgs,
+    VerifyConfigArgs {
+        ide,
+        show_merged,
+        path,
+    }: VerifyConfigArgs,
 ) -> CliResult<()> {
     let mut config_context = ConfigContext::default()
         .empty_target_final(ide)
-        .override_env(LayerConfig::FILE_PATH_ENV, path);
-
-    let layer_config =
-        std::future::ready(LayerConfig::resolve(&mut config_context).map_err(CliError::from))
-            .and_then(|mut config| async {
-                crate::profile::apply_profile_if_configured(&mut config, &NullProgress).await?;
-                Ok(config)
-            })
-            .and_then(|config| async {
-                config.verify(&mut config_context)?;
-                Ok(config)
-            })
-            .await;
-
-    let verified = match layer_config {
-        Ok(config) => VerifiedConfig::Success {
+        .override_env(LayerConfig::FILE_PATH_ENV, &path);
+
+    // Resolves the template key in the context, so that it's reused by `LayerConfig::resolve`.
+    let merged = show_merged
+        .then(|| LayerFileConfig::merged_from_path(&path, &mut config_context))
+        .transpose()
+        .map_err(|error| CliError::from(ConfigError::from(error)));
+
+    let layer_config = async {
+        let mut config = LayerConfig::resolve(&mut config_context)?;
+        crate::profile::apply_profile_if_configured(
+            &mut config,
+            &mut config_context,
+            &NullProgress,
+        )
+        .await?;
+        config.verify(&mut config_context)?;
+        Ok::<_, CliError>(config)
+    }
+    .await;
+
+    let verified = match layer_config.and_then(|config| Ok((config, merged?))) {
+        Ok((config, merged)) => VerifiedConfig::Success {
             config: config.target.into(),
             warnings: config_context.into_warnings(),
             compatible_target_types: TargetType::all()
                 .filter(|tt| tt.compatible_with(&config.feature))
                 .collect(),
+            merged,
         },
         Err(fail) => VerifiedConfig::Fail {
             errors: vec![fail.to_string()],
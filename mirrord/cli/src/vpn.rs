@@ -1,7 +1,7 @@
 use k8s_openapi::api::core::v1::ConfigMap;
 use kube::client::ClientBuilder;
 use mirrord_analytics::{AnalyticsError, NullReporter, Reporter};
-use mirrord_config::{LayerConfig, config::ConfigContext};
+use mirrord_config::{LayerConfig, config::ConfigContext, feature::network::dns::DnsOverrides};
 use mirrord_kube::{api::kubernetes::create_kube_config, retry::RetryKube};
 use mirrord_progress::{Progress, ProgressTracker};
 use mirrord_vpn::{agent::VpnAgent, config::VpnConfig, tunnel::VpnTunnel};
@@ -92,7 +92,12 @@ pub async fn vpn_command(args: VpnArgs) -> CliResult<()> {
 
     progress.success(None);
 
-    let vpn_tunnel = VpnTunnel::new(vpn_agnet, vpn_socket);
+    let dns_overrides = if layer_config.feature.network.dns.enabled {
+        DnsOverrides::new(&layer_config.feature.network.dns.overrides)?
+    } else {
+        Default::default()
+    };
+    let vpn_tunnel = VpnTunnel::new(vpn_agnet, vpn_socket).with_dns_overrides(dns_overrides);
 
     tokio::select! {
         _ = vpn_tunnel.start() => {}
@@ -0,0 +1,410 @@
+//! `mirrord exec --watch`, restarts the user process when watched files change.
+//!
+//! Instead of `execve`ing the user binary, the CLI spawns it as a child process and polls the
+//! files matching the `--watch` globs. When they change, only the child is restarted: the internal
+//! proxy and its agent connection stay alive, and every run gets the same environment, fetched
+//! from the target once.
+//!
+//! To make restarts cheap:
+//!
+//! - the CLI holds its own session with the internal proxy ([`ProxyControl`]), so that the proxy
+//!   does not exit on
+//!   [`idle_timeout`](mirrord_config::internal_proxy::InternalProxyConfig::idle_timeout) while
+//!   there is no user process;
+//! - port subscriptions of the previous run are retained in the agent for
+//!   [`PORT_SUBSCRIPTION_RETENTION`], and are reused by the next run.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use mirrord_config::MIRRORD_LAYER_INTPROXY_ADDR;
+use mirrord_progress::Progress;
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use regex::Regex;
+use thiserror::Error;
+use tokio::{
+    process::{Child, Command},
+    time::{self, MissedTickBehavior},
+};
+
+use crate::session::{ProxyControl, SessionError};
+
+/// How long the internal proxy retains port subscriptions of a finished run, see
+/// [`InternalProxyConfig::port_subscription_retention`](mirrord_config::internal_proxy::InternalProxyConfig::port_subscription_retention).
+pub(crate) const PORT_SUBSCRIPTION_RETENTION: u64 = 30;
+
+/// How often the watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the user process has to exit after `SIGTERM`, before it's killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Directories that are never scanned, unless a pattern starts inside of them.
+///
+/// Hidden directories are skipped as well.
+const IGNORED_DIRS: &[&str] = &["node_modules", "target"];
+
+/// Errors of `mirrord exec --watch`.
+#[derive(Debug, Error)]
+pub(crate) enum WatchError {
+    #[error("invalid watch pattern `{0}`: {1}")]
+    InvalidPattern(String, #[source] regex::Error),
+
+    #[error("failed to connect to the internal proxy: {0}")]
+    ProxyConnection(String),
+
+    #[error(transparent)]
+    ProxyControl(#[from] SessionError),
+
+    #[error("failed to start the process: {0}")]
+    Spawn(#[source] io::Error),
+
+    #[error("failed to stop the process: {0}")]
+    Stop(#[source] io::Error),
+}
+
+/// A glob pattern given with `--watch`, matched against paths relative to the working directory.
+///
+/// `*` and `?` do not match `/`, `**` matches any number of directories, and `{a,b}` matches any
+/// of the alternatives.
+#[derive(Debug)]
+struct WatchPattern {
+    /// Directory to scan, the part of the pattern before the first wildcard.
+    root: PathBuf,
+    regex: Regex,
+}
+
+impl WatchPattern {
+    fn new(glob: &str) -> Result<Self, WatchError> {
+        let glob = glob.strip_prefix("./").unwrap_or(glob);
+
+        let components = glob.split('/').collect::<Vec<_>>();
+        let root = components
+            .split_last()
+            .map(|(_, dirs)| {
+                dirs.iter()
+                    .take_while(|dir| !dir.contains(['*', '?', '{']))
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default();
+        let root = match root.as_str() {
+            "" if glob.starts_with('/') => PathBuf::from("/"),
+            "" => PathBuf::from("."),
+            root => PathBuf::from(root),
+        };
+
+        let regex = Regex::new(&glob_to_regex(glob))
+            .map_err(|error| WatchError::InvalidPattern(glob.to_string(), error))?;
+
+        Ok(Self { root, regex })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let path = path.strip_prefix(".").unwrap_or(path);
+        self.regex.is_match(&path.to_string_lossy())
+    }
+
+    /// Adds modification times of all files matching this pattern to `files`.
+    fn scan(&self, files: &mut HashMap<PathBuf, SystemTime>) {
+        let mut dirs = vec![self.root.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(metadata) = fs::metadata(&path) else {
+                    continue;
+                };
+
+                if metadata.is_dir() {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    if !name.starts_with('.') && !IGNORED_DIRS.contains(&name.as_ref()) {
+                        dirs.push(path);
+                    }
+                } else if self.matches(&path)
+                    && let Ok(modified) = metadata.modified()
+                {
+                    files.insert(path, modified);
+                }
+            }
+        }
+    }
+}
+
+/// Translates a glob pattern into an anchored regular expression, see [`WatchPattern`].
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    let mut in_alternatives = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.next_if_eq(&'*').is_some() => {
+                if chars.next_if_eq(&'/').is_some() {
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '{' if !in_alternatives => {
+                in_alternatives = true;
+                regex.push_str("(?:");
+            }
+            '}' if in_alternatives => {
+                in_alternatives = false;
+                regex.push(')');
+            }
+            ',' if in_alternatives => regex.push('|'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+/// Polls the files matching the `--watch` patterns for changes.
+pub(crate) struct FileWatcher {
+    patterns: Arc<[WatchPattern]>,
+    /// Modification times of the matching files, from the last scan.
+    files: HashMap<PathBuf, SystemTime>,
+}
+
+impl FileWatcher {
+    pub(crate) fn new(globs: &[String]) -> Result<Self, WatchError> {
+        let patterns = globs
+            .iter()
+            .map(|glob| WatchPattern::new(glob))
+            .collect::<Result<Arc<[_]>, _>>()?;
+
+        let mut files = HashMap::new();
+        for pattern in patterns.iter() {
+            pattern.scan(&mut files);
+        }
+
+        Ok(Self { patterns, files })
+    }
+
+    async fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let patterns = self.patterns.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut files = HashMap::new();
+            for pattern in patterns.iter() {
+                pattern.scan(&mut files);
+            }
+            files
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    /// Waits until a watched file is created, modified or removed, and returns its path.
+    ///
+    /// Returns only once the files stop changing, so that a burst of changes (e.g. a
+    /// `git checkout`) results in a single restart.
+    async fn changed(&mut self) -> PathBuf {
+        let mut interval = time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut changed = None;
+
+        loop {
+            interval.tick().await;
+
+            let files = self.scan().await;
+            let difference = first_difference(&self.files, &files);
+            self.files = files;
+
+            match (difference, changed.take()) {
+                (Some(path), previous) => changed = Some(previous.unwrap_or(path)),
+                (None, Some(path)) => break path,
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+/// Returns the path of any file that differs between the two scans.
+fn first_difference(
+    old: &HashMap<PathBuf, SystemTime>,
+    new: &HashMap<PathBuf, SystemTime>,
+) -> Option<PathBuf> {
+    new.iter()
+        .find(|(path, modified)| old.get(*path) != Some(modified))
+        .map(|(path, _)| path)
+        .or_else(|| old.keys().find(|path| !new.contains_key(*path)))
+        .cloned()
+}
+
+/// Runs the user process, and restarts it whenever the [`FileWatcher`] sees a change.
+///
+/// Every run gets the same `env_vars`. Returns when the user interrupts mirrord.
+pub(crate) async fn run_watched<P: Progress>(
+    mut watcher: FileWatcher,
+    binary_path: &Path,
+    binary_args: &[String],
+    env_vars: &HashMap<String, String>,
+    progress: &P,
+) -> Result<(), WatchError> {
+    let proxy_addr = env_vars
+        .get(MIRRORD_LAYER_INTPROXY_ADDR)
+        .and_then(|address| address.parse::<SocketAddr>().ok())
+        .ok_or_else(|| {
+            WatchError::ProxyConnection(format!("missing {MIRRORD_LAYER_INTPROXY_ADDR}"))
+        })?;
+    let _keepalive = ProxyControl::connect(proxy_addr)?;
+
+    let mut child = Some(spawn(binary_path, binary_args, env_vars)?);
+
+    loop {
+        tokio::select! {
+            path = watcher.changed() => {
+                progress.info(&format!(
+                    "{} changed, restarting the process",
+                    path.display()
+                ));
+                if let Some(child) = child.take() {
+                    stop(child).await?;
+                }
+                child = Some(spawn(binary_path, binary_args, env_vars)?);
+            }
+
+            status = wait(&mut child) => {
+                child = None;
+                progress.info(&format!(
+                    "process exited with {}, waiting for changes",
+                    describe(status)
+                ));
+            }
+
+            _ = tokio::signal::ctrl_c() => {
+                if let Some(child) = child.take() {
+                    stop(child).await?;
+                }
+                break Ok(());
+            }
+        }
+    }
+}
+
+fn spawn(
+    binary_path: &Path,
+    binary_args: &[String],
+    env_vars: &HashMap<String, String>,
+) -> Result<Child, WatchError> {
+    let mut command = Command::new(binary_path);
+
+    // Put original executable in argv[0] even if actually running patched version.
+    if let Some((argv0, args)) = binary_args.split_first() {
+        command.arg0(argv0).args(args);
+    }
+
+    command
+        .env_clear()
+        .envs(env_vars)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(WatchError::Spawn)
+}
+
+/// Waits for the child to exit, or forever if there is no child.
+async fn wait(child: &mut Option<Child>) -> io::Result<ExitStatus> {
+    match child {
+        Some(child) => child.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sends `SIGTERM` to the child, and kills it if it does not exit within [`STOP_TIMEOUT`].
+async fn stop(mut child: Child) -> Result<(), WatchError> {
+    if let Some(pid) = child.id() {
+        let _ = signal::kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+    }
+
+    match time::timeout(STOP_TIMEOUT, child.wait()).await {
+        Ok(result) => result.map(drop).map_err(WatchError::Stop),
+        Err(..) => child.kill().await.map_err(WatchError::Stop),
+    }
+}
+
+fn describe(status: io::Result<ExitStatus>) -> String {
+    match status {
+        Ok(status) => status.to_string(),
+        Err(error) => format!("an unknown status ({error})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("src/**/*.rs", "src/main.rs", true)]
+    #[case("src/**/*.rs", "src/api/v1/handler.rs", true)]
+    #[case("src/**/*.rs", "tests/main.rs", false)]
+    #[case("src/*.rs", "src/api/handler.rs", false)]
+    #[case("./src/*.rs", "./src/main.rs", true)]
+    #[case("**/*.{ts,tsx}", "web/app.tsx", true)]
+    #[case("**/*.{ts,tsx}", "web/app.js", false)]
+    #[case("config/app?.yaml", "config/app1.yaml", true)]
+    #[case("config/app?.yaml", "config/app.yaml", false)]
+    #[case("Cargo.toml", "Cargo.toml", true)]
+    #[case("Cargo.toml", "crates/Cargo.toml", false)]
+    fn pattern_matches(#[case] glob: &str, #[case] path: &str, #[case] matches: bool) {
+        let pattern = WatchPattern::new(glob).unwrap();
+        assert_eq!(pattern.matches(Path::new(path)), matches, "{pattern:?}");
+    }
+
+    #[rstest]
+    #[case("src/**/*.rs", "src")]
+    #[case("./src/api/*.rs", "src/api")]
+    #[case("**/*.rs", ".")]
+    #[case("Cargo.toml", ".")]
+    #[case("/etc/app/*.conf", "/etc/app")]
+    fn pattern_root(#[case] glob: &str, #[case] root: &str) {
+        assert_eq!(WatchPattern::new(glob).unwrap().root, Path::new(root));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(matches!(
+            WatchPattern::new("src/*.{rs,toml"),
+            Err(WatchError::InvalidPattern(..))
+        ));
+    }
+
+    #[test]
+    fn difference() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(1);
+        let old = HashMap::from([(PathBuf::from("a"), now), (PathBuf::from("b"), now)]);
+
+        assert_eq!(first_difference(&old, &old.clone()), None);
+
+        let modified = HashMap::from([(PathBuf::from("a"), now), (PathBuf::from("b"), later)]);
+        assert_eq!(first_difference(&old, &modified), Some(PathBuf::from("b")));
+
+        let removed = HashMap::from([(PathBuf::from("a"), now)]);
+        assert_eq!(first_difference(&old, &removed), Some(PathBuf::from("b")));
+    }
+}
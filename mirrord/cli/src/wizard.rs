@@ -39,7 +39,7 @@ use flate2::read::GzDecoder;
 use futures::{StreamExt, TryStreamExt};
 use itertools::Itertools;
 use k8s_openapi::api::{
-    apps::v1::{Deployment, ReplicaSet, StatefulSet},
+    apps::v1::{DaemonSet, Deployment, ReplicaSet, StatefulSet},
     batch::v1::{CronJob, Job},
     core::v1::{Namespace, Pod, Service},
 };
@@ -358,6 +358,13 @@ async fn list_targets(
                     .collect::<Vec<_>>()
                     .await
             }
+            TargetType::DaemonSet => {
+                seeker
+                    .list_all_namespaced::<DaemonSet>(None, None)
+                    .filter_map(|x| into_info(x, &seeker, &client))
+                    .collect::<Vec<_>>()
+                    .await
+            }
             TargetType::Targetless => vec![], // the frontend does not yet support targetless
         });
     }
@@ -674,3 +681,32 @@ impl IntoTargetInfo for ReplicaSet {
         Ok(into_info_option(self))
     }
 }
+
+impl IntoTargetInfo for DaemonSet {
+    async fn into_info(
+        self,
+        _seeker: &KubeResourceSeeker<'_>,
+        _client: &Client,
+    ) -> Result<Option<TargetInfo>, CliError> {
+        fn into_info_option(daemon_set: DaemonSet) -> Option<TargetInfo> {
+            let target_name = daemon_set.name()?.to_string();
+            let target_namespace = daemon_set.namespace()?.to_string();
+            let detected_ports = daemon_set
+                .spec?
+                .template
+                .spec?
+                .containers
+                .iter()
+                .flat_map(|container| container.ports.clone().unwrap_or_default())
+                .map(|port| port.container_port.unsigned_abs() as u16)
+                .collect();
+            Some(TargetInfo::new(
+                TargetType::DaemonSet,
+                target_name,
+                target_namespace,
+                detected_ports,
+            ))
+        }
+        Ok(into_info_option(self))
+    }
+}
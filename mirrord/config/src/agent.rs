@@ -327,6 +327,10 @@ pub struct AgentConfig {
     #[config(nested)]
     pub dns: AgentDnsConfig,
 
+    /// ### agent.client_limits {#agent-client_limits}
+    #[config(nested)]
+    pub client_limits: AgentClientLimitsConfig,
+
     /// ### agent.labels {#agent-labels}
     ///
     /// Allows setting up custom labels for the agent Job and Pod.
@@ -400,6 +404,25 @@ pub struct AgentConfig {
     /// ```
     pub metrics: Option<SocketAddr>,
 
+    /// ### agent.otlp_endpoint {#agent-otlp_endpoint}
+    ///
+    /// Enables OpenTelemetry trace export from the agent pod, using OTLP over gRPC.
+    ///
+    /// The agent creates a span for every HTTP request it steals or mirrors and passes its
+    /// context to the internal proxy, so that the request can be followed end-to-end (see
+    /// [`internal_proxy.otlp_endpoint`](#internal_proxy-otlp_endpoint)).
+    ///
+    /// The endpoint must be reachable from the agent pod.
+    ///
+    /// ```json
+    /// {
+    ///   "agent": {
+    ///     "otlp_endpoint": "http://jaeger-collector.observability:4317"
+    ///   }
+    /// }
+    /// ```
+    pub otlp_endpoint: Option<String>,
+
     /// ### agent.exclude_from_mesh {#agent-exclude_from_mesh}
     ///
     /// When running the agent as an ephemeral container, use this option to exclude
@@ -671,6 +694,56 @@ pub struct AgentDnsConfig {
     pub attempts: Option<u32>,
 }
 
+/// Resource limits the agent applies to every client (mirrord session) separately, so that a
+/// single noisy client can't starve the others sharing the agent.
+///
+/// ```json
+/// {
+///   "agent": {
+///     "client_limits": {
+///       "max_pending_bytes": 1048576,
+///       "max_in_flight_requests": 128,
+///       "file_workers": 4
+///     }
+///   }
+/// }
+/// ```
+#[derive(MirrordConfig, Default, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+#[config(derive = "JsonSchema")]
+#[cfg_attr(test, config(derive = "PartialEq, Eq"))]
+pub struct AgentClientLimitsConfig {
+    /// ### agent.client_limits.max_pending_bytes {#agent-client_limits-max_pending_bytes}
+    ///
+    /// How many bytes the agent can read on behalf of a client (from outgoing connections and
+    /// files), before they're sent to it. When the budget is used up, the agent stops reading
+    /// from the client's outgoing connections, and shortens its file reads, until the client
+    /// catches up.
+    ///
+    /// If not specified the agent uses a default value of 1 MiB. Values below 64 KiB are raised
+    /// to 64 KiB.
+    pub max_pending_bytes: Option<u32>,
+
+    /// ### agent.client_limits.max_in_flight_requests {#agent-client_limits-max_in_flight_requests}
+    ///
+    /// How many DNS queries and outgoing connection attempts of a client the agent handles at
+    /// once. Further requests wait until one of these finishes.
+    ///
+    /// If not specified the agent uses a default value of 128.
+    pub max_in_flight_requests: Option<u32>,
+
+    /// ### agent.client_limits.file_workers {#agent-client_limits-file_workers}
+    ///
+    /// How many file operations of a client the agent executes at once, so that a slow operation
+    /// (e.g. a read from a network filesystem) doesn't hold up the others. Operations on the same
+    /// file descriptor are always executed in order.
+    ///
+    /// Only takes effect with clients that support out-of-order file responses, older clients
+    /// get their file operations executed one at a time.
+    ///
+    /// If not specified the agent uses a default value of 4.
+    pub file_workers: Option<u32>,
+}
+
 #[cfg(test)]
 #[allow(clippy::too_many_arguments)]
 mod tests {
@@ -85,6 +85,12 @@ pub enum ConfigError {
 
     #[error("Failed to access file {}: {}", path.display(), error)]
     FileAccessFailed { path: PathBuf, error: io::Error },
+
+    #[error("Invalid config patch: {0}")]
+    InvalidPatch(String),
+
+    #[error("Config file does not match the config schema: {0}")]
+    InvalidConfigFile(String),
 }
 
 /// Errors that can occur when parsing configuration from a file.
@@ -96,6 +102,18 @@ pub enum FromFileError {
     ParseToml(#[from] toml::de::Error),
     ParseJson(#[from] serde_json::Error),
     ParseYaml(#[from] serde_yaml::Error),
+    /// An entry in `extends` is not a local path or a `file://` URL.
+    ExtendsUnsupported(String),
+    /// The `extends` key is not a string or an array of strings.
+    ExtendsInvalid(PathBuf),
+    /// The files extend each other in a cycle, the first file is repeated at the end.
+    ExtendsCycle(Vec<PathBuf>),
+    /// An extended file could not be read.
+    ExtendsRead {
+        path: PathBuf,
+        #[source]
+        error: Box<FromFileError>,
+    },
 }
 
 impl From<tera::Error> for FromFileError {
@@ -121,6 +139,37 @@ impl fmt::Display for FromFileError {
                     json, toml, yml, yaml",
                 );
             }
+            Self::ExtendsUnsupported(entry) => {
+                return write!(
+                    f,
+                    "`{entry}` in `extends` is not supported, \
+                    must be a local path or a `file://` URL",
+                );
+            }
+            Self::ExtendsInvalid(path) => {
+                return write!(
+                    f,
+                    "`extends` in {} must be a string or an array of strings",
+                    path.display(),
+                );
+            }
+            Self::ExtendsCycle(paths) => {
+                f.write_str("config files extend each other in a cycle: ")?;
+                for (i, path) in paths.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" -> ")?;
+                    }
+                    write!(f, "{}", path.display())?;
+                }
+                return Ok(());
+            }
+            Self::ExtendsRead { path, error } => {
+                return write!(
+                    f,
+                    "failed to load extended file {}: {error}",
+                    path.display()
+                );
+            }
             Self::TeraRender(error) => {
                 f.write_str("failed to render Tera")?;
                 error.as_ref()
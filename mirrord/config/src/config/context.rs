@@ -141,6 +141,13 @@ impl ConfigContext {
         self.warnings
     }
 
+    /// Drops all warnings previously stored with [`ConfigContext::add_warning`].
+    ///
+    /// Used when the config is generated again from scratch.
+    pub(crate) fn clear_warnings(&mut self) {
+        self.warnings.clear();
+    }
+
     /// Returns whether this context stores any warnings.
     pub fn has_warnings(&self) -> bool {
         self.warnings.is_empty().not()
@@ -1,7 +1,8 @@
 use std::path::PathBuf;
 
-use mirrord_analytics::CollectAnalytics;
+use mirrord_analytics::{AnalyticValue, CollectAnalytics};
 use mirrord_config_derive::MirrordConfig;
+use mirrord_protocol::CompressionAlgorithm;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -138,6 +139,55 @@ pub struct ExperimentalConfig {
     #[config(default = false)]
     pub dlopen_cgo: bool,
 
+    /// ### _experimental_ seccomp_interception {#experimental-seccomp_interception}
+    ///
+    /// Runs statically linked binaries under a seccomp supervisor, as the layer can't be loaded
+    /// into them. `open`/`openat` and `connect` calls made by the binary are then handled by
+    /// mirrord, through the same requests the layer makes. Linux only, requires Linux 5.14.
+    ///
+    /// Remote files can be opened only for reading.
+    ///
+    /// Defaults to `false`.
+    #[config(default = false)]
+    pub seccomp_interception: bool,
+
+    /// ### _experimental_ io_uring {#experimental-io_uring}
+    ///
+    /// How mirrord deals with `io_uring`, which bypasses the file hooks, so remote files would
+    /// silently be read locally. Linux only, ignored when `feature.fs` is `"local"`.
+    ///
+    /// - `"disable"`: every `io_uring_setup` fails with `ENOSYS`, whether or not the ring would be
+    ///   used for remote files, so runtimes fall back to classic syscalls;
+    /// - `"emulate"`: submissions that target files managed by mirrord are handled by mirrord, the
+    ///   rest is submitted to the kernel. Rings with `IORING_SETUP_SQPOLL` are not supported, and
+    ///   fail to set up as with `"disable"`;
+    /// - `"allow"`: mirrord doesn't interfere with `io_uring`.
+    ///
+    /// Only rings set up through the `syscall` libc function are detected, as done by the
+    /// `io-uring` crate (tokio-uring) and libuv. liburing (since 2.2) makes the syscalls itself,
+    /// so its rings are always allowed.
+    ///
+    /// Defaults to `"disable"`, set `"emulate"` to keep using `io_uring`, or `"allow"` if the
+    /// application doesn't read remote files with it.
+    #[config(default)]
+    pub io_uring: IoUringMode,
+
+    /// ### _experimental_ protocol_compression {#experimental-protocol_compression}
+    ///
+    /// Compresses the traffic between the internal proxy and the agent (or the operator), which
+    /// can speed up sessions over slow links, e.g. VPNs to remote clusters.
+    ///
+    /// - `"off"`: no compression;
+    /// - `"zstd"`: better compression ratio;
+    /// - `"lz4"`: faster, but compresses less.
+    ///
+    /// Only messages bigger than 1KiB are compressed, e.g. file reads and HTTP bodies. Ignored if
+    /// the agent or the operator don't support compression.
+    ///
+    /// Defaults to `"off"`.
+    #[config(default)]
+    pub protocol_compression: ProtocolCompression,
+
     /// ### _experimental_ applev {#experimental-applev}
     ///
     /// Configuraiton for inspecting and modifying apple variables. macOS only.
@@ -163,9 +213,75 @@ impl CollectAnalytics for &ExperimentalConfig {
         analytics.add("force_hook_connect", self.force_hook_connect);
         analytics.add("non_blocking_tcp_connect", self.non_blocking_tcp_connect);
         analytics.add("dlopen_cgo", self.dlopen_cgo);
+        analytics.add("seccomp_interception", self.seccomp_interception);
+        analytics.add("io_uring", self.io_uring);
+        analytics.add("protocol_compression", self.protocol_compression);
         analytics.add("applev", self.applev.is_some());
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema, Default)]
 pub struct AppleVariablesConfig {}
+
+/// How mirrord deals with `io_uring`, see
+/// [`ExperimentalConfig::io_uring`](#experimental-io_uring).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IoUringMode {
+    /// mirrord doesn't interfere with `io_uring`.
+    Allow,
+
+    /// `io_uring_setup` always fails with `ENOSYS`.
+    #[default]
+    Disable,
+
+    /// Submissions that target files managed by mirrord are handled by mirrord.
+    Emulate,
+}
+
+impl From<IoUringMode> for AnalyticValue {
+    fn from(value: IoUringMode) -> Self {
+        match value {
+            IoUringMode::Allow => AnalyticValue::Number(0),
+            IoUringMode::Disable => AnalyticValue::Number(1),
+            IoUringMode::Emulate => AnalyticValue::Number(2),
+        }
+    }
+}
+
+/// Compression of the agent connection, see
+/// [`ExperimentalConfig::protocol_compression`](#experimental-protocol_compression).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProtocolCompression {
+    /// Messages are not compressed.
+    #[default]
+    Off,
+
+    /// Messages are compressed with zstd.
+    Zstd,
+
+    /// Messages are compressed with lz4.
+    Lz4,
+}
+
+impl ProtocolCompression {
+    /// Algorithm to switch on in the agent connection, if any.
+    pub fn algorithm(self) -> Option<CompressionAlgorithm> {
+        match self {
+            Self::Off => None,
+            Self::Zstd => Some(CompressionAlgorithm::Zstd),
+            Self::Lz4 => Some(CompressionAlgorithm::Lz4),
+        }
+    }
+}
+
+impl From<ProtocolCompression> for AnalyticValue {
+    fn from(value: ProtocolCompression) -> Self {
+        match value {
+            ProtocolCompression::Off => AnalyticValue::Number(0),
+            ProtocolCompression::Zstd => AnalyticValue::Number(1),
+            ProtocolCompression::Lz4 => AnalyticValue::Number(2),
+        }
+    }
+}
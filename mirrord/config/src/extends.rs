@@ -0,0 +1,316 @@
+//! Loading of config files that build upon other config files with the
+//! [`extends`](crate::LayerConfig::extends) key.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tera::Tera;
+
+use crate::config::FromFileError;
+
+/// Name of the key that lists the extended files.
+pub const EXTENDS_KEY: &str = "extends";
+
+/// Result of deep-merging a config file with all the files it extends.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MergedConfigFile {
+    /// The merged config, without the [`EXTENDS_KEY`]s.
+    pub config: Value,
+
+    /// Files that set the values in [`Self::config`], by JSON pointer of the value.
+    ///
+    /// Only leaves are listed here: arrays, scalars and empty objects.
+    pub provenance: BTreeMap<String, PathBuf>,
+
+    /// All the merged files, in merge order (the extending file is the last one).
+    pub files: Vec<PathBuf>,
+}
+
+impl MergedConfigFile {
+    /// Loads the config file from `path`, and deep-merges it with the files it extends.
+    ///
+    /// Every file is rendered with Tera with the given `key`, see
+    /// [`LayerFileConfig::from_path`](crate::LayerFileConfig::from_path).
+    pub fn load(path: &Path, key: &str) -> Result<Self, FromFileError> {
+        Self::load_parsed(path, key, None)
+    }
+
+    /// Same as [`MergedConfigFile::load`], but with the config file at `path` already rendered
+    /// and parsed into `value`, so that only the extended files are read.
+    pub(crate) fn load_parsed(
+        path: &Path,
+        key: &str,
+        value: Option<Value>,
+    ) -> Result<Self, FromFileError> {
+        let mut merged = Self {
+            config: Value::Object(Default::default()),
+            ..Default::default()
+        };
+        merged.load_file(path, key, value, &mut Vec::new())?;
+
+        Ok(merged)
+    }
+
+    fn load_file(
+        &mut self,
+        path: &Path,
+        key: &str,
+        value: Option<Value>,
+        chain: &mut Vec<PathBuf>,
+    ) -> Result<(), FromFileError> {
+        let path = path.canonicalize()?;
+        if chain.contains(&path) {
+            let mut cycle = chain.clone();
+            cycle.push(path);
+            return Err(FromFileError::ExtendsCycle(cycle));
+        }
+
+        let mut value = match value {
+            Some(value) => value,
+            None => parse_value(&path, &render_file(&path, key)?)?,
+        };
+        let parents = take_extends(&mut value, &path)?;
+
+        chain.push(path.clone());
+        for parent in parents {
+            let parent = resolve_extended_path(&path, &parent)?;
+            self.load_file(&parent, key, None, chain)
+                .map_err(|error| match error {
+                    error @ (FromFileError::ExtendsCycle(..)
+                    | FromFileError::ExtendsRead { .. }) => error,
+                    error => FromFileError::ExtendsRead {
+                        path: parent.clone(),
+                        error: Box::new(error),
+                    },
+                })?;
+        }
+        chain.pop();
+
+        merge(
+            &mut self.config,
+            value,
+            &path,
+            &mut self.provenance,
+            String::new(),
+        );
+        self.files.push(path);
+
+        Ok(())
+    }
+}
+
+/// Renders the config file at `path` with Tera, providing `key` in the context.
+pub(crate) fn render_file(path: &Path, key: &str) -> Result<String, FromFileError> {
+    let mut template_engine = Tera::default();
+    template_engine.add_template_file(path, Some("main"))?;
+
+    let mut tera_context = tera::Context::new();
+    tera_context.insert("key", key);
+
+    Ok(template_engine.render("main", &tera_context)?)
+}
+
+/// Parses rendered config file contents, the format is taken from the extension of `path`.
+pub(crate) fn parse_value(path: &Path, rendered: &str) -> Result<Value, FromFileError> {
+    match path.extension().and_then(OsStr::to_str) {
+        // No Extension? assume json
+        Some("json") | None => Ok(serde_json::from_str(rendered)?),
+        Some("toml") => Ok(toml::from_str(rendered)?),
+        Some("yaml" | "yml") => Ok(serde_yaml::from_str(rendered)?),
+        ext => Err(FromFileError::InvalidExtension(ext.map(String::from))),
+    }
+}
+
+/// Removes the [`EXTENDS_KEY`] from the root of the config, returning the extended files.
+pub(crate) fn take_extends(value: &mut Value, path: &Path) -> Result<Vec<String>, FromFileError> {
+    let Some(extends) = value
+        .as_object_mut()
+        .and_then(|object| object.remove(EXTENDS_KEY))
+    else {
+        return Ok(Vec::new());
+    };
+
+    match extends {
+        Value::Null => Ok(Vec::new()),
+        Value::String(parent) => Ok(vec![parent]),
+        Value::Array(parents) => parents
+            .into_iter()
+            .map(|parent| match parent {
+                Value::String(parent) => Ok(parent),
+                _ => Err(FromFileError::ExtendsInvalid(path.to_path_buf())),
+            })
+            .collect(),
+        _ => Err(FromFileError::ExtendsInvalid(path.to_path_buf())),
+    }
+}
+
+/// Resolves an entry of `extends` found in the file at `extending`.
+fn resolve_extended_path(extending: &Path, entry: &str) -> Result<PathBuf, FromFileError> {
+    let path = match entry.split_once("://") {
+        Some(("file", path)) => Path::new(path),
+        Some(..) => return Err(FromFileError::ExtendsUnsupported(entry.to_string())),
+        None => Path::new(entry),
+    };
+
+    Ok(match extending.parent() {
+        Some(directory) if path.is_relative() => directory.join(path),
+        _ => path.to_path_buf(),
+    })
+}
+
+/// Deep-merges `overlay` into `base`, recording the `source` of every new leaf.
+///
+/// Objects are merged key by key, any other value replaces the value in `base`.
+///
+/// `pointer` is the JSON pointer of `base` in the merged config.
+fn merge(
+    base: &mut Value,
+    overlay: Value,
+    source: &Path,
+    provenance: &mut BTreeMap<String, PathBuf>,
+    pointer: String,
+) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let pointer = format!("{pointer}/{}", key.replace('~', "~0").replace('/', "~1"));
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, source, provenance, pointer),
+                    None => {
+                        record_leaves(&value, source, provenance, pointer);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => {
+            let nested = format!("{pointer}/");
+            provenance.retain(|existing, _| *existing != pointer && !existing.starts_with(&nested));
+            record_leaves(&overlay, source, provenance, pointer);
+            *base = overlay;
+        }
+    }
+}
+
+fn record_leaves(
+    value: &Value,
+    source: &Path,
+    provenance: &mut BTreeMap<String, PathBuf>,
+    pointer: String,
+) {
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                let pointer = format!("{pointer}/{}", key.replace('~', "~0").replace('/', "~1"));
+                record_leaves(value, source, provenance, pointer);
+            }
+        }
+        _ => {
+            provenance.insert(pointer, source.to_path_buf());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn write(directory: &TempDir, name: &str, contents: &str) -> PathBuf {
+        let path = directory.path().join(name);
+        fs::write(&path, contents).unwrap();
+        path.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn deep_merge_with_provenance() {
+        let directory = tempfile::tempdir().unwrap();
+        let base = write(
+            &directory,
+            "base.json",
+            r#"{
+                "feature": { "fs": "read", "env": { "include": "A;B" } },
+                "skip_processes": ["a", "b"]
+            }"#,
+        );
+        let team = write(
+            &directory,
+            "team.toml",
+            r#"
+            extends = "base.json"
+            [feature.network]
+            dns = false
+            "#,
+        );
+        let main = write(
+            &directory,
+            "main.yaml",
+            "extends: [team.toml]\nfeature:\n  fs: write\nskip_processes: [c]\n",
+        );
+
+        let merged = MergedConfigFile::load(&main, "key").unwrap();
+
+        assert_eq!(
+            merged.config,
+            json!({
+                "feature": {
+                    "fs": "write",
+                    "env": { "include": "A;B" },
+                    "network": { "dns": false },
+                },
+                "skip_processes": ["c"],
+            })
+        );
+        assert_eq!(merged.files, [base.clone(), team.clone(), main.clone()]);
+        assert_eq!(
+            merged.provenance,
+            BTreeMap::from([
+                ("/feature/env/include".to_string(), base),
+                ("/feature/fs".to_string(), main.clone()),
+                ("/feature/network/dns".to_string(), team),
+                ("/skip_processes".to_string(), main),
+            ])
+        );
+    }
+
+    #[test]
+    fn cycle_is_detected() {
+        let directory = tempfile::tempdir().unwrap();
+        let first = write(&directory, "first.json", r#"{ "extends": "second.json" }"#);
+        let second = write(
+            &directory,
+            "second.json",
+            r#"{ "extends": "file://./first.json" }"#,
+        );
+
+        let error = MergedConfigFile::load(&first, "key").unwrap_err();
+        let FromFileError::ExtendsCycle(cycle) = error else {
+            panic!("unexpected error: {error}");
+        };
+        assert_eq!(cycle, [first.clone(), second, first]);
+    }
+
+    #[test]
+    fn remote_urls_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let main = write(
+            &directory,
+            "main.json",
+            r#"{ "extends": "https://example.com/base.json" }"#,
+        );
+
+        assert!(matches!(
+            MergedConfigFile::load(&main, "key"),
+            Err(FromFileError::ExtendsUnsupported(..))
+        ));
+    }
+}
@@ -5,12 +5,20 @@ use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::config::{self, source::MirrordConfigSource};
+use crate::{
+    config::{self, source::MirrordConfigSource},
+    container::ContainerRuntime,
+};
 
+pub mod mongodb;
 pub mod mysql;
 pub mod pg;
 pub mod redis;
 
+pub use mongodb::{
+    MongodbBranchCollectionCopyConfig, MongodbBranchConfig, MongodbBranchCopyConfig,
+    MongodbLocalConfig, MongodbRuntime,
+};
 pub use mysql::{MysqlBranchConfig, MysqlBranchCopyConfig, MysqlBranchTableCopyConfig};
 pub use pg::{PgBranchConfig, PgBranchCopyConfig, PgBranchTableCopyConfig};
 pub use redis::{
@@ -72,6 +80,22 @@ impl DatabaseBranchesConfig {
             .filter(|db| matches!(db, DatabaseBranchConfig::Redis { .. }))
             .count()
     }
+
+    pub fn count_mongodb(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|db| matches!(db, DatabaseBranchConfig::Mongodb { .. }))
+            .count()
+    }
+
+    /// Counts PostgreSQL and MySQL branches that run locally instead of in the cluster.
+    pub fn count_local_sql(&self) -> usize {
+        self.0
+            .iter()
+            .filter_map(DatabaseBranchConfig::sql_base)
+            .filter(|base| base.location == DatabaseBranchLocation::Local)
+            .count()
+    }
 }
 
 /// Configuration for a database branch.
@@ -99,6 +123,19 @@ pub enum DatabaseBranchConfig {
     Mysql(MysqlBranchConfig),
     Pg(PgBranchConfig),
     Redis(RedisBranchConfig),
+    Mongodb(MongodbBranchConfig),
+}
+
+impl DatabaseBranchConfig {
+    /// Returns the shared config of PostgreSQL and MySQL branches, [`None`] for Redis and
+    /// MongoDB.
+    pub fn sql_base(&self) -> Option<&DatabaseBranchBaseConfig> {
+        match self {
+            Self::Mysql(mysql_config) => Some(&mysql_config.base),
+            Self::Pg(pg_config) => Some(&pg_config.base),
+            Self::Redis(_) | Self::Mongodb(_) => None,
+        }
+    }
 }
 
 /// Despite the database type, all database branch config objects share the following fields.
@@ -139,6 +176,7 @@ pub struct DatabaseBranchBaseConfig {
     /// #### feature.db_branches.base.version {#feature-db_branches-base-version}
     ///
     /// Mirrord operator uses a default version of the database image unless `version` is given.
+    /// When `location` is `local`, `version` is used as the tag of the local container image.
     pub version: Option<String>,
 
     /// #### feature.db_branches.base.connection {#feature-db_branches-base-connection}
@@ -147,6 +185,84 @@ pub struct DatabaseBranchBaseConfig {
     /// When the branch database is ready for use, Mirrord operator will replace the connection
     /// information with the branch database's.
     pub connection: ConnectionSource,
+
+    /// #### feature.db_branches.base.location {#feature-db_branches-base-location}
+    ///
+    /// Where the branch database should run.
+    /// - `remote`: mirrord operator creates the branch database in the cluster (default).
+    /// - `local`: mirrord spawns a local database container, seeds it from the source database
+    ///   according to `copy`, and points the `connection` variable at it.
+    #[serde(default)]
+    pub location: DatabaseBranchLocation,
+
+    /// #### feature.db_branches.base.local {#feature-db_branches-base-local}
+    ///
+    /// Local database runtime configuration.
+    /// Only used when `location` is `local`.
+    #[serde(default)]
+    pub local: DatabaseBranchLocalConfig,
+}
+
+/// Location for a PostgreSQL or MySQL branch database.
+#[derive(Clone, Debug, Eq, PartialEq, JsonSchema, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBranchLocation {
+    /// Use a local database container that mirrord manages.
+    Local,
+    /// Use a branch database created by mirrord operator (default behavior).
+    #[default]
+    Remote,
+}
+
+/// Configuration for a local PostgreSQL or MySQL branch database.
+///
+/// The local database is seeded by running `pg_dump`/`mysqldump` with mirrord loaded, so the
+/// source database is reached the same way the application would reach it.
+///
+/// Example:
+/// ```json
+/// {
+///   "type": "pg",
+///   "location": "local",
+///   "connection": {
+///     "url": { "type": "env", "variable": "DATABASE_URL" }
+///   },
+///   "copy": {
+///     "mode": "schema",
+///     "tables": {
+///       "users": { "filter": "name = 'alice'" }
+///     }
+///   },
+///   "local": {
+///     "port": 15432,
+///     "container_runtime": "podman"
+///   }
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, JsonSchema, Serialize, Deserialize, Default)]
+pub struct DatabaseBranchLocalConfig {
+    /// Local port to bind the database to (default: 5432 for PostgreSQL, 3306 for MySQL).
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Which container runtime to use (Docker, Podman, or nerdctl).
+    #[serde(default)]
+    pub container_runtime: ContainerRuntime,
+
+    /// Custom path to the container command.
+    /// If not provided, uses the runtime name from PATH (e.g., "docker").
+    #[serde(default)]
+    pub container_command: Option<String>,
+
+    /// Custom path to the dump binary used to seed the local database.
+    /// If not provided, uses "pg_dump" or "mysqldump" from PATH.
+    #[serde(default)]
+    pub dump_command: Option<String>,
+
+    /// Custom path to the psql binary, used to copy filtered rows of PostgreSQL tables.
+    /// If not provided, uses "psql" from PATH.
+    #[serde(default)]
+    pub client_command: Option<String>,
 }
 
 /// Different ways of connecting to the source database.
@@ -210,6 +326,8 @@ impl CollectAnalytics for &DatabaseBranchesConfig {
         analytics.add("mysql_branch_count", self.count_mysql());
         analytics.add("pg_branch_count", self.count_pg());
         analytics.add("redis_branch_count", self.count_redis());
+        analytics.add("mongodb_branch_count", self.count_mongodb());
+        analytics.add("local_sql_branch_count", self.count_local_sql());
     }
 }
 
@@ -220,3 +338,44 @@ fn default_ttl_secs() -> u64 {
 pub fn default_creation_timeout_secs() -> u64 {
     60
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_pg_branch_config() {
+        let json = r#"[
+            {
+                "type": "pg",
+                "location": "local",
+                "connection": {
+                    "url": { "type": "env", "variable": "DATABASE_URL" }
+                },
+                "local": { "port": 15432 }
+            },
+            {
+                "type": "mysql",
+                "connection": {
+                    "url": { "type": "env", "variable": "MYSQL_URL" }
+                }
+            }
+        ]"#;
+
+        let config: DatabaseBranchesConfig = serde_json::from_str(json).unwrap();
+        let pg = config
+            .first()
+            .and_then(DatabaseBranchConfig::sql_base)
+            .unwrap();
+        assert_eq!(pg.location, DatabaseBranchLocation::Local);
+        assert_eq!(pg.local.port, Some(15432));
+        assert_eq!(pg.local.container_runtime, ContainerRuntime::Docker);
+
+        let mysql = config
+            .get(1)
+            .and_then(DatabaseBranchConfig::sql_base)
+            .unwrap();
+        assert_eq!(mysql.location, DatabaseBranchLocation::Remote);
+        assert_eq!(config.count_local_sql(), 1);
+    }
+}
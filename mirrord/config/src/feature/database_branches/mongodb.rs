@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::ConnectionSource;
+use crate::container::ContainerRuntime;
+
+/// When configuring a branch for MongoDB, set `type` to `mongodb`.
+///
+/// MongoDB branches always run locally: mirrord spawns `mongod`, copies the selected collections
+/// from the source database, and points the `connection` variable at the local instance.
+///
+/// Example:
+/// ```json
+/// {
+///   "type": "mongodb",
+///   "id": "orders-branch",
+///   "connection": {
+///     "url": {
+///       "type": "env",
+///       "variable": "MONGODB_URI"
+///     }
+///   },
+///   "copy": {
+///     "mode": "empty",
+///     "collections": {
+///       "orders": { "filter": "{\"status\": \"pending\"}" },
+///       "users": {}
+///     }
+///   }
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, JsonSchema, Serialize, Deserialize)]
+pub struct MongodbBranchConfig {
+    /// Optional unique identifier for reusing branches across sessions.
+    ///
+    /// When set, the local branch container keeps running after the session ends, and later
+    /// sessions with the same `id` reuse it as is, without copying the data again. Remove it with
+    /// `mirrord db-branches destroy`.
+    ///
+    /// Without an `id`, the container is removed when the session ends.
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// Name of the database to copy. If not provided, the database from the source connection
+    /// URI is used.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// How to get the connection URI of the source database.
+    pub connection: ConnectionSource,
+
+    /// What to copy from the source database.
+    #[serde(default)]
+    pub copy: MongodbBranchCopyConfig,
+
+    /// Local MongoDB runtime configuration.
+    #[serde(default)]
+    pub local: MongodbLocalConfig,
+}
+
+/// Users can choose from the following copy mode to bootstrap their MongoDB branch database:
+///
+/// - Empty
+///
+///   Creates an empty database, and copies only the documents of the given `collections`.
+///
+/// - All
+///
+///   Copies all collections of the database. This option shall only be used when the data volume
+///   of the source database is minimal.
+#[derive(Clone, Debug, Eq, PartialEq, JsonSchema, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum MongodbBranchCopyConfig {
+    Empty {
+        collections: Option<HashMap<String, MongodbBranchCollectionCopyConfig>>,
+    },
+
+    All,
+}
+
+impl Default for MongodbBranchCopyConfig {
+    fn default() -> Self {
+        MongodbBranchCopyConfig::Empty {
+            collections: Default::default(),
+        }
+    }
+}
+
+/// mirrord will copy documents of the given collections from the source DB, optionally filtered
+/// with a query document in extended JSON.
+///
+/// Example:
+///
+/// ```json
+/// {
+///   "users": {
+///     "filter": "{\"name\": {\"$in\": [\"alice\", \"bob\"]}}"
+///   },
+///   "orders": {}
+/// }
+/// ```
+///
+/// With the config above, only alice and bob from the `users` collection, and all of the
+/// `orders` will be copied.
+#[derive(Clone, Debug, Eq, PartialEq, JsonSchema, Serialize, Deserialize)]
+pub struct MongodbBranchCollectionCopyConfig {
+    pub filter: Option<String>,
+}
+
+/// Configuration for local MongoDB runtime.
+#[derive(Clone, Debug, Eq, PartialEq, JsonSchema, Serialize, Deserialize)]
+pub struct MongodbLocalConfig {
+    /// Local port to bind MongoDB to (default: 27017).
+    #[serde(default = "default_local_port")]
+    pub port: u16,
+
+    /// MongoDB version/tag to use (default: "7").
+    /// Used as the container image tag.
+    #[serde(default = "default_mongodb_version")]
+    pub version: String,
+
+    /// Runtime backend for local MongoDB: `container`, `mongod`, or `auto`.
+    #[serde(default)]
+    pub runtime: MongodbRuntime,
+
+    /// Which container runtime to use (Docker, Podman, or nerdctl).
+    /// Only applies when `runtime` is `container` or `auto`.
+    #[serde(default)]
+    pub container_runtime: ContainerRuntime,
+
+    /// Custom path to the container command.
+    /// If not provided, uses the runtime name from PATH (e.g., "docker").
+    #[serde(default)]
+    pub container_command: Option<String>,
+
+    /// Custom path to the mongod binary.
+    /// If not provided, uses "mongod" from PATH.
+    #[serde(default)]
+    pub server_command: Option<String>,
+
+    /// Custom path to the mongodump binary, used to read from the source database.
+    /// If not provided, uses "mongodump" from PATH.
+    #[serde(default)]
+    pub dump_command: Option<String>,
+
+    /// Custom path to the mongorestore binary, used to write into the local database.
+    /// If not provided, uses "mongorestore" from PATH.
+    #[serde(default)]
+    pub restore_command: Option<String>,
+}
+
+impl Default for MongodbLocalConfig {
+    fn default() -> Self {
+        Self {
+            port: default_local_port(),
+            version: default_mongodb_version(),
+            runtime: MongodbRuntime::default(),
+            container_runtime: ContainerRuntime::default(),
+            container_command: None,
+            server_command: None,
+            dump_command: None,
+            restore_command: None,
+        }
+    }
+}
+
+/// Runtime backend for running local MongoDB.
+///
+/// Backends:
+/// - `container` (default) - Uses a container runtime (Docker/Podman/nerdctl)
+/// - `mongod` - Uses native mongod binary
+/// - `auto` - Tries container first, falls back to mongod
+#[derive(Clone, Debug, Eq, PartialEq, JsonSchema, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MongodbRuntime {
+    /// Use a container runtime (configure which one via `container_runtime`). (default)
+    #[default]
+    Container,
+    /// Use native mongod binary.
+    Mongod,
+    /// Auto-detect: try container first, fall back to mongod.
+    Auto,
+}
+
+fn default_local_port() -> u16 {
+    27017
+}
+
+fn default_mongodb_version() -> String {
+    "7".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::database_branches::ConnectionSourceKind;
+
+    #[test]
+    fn test_collections_config() {
+        let json = r#"{
+            "connection": {
+                "url": {
+                    "type": "env",
+                    "variable": "MONGODB_URI"
+                }
+            },
+            "copy": {
+                "mode": "empty",
+                "collections": {
+                    "users": { "filter": "{\"name\": \"alice\"}" },
+                    "orders": {}
+                }
+            }
+        }"#;
+
+        let config: MongodbBranchConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            config.connection,
+            ConnectionSource::Url(ConnectionSourceKind::Env {
+                container: None,
+                variable: "MONGODB_URI".to_string()
+            })
+        );
+        let MongodbBranchCopyConfig::Empty {
+            collections: Some(collections),
+        } = config.copy
+        else {
+            panic!("expected empty copy mode with collections");
+        };
+        assert_eq!(
+            collections.get("users").and_then(|c| c.filter.as_deref()),
+            Some(r#"{"name": "alice"}"#)
+        );
+        assert_eq!(
+            collections.get("orders"),
+            Some(&MongodbBranchCollectionCopyConfig { filter: None })
+        );
+        assert_eq!(config.local, MongodbLocalConfig::default());
+    }
+
+    #[test]
+    fn test_local_runtime_config() {
+        let json = r#"{
+            "connection": {
+                "url": { "type": "env_from", "variable": "MONGODB_URI" }
+            },
+            "copy": { "mode": "all" },
+            "local": {
+                "port": 27018,
+                "version": "6.0",
+                "runtime": "mongod",
+                "server_command": "/opt/mongodb/bin/mongod"
+            }
+        }"#;
+
+        let config: MongodbBranchConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.copy, MongodbBranchCopyConfig::All);
+        assert_eq!(config.local.port, 27018);
+        assert_eq!(config.local.version, "6.0");
+        assert_eq!(config.local.runtime, MongodbRuntime::Mongod);
+        assert_eq!(
+            config.local.server_command.as_deref(),
+            Some("/opt/mongodb/bin/mongod")
+        );
+    }
+}
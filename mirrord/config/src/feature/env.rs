@@ -15,6 +15,7 @@ pub mod mapper;
 pub const MIRRORD_OVERRIDE_ENV_VARS_INCLUDE_ENV: &str = "MIRRORD_OVERRIDE_ENV_VARS_INCLUDE";
 pub const MIRRORD_OVERRIDE_ENV_VARS_EXCLUDE_ENV: &str = "MIRRORD_OVERRIDE_ENV_VARS_EXCLUDE";
 pub const MIRRORD_OVERRIDE_ENV_FILE_ENV: &str = "MIRRORD_OVERRIDE_ENV_VARS_FILE";
+pub const MIRRORD_ENV_FROM_SPEC_ENV: &str = "MIRRORD_ENV_FROM_SPEC";
 
 /// Allows the user to set or override the local process' environment variables with the ones
 /// from the remote pod.
@@ -140,6 +141,69 @@ pub struct EnvConfig {
     ///
     /// * `DATA_1234: common-value` => `DATA_1234: magic-value`
     pub mapping: Option<HashMap<String, String>>,
+
+    /// #### feature.env.from_spec {#feature-env-from_spec}
+    ///
+    /// Resolve the target container's `env` and `envFrom` from its spec in the Kubernetes API,
+    /// in addition to reading the environment of the running process.
+    ///
+    /// Useful when the referenced Secrets or ConfigMaps were updated after the pod started.
+    /// Values resolved from the spec take precedence over the ones read from the process.
+    /// Requests are made with your own Kubernetes user, so it needs permissions to get the
+    /// referenced Secrets and ConfigMaps.
+    ///
+    /// Variables that come from the downward API (`fieldRef`, `resourceFieldRef`) are only read
+    /// from the process. The [`include`](#feature-env-include) and
+    /// [`exclude`](#feature-env-exclude) filters apply, and variables that are never copied from
+    /// the target (e.g. `PATH` and `HOME`) are skipped as well.
+    #[config(env = MIRRORD_ENV_FROM_SPEC_ENV, default = false)]
+    pub from_spec: bool,
+
+    /// #### feature.env.sources {#feature-env-sources}
+    ///
+    /// Load environment variables from these Secrets and ConfigMaps, in the target's namespace.
+    ///
+    /// Works also for targetless runs, where there is no process to read the environment from.
+    /// Later sources take precedence, and all of them take precedence over the environment of
+    /// the target. Requires your Kubernetes user to have permissions to get the resources. The
+    /// same filters as with [`from_spec`](#feature-env-from_spec) apply.
+    ///
+    /// ```json
+    /// {
+    ///   "feature": {
+    ///     "env": {
+    ///       "sources": [
+    ///         { "config_map": { "name": "app-config" } },
+    ///         { "secret": { "name": "db-credentials", "prefix": "DB_" } }
+    ///       ]
+    ///     }
+    ///   }
+    /// }
+    /// ```
+    pub sources: Option<Vec<EnvSource>>,
+}
+
+/// A Kubernetes resource to load environment variables from, see
+/// [`sources`](#feature-env-sources).
+///
+/// Every key of the resource becomes an environment variable, like with `envFrom` in a pod spec.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum EnvSource {
+    /// Load the variables from a Secret.
+    Secret {
+        /// Name of the Secret.
+        name: String,
+        /// Prepended to every variable name.
+        prefix: Option<String>,
+    },
+    /// Load the variables from a ConfigMap.
+    ConfigMap {
+        /// Name of the ConfigMap.
+        name: String,
+        /// Prepended to every variable name.
+        prefix: Option<String>,
+    },
 }
 
 impl MirrordToggleableConfig for EnvFileConfig {
@@ -159,6 +223,8 @@ impl MirrordToggleableConfig for EnvFileConfig {
                 .source_value(context)
                 .transpose()?,
             mapping: None,
+            from_spec: false,
+            sources: None,
         })
     }
 }
@@ -201,6 +267,14 @@ impl CollectAnalytics for &EnvConfig {
                 .map(|v| v.len() as u32)
                 .unwrap_or_default(),
         );
+        analytics.add("from_spec", self.from_spec);
+        analytics.add(
+            "sources_count",
+            self.sources
+                .as_ref()
+                .map(|v| v.len() as u32)
+                .unwrap_or_default(),
+        );
     }
 }
 
@@ -254,4 +328,34 @@ mod tests {
         assert_eq!(env.include.map(|vec| vec.join(";")).as_deref(), include.1);
         assert_eq!(env.exclude.map(|vec| vec.join(";")).as_deref(), exclude.1);
     }
+
+    #[test]
+    fn sources() {
+        let env: EnvFileConfig = serde_json::from_value(serde_json::json!({
+            "from_spec": true,
+            "sources": [
+                { "config_map": { "name": "app-config" } },
+                { "secret": { "name": "db-credentials", "prefix": "DB_" } }
+            ]
+        }))
+        .unwrap();
+        let env = env
+            .generate_config(&mut ConfigContext::default().strict_env(true))
+            .unwrap();
+
+        assert!(env.from_spec);
+        assert_eq!(
+            env.sources.unwrap(),
+            vec![
+                EnvSource::ConfigMap {
+                    name: "app-config".to_string(),
+                    prefix: None,
+                },
+                EnvSource::Secret {
+                    name: "db-credentials".to_string(),
+                    prefix: Some("DB_".to_string()),
+                },
+            ]
+        );
+    }
 }
@@ -90,6 +90,9 @@ impl MirrordConfig for FsUserConfig {
                     .source_value(context)
                     .transpose()?,
                 not_found: None,
+                overlay: None,
+                overlay_dir: None,
+                overlay_export: None,
                 mapping: None,
                 readonly_file_buffer: READONLY_FILE_BUFFER_DEFAULT,
             },
@@ -119,6 +122,9 @@ impl MirrordToggleableConfig for FsUserConfig {
             read_only,
             local,
             not_found: None,
+            overlay: None,
+            overlay_dir: None,
+            overlay_export: None,
             mapping: None,
             readonly_file_buffer: READONLY_FILE_BUFFER_DEFAULT,
         })
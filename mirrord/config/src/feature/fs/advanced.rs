@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use mirrord_analytics::{AnalyticValue, CollectAnalytics};
 use mirrord_config_derive::MirrordConfig;
@@ -30,6 +30,7 @@ pub const READONLY_FILE_BUFFER_HARD_LIMIT: u64 = 15 * 1024 * 1024;
 /// 3. `"local"` or `false` - Read from the local file system.
 /// 4. `"localwithoverrides"` - perform fs operation locally, unless the path matches a pre-defined
 ///    or user-specified exception.
+/// 5. `"overlay"` - Read from the remote file system, but write to a local copy-on-write overlay.
 ///
 /// > Note: by default, some paths are read locally or remotely, regardless of the selected FS mode.
 /// > This is described in further detail below.
@@ -42,6 +43,8 @@ pub const READONLY_FILE_BUFFER_HARD_LIMIT: u64 = 15 * 1024 * 1024;
 /// 3. `"local"` - List of patterns that should be read locally.
 /// 4. `"not_found"` - List of patters that should never be read nor written. These files should be
 ///    treated as non-existent.
+/// 5. `"overlay"` - List of patterns that should be read remotely, but written to a local
+///    copy-on-write overlay.
 /// 4. `"mapping"` - Map of patterns and their corresponding replacers. The replacement happens before any specific behavior as defined above or mode (uses [`Regex::replace`](https://docs.rs/regex/latest/regex/struct.Regex.html#method.replace))
 ///
 /// The logic for choosing the behavior is as follows:
@@ -122,6 +125,44 @@ pub struct FsConfig {
     /// Specify file path patterns that if matched will be treated as non-existent.
     pub not_found: Option<VecOrSingle<String>>,
 
+    /// #### feature.fs.overlay {#feature-fs-overlay}
+    ///
+    /// Specify file path patterns that if matched will be read from the remote, but written to a
+    /// local copy-on-write overlay, so that the target's filesystem is never changed.
+    ///
+    /// The first time a remote file is changed, it is copied to
+    /// [`feature.fs.overlay_dir`](#feature-fs-overlay_dir), and from then on the local copy is
+    /// used instead. Removed files are not found anymore, even though they still exist in the
+    /// target. Directory listings come from the target, so files created in the overlay don't
+    /// show up in them. Renaming files between the overlay and the target fails with `EXDEV`, as
+    /// with separate filesystems.
+    ///
+    /// Setting [`feature.fs.mode`](#feature-fs-mode) to `"overlay"` does the same for all remote
+    /// paths that don't match [`feature.fs.read_write`](#feature-fs-read_write).
+    pub overlay: Option<VecOrSingle<String>>,
+
+    /// #### feature.fs.overlay_dir {#feature-fs-overlay_dir}
+    ///
+    /// Local directory for the [`feature.fs.overlay`](#feature-fs-overlay), remote paths are
+    /// stored under it with the same structure, e.g. `/app/config.yaml` is stored as
+    /// `<overlay_dir>/app/config.yaml`.
+    ///
+    /// The directory must be empty or not exist, and is removed at the end of the session, unless
+    /// [`feature.fs.overlay_export`](#feature-fs-overlay_export) is set.
+    ///
+    /// Defaults to a new temporary directory. With `mirrord container`, the directory is in the
+    /// user container.
+    pub overlay_dir: Option<PathBuf>,
+
+    /// #### feature.fs.overlay_export {#feature-fs-overlay_export}
+    ///
+    /// If set, the [`feature.fs.overlay`](#feature-fs-overlay) is moved to this directory at the
+    /// end of the session, so that the changes can be inspected. Removed files are marked with
+    /// empty `.wh.<name>` files.
+    ///
+    /// Not supported with `mirrord container`.
+    pub overlay_export: Option<PathBuf>,
+
     /// #### feature.fs.mapping {#feature-fs-mapping}
     ///
     /// Specify map of patterns that if matched will replace the path according to specification.
@@ -175,6 +216,9 @@ impl MirrordToggleableConfig for AdvancedFsUserConfig {
             read_only,
             local,
             not_found: None,
+            overlay: None,
+            overlay_dir: None,
+            overlay_export: None,
             mapping: None,
             readonly_file_buffer: READONLY_FILE_BUFFER_DEFAULT,
         })
@@ -194,6 +238,16 @@ impl FsConfig {
     pub fn is_active(&self) -> bool {
         !matches!(self.mode, FsModeConfig::Local)
     }
+
+    /// Checks if any remote paths are written to the local copy-on-write overlay.
+    pub fn uses_overlay(&self) -> bool {
+        self.is_active()
+            && (self.mode.is_overlay()
+                || self
+                    .overlay
+                    .as_deref()
+                    .is_some_and(|paths| !paths.is_empty()))
+    }
 }
 
 impl From<FsModeConfig> for AnalyticValue {
@@ -203,6 +257,7 @@ impl From<FsModeConfig> for AnalyticValue {
             FsModeConfig::LocalWithOverrides => Self::Number(1),
             FsModeConfig::Read => Self::Number(2),
             FsModeConfig::Write => Self::Number(3),
+            FsModeConfig::Overlay => Self::Number(4),
         }
     }
 }
@@ -235,6 +290,11 @@ impl CollectAnalytics for &FsConfig {
                 .map(<[_]>::len)
                 .unwrap_or_default(),
         );
+        analytics.add(
+            "overlay_paths",
+            self.overlay.as_deref().map(<[_]>::len).unwrap_or_default(),
+        );
+        analytics.add("overlay_export", self.overlay_export.is_some());
         analytics.add("readonly_file_buffer", self.readonly_file_buffer);
     }
 }
@@ -19,7 +19,8 @@ use crate::{
 /// can be read/write remotely based on our default/user specified.
 /// Default option for general file configuration.
 ///
-/// The accepted values are: `"local"`, `"localwithoverrides`, `"read"`, or `"write`.
+/// The accepted values are: `"local"`, `"localwithoverrides`, `"read"`, `"write"`, or
+/// `"overlay"`.
 #[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug, Copy, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "lowercase")]
 pub enum FsModeConfig {
@@ -43,6 +44,12 @@ pub enum FsModeConfig {
     ///
     /// mirrord will read/write from the remote.
     Write,
+
+    /// **feature.fs.mode.overlay** {#feature-fs-mode-overlay}
+    ///
+    /// mirrord will read files from the remote, but writes will go to a local copy-on-write
+    /// overlay, see [`feature.fs.overlay`](#feature-fs-overlay).
+    Overlay,
 }
 
 impl FsModeConfig {
@@ -57,6 +64,10 @@ impl FsModeConfig {
     pub fn is_write(self) -> bool {
         self == FsModeConfig::Write
     }
+
+    pub fn is_overlay(self) -> bool {
+        self == FsModeConfig::Overlay
+    }
 }
 
 impl FromStr for FsModeConfig {
@@ -68,6 +79,7 @@ impl FromStr for FsModeConfig {
             "localwithoverrides" => Ok(FsModeConfig::LocalWithOverrides),
             "read" => Ok(FsModeConfig::Read),
             "write" => Ok(FsModeConfig::Write),
+            "overlay" => Ok(FsModeConfig::Overlay),
             _ => Err(ConfigError::InvalidFsMode(s.to_string())),
         }
     }
@@ -1,5 +1,6 @@
-use std::ops::Deref;
+use std::{collections::BTreeMap, net::IpAddr, ops::Deref};
 
+use fancy_regex::Regex;
 use mirrord_analytics::CollectAnalytics;
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
@@ -11,6 +12,8 @@ use crate::{
     util::{MirrordToggleableConfig, VecOrSingle},
 };
 
+pub mod message;
+
 /// List of addresses/ports/subnets that should be resolved through either the remote pod or local
 /// app, depending how you set this up with either `remote` or `local`.
 ///
@@ -75,6 +78,102 @@ pub enum DnsFilterConfig {
     Local(VecOrSingle<String>),
 }
 
+/// Record returned for the hostnames matching a key of
+/// [`feature.network.dns.overrides`](#feature-network-dns-overrides).
+///
+/// Either a list of addresses:
+///
+/// ```json
+/// ["127.0.0.1", "::1"]
+/// ```
+///
+/// Or another hostname, that is resolved remotely instead:
+///
+/// ```json
+/// { "cname": "payments-v2.staging.svc.cluster.local" }
+/// ```
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum DnsOverrideConfig {
+    /// Addresses returned without asking the cluster.
+    Addresses(VecOrSingle<IpAddr>),
+
+    /// Hostname resolved remotely instead. Other overrides don't apply to it.
+    Cname { cname: String },
+}
+
+/// Compiled [`DnsConfig::overrides`], used to answer DNS queries before they're sent to the
+/// cluster.
+#[derive(Debug, Default)]
+pub struct DnsOverrides {
+    /// Overrides for exact hostnames, lowercase and without the trailing dot.
+    names: Vec<(String, DnsOverrideConfig)>,
+    /// Overrides with regex keys, checked when no exact hostname matches.
+    patterns: Vec<(Regex, DnsOverrideConfig)>,
+}
+
+impl DnsOverrides {
+    /// Keys made only of letters, digits, `.`, `-` and `_` are hostnames, other keys are regexes.
+    fn is_hostname(key: &str) -> bool {
+        key.bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'-' | b'_'))
+    }
+
+    pub fn new(overrides: &BTreeMap<String, DnsOverrideConfig>) -> Result<Self, ConfigError> {
+        let (compiled, errors) = Self::new_lossy(overrides);
+
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(compiled),
+        }
+    }
+
+    /// Like [`Self::new`], but leaves out the overrides with invalid regular expressions, and
+    /// returns their errors instead.
+    pub fn new_lossy(overrides: &BTreeMap<String, DnsOverrideConfig>) -> (Self, Vec<ConfigError>) {
+        let mut compiled = Self::default();
+        let mut errors = Vec::new();
+
+        for (key, value) in overrides {
+            if Self::is_hostname(key) {
+                let name = key.trim_end_matches('.').to_ascii_lowercase();
+                compiled.names.push((name, value.clone()));
+                continue;
+            }
+
+            match Regex::new(key) {
+                Ok(regex) => compiled.patterns.push((regex, value.clone())),
+                Err(error) => errors.push(ConfigError::InvalidValue {
+                    name: "feature.network.dns.overrides",
+                    provided: key.clone(),
+                    error: Box::new(error),
+                }),
+            }
+        }
+
+        (compiled, errors)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Returns the override for the given hostname, if any.
+    pub fn resolve(&self, node: &str) -> Option<&DnsOverrideConfig> {
+        let node = node.trim_end_matches('.');
+
+        self.names
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(node))
+            .or_else(|| {
+                self.patterns
+                    .iter()
+                    .find(|(regex, _)| regex.is_match(node).unwrap_or(false))
+            })
+            .map(|(_, value)| value)
+    }
+}
+
 /// Resolve DNS via the remote pod.
 ///
 /// Defaults to `true`.
@@ -99,10 +198,46 @@ pub struct DnsConfig {
     /// Unstable: the precise syntax of this config is subject to change.
     #[config(default, unstable)]
     pub filter: Option<DnsFilterConfig>,
+
+    /// **feature.network.dns.overrides** {#feature-network-dns-overrides}
+    ///
+    /// Answers for specific hostnames, returned instead of resolving them in the cluster.
+    ///
+    /// Maps hostnames to a list of addresses, or to another hostname that is resolved remotely
+    /// instead (like a CNAME record). Keys made only of letters, digits, `.`, `-` and `_` match
+    /// that exact hostname, other keys are regular expressions. Exact hostnames are checked
+    /// first, then the regular expressions in alphabetical order.
+    ///
+    /// On Linux and macOS, overrides apply to `getaddrinfo`/`gethostbyname`, even for hostnames
+    /// that [`feature.network.dns.filter`](#feature-network-dns-filter) resolves locally, and to
+    /// the hostnames in outgoing filters. Go programs are made to resolve through `getaddrinfo`
+    /// (with `GODEBUG=netdns=cgo`). Queries sent directly to the nameserver on UDP port `53`
+    /// (e.g. by Go programs built with `CGO_ENABLED=0`) are answered by the internal proxy, and
+    /// `mirrord vpn` answers the overridden queries itself. These match the queried name as is,
+    /// including the search domain appended by the resolver.
+    ///
+    /// ```json
+    /// {
+    ///   "overrides": {
+    ///     "payments": ["127.0.0.1"],
+    ///     "^.*\\.legacy\\.svc$": { "cname": "gateway.default.svc.cluster.local" }
+    ///   }
+    /// }
+    /// ```
+    #[config(default)]
+    pub overrides: BTreeMap<String, DnsOverrideConfig>,
 }
 
 impl DnsConfig {
     pub fn verify(&self, context: &mut ConfigContext) -> Result<(), ConfigError> {
+        if !self.overrides.is_empty() && !self.enabled {
+            context.add_warning(
+                "Remote DNS resolution is disabled, provided DNS overrides will be ignored"
+                    .to_string(),
+            );
+        }
+        DnsOverrides::new(&self.overrides)?;
+
         let filters = match &self.filter {
             Some(..) if !self.enabled => {
                 context.add_warning(
@@ -160,6 +295,7 @@ impl MirrordToggleableConfig for DnsFileConfig {
 impl CollectAnalytics for &DnsConfig {
     fn collect_analytics(&self, analytics: &mut mirrord_analytics::Analytics) {
         analytics.add("enabled", self.enabled);
+        analytics.add("overrides", self.overrides.len());
 
         if let Some(filter) = self.filter.as_ref() {
             match filter {
@@ -170,3 +306,70 @@ impl CollectAnalytics for &DnsConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let overrides = [
+            (
+                "payments".to_string(),
+                DnsOverrideConfig::Addresses(VecOrSingle::Single("127.0.0.1".parse().unwrap())),
+            ),
+            (
+                "^.*\\.legacy$".to_string(),
+                DnsOverrideConfig::Cname {
+                    cname: "gateway".to_string(),
+                },
+            ),
+            (
+                "^payments.*".to_string(),
+                DnsOverrideConfig::Cname {
+                    cname: "other".to_string(),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let overrides = DnsOverrides::new(&overrides).unwrap();
+
+        assert!(matches!(
+            overrides.resolve("Payments."),
+            Some(DnsOverrideConfig::Addresses(..))
+        ));
+        assert!(matches!(
+            overrides.resolve("db.legacy"),
+            Some(DnsOverrideConfig::Cname { cname }) if cname == "gateway"
+        ));
+        assert!(matches!(
+            overrides.resolve("payments-api"),
+            Some(DnsOverrideConfig::Cname { cname }) if cname == "other"
+        ));
+        assert!(overrides.resolve("legacy").is_none());
+    }
+    #[test]
+    fn invalid_overrides() {
+        let overrides = [
+            (
+                "payments".to_string(),
+                DnsOverrideConfig::Addresses(VecOrSingle::Single("127.0.0.1".parse().unwrap())),
+            ),
+            (
+                "^(unclosed".to_string(),
+                DnsOverrideConfig::Cname {
+                    cname: "gateway".to_string(),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(DnsOverrides::new(&overrides).is_err());
+
+        let (overrides, errors) = DnsOverrides::new_lossy(&overrides);
+        assert_eq!(errors.len(), 1);
+        assert!(overrides.resolve("payments").is_some());
+    }
+}
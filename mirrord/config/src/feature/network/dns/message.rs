@@ -0,0 +1,278 @@
+//! Just enough of the DNS message format to answer queries with [`DnsOverrides`], used where
+//! queries are sent directly to the nameserver, e.g. by Go programs built with `CGO_ENABLED=0`,
+//! or through `mirrord vpn`.
+//!
+//! [`DnsOverrides`]: super::DnsOverrides
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const DNS_HEADER_LEN: usize = 12;
+
+const DNS_TYPE_A: u16 = 1;
+const DNS_TYPE_CNAME: u16 = 5;
+const DNS_TYPE_AAAA: u16 = 28;
+const DNS_CLASS_IN: u16 = 1;
+
+/// TTL of the records we synthesize.
+const DNS_TTL: u32 = 30;
+/// Compression pointer to the name in the question, which always follows the header.
+const QUESTION_NAME_POINTER: [u8; 2] = [0xC0, DNS_HEADER_LEN as u8];
+
+/// A DNS query with a single question.
+#[derive(Debug, Clone)]
+pub struct DnsQuery {
+    id: u16,
+    /// Whether the query asked for recursion, copied to the response.
+    recursion_desired: bool,
+    name: String,
+    record_type: u16,
+    /// The question section, copied to the response.
+    question: Vec<u8>,
+}
+
+impl DnsQuery {
+    /// Parses a DNS message, returns [`None`] if it's not a standard query with a single
+    /// question.
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let header = message.get(..DNS_HEADER_LEN)?;
+        let id = u16::from_be_bytes([header[0], header[1]]);
+        // Only standard queries (QR = 0, OPCODE = 0) with a single question.
+        let is_query = header[2] & 0xF8 == 0;
+        let question_count = u16::from_be_bytes([header[4], header[5]]);
+        if !is_query || question_count != 1 {
+            return None;
+        }
+
+        let mut labels = Vec::new();
+        let mut offset = DNS_HEADER_LEN;
+        loop {
+            let length = usize::from(*message.get(offset)?);
+            offset += 1;
+            if length == 0 {
+                break;
+            }
+            // Compression pointers are not expected in queries.
+            if length > 63 {
+                return None;
+            }
+            labels.push(std::str::from_utf8(message.get(offset..offset + length)?).ok()?);
+            offset += length;
+        }
+        let record_type = message.get(offset..offset + 4)?;
+        let record_type = u16::from_be_bytes([record_type[0], record_type[1]]);
+
+        Some(Self {
+            id,
+            recursion_desired: header[2] & 0x01 != 0,
+            name: labels.join("."),
+            record_type,
+            question: message[DNS_HEADER_LEN..offset + 4].to_vec(),
+        })
+    }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Queried hostname, without the trailing dot.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether this query asks for addresses (`A` or `AAAA` records).
+    pub fn is_address_query(&self) -> bool {
+        matches!(self.record_type, DNS_TYPE_A | DNS_TYPE_AAAA)
+    }
+
+    /// Builds the same query for another hostname, keeping its id.
+    pub fn with_name(&self, name: &str) -> Option<Vec<u8>> {
+        let name = encode_name(name)?;
+        // Type and class of the original question.
+        let type_and_class = &self.question[self.question.len() - 4..];
+
+        let mut message = Vec::with_capacity(DNS_HEADER_LEN + name.len() + 4);
+        message.extend(self.id.to_be_bytes());
+        message.push(u8::from(self.recursion_desired));
+        message.push(0);
+        message.extend(1u16.to_be_bytes());
+        message.extend([0; 6]);
+        message.extend(name);
+        message.extend(type_and_class);
+
+        Some(message)
+    }
+
+    /// Builds the response to this query.
+    ///
+    /// The answer contains the `cname` record, if given, followed by the `addresses` of the
+    /// queried type.
+    pub fn response(&self, cname: Option<&str>, addresses: &[IpAddr]) -> Option<Vec<u8>> {
+        let mut answers = Vec::new();
+        let mut answer_count = 0u16;
+        let mut owner = QUESTION_NAME_POINTER;
+
+        if let Some(cname) = cname {
+            let target = encode_name(cname)?;
+            // Subsequent records belong to the CNAME target, which starts after this record's
+            // fixed fields.
+            let target_offset = DNS_HEADER_LEN + self.question.len() + answers.len() + 12;
+            owner = (0xC000 | u16::try_from(target_offset).ok()?).to_be_bytes();
+
+            answers.extend(QUESTION_NAME_POINTER);
+            answers.extend(DNS_TYPE_CNAME.to_be_bytes());
+            answers.extend(DNS_CLASS_IN.to_be_bytes());
+            answers.extend(DNS_TTL.to_be_bytes());
+            answers.extend((target.len() as u16).to_be_bytes());
+            answers.extend(target);
+            answer_count += 1;
+        }
+
+        for address in addresses {
+            let (record_type, data) = match address {
+                IpAddr::V4(address) => (DNS_TYPE_A, address.octets().to_vec()),
+                IpAddr::V6(address) => (DNS_TYPE_AAAA, address.octets().to_vec()),
+            };
+            if record_type != self.record_type {
+                continue;
+            }
+
+            answers.extend(owner);
+            answers.extend(record_type.to_be_bytes());
+            answers.extend(DNS_CLASS_IN.to_be_bytes());
+            answers.extend(DNS_TTL.to_be_bytes());
+            answers.extend((data.len() as u16).to_be_bytes());
+            answers.extend(data);
+            answer_count += 1;
+        }
+
+        let mut message = Vec::with_capacity(DNS_HEADER_LEN + self.question.len() + answers.len());
+        message.extend(self.id.to_be_bytes());
+        // QR, AA, RD copied from the query, RA, RCODE = 0.
+        message.push(0x84 | u8::from(self.recursion_desired));
+        message.push(0x80);
+        message.extend(1u16.to_be_bytes());
+        message.extend(answer_count.to_be_bytes());
+        message.extend([0; 4]);
+        message.extend(&self.question);
+        message.extend(answers);
+
+        Some(message)
+    }
+}
+
+/// Parses a DNS response, returns its id and the addresses from its `A` and `AAAA` records.
+pub fn response_addresses(message: &[u8]) -> Option<(u16, Vec<IpAddr>)> {
+    let header = message.get(..DNS_HEADER_LEN)?;
+    let id = u16::from_be_bytes([header[0], header[1]]);
+    if header[2] & 0x80 == 0 {
+        return None;
+    }
+    let question_count = u16::from_be_bytes([header[4], header[5]]);
+    let answer_count = u16::from_be_bytes([header[6], header[7]]);
+
+    let mut offset = DNS_HEADER_LEN;
+    for _ in 0..question_count {
+        offset = skip_name(message, offset)? + 4;
+    }
+
+    let mut addresses = Vec::new();
+    for _ in 0..answer_count {
+        offset = skip_name(message, offset)?;
+        let fields = message.get(offset..offset + 10)?;
+        let record_type = u16::from_be_bytes([fields[0], fields[1]]);
+        let data_length = usize::from(u16::from_be_bytes([fields[8], fields[9]]));
+        offset += 10;
+        let data = message.get(offset..offset + data_length)?;
+        offset += data_length;
+
+        match record_type {
+            DNS_TYPE_A => {
+                addresses.push(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data).ok()?)))
+            }
+            DNS_TYPE_AAAA => {
+                addresses.push(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?)))
+            }
+            _ => {}
+        }
+    }
+
+    Some((id, addresses))
+}
+
+/// Returns the offset right after the (possibly compressed) name starting at `offset`.
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(offset)?;
+        match length {
+            0 => return Some(offset + 1),
+            // Compression pointer, ends the name.
+            length if length & 0xC0 == 0xC0 => return Some(offset + 2),
+            length => offset += 1 + usize::from(length),
+        }
+    }
+}
+
+/// Encodes a hostname as DNS labels.
+fn encode_name(name: &str) -> Option<Vec<u8>> {
+    let mut encoded = Vec::with_capacity(name.len() + 2);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return None;
+        }
+        encoded.push(label.len() as u8);
+        encoded.extend(label.as_bytes());
+    }
+    encoded.push(0);
+
+    Some(encoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A query for `payments.svc` `A` records, with id `7`.
+    fn query() -> Vec<u8> {
+        let mut message = vec![0, 7, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        message.extend(encode_name("payments.svc").unwrap());
+        message.extend(DNS_TYPE_A.to_be_bytes());
+        message.extend(DNS_CLASS_IN.to_be_bytes());
+        message
+    }
+
+    #[test]
+    fn parse_query() {
+        let query = DnsQuery::parse(&query()).unwrap();
+        assert_eq!(query.id(), 7);
+        assert_eq!(query.name(), "payments.svc");
+        assert!(query.is_address_query());
+
+        let rewritten = DnsQuery::parse(&query.with_name("gateway.default.svc").unwrap()).unwrap();
+        assert_eq!(rewritten.id(), 7);
+        assert_eq!(rewritten.name(), "gateway.default.svc");
+        assert!(rewritten.is_address_query());
+    }
+
+    /// Responses we build can be read back, and only contain the records of the queried type.
+    #[test]
+    fn response_roundtrip() {
+        let query = DnsQuery::parse(&query()).unwrap();
+        let addresses = ["10.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+
+        let response = query.response(None, &addresses).unwrap();
+        assert_eq!(
+            response_addresses(&response),
+            Some((7, vec!["10.0.0.1".parse().unwrap()]))
+        );
+
+        let response = query
+            .response(Some("gateway.default.svc"), &addresses)
+            .unwrap();
+        assert_eq!(
+            response_addresses(&response),
+            Some((7, vec!["10.0.0.1".parse().unwrap()]))
+        );
+
+        assert!(response_addresses(&query.with_name("gateway").unwrap()).is_none());
+    }
+}
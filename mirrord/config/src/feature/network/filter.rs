@@ -184,6 +184,22 @@ pub struct ProtocolAndAddressFilter {
     pub address: AddressFilter,
 }
 
+impl ProtocolAndAddressFilter {
+    /// Returns the hostname of this filter, if it can match a TCP connection to the given `port`
+    /// by its TLS SNI or HTTP `Host`.
+    pub fn inspected_hostname(&self, port: u16) -> Option<&str> {
+        match (&self.protocol, &self.address) {
+            (ProtocolFilter::Udp, _) => None,
+            (_, AddressFilter::Name(name, filter_port))
+                if *filter_port == 0 || *filter_port == port =>
+            {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ProtocolAndAddressFilterError {
     #[error(transparent)]
@@ -448,6 +464,21 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[case("tcp://google.com:443", 443, Some("google.com"))]
+    #[case("google.com", 8080, Some("google.com"))]
+    #[case("google.com:443", 80, None)]
+    #[case("udp://google.com", 443, None)]
+    #[case("1.2.3.4:443", 443, None)]
+    fn inspected_hostnames(
+        #[case] input: &'static str,
+        #[case] port: u16,
+        #[case] expected: Option<&str>,
+    ) {
+        let filter = ProtocolAndAddressFilter::from_str(input).unwrap();
+        assert_eq!(filter.inspected_hostname(port), expected);
+    }
+
     #[rstest]
     #[case(name_with_subnet())]
     #[case(port_protocol())]
@@ -131,6 +131,26 @@ pub struct OutgoingConfig {
     #[config(default)]
     pub filter: Option<OutgoingFilterConfig>,
 
+    /// **feature.network.outgoing.inspect_hostnames**
+    /// {#feature.network.outgoing.inspect_hostnames}
+    ///
+    /// Hostname filters in [`filter`](#feature.network.outgoing.filter) normally match only the
+    /// addresses the hostname resolves to when the application calls `connect`. This breaks when
+    /// the application resolves the name once and reuses the addresses, and can't tell apart
+    /// virtual hosts that share an address.
+    ///
+    /// When enabled, a TCP connection that doesn't match any filter by its address, but could
+    /// match a hostname filter by its port, is first inspected by mirrord: if the TLS SNI or the
+    /// HTTP `Host` header sent by the application is one of the hostnames, the connection is
+    /// treated as matching the filter.
+    ///
+    /// Connections where the server speaks first are not inspected, and after a short wait are
+    /// treated as not matching.
+    ///
+    /// Defaults to `false`.
+    #[config(default = false)]
+    pub inspect_hostnames: bool,
+
     /// **feature.network.outgoing.unix_streams** {#feature.network.outgoing.unix_streams}
     ///
     /// Connect to these unix streams remotely (and to all other paths locally).
@@ -170,6 +190,7 @@ impl CollectAnalytics for &OutgoingConfig {
         analytics.add("tcp", self.tcp);
         analytics.add("udp", self.udp);
         analytics.add("ignore_localhost", self.ignore_localhost);
+        analytics.add("inspect_hostnames", self.inspect_hostnames);
         analytics.add(
             "unix_streams",
             self.unix_streams
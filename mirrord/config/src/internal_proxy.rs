@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use mirrord_config_derive::MirrordConfig;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -118,6 +120,24 @@ pub struct InternalProxyConfig {
     #[config(default = true)]
     pub json_log: bool,
 
+    /// ### internal_proxy.otlp_endpoint {#internal_proxy-otlp_endpoint}
+    ///
+    /// Enables OpenTelemetry trace export from the internal proxy, using OTLP over gRPC.
+    ///
+    /// When the agent also exports traces (see [`agent.otlp_endpoint`](#agent-otlp_endpoint)),
+    /// spans of the internal proxy continue the agent's trace of each stolen or mirrored HTTP
+    /// request, and the trace context is passed to the local application in the `traceparent`
+    /// header.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "otlp_endpoint": "http://localhost:4317"
+    ///   }
+    /// }
+    /// ```
+    pub otlp_endpoint: Option<String>,
+
     /// ### internal_proxy.process_logging_interval {#internal_proxy-process_logging_interval}
     ///
     /// How often to log information about connected processes in seconds.
@@ -134,4 +154,41 @@ pub struct InternalProxyConfig {
     /// ```
     #[config(default = 60)]
     pub process_logging_interval: u64,
+
+    /// <!--${internal}-->
+    ///
+    /// How long the internal proxy keeps the port subscriptions of a closed process in the agent,
+    /// in seconds. A process that subscribes to the same ports within this period reuses them,
+    /// without waiting for the agent.
+    ///
+    /// Set by `mirrord exec --watch`, which restarts the user process on file changes.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "port_subscription_retention": 30
+    ///   }
+    /// }
+    /// ```
+    #[config(default = 0)]
+    pub port_subscription_retention: u64,
+
+    /// ### internal_proxy.capture_outgoing {#internal_proxy-capture_outgoing}
+    ///
+    /// Path of a pcap-ng file where the internal proxy writes the traffic of all outgoing
+    /// connections made through the agent, to be opened with tools like Wireshark.
+    ///
+    /// The packets are synthesized with the real in-cluster addresses of the connections, as
+    /// mirrord doesn't see the actual packets sent by the agent.
+    ///
+    /// Also set by `mirrord exec --capture-outgoing <PATH>`.
+    ///
+    /// ```json
+    /// {
+    ///   "internal_proxy": {
+    ///     "capture_outgoing": "/tmp/outgoing.pcapng"
+    ///   }
+    /// }
+    /// ```
+    pub capture_outgoing: Option<PathBuf>,
 }
@@ -15,15 +15,22 @@ pub mod config;
 pub mod container;
 pub mod env_key;
 pub mod experimental;
+pub mod extends;
 pub mod external_proxy;
 pub mod feature;
 pub mod internal_proxy;
 pub mod logfile_path;
+pub mod patch;
 pub mod retry;
 pub mod target;
 pub mod util;
 
-use std::{collections::HashMap, ffi::OsStr, ops::Not, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    ops::Not,
+    path::Path,
+};
 
 use base64::prelude::*;
 use config::{ConfigContext, ConfigError, MirrordConfig};
@@ -41,7 +48,6 @@ use mirrord_protocol::tcp::JsonPathQuery;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use target::Target;
-use tera::Tera;
 use tracing::warn;
 
 use crate::{
@@ -50,6 +56,7 @@ use crate::{
     config::{FromFileError, source::MirrordConfigSource},
     container::ContainerConfig,
     env_key::EnvKey,
+    extends::MergedConfigFile,
     external_proxy::ExternalProxyConfig,
     feature::{
         FeatureConfig,
@@ -266,6 +273,26 @@ pub struct LayerConfig {
     #[config(env = "MIRRORD_OPERATOR_ENABLE")]
     pub operator: Option<bool>,
 
+    /// ## extends {#root-extends}
+    ///
+    /// Paths of config files that this config file builds upon.
+    ///
+    /// Relative paths are resolved against the directory of the extending file, `file://` URLs
+    /// are also accepted. The files are deep-merged in order, and the extending file is merged
+    /// last: objects are merged key by key, while any other value (including arrays) replaces the
+    /// inherited one. Extended files can extend other files, but not in a cycle.
+    ///
+    /// ```json
+    /// {
+    ///   "extends": ["../base.json", "./team.yaml"],
+    ///   "target": "deployment/my-app"
+    /// }
+    /// ```
+    ///
+    /// Use `mirrord verify-config --show-merged <path>` to see the result of the merge, and the
+    /// file that each value comes from.
+    pub extends: Option<VecOrSingle<String>>,
+
     /// ## profile {#root-profile}
     ///
     /// Name of the mirrord profile to use.
@@ -483,6 +510,43 @@ impl LayerConfig {
         }
     }
 
+    /// Same as [`LayerConfig::resolve`], but applies the JSON merge `patch` to the config file
+    /// before the config is generated, see [`patch`].
+    ///
+    /// The patch takes precedence over the config file (and the files it extends), while the
+    /// environment variables (and so the CLI flags) still take precedence over the patch.
+    ///
+    /// With the `patch-validation` feature, the config file is validated against the config
+    /// schema before and after the patch is applied, so that [`ConfigError::InvalidPatch`] is
+    /// returned only when it's the patch that makes the config invalid.
+    ///
+    /// The config is generated from scratch, so warnings previously stored in the context are
+    /// dropped.
+    pub fn resolve_with_patch(
+        context: &mut ConfigContext,
+        patch: &serde_json::Value,
+    ) -> Result<Self, ConfigError> {
+        patch::check_patch(patch)?;
+
+        let mut file = if let Ok(path) = context.get_env(Self::FILE_PATH_ENV) {
+            LayerFileConfig::value_from_path(path, context)?
+        } else {
+            serde_json::Value::Object(Default::default())
+        };
+        #[cfg(feature = "patch-validation")]
+        patch::validate(&file).map_err(ConfigError::InvalidConfigFile)?;
+
+        patch::merge_patch(&mut file, patch);
+
+        #[cfg(feature = "patch-validation")]
+        patch::validate(&file).map_err(ConfigError::InvalidPatch)?;
+
+        context.clear_warnings();
+        serde_json::from_value::<LayerFileConfig>(file)
+            .map_err(FromFileError::from)?
+            .generate_config(context)
+    }
+
     /// Verifies that there are no conflicting settings in this config.
     ///
     /// Fills the given [`ConfigContext`] with warnings.
@@ -611,8 +675,17 @@ impl LayerConfig {
             Err(ConfigError::TargetJobWithoutCopyTarget)?
         }
 
+        if self.target.path.is_some() && self.target.selector.is_some() {
+            return Err(ConfigError::Conflict(
+                "`target.path` and `target.selector` cannot be used together, \
+                please specify only one of them."
+                    .into(),
+            ));
+        }
+
         let is_targetless = match self.target.path.as_ref() {
             Some(Target::Targetless) => true,
+            None if self.target.selector.is_some() => false,
             None => context.is_empty_target_final().not(),
             _ => false,
         };
@@ -666,6 +739,17 @@ impl LayerConfig {
                 ));
             }
 
+            if self.feature.copy_target.scale_down
+                && matches!(self.target.path, Some(Target::DaemonSet(..)))
+            {
+                return Err(ConfigError::Conflict(
+                    "DaemonSets cannot be scaled down, \
+                    please either disable the `scale_down` option of the copy target feature \
+                    or specify a different target."
+                        .into(),
+                ));
+            }
+
             if !self.feature.network.incoming.is_steal() {
                 context.add_warning(
                     "Using copy target feature without steal mode \
@@ -688,6 +772,43 @@ impl LayerConfig {
             return Err(ConfigError::TargetRequiresOperator);
         }
 
+        let mut additional_ports = HashSet::new();
+        for additional in &self.target.additional {
+            if matches!(additional.path, Target::Targetless) {
+                return Err(ConfigError::Conflict(
+                    "An additional target cannot be targetless, \
+                    please specify a Kubernetes resource in `target.additional.path`."
+                        .into(),
+                ));
+            }
+
+            if additional.ports.is_empty() {
+                return Err(ConfigError::Conflict(format!(
+                    "Additional target `{}` has no ports, \
+                    please specify the ports of the incoming traffic in `target.additional.ports`.",
+                    additional.path
+                )));
+            }
+
+            if let Some(port) = additional
+                .ports
+                .iter()
+                .find(|port| !additional_ports.insert(**port))
+            {
+                return Err(ConfigError::Conflict(format!(
+                    "Port {port} is listed in more than one additional target."
+                )));
+            }
+
+            if !self.feature.copy_target.enabled && additional.path.requires_copy() {
+                Err(ConfigError::TargetJobWithoutCopyTarget)?
+            }
+
+            if additional.path.requires_operator() && !self.operator.unwrap_or(true) {
+                return Err(ConfigError::TargetRequiresOperator);
+            }
+        }
+
         if self
             .feature
             .network
@@ -819,33 +940,69 @@ impl LayerFileConfig {
     ///
     /// The marker prefix on auto-generated keys allows `generate_config` to distinguish them
     /// from user-provided keys (see [`EnvKey::AUTOGENERATED_MARKER`] for details).
+    ///
+    /// # Extended Files
+    ///
+    /// If the file has an [`extends`](LayerConfig::extends) key, it's deep-merged with the files
+    /// it extends, see [`MergedConfigFile`]. All the files are rendered with the same key.
     pub fn from_path<P>(path: P, context: &mut ConfigContext) -> Result<Self, FromFileError>
     where
         P: AsRef<Path>,
     {
+        let value = Self::value_from_path(path, context)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Same as [`LayerFileConfig::from_path`], but returns the config file (deep-merged with the
+    /// files it extends) without deserializing it.
+    pub fn value_from_path<P>(
+        path: P,
+        context: &mut ConfigContext,
+    ) -> Result<serde_json::Value, FromFileError>
+    where
+        P: AsRef<Path>,
+    {
+        let key = Self::resolve_key(path.as_ref(), context);
+        let value =
+            extends::parse_value(path.as_ref(), &extends::render_file(path.as_ref(), &key)?)?;
+
+        if value
+            .get(extends::EXTENDS_KEY)
+            .is_some_and(|extends| !extends.is_null())
+        {
+            Ok(MergedConfigFile::load_parsed(path.as_ref(), &key, Some(value))?.config)
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Loads the config file from `path` deep-merged with the files it extends, without
+    /// deserializing it.
+    ///
+    /// Used to show the user where the values of the config come from.
+    pub fn merged_from_path<P>(
+        path: P,
+        context: &mut ConfigContext,
+    ) -> Result<MergedConfigFile, FromFileError>
+    where
+        P: AsRef<Path>,
+    {
+        let key = Self::resolve_key(path.as_ref(), context);
+        MergedConfigFile::load(path.as_ref(), &key)
+    }
+
+    /// Resolves the key for template rendering, and stores it in the context, see
+    /// [`LayerFileConfig::from_path`].
+    fn resolve_key(path: &Path, context: &mut ConfigContext) -> String {
         let key = context
             .get_env(env_key::MIRRORD_ENV_KEY)
             .ok()
-            .or_else(|| Self::extract_key_from_file(path.as_ref()))
+            .or_else(|| Self::extract_key_from_file(path))
             .unwrap_or_else(EnvKey::autogenerated_with_marker);
 
         context.override_env_mut(env_key::MIRRORD_ENV_KEY, &key);
 
-        let mut template_engine = Tera::default();
-        template_engine.add_template_file(path.as_ref(), Some("main"))?;
-
-        let mut tera_context = tera::Context::new();
-        tera_context.insert("key", &key);
-
-        let rendered = template_engine.render("main", &tera_context)?;
-
-        match path.as_ref().extension().and_then(OsStr::to_str) {
-            // No Extension? assume json
-            Some("json") | None => Ok(serde_json::from_str::<Self>(&rendered)?),
-            Some("toml") => Ok(toml::from_str::<Self>(&rendered)?),
-            Some("yaml" | "yml") => Ok(serde_yaml::from_str::<Self>(&rendered)?),
-            ext => Err(FromFileError::InvalidExtension(ext.map(String::from))),
-        }
+        key
     }
 
     /// Extracts just the `key` field from a config file without template rendering.
@@ -892,6 +1049,7 @@ mod tests {
     use rstest::*;
     use schemars::schema::RootSchema;
     use tempfile::NamedTempFile;
+    use tera::Tera;
 
     use super::*;
     use crate::{
@@ -1131,6 +1289,8 @@ mod tests {
                     container: None,
                 })),
                 namespace: Some("default".to_owned()),
+                additional: Vec::new(),
+                selector: None,
             }),
             skip_processes: None,
             skip_extra_build_tools: None,
@@ -1190,6 +1350,7 @@ mod tests {
             }),
             container: None,
             operator: None,
+            extends: None,
             profile: None,
             sip_binaries: None,
             kube_context: None,
@@ -1437,4 +1598,44 @@ mod tests {
 
         assert_eq!(pod_target.pod, "test-my-session");
     }
+
+    /// Additional targets must not be targetless, and must not share ports.
+    #[rstest]
+    #[case(r#"[{ "path": "pod/worker", "ports": [9090] }]"#, true)]
+    #[case(r#"[{ "path": "targetless", "ports": [9090] }]"#, false)]
+    #[case(r#"[{ "path": "pod/worker", "ports": [] }]"#, false)]
+    #[case(
+        r#"[{ "path": "pod/worker", "ports": [9090] }, { "path": "pod/other", "ports": [9090] }]"#,
+        false
+    )]
+    fn verify_additional_targets(#[case] additional: &str, #[case] valid: bool) {
+        let config =
+            format!(r#"{{ "target": {{ "path": "pod/api", "additional": {additional} }} }}"#);
+
+        let mut cfg_context = ConfigContext::default().strict_env(true);
+        let config = ConfigType::Json
+            .parse(&config)
+            .generate_config(&mut cfg_context)
+            .unwrap();
+
+        assert_eq!(config.verify(&mut cfg_context).is_ok(), valid);
+    }
+
+    /// A label selector replaces `target.path`, and makes the run targeted.
+    #[rstest]
+    #[case(r#"{ "selector": { "labels": "app=api" } }"#, true)]
+    #[case(r#"{ "path": "pod/api", "selector": { "labels": "app=api" } }"#, false)]
+    fn verify_target_selector(#[case] target: &str, #[case] valid: bool) {
+        let config = format!(
+            r#"{{ "target": {target}, "feature": {{ "network": {{ "incoming": "steal" }} }} }}"#
+        );
+
+        let mut cfg_context = ConfigContext::default().strict_env(true);
+        let config = ConfigType::Json
+            .parse(&config)
+            .generate_config(&mut cfg_context)
+            .unwrap();
+
+        assert_eq!(config.verify(&mut cfg_context).is_ok(), valid);
+    }
 }
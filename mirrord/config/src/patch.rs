@@ -0,0 +1,205 @@
+//! JSON merge patches ([RFC 7386](https://datatracker.ietf.org/doc/html/rfc7386)) applied to the
+//! config file before the config is generated, see [`LayerConfig::resolve_with_patch`].
+//!
+//! Used by mirrord profiles to make arbitrary changes in the user's config.
+//!
+//! [`LayerConfig::resolve_with_patch`]: crate::LayerConfig::resolve_with_patch
+
+use serde_json::Value;
+
+#[cfg(feature = "patch-validation")]
+use crate::LayerFileConfig;
+use crate::{config::ConfigError, extends::EXTENDS_KEY};
+
+/// Root keys that cannot be changed with a patch, as they're processed before the patch is
+/// applied.
+const FORBIDDEN_KEYS: [&str; 2] = ["profile", EXTENDS_KEY];
+
+/// Applies the merge `patch` to `target`.
+///
+/// Objects are merged key by key, `null`s remove the value from `target`, and any other value
+/// replaces the value in `target` (arrays are not merged).
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        return;
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Verifies that the `patch` does not touch any of the [`FORBIDDEN_KEYS`].
+pub fn check_patch(patch: &Value) -> Result<(), ConfigError> {
+    match FORBIDDEN_KEYS.iter().find(|key| patch.get(key).is_some()) {
+        Some(key) => Err(ConfigError::InvalidPatch(format!(
+            "the patch cannot change `{key}`"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Validates the config file against the [`LayerFileConfig`] schema, the same one that we publish
+/// as `mirrord-schema.json`.
+///
+/// Returns the validation errors as a single message.
+///
+/// Only with the `patch-validation` feature, so that the layer does not link the validator.
+#[cfg(feature = "patch-validation")]
+pub fn validate(config: &Value) -> Result<(), String> {
+    let schema = serde_json::to_value(schemars::schema_for!(LayerFileConfig))
+        .map_err(|error| error.to_string())?;
+    let validator = jsonschema::validator_for(&schema).map_err(|error| error.to_string())?;
+
+    let errors = validator
+        .iter_errors(config)
+        .map(|error| format!("`{}`: {error}", error.instance_path))
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        LayerConfig,
+        config::ConfigContext,
+        feature::{fs::FsModeConfig, network::incoming::IncomingMode},
+    };
+
+    /// Examples from the RFC.
+    #[test]
+    fn rfc_examples() {
+        let mut target = json!({
+            "title": "Goodbye!",
+            "author": { "givenName": "John", "familyName": "Doe" },
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        let patch = json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-123-456-7890",
+            "author": { "familyName": null },
+            "tags": ["example"]
+        });
+
+        merge_patch(&mut target, &patch);
+
+        assert_eq!(
+            target,
+            json!({
+                "title": "Hello!",
+                "author": { "givenName": "John" },
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+
+        let mut target = json!({ "a": "foo" });
+        merge_patch(&mut target, &json!({ "a": { "b": "c" } }));
+        assert_eq!(target, json!({ "a": { "b": "c" } }));
+
+        let mut target = json!(["a", "b"]);
+        merge_patch(&mut target, &json!({ "a": "b", "c": null }));
+        assert_eq!(target, json!({ "a": "b" }));
+    }
+
+    #[test]
+    fn forbidden_keys() {
+        assert!(check_patch(&json!({ "feature": { "fs": "read" } })).is_ok());
+        assert!(check_patch(&json!({ "profile": "other" })).is_err());
+        assert!(check_patch(&json!({ "extends": "other.json" })).is_err());
+    }
+
+    #[cfg(feature = "patch-validation")]
+    #[test]
+    fn validation() {
+        validate(&json!({
+            "feature": {
+                "fs": { "mode": "read", "read_write": ["/tmp/.+"] },
+                "network": { "incoming": { "ignore_ports": [9999] } }
+            }
+        }))
+        .unwrap();
+
+        let error = validate(&json!({ "feature": { "fs": { "mode": "everything" } } }))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("/feature/fs"), "{error}");
+
+        validate(&json!({ "feature": { "unknown": true } })).unwrap_err();
+    }
+
+    /// The patch overrides the defaults, but not the environment.
+    #[test]
+    fn precedence() {
+        let mut context = ConfigContext::default()
+            .strict_env(true)
+            .override_env("MIRRORD_ACCEPT_INVALID_CERTIFICATES", "true");
+        let patch = json!({
+            "accept_invalid_certificates": false,
+            "feature": {
+                "fs": "read",
+                "network": { "incoming": "steal" }
+            }
+        });
+
+        let config = LayerConfig::resolve_with_patch(&mut context, &patch).unwrap();
+
+        assert_eq!(config.accept_invalid_certificates, Some(true));
+        assert_eq!(config.feature.fs.mode, FsModeConfig::Read);
+        assert_eq!(config.feature.network.incoming.mode, IncomingMode::Steal);
+    }
+
+    /// Errors in the config file are not attributed to the patch.
+    #[cfg(feature = "patch-validation")]
+    #[test]
+    fn invalid_config_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.json");
+        std::fs::write(
+            &path,
+            r#"{ "feature": { "fs": { "mode": "everything" } } }"#,
+        )
+        .unwrap();
+        let mut context = ConfigContext::default()
+            .strict_env(true)
+            .override_env(LayerConfig::FILE_PATH_ENV, path.to_str().unwrap());
+
+        let error =
+            LayerConfig::resolve_with_patch(&mut context, &json!({ "feature": { "fs": "read" } }))
+                .unwrap_err();
+        assert!(
+            matches!(error, ConfigError::InvalidConfigFile(..)),
+            "{error}"
+        );
+
+        std::fs::write(&path, r#"{ "feature": { "fs": "read" } }"#).unwrap();
+        let error = LayerConfig::resolve_with_patch(
+            &mut context,
+            &json!({ "feature": { "fs": { "mode": "everything" } } }),
+        )
+        .unwrap_err();
+        assert!(matches!(error, ConfigError::InvalidPatch(..)), "{error}");
+    }
+}
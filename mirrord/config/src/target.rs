@@ -1,15 +1,16 @@
 use std::{fmt, str::FromStr};
 
 use cron_job::CronJobTarget;
+use daemon_set::DaemonSetTarget;
 use mirrord_analytics::CollectAnalytics;
 use replica_set::ReplicaSetTarget;
 use schemars::{JsonSchema, r#gen::SchemaGenerator, schema::SchemaObject};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de};
 use strum_macros::{EnumDiscriminants, EnumString};
 
 use self::{
     deployment::DeploymentTarget, job::JobTarget, pod::PodTarget, rollout::RolloutTarget,
-    service::ServiceTarget, stateful_set::StatefulSetTarget,
+    selector::TargetSelector, service::ServiceTarget, stateful_set::StatefulSetTarget,
 };
 use crate::{
     config::{
@@ -22,11 +23,13 @@ use crate::{
 };
 
 pub mod cron_job;
+pub mod daemon_set;
 pub mod deployment;
 pub mod job;
 pub mod pod;
 pub mod replica_set;
 pub mod rollout;
+pub mod selector;
 pub mod service;
 pub mod stateful_set;
 
@@ -49,6 +52,10 @@ pub enum TargetFileConfig {
         #[schemars(schema_with = "make_simple_target_custom_schema")]
         path: Option<Target>,
         namespace: Option<String>,
+        #[serde(default)]
+        additional: Vec<AdditionalTarget>,
+        #[serde(default)]
+        selector: Option<TargetSelector>,
     },
 }
 
@@ -84,11 +91,14 @@ fn make_simple_target_custom_schema(r#gen: &mut SchemaGenerator) -> schemars::sc
 /// - `cronjob/{cronjob-name}[/container/{container-name}]`;
 /// - `statefulset/{statefulset-name}[/container/{container-name}]`;
 /// - `service/{service-name}[/container/{container-name}]`;
+/// - `daemonset/{daemonset-name}[/container/{container-name}][/node/{node-name}]`;
 ///
 /// Please note that:
 ///
 /// - `job`, `cronjob`, `statefulset` and `service` targets require the mirrord Operator
 /// - `job` and `cronjob` targets require the [`copy_target`](#feature-copy_target) feature
+/// - `daemonset` targets pick the pod running on `node-name` when it's given, and any ready pod of
+///   the DaemonSet otherwise; `node-name` is not supported with the mirrord Operator
 ///
 /// Shortened setup with a target:
 ///
@@ -168,6 +178,7 @@ pub struct TargetConfig {
     ///   Operator)
     /// - `service/{service-name}[/container/{container-name}]`; (requires mirrord Operator)
     /// - `replicaset/{replicaset-name}[/container/{container-name}]`; (requires mirrord Operator)
+    /// - `daemonset/{daemonset-name}[/container/{container-name}][/node/{node-name}]`;
     #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<Target>,
 
@@ -180,6 +191,114 @@ pub struct TargetConfig {
     /// Defaults to the Kubernetes user's default namespace (defined in Kubernetes context).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub namespace: Option<String>,
+
+    /// ### target.additional {#target-additional}
+    ///
+    /// Additional targets for the same session, for applications that need incoming traffic
+    /// from more than one target (e.g. an API gateway and a worker).
+    ///
+    /// Each additional target gets its own agent, and incoming traffic on the listed `ports`
+    /// comes from that target. Incoming traffic on all other ports, environment variables, file
+    /// operations, DNS and outgoing traffic still go through the primary target
+    /// ([`target.path`](#target-path)).
+    ///
+    /// ```json
+    /// {
+    ///   "target": {
+    ///     "path": "deployment/api-gateway",
+    ///     "additional": [
+    ///       {
+    ///         "path": "deployment/worker",
+    ///         "ports": [9090]
+    ///       }
+    ///     ]
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// An additional target must not be `targetless`, and each port can be listed in only one
+    /// additional target.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional: Vec<AdditionalTarget>,
+
+    /// ### target.selector {#target-selector}
+    ///
+    /// Selects the target pod with a Kubernetes label selector instead of naming it in
+    /// [`target.path`](#target-path), so that a shared config does not have to hard-code
+    /// workload names that differ between environments.
+    ///
+    /// When the session starts, the selector is resolved to a ready pod in
+    /// [`target.namespace`](#target-namespace). When more than one pod is ready, `pick` decides
+    /// which one is used:
+    ///
+    /// - `newest` (default): the most recently created pod;
+    /// - `oldest`: the least recently created pod;
+    /// - `random`: a random pod;
+    /// - `least_loaded`: the pod with the fewest mirrord agents already attached to it.
+    ///
+    /// Ties are broken by pod name.
+    ///
+    /// ```json
+    /// {
+    ///   "target": {
+    ///     "selector": {
+    ///       "labels": "app=checkout,track!=canary",
+    ///       "container": "main",
+    ///       "pick": "least_loaded"
+    ///     },
+    ///     "namespace": "staging"
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// Cannot be used together with [`target.path`](#target-path).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selector: Option<TargetSelector>,
+}
+
+/// A target used in the session together with the primary [`TargetConfig::path`], see
+/// [`TargetConfig::additional`].
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AdditionalTarget {
+    /// Kubernetes resource to target, in the same format as [`TargetConfig::path`].
+    #[serde(deserialize_with = "string_or_struct")]
+    #[schemars(schema_with = "make_simple_target_custom_schema")]
+    pub path: Target,
+
+    /// Namespace where the target lives.
+    ///
+    /// Defaults to the namespace of the primary target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// Ports of the incoming traffic that comes from this target.
+    pub ports: Vec<u16>,
+}
+
+fn string_or_struct<'de, D>(deserializer: D) -> std::result::Result<Target, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    string_or_struct_option(deserializer)?
+        .ok_or_else(|| de::Error::custom("additional target path cannot be empty"))
+}
+
+impl TargetConfig {
+    /// Returns the [`TargetConfig`] of the given additional target, used to start its session.
+    ///
+    /// The namespace is inherited from this config when not specified.
+    pub fn for_additional(&self, additional: &AdditionalTarget) -> Self {
+        Self {
+            path: Some(additional.path.clone()),
+            namespace: additional
+                .namespace
+                .clone()
+                .or_else(|| self.namespace.clone()),
+            additional: Vec::new(),
+            selector: None,
+        }
+    }
 }
 
 impl Default for TargetFileConfig {
@@ -214,15 +333,28 @@ impl MirrordConfig for TargetFileConfig {
     /// Generate the final config object, out of the configuration parsed from a configuration file,
     /// factoring in environment variables (which are also set by the front end - CLI/IDE-plugin).
     fn generate_config(self, context: &mut ConfigContext) -> Result<Self::Generated> {
-        let (path_from_conf_file, namespace_from_conf_file) = match self {
-            TargetFileConfig::Simple(path) => (path, None),
-            TargetFileConfig::Advanced { path, namespace } => (path, namespace),
+        let (path_from_conf_file, namespace_from_conf_file, additional, selector) = match self {
+            TargetFileConfig::Simple(path) => (path, None, Vec::new(), None),
+            TargetFileConfig::Advanced {
+                path,
+                namespace,
+                additional,
+                selector,
+            } => (path, namespace, additional, selector),
         };
 
         // Env overrides configuration if both there.
-        let path = Self::get_target_path_from_env(context)?.or(path_from_conf_file);
+        let path_from_env = Self::get_target_path_from_env(context)?;
+        // A target path given in the env replaces the selector as well.
+        let selector = selector.filter(|_| path_from_env.is_none());
+        let path = path_from_env.or(path_from_conf_file);
         let namespace = Self::get_target_namespace_from_env(context)?.or(namespace_from_conf_file);
-        Ok(TargetConfig { path, namespace })
+        Ok(TargetConfig {
+            path,
+            namespace,
+            additional,
+            selector,
+        })
     }
 }
 
@@ -245,6 +377,7 @@ mirrord-layer failed to parse the provided target!
     >> `statefulset/{statefulset-name}[/container/{container-name}]`;
     >> `service/{service-name}[/container/{container-name}]`;
     >> `replicaset/{replicaset-name}[/container/{container-name}]`;
+    >> `daemonset/{daemonset-name}[/container/{container-name}][/node/{node-name}]`;
 
 - Note:
     >> specifying container name is optional, defaults to a container chosen by mirrord
@@ -271,6 +404,7 @@ mirrord-layer failed to parse the provided target!
 /// - `statefulset/{statefulset-name}[/container/{container-name}]`;
 /// - `service/{service-name}[/container/{container-name}]`;
 /// - `replicaset/{replicaset-name}[/container/{container-name}]`;
+/// - `daemonset/{daemonset-name}[/container/{container-name}][/node/{node-name}]`;
 ///
 /// Used to derive `TargetType` via the strum crate
 #[warn(clippy::wildcard_enum_match_arm)]
@@ -317,6 +451,10 @@ pub enum Target {
     /// [ReplicaSet](https://kubernetes.io/docs/concepts/workloads/controllers/replicaset/).
     ReplicaSet(replica_set::ReplicaSetTarget),
 
+    /// <!--${internal}-->
+    /// [DaemonSet](https://kubernetes.io/docs/concepts/workloads/controllers/daemonset/).
+    DaemonSet(daemon_set::DaemonSetTarget),
+
     /// <!--${internal}-->
     /// Spawn a new pod.
     Targetless,
@@ -339,6 +477,7 @@ impl JsonSchema for Target {
             schema_gen.subschema_for::<stateful_set::StatefulSetTarget>(),
             schema_gen.subschema_for::<service::ServiceTarget>(),
             schema_gen.subschema_for::<replica_set::ReplicaSetTarget>(),
+            schema_gen.subschema_for::<daemon_set::DaemonSetTarget>(),
             schemars::schema::Schema::Object(schemars::schema::SchemaObject {
                 enum_values: Some(vec![serde_json::Value::String("targetless".to_string())]),
                 ..Default::default()
@@ -372,6 +511,9 @@ impl FromStr for Target {
             Some("replicaset") => {
                 replica_set::ReplicaSetTarget::from_split(&mut split).map(Target::ReplicaSet)
             }
+            Some("daemonset") => {
+                daemon_set::DaemonSetTarget::from_split(&mut split).map(Target::DaemonSet)
+            }
             _ => Err(ConfigError::InvalidTarget(format!(
                 "Provided target: {target} is unsupported. Did you remember to add a prefix, e.g. pod/{target}? \n{FAIL_PARSE_DEPLOYMENT_OR_POD}",
             ))),
@@ -410,6 +552,7 @@ impl fmt::Display for TargetType {
             TargetType::StatefulSet => "statefulset",
             TargetType::Service => "service",
             TargetType::ReplicaSet => "replicaset",
+            TargetType::DaemonSet => "daemonset",
         };
 
         f.write_str(stringified)
@@ -428,6 +571,7 @@ impl TargetType {
             Self::StatefulSet,
             Self::Service,
             Self::ReplicaSet,
+            Self::DaemonSet,
         ]
         .into_iter()
     }
@@ -435,7 +579,9 @@ impl TargetType {
     pub fn compatible_with(&self, config: &FeatureConfig) -> bool {
         match self {
             Self::Targetless | Self::Rollout => !config.copy_target.enabled,
-            Self::Pod => !(config.copy_target.enabled && config.copy_target.scale_down),
+            Self::Pod | Self::DaemonSet => {
+                !(config.copy_target.enabled && config.copy_target.scale_down)
+            }
             Self::Job | Self::CronJob => config.copy_target.enabled,
             Self::Service => !config.copy_target.enabled,
             Self::Deployment | Self::StatefulSet | Self::ReplicaSet => true,
@@ -501,6 +647,36 @@ impl_target_display!(StatefulSetTarget, stateful_set, "statefulset");
 impl_target_display!(ServiceTarget, service, "service");
 impl_target_display!(ReplicaSetTarget, replica_set, "replicaset");
 
+impl TargetDisplay for DaemonSetTarget {
+    fn type_(&self) -> &str {
+        "daemonset"
+    }
+
+    fn name(&self) -> &str {
+        self.daemon_set.as_str()
+    }
+
+    fn container(&self) -> Option<&String> {
+        self.container.as_ref()
+    }
+}
+
+impl fmt::Display for DaemonSetTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_(), self.name())?;
+
+        if let Some(container) = self.container() {
+            write!(f, "/container/{container}")?;
+        }
+
+        if let Some(node) = &self.node {
+            write!(f, "/node/{node}")?;
+        }
+
+        Ok(())
+    }
+}
+
 impl fmt::Display for Target {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -513,6 +689,7 @@ impl fmt::Display for Target {
             Target::StatefulSet(target) => target.fmt(f),
             Target::Service(target) => target.fmt(f),
             Target::ReplicaSet(target) => target.fmt(f),
+            Target::DaemonSet(target) => target.fmt(f),
         }
     }
 }
@@ -529,6 +706,7 @@ impl TargetDisplay for Target {
             Target::StatefulSet(target) => target.type_(),
             Target::Service(target) => target.type_(),
             Target::ReplicaSet(target) => target.type_(),
+            Target::DaemonSet(target) => target.type_(),
         }
     }
 
@@ -543,6 +721,7 @@ impl TargetDisplay for Target {
             Target::StatefulSet(target) => target.name(),
             Target::Service(target) => target.name(),
             Target::ReplicaSet(target) => target.name(),
+            Target::DaemonSet(target) => target.name(),
         }
     }
 
@@ -557,6 +736,7 @@ impl TargetDisplay for Target {
             Target::StatefulSet(target) => target.container(),
             Target::Service(target) => target.container(),
             Target::ReplicaSet(target) => target.container(),
+            Target::DaemonSet(target) => target.container(),
         }
     }
 }
@@ -575,6 +755,7 @@ bitflags::bitflags! {
         const STATEFUL_SET = 128;
         const SERVICE = 256;
         const REPLICA_SET = 512;
+        const DAEMON_SET = 1024;
     }
 }
 
@@ -634,12 +815,22 @@ impl CollectAnalytics for &TargetConfig {
                         flags |= TargetAnalyticFlags::CONTAINER;
                     }
                 }
+                Target::DaemonSet(target) => {
+                    flags |= TargetAnalyticFlags::DAEMON_SET;
+                    if target.container.is_some() {
+                        flags |= TargetAnalyticFlags::CONTAINER;
+                    }
+                }
                 Target::Targetless => {
                     // Targetless is essentially 0, so no need to set any flags.
                 }
             }
         }
-        analytics.add("target_mode", flags.bits())
+        analytics.add("target_mode", flags.bits());
+        analytics.add("additional_targets", self.additional.len());
+        if let Some(selector) = &self.selector {
+            analytics.add("target_selector_pick", selector.pick as u32);
+        }
     }
 }
 
@@ -647,14 +838,16 @@ impl CollectAnalytics for &TargetConfig {
 mod tests {
     use rstest::rstest;
 
-    use super::*;
+    use super::{selector::TargetPick, *};
     use crate::config::{ConfigContext, MirrordConfig};
 
     #[rstest]
     #[case(None, None,
         TargetConfig {
             path: None,
-            namespace: None
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
         }
     )] // Nothing specified - no target config (targetless mode).
     #[case(
@@ -662,7 +855,9 @@ mod tests {
         Some("ns"),
         TargetConfig{
             path: None,
-            namespace: Some("ns".to_string())
+            namespace: Some("ns".to_string()),
+            additional: Vec::new(),
+            selector: None,
         }
     )] // Namespace without target - error.
     #[case(
@@ -670,7 +865,9 @@ mod tests {
         None,
         TargetConfig{
             path: Some(Target::Pod(PodTarget {pod: "foo".to_string(), container: None})),
-            namespace: None
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
         }
     )] // Only pod specified
     #[case(
@@ -681,7 +878,9 @@ mod tests {
                 pod: "foo".to_string(),
                 container: Some("bar".to_string())
             })),
-            namespace: None
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
         }
     )] // Pod and container specified.
     #[case(
@@ -689,7 +888,9 @@ mod tests {
         Some("baz"),
         TargetConfig{
             path: Some(Target::Pod(PodTarget {pod: "foo".to_string(), container: None})),
-            namespace: Some("baz".to_string())
+            namespace: Some("baz".to_string()),
+            additional: Vec::new(),
+            selector: None,
         }
     )] // Pod and namespace specified.
     #[case(
@@ -700,9 +901,25 @@ mod tests {
                 rollout: "foo".to_string(),
                 container: None
             })),
-            namespace: None
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
         }
     )] // Rollout specified.
+    #[case(
+        Some("daemonset/foo/container/bar/node/baz"),
+        None,
+        TargetConfig{
+            path: Some(Target::DaemonSet(DaemonSetTarget {
+                daemon_set: "foo".to_string(),
+                container: Some("bar".to_string()),
+                node: Some("baz".to_string()),
+            })),
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
+        }
+    )] // DaemonSet with container and node specified.
     fn default(
         #[case] path_env: Option<&str>,
         #[case] namespace_env: Option<&str>,
@@ -726,7 +943,9 @@ mod tests {
         r#"{ "namespace": "my-test-namespace" }"#,
         TargetConfig {
             path: None,
-            namespace: Some("my-test-namespace".to_string())
+            namespace: Some("my-test-namespace".to_string()),
+            additional: Vec::new(),
+            selector: None,
         }
     )]
     // simple variant of file config - path string, not an object.
@@ -734,7 +953,9 @@ mod tests {
         r#""pod/my-cool-pod""#,
         TargetConfig{
             path: Some(Target::Pod(PodTarget {pod: "my-cool-pod".to_string(), container: None})),
-            namespace: None
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
         }
     )]
     // advanced variant of file config.
@@ -742,7 +963,9 @@ mod tests {
         r#"{ "path": "pod/my-cool-pod" }"#,
         TargetConfig{
             path: Some(Target::Pod(PodTarget {pod: "my-cool-pod".to_string(), container: None})),
-            namespace: None
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
         }
     )]
     // advanced variant of file config, with object as path.
@@ -754,7 +977,77 @@ mod tests {
         }"#,
         TargetConfig{
             path: Some(Target::Pod(PodTarget {pod: "my-cool-pod".to_string(), container: None})),
-            namespace: None
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
+        }
+    )]
+    // advanced variant of file config, with a daemonset pinned to a node.
+    #[case(
+        r#"{
+            "path": {
+                "daemon_set": "log-shipper",
+                "node": "worker-1.cluster.internal"
+            }
+        }"#,
+        TargetConfig{
+            path: Some(Target::DaemonSet(DaemonSetTarget {
+                daemon_set: "log-shipper".to_string(),
+                container: None,
+                node: Some("worker-1.cluster.internal".to_string()),
+            })),
+            namespace: None,
+            additional: Vec::new(),
+            selector: None,
+        }
+    )]
+    // advanced variant of file config, with a label selector.
+    #[case(
+        r#"{
+            "selector": {
+                "labels": "app=checkout,track!=canary",
+                "pick": "least_loaded"
+            }
+        }"#,
+        TargetConfig{
+            path: None,
+            namespace: None,
+            additional: Vec::new(),
+            selector: Some(TargetSelector {
+                labels: "app=checkout,track!=canary".to_string(),
+                container: None,
+                pick: TargetPick::LeastLoaded,
+            }),
+        }
+    )]
+    // advanced variant of file config, with additional targets.
+    #[case(
+        r#"{
+            "path": "pod/my-cool-pod",
+            "additional": [
+                { "path": "deployment/worker", "ports": [9090] },
+                { "path": { "pod": "other-pod" }, "namespace": "other", "ports": [80, 81] }
+            ]
+        }"#,
+        TargetConfig{
+            path: Some(Target::Pod(PodTarget {pod: "my-cool-pod".to_string(), container: None})),
+            namespace: None,
+            additional: vec![
+                AdditionalTarget {
+                    path: Target::Deployment(DeploymentTarget {
+                        deployment: "worker".to_string(),
+                        container: None,
+                    }),
+                    namespace: None,
+                    ports: vec![9090],
+                },
+                AdditionalTarget {
+                    path: Target::Pod(PodTarget {pod: "other-pod".to_string(), container: None}),
+                    namespace: Some("other".to_string()),
+                    ports: vec![80, 81],
+                },
+            ],
+            selector: None,
         }
     )]
     fn parse_target_config_from_json(
@@ -783,4 +1076,46 @@ mod tests {
             .unwrap();
         assert_eq!(target_config, expected_target_config);
     }
+
+    /// A target path from the env replaces the selector from the config file.
+    #[test]
+    fn env_path_overrides_selector() {
+        let mut cfg_context = ConfigContext::default()
+            .override_env("MIRRORD_IMPERSONATED_TARGET", "pod/foo")
+            .strict_env(true);
+        let target_file_config: TargetFileConfig =
+            serde_json::from_str(r#"{ "selector": { "labels": "app=foo" } }"#).unwrap();
+        let target_config = target_file_config
+            .generate_config(&mut cfg_context)
+            .unwrap();
+
+        assert_eq!(
+            target_config.path,
+            Some(Target::Pod(PodTarget {
+                pod: "foo".to_string(),
+                container: None
+            }))
+        );
+        assert_eq!(target_config.selector, None);
+    }
+
+    #[rstest]
+    #[case("daemonset/foo")]
+    #[case("daemonset/foo/container/bar")]
+    #[case("daemonset/foo/node/baz")]
+    #[case("daemonset/foo/container/bar/node/baz")]
+    fn daemon_set_target_roundtrip(#[case] target: &str) {
+        let parsed = target.parse::<Target>().unwrap();
+        assert!(matches!(parsed, Target::DaemonSet(_)));
+        assert_eq!(parsed.to_string(), target);
+    }
+
+    #[rstest]
+    #[case("daemonset")]
+    #[case("daemonset/foo/container")]
+    #[case("daemonset/foo/node/baz/node/qux")]
+    #[case("daemonset/foo/replica/bar")]
+    fn daemon_set_target_invalid(#[case] target: &str) {
+        assert!(target.parse::<Target>().is_err());
+    }
 }
@@ -0,0 +1,56 @@
+use std::str::Split;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{FAIL_PARSE_DEPLOYMENT_OR_POD, FromSplit};
+use crate::config::{ConfigError, Result};
+
+/// <!--${internal}-->
+/// Targets a pod of a [DaemonSet](https://kubernetes.io/docs/concepts/workloads/controllers/daemonset/).
+///
+/// As a DaemonSet runs one pod per node, `node` can be used to pick the pod scheduled on a
+/// specific node. When it is not set, mirrord picks any ready pod of the DaemonSet.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonSetTarget {
+    pub daemon_set: String,
+    pub container: Option<String>,
+    /// Name of the node that runs the targeted pod.
+    ///
+    /// Not supported when using the mirrord operator.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+}
+
+impl FromSplit for DaemonSetTarget {
+    /// Parses `{daemonset-name}[/container/{container-name}][/node/{node-name}]`.
+    fn from_split(split: &mut Split<char>) -> Result<Self> {
+        let daemon_set = split
+            .next()
+            .ok_or_else(|| ConfigError::InvalidTarget(FAIL_PARSE_DEPLOYMENT_OR_POD.to_string()))?;
+
+        let mut target = Self {
+            daemon_set: daemon_set.to_string(),
+            container: None,
+            node: None,
+        };
+
+        loop {
+            match (split.next(), split.next()) {
+                (Some("container"), Some(container)) if target.container.is_none() => {
+                    target.container = Some(container.to_string());
+                }
+                (Some("node"), Some(node)) if target.node.is_none() => {
+                    target.node = Some(node.to_string());
+                }
+                (None, None) => break Ok(target),
+                _ => {
+                    break Err(ConfigError::InvalidTarget(
+                        FAIL_PARSE_DEPLOYMENT_OR_POD.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+}
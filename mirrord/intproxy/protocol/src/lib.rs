@@ -51,10 +51,12 @@ pub enum LayerToProxyMessage {
     Incoming(IncomingRequest),
     /// Fetch environment variables from the target.
     GetEnv(GetEnvVarsRequest),
+    /// A request from the `mirrord session` commands.
+    SessionControl(SessionControlRequest),
 }
 
 /// Layer process information
-#[derive(Encode, Decode, Debug, PartialEq, Eq)]
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
 pub struct ProcessInfo {
     /// Process ID.
     pub pid: i32,
@@ -143,6 +145,22 @@ pub struct OutgoingConnectRequest {
     pub remote_address: SocketAddress,
     /// The protocol stack the user application wants to use.
     pub protocol: NetProtocol,
+    /// If set, the proxy first looks for a hostname in the data sent by the user application, and
+    /// decides whether the connection should be made remotely or locally.
+    pub inspect: Option<HostnameInspection>,
+}
+
+/// Describes how the proxy decides where to make an outgoing TCP connection, based on the TLS SNI
+/// or HTTP `Host` found in the first bytes sent by the user application.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct HostnameInspection {
+    /// Hostnames from the outgoing filter.
+    pub hostnames: Vec<String>,
+    /// Whether the connection should be made remotely when one of [`Self::hostnames`] is found
+    /// (`remote` filter), or when none is found (`local` filter).
+    pub remote_on_match: bool,
+    /// Address to connect to when the connection is made locally.
+    pub local_address: SocketAddr,
 }
 
 /// A request for additional metadata for an outgoing connection.
@@ -252,6 +270,37 @@ pub enum ProxyToLayerMessage {
     GetEnv(RemoteResult<HashMap<String, String>>),
     /// Internal proxy encountered a fatal error.
     ProxyFailed(String),
+    /// A response to [`LayerToProxyMessage::SessionControl`].
+    SessionControl(SessionControlResponse),
+}
+
+/// A request from the `mirrord session` commands, which manage internal proxies that outlive the
+/// processes that use them.
+///
+/// Sent over a regular `layer <-> proxy` session, started with a [`NewSessionRequest`] that has
+/// [`ProcessInfo::loaded`] set to `false`.
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionControlRequest {
+    /// List the layers connected to the internal proxy.
+    ListLayers,
+    /// Stop the internal proxy, closing all connected layers.
+    Stop,
+}
+
+/// A response to [`SessionControlRequest`].
+#[derive(Encode, Decode, Debug, PartialEq, Eq)]
+pub enum SessionControlResponse {
+    /// A response to [`SessionControlRequest::ListLayers`].
+    Layers(Vec<ConnectedLayer>),
+    /// A response to [`SessionControlRequest::Stop`], the internal proxy exits after sending it.
+    Stopping,
+}
+
+/// A layer connected to the internal proxy, see [`SessionControlRequest::ListLayers`].
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct ConnectedLayer {
+    pub id: LayerId,
+    pub process_info: ProcessInfo,
 }
 
 /// A response to layer's [`IncomingRequest`].
@@ -554,6 +603,13 @@ impl_request!(
     res_path = ProxyToLayerMessage::GetEnv,
 );
 
+impl_request!(
+    req = SessionControlRequest,
+    res = SessionControlResponse,
+    req_path = LayerToProxyMessage::SessionControl,
+    res_path = ProxyToLayerMessage::SessionControl,
+);
+
 impl_request!(
     req = RenameRequest,
     res = RemoteResult<()>,
@@ -153,6 +153,9 @@ pub enum AgentConnectionMessage {
 pub struct AgentConnection {
     pub connection: Connection<Client>,
     pub reconnect: ReconnectFlow,
+    /// Whether [`ClientMessage::SwitchCompression`](mirrord_protocol::ClientMessage) can be sent
+    /// through this connection. The agent's protocol version is checked separately.
+    pub supports_compression: bool,
 }
 
 impl AgentConnection {
@@ -166,6 +169,12 @@ impl AgentConnection {
     ) -> Result<Self, AgentConnectionError> {
         let kind = connect_info.discriminant();
 
+        let supports_compression = match &connect_info {
+            AgentConnectInfo::Operator(session) => session.allow_compression,
+            // The external proxy compresses its side of the connection on its own.
+            _ => true,
+        };
+
         let (connection, reconnect) = match connect_info {
             AgentConnectInfo::Operator(session) => {
                 let connection =
@@ -224,6 +233,7 @@ impl AgentConnection {
         Ok(Self {
             connection,
             reconnect,
+            supports_compression,
         })
     }
 
@@ -234,6 +244,7 @@ impl AgentConnection {
         Ok(Self {
             connection,
             reconnect: ReconnectFlow::Break(AgentConnectInfoDiscriminants::DirectKubernetes),
+            supports_compression: true,
         })
     }
 
@@ -246,10 +257,23 @@ impl fmt::Debug for AgentConnection {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("AgentConnection")
             .field("reconnect", &self.reconnect)
+            .field("supports_compression", &self.supports_compression)
             .finish()
     }
 }
 
+/// Connection to one of the additional targets of the session, see
+/// [`TargetConfig::additional`](mirrord_config::target::TargetConfig::additional).
+///
+/// Only incoming traffic on [`Self::ports`] goes through this connection, everything else goes
+/// through the primary [`AgentConnection`].
+#[derive(Debug)]
+pub struct AdditionalAgentConnection {
+    pub connection: AgentConnection,
+    /// Ports of the incoming traffic that comes from this target.
+    pub ports: Vec<u16>,
+}
+
 #[derive(Error, Debug)]
 pub enum AgentConnectionTaskError {
     #[error("{0} connection was requested to reconnect")]
@@ -191,7 +191,27 @@ where
     /// # Panics
     ///
     /// This method panics when attempting to register a task with a duplicate id.
-    pub fn register<T>(&mut self, mut task: T, id: Id, channel_size: usize) -> TaskSender<T>
+    pub fn register<T>(&mut self, task: T, id: Id, channel_size: usize) -> TaskSender<T>
+    where
+        T: 'static + BackgroundTask<MessageOut = MOut> + Send,
+        Err: From<T::Error>,
+        T::MessageIn: Send,
+    {
+        let agent_tx = self.agent_tx.another();
+        self.register_with_agent_tx(task, id, channel_size, agent_tx)
+    }
+
+    /// Same as [`Self::register`], but the task's [`MessageBus`] sends messages to the agent
+    /// through the given `agent_tx`, instead of the one this struct was created with.
+    ///
+    /// Used for tasks that talk to one of the additional agents of the session.
+    pub fn register_with_agent_tx<T>(
+        &mut self,
+        mut task: T,
+        id: Id,
+        channel_size: usize,
+        agent_tx: TxHandle<Client>,
+    ) -> TaskSender<T>
     where
         T: 'static + BackgroundTask<MessageOut = MOut> + Send,
         Err: From<T::Error>,
@@ -215,7 +235,7 @@ where
             tx: out_msg_tx,
             rx: in_msg_rx,
             token: token.clone(),
-            agent_tx: self.agent_tx.another(),
+            agent_tx,
         };
 
         self.handles.insert(
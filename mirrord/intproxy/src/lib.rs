@@ -4,6 +4,7 @@
 
 use std::{
     collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddr,
     ops::ControlFlow,
     time::Duration,
 };
@@ -14,20 +15,23 @@ use layer_conn::LayerConnection;
 use layer_initializer::LayerInitializer;
 use main_tasks::{FromLayer, LayerForked, MainTaskId, ProxyMessage, ToLayer};
 use mirrord_config::{
-    experimental::ExperimentalConfig, feature::network::incoming::tls_delivery::LocalTlsDelivery,
+    experimental::ExperimentalConfig,
+    feature::network::{dns::DnsOverrides, incoming::tls_delivery::LocalTlsDelivery},
 };
 use mirrord_intproxy_protocol::{
-    IncomingRequest, LayerId, LayerToProxyMessage, LocalMessage, MessageId, ProcessInfo,
+    ConnectedLayer, IncomingRequest, LayerId, LayerToProxyMessage, LocalMessage, MessageId,
+    ProcessInfo, ProxyToLayerMessage, SessionControlRequest, SessionControlResponse,
 };
 use mirrord_protocol::{
-    CLIENT_READY_FOR_LOGS, ClientMessage, DaemonMessage, FileRequest, LogLevel,
+    CLIENT_READY_FOR_LOGS, COMPRESSION_VERSION, ClientMessage, CompressionAlgorithm, DaemonMessage,
+    FileRequest, LogLevel, LogMessage, Port,
 };
 use mirrord_protocol_io::{Client, TxHandle};
 use ping_pong::{PingPong, PingPongMessage};
 use proxies::{
     files::{FilesProxy, FilesProxyMessage},
-    incoming::{IncomingProxy, IncomingProxyMessage},
-    outgoing::{OutgoingProxy, OutgoingProxyMessage},
+    incoming::{IncomingProxy, IncomingProxyMessage, port_subscription_ext::PortSubscriptionExt},
+    outgoing::{OutgoingProxy, OutgoingProxyMessage, capture::OutgoingCapture},
     simple::{SimpleProxy, SimpleProxyMessage},
 };
 use semver::Version;
@@ -38,7 +42,7 @@ use tokio::{
 };
 
 use crate::{
-    agent_conn::{AgentConnection, AgentConnectionMessage},
+    agent_conn::{AdditionalAgentConnection, AgentConnection, AgentConnectionMessage},
     background_tasks::{RestartableBackgroundTaskWrapper, TaskError},
     error::{ProxyRuntimeError, ProxyStartupError},
     failover_strategy::FailoverStrategy,
@@ -69,8 +73,22 @@ struct TaskTxs {
     files: TaskSender<FilesProxy>,
 }
 
+/// Main tasks of an additional target of the session, see [`AdditionalAgentConnection`].
+struct AdditionalTarget {
+    /// Ports of the incoming traffic that comes from this target.
+    ports: HashSet<Port>,
+    _agent: TaskSender<RestartableBackgroundTaskWrapper<AgentConnection>>,
+    incoming: TaskSender<IncomingProxy>,
+    /// Send handle for the agent connection of this target.
+    agent_tx: TxHandle<Client>,
+    /// Compression we switch on in the agent connection of this target, if the agent supports
+    /// it.
+    compression: Option<CompressionAlgorithm>,
+}
+
 /// This struct contains logic for proxying between multiple layer instances and one agent.
-/// It maintains a singe agent connection.
+/// It maintains a singe agent connection, and optionally connections to additional targets, which
+/// only handle incoming traffic on their ports.
 ///
 /// Utilizes multiple [`BackgroundTask`](background_tasks::BackgroundTask)s to split logic of
 /// different mirrod features (e.g. file operations and incoming traffic).
@@ -102,6 +120,27 @@ pub struct IntProxy {
 
     /// Send handle for the agent connection
     agent_tx: TxHandle<Client>,
+
+    /// Additional targets of the session, by index of the target in the config.
+    additional: Vec<AdditionalTarget>,
+
+    /// Listeners of the layers that subscribed to ports of additional targets, with the index of
+    /// the target.
+    ///
+    /// Used to route [`IncomingRequest::ConnMetadata`] requests.
+    additional_listeners: HashMap<SocketAddr, usize>,
+
+    /// Interval for pinging the additional agents, their pongs are not tracked.
+    additional_ping_interval: Interval,
+
+    /// How long port subscriptions of closed layers are retained in the agents.
+    port_subscription_retention: Duration,
+
+    /// Set when a layer sends [`SessionControlRequest::Stop`], makes the proxy exit.
+    stop_requested: bool,
+
+    /// Compression we switch on in the primary agent connection, if the agent supports it.
+    compression: Option<CompressionAlgorithm>,
 }
 
 impl IntProxy {
@@ -118,12 +157,16 @@ impl IntProxy {
     /// Creates a new [`IntProxy`] using existing [`AgentConnection`].
     /// The returned instance will accept connections from the layers using the given
     /// [`TcpListener`].
+    #[allow(clippy::too_many_arguments)]
     pub fn new_with_connection(
         agent_conn: AgentConnection,
         listener: TcpListener,
         file_buffer_size: u64,
         https_delivery: LocalTlsDelivery,
         process_logging_interval: Duration,
+        port_subscription_retention: Duration,
+        outgoing_capture: Option<OutgoingCapture>,
+        dns_overrides: DnsOverrides,
         experimental: &ExperimentalConfig,
     ) -> Self {
         let mut background_tasks: BackgroundTasks<MainTaskId, ProxyMessage, ProxyRuntimeError> =
@@ -136,6 +179,10 @@ impl IntProxy {
         );
 
         let agent_conn_reconnectable = agent_conn.reconnectable();
+        let compression = experimental
+            .protocol_compression
+            .algorithm()
+            .filter(|_| agent_conn.supports_compression);
 
         // We need to negotiate mirrord-protocol version
         // before we can process layers' requests.
@@ -161,7 +208,9 @@ impl IntProxy {
             Self::CHANNEL_SIZE,
         );
         let outgoing = background_tasks.register(
-            OutgoingProxy::new(experimental.non_blocking_tcp_connect),
+            OutgoingProxy::new(experimental.non_blocking_tcp_connect)
+                .with_capture(outgoing_capture)
+                .with_dns_overrides(dns_overrides),
             MainTaskId::OutgoingProxy,
             Self::CHANNEL_SIZE,
         );
@@ -169,7 +218,8 @@ impl IntProxy {
             IncomingProxy::new(
                 Duration::from_millis(experimental.idle_local_http_connection_timeout),
                 https_delivery,
-            ),
+            )
+            .with_subscription_retention(port_subscription_retention),
             MainTaskId::IncomingProxy,
             Self::CHANNEL_SIZE,
         );
@@ -193,6 +243,9 @@ impl IntProxy {
         let mut process_logging_interval = time::interval(process_logging_interval);
         process_logging_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
+        let mut additional_ping_interval = time::interval(Self::PING_INTERVAL);
+        additional_ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         Self {
             any_connection_accepted: false,
             background_tasks,
@@ -214,9 +267,62 @@ impl IntProxy {
             connected_layers: HashMap::new(),
             process_logging_interval,
             agent_tx,
+            additional: Default::default(),
+            additional_listeners: Default::default(),
+            additional_ping_interval,
+            port_subscription_retention,
+            stop_requested: false,
+            compression,
         }
     }
 
+    /// Adds connections to the additional targets of the session.
+    ///
+    /// Each target gets its own [`IncomingProxy`], which handles subscriptions for the target's
+    /// ports. Everything else is still handled by the primary agent connection.
+    pub fn with_additional_connections(
+        mut self,
+        connections: Vec<AdditionalAgentConnection>,
+        https_delivery: LocalTlsDelivery,
+        experimental: &ExperimentalConfig,
+    ) -> Self {
+        for (index, AdditionalAgentConnection { connection, ports }) in
+            connections.into_iter().enumerate()
+        {
+            let agent_tx = connection.connection.tx_handle();
+            let compression = experimental
+                .protocol_compression
+                .algorithm()
+                .filter(|_| connection.supports_compression);
+
+            let incoming = self.background_tasks.register_with_agent_tx(
+                IncomingProxy::new(
+                    Duration::from_millis(experimental.idle_local_http_connection_timeout),
+                    https_delivery.clone(),
+                )
+                .with_subscription_retention(self.port_subscription_retention),
+                MainTaskId::AdditionalIncomingProxy(index),
+                Self::CHANNEL_SIZE,
+                agent_tx.another(),
+            );
+            let agent = self.background_tasks.register_restartable(
+                connection,
+                MainTaskId::AdditionalAgentConnection(index),
+                Self::CHANNEL_SIZE,
+            );
+
+            self.additional.push(AdditionalTarget {
+                ports: ports.into_iter().collect(),
+                _agent: agent,
+                incoming,
+                agent_tx,
+                compression,
+            });
+        }
+
+        self
+    }
+
     /// Check if any layer connections are still alive
     fn has_layer_connections(&self) -> bool {
         !self.task_txs.layers.is_empty()
@@ -261,6 +367,14 @@ impl IntProxy {
                 mirrord_protocol::VERSION.clone(),
             ))
             .await;
+        for additional in &self.additional {
+            additional
+                .agent_tx
+                .send(ClientMessage::SwitchProtocolVersion(
+                    mirrord_protocol::VERSION.clone(),
+                ))
+                .await;
+        }
 
         let mut proxy = self;
 
@@ -276,12 +390,23 @@ impl IntProxy {
                         tracing::error!(%error, "Proxy encountered a critical error, and is entering the failover state...");
                         return ControlFlow::Continue(FailoverStrategy::from_failed_proxy(proxy, error));
                     }
+
+                    if proxy.stop_requested {
+                        tracing::info!("Stopping on a session control request");
+                        break;
+                    }
                 }
 
                 _ = proxy.ping_pong_update_debounce.tick(), if proxy.has_layer_connections() => {
                     proxy.ping_pong_update_allowed = true;
                 }
 
+                _ = proxy.additional_ping_interval.tick(), if !proxy.additional.is_empty() => {
+                    for additional in &proxy.additional {
+                        additional.agent_tx.send(ClientMessage::Ping).await;
+                    }
+                }
+
                 _ = proxy.process_logging_interval.tick() => {
                     // Always log this, even if there are no connected layers.
                     // This way we can be sure that intproxy's Tokio runtime is making progress.
@@ -361,6 +486,12 @@ impl IntProxy {
                         .incoming
                         .send(IncomingProxyMessage::LayerForked(msg))
                         .await;
+                    for additional in &self.additional {
+                        additional
+                            .incoming
+                            .send(IncomingProxyMessage::LayerForked(msg))
+                            .await;
+                    }
                     self.task_txs
                         .outgoing
                         .send(OutgoingProxyMessage::LayerForked(msg))
@@ -426,6 +557,12 @@ impl IntProxy {
                     .incoming
                     .send(IncomingProxyMessage::LayerClosed(msg))
                     .await;
+                for additional in &self.additional {
+                    additional
+                        .incoming
+                        .send(IncomingProxyMessage::LayerClosed(msg))
+                        .await;
+                }
                 self.task_txs
                     .outgoing
                     .send(OutgoingProxyMessage::LayerClosed(msg))
@@ -436,6 +573,10 @@ impl IntProxy {
                 self.pending_layers.retain(|(layer_id, _)| layer_id.0 != id);
             }
 
+            (MainTaskId::AdditionalAgentConnection(index), TaskUpdate::Message(msg)) => {
+                self.handle_additional_agent_update(index, msg).await?
+            }
+
             (task_id, TaskUpdate::Finished(res)) => match res {
                 Ok(()) => {
                     tracing::error!(%task_id, "One of the main tasks finished unexpectedly");
@@ -503,6 +644,12 @@ impl IntProxy {
                     .send(FilesProxyMessage::FileRes(msg))
                     .await
             }
+            DaemonMessage::IdentifiedFile(msg) => {
+                self.task_txs
+                    .files
+                    .send(FilesProxyMessage::IdentifiedFileRes(msg))
+                    .await
+            }
             DaemonMessage::GetAddrInfoResponse(msg) => {
                 self.task_txs
                     .simple
@@ -533,6 +680,14 @@ impl IntProxy {
                     self.agent_tx.send(ClientMessage::ReadyForLogs).await;
                 }
 
+                if let Some(algorithm) = self.compression
+                    && COMPRESSION_VERSION.matches(&protocol_version)
+                {
+                    self.agent_tx
+                        .send(ClientMessage::SwitchCompression(algorithm))
+                        .await;
+                }
+
                 self.task_txs
                     .files
                     .send(FilesProxyMessage::ProtocolVersion(protocol_version.clone()))
@@ -577,6 +732,9 @@ impl IntProxy {
                     .send(SimpleProxyMessage::GetEnvRes(res.map(Into::into)))
                     .await
             }
+            DaemonMessage::SwitchCompressionResponse(algorithm) => {
+                tracing::debug!(?algorithm, "Agent switched on compression");
+            }
             message @ DaemonMessage::PauseTarget(_)
             | message @ DaemonMessage::Vpn(_)
             | message @ DaemonMessage::ReverseDnsLookup(_) => {
@@ -617,12 +775,12 @@ impl IntProxy {
                     .await
             }
             LayerToProxyMessage::Incoming(req) => {
-                self.task_txs
-                    .incoming
-                    .send(IncomingProxyMessage::LayerRequest(
-                        message_id, layer_id, req,
-                    ))
-                    .await
+                let index = self.additional_target_for(&req);
+                let message = IncomingProxyMessage::LayerRequest(message_id, layer_id, req);
+                match index.and_then(|index| self.additional.get(index)) {
+                    Some(additional) => additional.incoming.send(message).await,
+                    None => self.task_txs.incoming.send(message).await,
+                }
             }
             LayerToProxyMessage::GetEnv(req) => {
                 self.task_txs
@@ -630,12 +788,184 @@ impl IntProxy {
                     .send(SimpleProxyMessage::GetEnvReq(message_id, layer_id, req))
                     .await
             }
+            LayerToProxyMessage::SessionControl(req) => {
+                self.handle_session_control(message_id, layer_id, req).await
+            }
             other => Err(ProxyRuntimeError::UnexpectedLayerMessage(other))?,
         }
 
         Ok(())
     }
 
+    /// Handles a [`SessionControlRequest`] from the `mirrord session` commands.
+    async fn handle_session_control(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        request: SessionControlRequest,
+    ) {
+        let response = match request {
+            SessionControlRequest::ListLayers => SessionControlResponse::Layers(
+                self.connected_layers
+                    .iter()
+                    .map(|(id, process_info)| ConnectedLayer {
+                        id: *id,
+                        process_info: process_info.clone(),
+                    })
+                    .collect(),
+            ),
+            SessionControlRequest::Stop => {
+                self.stop_requested = true;
+                SessionControlResponse::Stopping
+            }
+        };
+
+        self.pending_layers.remove(&(layer_id, message_id));
+        if let Some(tx) = self.task_txs.layers.get(&layer_id) {
+            tx.send(LocalMessage {
+                message_id,
+                inner: ProxyToLayerMessage::SessionControl(response),
+            })
+            .await;
+        }
+    }
+
+    /// Returns the index of the additional target that should handle the given request from the
+    /// layer, or [`None`] if it should be handled by the primary target.
+    fn additional_target_for(&mut self, request: &IncomingRequest) -> Option<usize> {
+        let for_port = |port| {
+            self.additional
+                .iter()
+                .position(|additional| additional.ports.contains(&port))
+        };
+
+        match request {
+            IncomingRequest::PortSubscribe(subscribe) => {
+                let index = for_port(subscribe.subscription.port());
+                match index {
+                    Some(index) => self
+                        .additional_listeners
+                        .insert(subscribe.listening_on, index),
+                    None => self.additional_listeners.remove(&subscribe.listening_on),
+                };
+                index
+            }
+            IncomingRequest::PortUnsubscribe(unsubscribe) => for_port(unsubscribe.port),
+            IncomingRequest::ConnMetadata(request) => self
+                .additional_listeners
+                .get(&request.listener_address)
+                .copied(),
+        }
+    }
+
+    /// Handles a message from the agent connection of an additional target.
+    ///
+    /// Reconnects of this connection only affect the [`IncomingProxy`] of the target.
+    async fn handle_additional_agent_update(
+        &mut self,
+        index: usize,
+        message: ProxyMessage,
+    ) -> Result<(), ProxyRuntimeError> {
+        let Some(additional) = self.additional.get_mut(index) else {
+            return Ok(());
+        };
+
+        match message {
+            ProxyMessage::FromAgent(message) => match message {
+                DaemonMessage::Pong => {}
+                DaemonMessage::OperatorPing(id) => {
+                    additional
+                        .agent_tx
+                        .send(ClientMessage::OperatorPong(id))
+                        .await
+                }
+                DaemonMessage::Close(reason) => Err(ProxyRuntimeError::AgentFailed(reason))?,
+                DaemonMessage::Tcp(msg) => {
+                    additional
+                        .incoming
+                        .send(IncomingProxyMessage::AgentMirror(msg))
+                        .await
+                }
+                DaemonMessage::TcpSteal(msg) => {
+                    additional
+                        .incoming
+                        .send(IncomingProxyMessage::AgentSteal(msg))
+                        .await
+                }
+                DaemonMessage::SwitchProtocolVersionResponse(protocol_version) => {
+                    if CLIENT_READY_FOR_LOGS.matches(&protocol_version) {
+                        additional.agent_tx.send(ClientMessage::ReadyForLogs).await;
+                    }
+
+                    if let Some(algorithm) = additional.compression
+                        && COMPRESSION_VERSION.matches(&protocol_version)
+                    {
+                        additional
+                            .agent_tx
+                            .send(ClientMessage::SwitchCompression(algorithm))
+                            .await;
+                    }
+
+                    additional
+                        .incoming
+                        .send(IncomingProxyMessage::AgentProtocolVersion(protocol_version))
+                        .await
+                }
+                DaemonMessage::SwitchCompressionResponse(algorithm) => {
+                    tracing::debug!(
+                        ?algorithm,
+                        target_index = index,
+                        "Additional agent switched on compression"
+                    );
+                }
+                DaemonMessage::LogMessage(LogMessage { level, message }) => match level {
+                    LogLevel::Error => tracing::error!(
+                        message,
+                        target_index = index,
+                        "Received a log message from an additional agent"
+                    ),
+                    LogLevel::Warn => tracing::warn!(
+                        message,
+                        target_index = index,
+                        "Received a log message from an additional agent"
+                    ),
+                    LogLevel::Info => tracing::info!(
+                        message,
+                        target_index = index,
+                        "Received a log message from an additional agent"
+                    ),
+                },
+                message => Err(ProxyRuntimeError::UnexpectedAgentMessage(
+                    UnexpectedAgentMessage(message.into()),
+                ))?,
+            },
+            ProxyMessage::ConnectionRefresh(refresh) => {
+                let new_agent_tx = match &refresh {
+                    ConnectionRefresh::End(tx) => Some(tx.another()),
+                    ConnectionRefresh::Start | ConnectionRefresh::Request => None,
+                };
+
+                additional
+                    .incoming
+                    .send(IncomingProxyMessage::ConnectionRefresh(refresh))
+                    .await;
+
+                if let Some(new_agent_tx) = new_agent_tx {
+                    additional.agent_tx = new_agent_tx;
+                    additional
+                        .agent_tx
+                        .send(ClientMessage::SwitchProtocolVersion(
+                            mirrord_protocol::VERSION.clone(),
+                        ))
+                        .await;
+                }
+            }
+            other => self.handle(other).await?,
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     async fn handle_connection_refresh(
         &mut self,
@@ -728,17 +1058,19 @@ mod test {
     use hyper::{HeaderMap, Method, StatusCode, Uri, Version};
     use mirrord_analytics::NullReporter;
     use mirrord_config::{
-        LayerFileConfig, config::MirrordConfig, experimental::ExperimentalFileConfig,
+        LayerFileConfig,
+        config::MirrordConfig,
+        experimental::{ExperimentalFileConfig, ProtocolCompression},
     };
     use mirrord_intproxy_protocol::{
         IncomingRequest, LayerToProxyMessage, LocalMessage, NetProtocol, NewSessionRequest,
         OutgoingConnectRequest, OutgoingRequest, OutgoingResponse, PortSubscribe, PortSubscription,
-        ProcessInfo, ProxyToLayerMessage,
+        ProcessInfo, ProxyToLayerMessage, SessionControlRequest, SessionControlResponse,
         codec::{AsyncDecoder, AsyncEncoder},
     };
     use mirrord_protocol::{
-        ClientMessage, DaemonMessage, ErrorKindInternal, FileRequest, FileResponse, RemoteIOError,
-        ResponseError, VERSION,
+        ClientMessage, CompressionAlgorithm, DaemonMessage, ErrorKindInternal, FileRequest,
+        FileResponse, IdentifiedFileRequest, RemoteIOError, ResponseError, VERSION,
         dns::{AddressFamily, GetAddrInfoRequestV2, GetAddrInfoResponse, SockType},
         file::{OpenFileRequest, StatFsRequestV2},
         outgoing::{LayerConnectV2, SocketAddress, tcp::LayerTcpOutgoing},
@@ -762,7 +1094,8 @@ mod test {
     use crate::{
         IntProxy,
         agent_conn::{
-            AgentConnectInfo, AgentConnectInfoDiscriminants, AgentConnection, ReconnectFlow,
+            AdditionalAgentConnection, AgentConnectInfo, AgentConnectInfoDiscriminants,
+            AgentConnection, ReconnectFlow,
         },
     };
 
@@ -785,6 +1118,7 @@ mod test {
         let agent_conn = AgentConnection {
             connection,
             reconnect: ReconnectFlow::Break(AgentConnectInfoDiscriminants::DirectKubernetes),
+            supports_compression: true,
         };
         let proxy = IntProxy::new_with_connection(
             agent_conn,
@@ -792,6 +1126,9 @@ mod test {
             4096,
             Default::default(),
             Duration::from_secs(60),
+            Duration::ZERO,
+            None,
+            Default::default(),
             &ExperimentalFileConfig::default()
                 .generate_config(&mut Default::default())
                 .unwrap(),
@@ -877,7 +1214,10 @@ mod test {
                     proxy_tx.send(DaemonMessage::Pong).await.unwrap();
                 }
                 ClientMessage::ReadyForLogs => {}
-                ClientMessage::FileRequest(FileRequest::StatFsV2(StatFsRequestV2 { path })) => {
+                ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest {
+                    request: FileRequest::StatFsV2(StatFsRequestV2 { path }),
+                    ..
+                }) => {
                     assert_eq!(path, PathBuf::from("/some/path"));
                     break;
                 }
@@ -889,6 +1229,82 @@ mod test {
 
         proxy_handle.await.unwrap().unwrap();
     }
+
+    /// Verifies that [`IntProxy`] switches on the configured compression only when the agent
+    /// (and the operator) support it.
+    #[rstest::rstest]
+    #[case::supported(VERSION.clone(), true, true)]
+    #[case::unsupported(semver::Version::new(1, 27, 0), true, false)]
+    #[case::unsupported_by_operator(VERSION.clone(), false, false)]
+    #[tokio::test]
+    async fn intproxy_switches_compression(
+        #[case] agent_version: semver::Version,
+        #[case] supports_compression: bool,
+        #[case] expect_switch: bool,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+
+        let (connection, proxy_tx, proxy_rx) = Connection::dummy();
+
+        let agent_conn = AgentConnection {
+            connection,
+            reconnect: ReconnectFlow::Break(AgentConnectInfoDiscriminants::DirectKubernetes),
+            supports_compression,
+        };
+        let proxy = IntProxy::new_with_connection(
+            agent_conn,
+            listener,
+            4096,
+            Default::default(),
+            Duration::from_secs(60),
+            Duration::ZERO,
+            None,
+            Default::default(),
+            &ExperimentalFileConfig {
+                protocol_compression: Some(ProtocolCompression::Lz4),
+                ..Default::default()
+            }
+            .generate_config(&mut Default::default())
+            .unwrap(),
+        );
+        let proxy_handle = tokio::spawn(proxy.run(Duration::from_secs(60), Duration::ZERO));
+
+        match proxy_rx.next().await.unwrap() {
+            ClientMessage::SwitchProtocolVersion(version) => {
+                assert_eq!(version, *mirrord_protocol::VERSION)
+            }
+            other => panic!("unexpected client message from the proxy: {other:?}"),
+        }
+        proxy_tx
+            .send(DaemonMessage::SwitchProtocolVersionResponse(agent_version))
+            .await
+            .unwrap();
+
+        let mut switched = false;
+        tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                match proxy_rx.next().await.unwrap() {
+                    ClientMessage::Ping => {
+                        proxy_tx.send(DaemonMessage::Pong).await.unwrap();
+                    }
+                    ClientMessage::ReadyForLogs => {}
+                    ClientMessage::SwitchCompression(CompressionAlgorithm::Lz4) => {
+                        switched = true;
+                    }
+                    other => panic!("unexpected client message from the proxy: {other:?}"),
+                }
+            }
+        })
+        .await
+        .unwrap_err();
+
+        assert_eq!(switched, expect_switch);
+
+        proxy_handle.abort();
+    }
+
     /// Verifies that [`IntProxy`] goes in failover state when a runtime error happens
     #[tokio::test]
     async fn switch_to_failover() {
@@ -902,6 +1318,7 @@ mod test {
         let agent_conn = AgentConnection {
             connection,
             reconnect: ReconnectFlow::Break(AgentConnectInfoDiscriminants::DirectKubernetes),
+            supports_compression: true,
         };
 
         let proxy = IntProxy::new_with_connection(
@@ -910,6 +1327,9 @@ mod test {
             4096,
             Default::default(),
             Duration::from_secs(60),
+            Duration::ZERO,
+            None,
+            Default::default(),
             &ExperimentalFileConfig::default()
                 .generate_config(&mut Default::default())
                 .unwrap(),
@@ -995,6 +1415,7 @@ mod test {
         let agent_conn = AgentConnection {
             connection,
             reconnect: ReconnectFlow::Break(AgentConnectInfoDiscriminants::DirectKubernetes),
+            supports_compression: true,
         };
 
         let proxy = IntProxy::new_with_connection(
@@ -1003,6 +1424,9 @@ mod test {
             4096,
             Default::default(),
             Duration::from_secs(60),
+            Duration::ZERO,
+            None,
+            Default::default(),
             &ExperimentalFileConfig::default()
                 .generate_config(&mut Default::default())
                 .unwrap(),
@@ -1074,6 +1498,9 @@ mod test {
             4096,
             Default::default(),
             Duration::from_secs(60),
+            Duration::ZERO,
+            None,
+            Default::default(),
             &ExperimentalFileConfig::default()
                 .generate_config(&mut Default::default())
                 .unwrap(),
@@ -1268,7 +1695,10 @@ mod test {
 
         assert_eq!(
             next_proxy_msg(&to_proxy, &from_proxy).await,
-            ClientMessage::FileRequest(file_request)
+            ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest {
+                id: 0,
+                request: file_request,
+            })
         );
 
         drop(to_proxy);
@@ -1318,6 +1748,7 @@ mod test {
                     OutgoingConnectRequest {
                         remote_address: socket_addr.clone(),
                         protocol: NetProtocol::Stream,
+                        inspect: None,
                     },
                 )),
             })
@@ -1560,4 +1991,181 @@ mod test {
             }))
         ));
     }
+
+    /// Verifies that [`IntProxy`] lists connected layers and exits on
+    /// [`SessionControlRequest`]s.
+    #[tokio::test]
+    #[rstest::rstest]
+    #[timeout(Duration::from_secs(5))]
+    async fn session_control() {
+        let ReconnectTestSetup {
+            mut conn_rx,
+            mut from_layer,
+            mut to_layer,
+        } = setup_reconnect_test().await;
+
+        let (to_proxy, from_proxy) = conn_rx.recv().await.unwrap();
+        switch_protocol_version(&to_proxy, &from_proxy).await;
+
+        from_layer
+            .send(&LocalMessage {
+                message_id: 1,
+                inner: LayerToProxyMessage::SessionControl(SessionControlRequest::ListLayers),
+            })
+            .await
+            .unwrap();
+        from_layer.flush().await.unwrap();
+
+        match to_layer.receive().await.unwrap().unwrap() {
+            LocalMessage {
+                message_id: 1,
+                inner: ProxyToLayerMessage::SessionControl(SessionControlResponse::Layers(layers)),
+            } => {
+                assert_eq!(layers.len(), 1);
+                assert_eq!(layers[0].process_info.pid, 1337);
+            }
+            other => panic!("unexpected local message from the proxy: {other:?}"),
+        }
+
+        from_layer
+            .send(&LocalMessage {
+                message_id: 2,
+                inner: LayerToProxyMessage::SessionControl(SessionControlRequest::Stop),
+            })
+            .await
+            .unwrap();
+        from_layer.flush().await.unwrap();
+
+        assert!(matches!(
+            to_layer.receive().await.unwrap().unwrap(),
+            LocalMessage {
+                message_id: 2,
+                inner: ProxyToLayerMessage::SessionControl(SessionControlResponse::Stopping),
+            }
+        ));
+
+        // The proxy exits and closes the layer connection.
+        assert!(to_layer.receive().await.unwrap().is_none());
+    }
+
+    /// Verifies that [`IntProxy`] sends port subscriptions to the agent of the additional target
+    /// that lists the port, and all other subscriptions to the primary agent.
+    #[tokio::test]
+    #[rstest::rstest]
+    #[timeout(Duration::from_secs(5))]
+    async fn additional_target_subscriptions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+
+        let (connection, to_primary, from_primary) = Connection::dummy();
+        let agent_conn = AgentConnection {
+            connection,
+            reconnect: ReconnectFlow::Break(AgentConnectInfoDiscriminants::DirectKubernetes),
+            supports_compression: true,
+        };
+        let (connection, to_additional, from_additional) = Connection::dummy();
+        let additional_conn = AdditionalAgentConnection {
+            connection: AgentConnection {
+                connection,
+                reconnect: ReconnectFlow::Break(AgentConnectInfoDiscriminants::DirectKubernetes),
+                supports_compression: true,
+            },
+            ports: vec![9090],
+        };
+
+        let experimental = ExperimentalFileConfig::default()
+            .generate_config(&mut Default::default())
+            .unwrap();
+        let proxy = IntProxy::new_with_connection(
+            agent_conn,
+            listener,
+            4096,
+            Default::default(),
+            Duration::from_secs(60),
+            Duration::ZERO,
+            None,
+            Default::default(),
+            &experimental,
+        )
+        .with_additional_connections(
+            vec![additional_conn],
+            Default::default(),
+            &experimental,
+        );
+        tokio::spawn(proxy.run(Duration::from_secs(60), Duration::ZERO));
+
+        switch_protocol_version(&to_primary, &from_primary).await;
+        switch_protocol_version(&to_additional, &from_additional).await;
+
+        let conn = TcpStream::connect(proxy_addr).await.unwrap();
+        let (mut from_layer, mut to_layer) = mirrord_intproxy_protocol::codec::make_async_framed::<
+            LocalMessage<LayerToProxyMessage>,
+            LocalMessage<ProxyToLayerMessage>,
+        >(conn);
+        from_layer
+            .send(&LocalMessage {
+                message_id: 0,
+                inner: LayerToProxyMessage::NewSession(NewSessionRequest {
+                    process_info: ProcessInfo {
+                        pid: 1337,
+                        parent_pid: 1336,
+                        name: "hello there".into(),
+                        cmdline: vec!["hello there".into()],
+                        loaded: true,
+                    },
+                    parent_layer: None,
+                }),
+            })
+            .await
+            .unwrap();
+        from_layer.flush().await.unwrap();
+        assert!(matches!(
+            to_layer.receive().await,
+            Ok(Some(LocalMessage {
+                message_id: 0,
+                inner: ProxyToLayerMessage::NewSession(..),
+            }))
+        ));
+
+        for (message_id, port) in [(1, 9090), (2, 80)] {
+            from_layer
+                .send(&LocalMessage {
+                    message_id,
+                    inner: LayerToProxyMessage::Incoming(IncomingRequest::PortSubscribe(
+                        PortSubscribe {
+                            listening_on: format!("127.0.0.1:{port}").parse().unwrap(),
+                            subscription: PortSubscription::Steal(StealType::All(port)),
+                        },
+                    )),
+                })
+                .await
+                .unwrap();
+        }
+        from_layer.flush().await.unwrap();
+
+        assert_eq!(
+            next_proxy_msg(&to_additional, &from_additional).await,
+            ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(StealType::All(9090)))
+        );
+        assert_eq!(
+            next_proxy_msg(&to_primary, &from_primary).await,
+            ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(StealType::All(80)))
+        );
+
+        to_additional
+            .send(DaemonMessage::TcpSteal(DaemonTcp::SubscribeResult(Ok(
+                9090,
+            ))))
+            .await
+            .unwrap();
+        assert!(matches!(
+            to_layer.receive().await,
+            Ok(Some(LocalMessage {
+                message_id: 1,
+                inner: ProxyToLayerMessage::Incoming(
+                    mirrord_intproxy_protocol::IncomingResponse::PortSubscribe(Ok(()))
+                )
+            }))
+        ));
+    }
 }
@@ -107,6 +107,12 @@ pub enum MainTaskId {
     AgentConnection,
     FilesProxy,
     LayerConnection(LayerId),
+    /// Connection to the additional target with the given index, see
+    /// [`AdditionalAgentConnection`](crate::agent_conn::AdditionalAgentConnection).
+    AdditionalAgentConnection(usize),
+    /// [`IncomingProxy`](crate::proxies::incoming::IncomingProxy) of the additional target with
+    /// the given index.
+    AdditionalIncomingProxy(usize),
 }
 
 impl fmt::Display for MainTaskId {
@@ -120,6 +126,10 @@ impl fmt::Display for MainTaskId {
             Self::LayerConnection(id) => write!(f, "LAYER_CONNECTION_{}", id.0),
             Self::IncomingProxy => f.write_str("INCOMING_PROXY"),
             Self::FilesProxy => f.write_str("FILES_PROXY"),
+            Self::AdditionalAgentConnection(index) => {
+                write!(f, "ADDITIONAL_AGENT_CONNECTION_{index}")
+            }
+            Self::AdditionalIncomingProxy(index) => write!(f, "ADDITIONAL_INCOMING_PROXY_{index}"),
         }
     }
 }
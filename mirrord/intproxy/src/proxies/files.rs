@@ -1,15 +1,15 @@
 use core::fmt;
 use std::{
     borrow::Borrow,
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{HashMap, HashSet},
     ops::Not,
     vec,
 };
 
 use mirrord_intproxy_protocol::{LayerId, MessageId, ProxyToLayerMessage};
 use mirrord_protocol::{
-    ClientMessage, DaemonMessage, ErrorKindInternal, FileRequest, FileResponse, RemoteIOError,
-    ResponseError, file::*,
+    ClientMessage, DaemonMessage, ErrorKindInternal, FileRequest, FileResponse,
+    IdentifiedFileRequest, IdentifiedFileResponse, RemoteIOError, ResponseError, file::*,
 };
 use semver::Version;
 use thiserror::Error;
@@ -137,6 +137,8 @@ pub enum FilesProxyMessage {
     FileReq(MessageId, LayerId, FileRequest),
     /// Agent sent file response.
     FileRes(FileResponse),
+    /// Agent sent a response to an [`IdentifiedFileRequest`].
+    IdentifiedFileRes(IdentifiedFileResponse),
     /// Protocol version was negotiated with the agent.
     ProtocolVersion(Version),
     /// Layer instance forked.
@@ -202,7 +204,7 @@ impl fmt::Debug for BufferedDirData {
     }
 }
 
-/// Additional request data that is saved by [`FilesProxy`] in its [`RequestQueue`].
+/// Additional request data that is saved by [`FilesProxy`] for each outstanding request.
 /// Allows for handling buffered reads by marking requests that should be handled in a special way.
 #[derive(Debug, Default)]
 enum AdditionalRequestData {
@@ -246,7 +248,7 @@ pub struct RouterFileOps {
     /// Prepared error responses to outstanding [`FileRequest`]s.
     /// We must flush these when connection to the mirrord-agent is lost, otherwise the layer will
     /// hang.
-    queued_error_responses: VecDeque<AgentLostFileResponse>,
+    queued_error_responses: HashMap<(LayerId, MessageId), AgentLostFileResponse>,
 }
 
 impl fmt::Debug for RouterFileOps {
@@ -341,7 +343,8 @@ impl RouterFileOps {
         };
 
         if let Some(response) = request.agent_lost_response(layer_id, message_id) {
-            self.queued_error_responses.push_back(response);
+            self.queued_error_responses
+                .insert((layer_id, message_id), response);
         }
 
         Ok(Some(request))
@@ -387,17 +390,24 @@ impl RouterFileOps {
             }
         }
 
-        self.queued_error_responses.pop_front();
-
         response
     }
 
+    /// Notify this manager that the request was answered, either by the agent or locally.
+    #[tracing::instrument(level = Level::TRACE)]
+    pub fn request_done(&mut self, layer_id: LayerId, message_id: MessageId) {
+        self.queued_error_responses.remove(&(layer_id, message_id));
+    }
+
     /// Notify this manager that the agent was lost.
     /// Return messages to be sent to the user.
     #[tracing::instrument(level = Level::TRACE)]
-    pub fn agent_lost(&mut self) -> VecDeque<AgentLostFileResponse> {
+    pub fn agent_lost(&mut self) -> Vec<AgentLostFileResponse> {
         self.current_fd_offset = self.highest_user_facing_fd.map(|fd| fd + 1).unwrap_or(0);
-        std::mem::take(&mut self.queued_error_responses)
+        self.queued_error_responses
+            .drain()
+            .map(|(_, response)| response)
+            .collect()
     }
 }
 
@@ -435,9 +445,16 @@ pub struct FilesProxy {
     /// If equal to 0, this proxy does not buffer files.
     file_buffer_size: u64,
 
-    /// Stores metadata of outstanding requests.
+    /// Stores metadata of outstanding requests sent without an id, which the agent answers in
+    /// order.
     request_queue: RequestQueue<AdditionalRequestData>,
 
+    /// Stores metadata of outstanding [`IdentifiedFileRequest`]s, which the agent may answer in
+    /// any order.
+    identified_requests: HashMap<u64, (MessageId, LayerId, AdditionalRequestData)>,
+    /// Id for the next [`IdentifiedFileRequest`].
+    next_request_id: u64,
+
     /// For tracking remote file descriptors across layer instances (forks).
     remote_files: RemoteResources<u64>,
     /// Locally stored data of buffered files.
@@ -460,6 +477,7 @@ impl fmt::Debug for FilesProxy {
             .field("buffered_dirs", &self.buffered_dirs)
             .field("protocol_version", &self.protocol_version)
             .field("request_queue", &self.request_queue)
+            .field("identified_requests", &self.identified_requests.len())
             .field("reconnect_tracker", &self.reconnect_tracker)
             .finish()
     }
@@ -481,6 +499,8 @@ impl FilesProxy {
             file_buffer_size,
 
             request_queue: Default::default(),
+            identified_requests: Default::default(),
+            next_request_id: 0,
 
             remote_files: Default::default(),
             buffered_files: Default::default(),
@@ -499,6 +519,13 @@ impl FilesProxy {
             .is_some_and(|version| READDIR_BATCH_VERSION.matches(version))
     }
 
+    /// Returns whether [`mirrord_protocol`] version allows for [`IdentifiedFileRequest`]s.
+    fn identify_requests(&self) -> bool {
+        self.protocol_version
+            .as_ref()
+            .is_some_and(|version| FILE_REQUEST_ID_VERSION.matches(version))
+    }
+
     /// Returns whether this proxy is configured to buffer readonly files.
     fn buffer_reads(&self) -> bool {
         self.file_buffer_size > 0
@@ -512,22 +539,21 @@ impl FilesProxy {
 
     #[tracing::instrument(level = Level::TRACE, skip(message_bus))]
     async fn layer_closed(&mut self, closed: LayerClosed, message_bus: &mut MessageBus<Self>) {
-        for fd in self.remote_files.remove_all(closed.id) {
+        let fds = self.remote_files.remove_all(closed.id).collect::<Vec<_>>();
+        for fd in fds {
             self.buffered_files.remove(&fd);
-            message_bus
-                .send_agent(ClientMessage::FileRequest(FileRequest::Close(
-                    CloseFileRequest { fd },
-                )))
+            self.send_untracked(FileRequest::Close(CloseFileRequest { fd }), message_bus)
                 .await;
         }
 
-        for remote_fd in self.remote_dirs.remove_all(closed.id) {
+        let dir_fds = self.remote_dirs.remove_all(closed.id).collect::<Vec<_>>();
+        for remote_fd in dir_fds {
             self.buffered_dirs.remove(&remote_fd);
-            message_bus
-                .send_agent(ClientMessage::FileRequest(FileRequest::CloseDir(
-                    CloseDirRequest { remote_fd },
-                )))
-                .await;
+            self.send_untracked(
+                FileRequest::CloseDir(CloseDirRequest { remote_fd }),
+                message_bus,
+            )
+            .await;
         }
     }
 
@@ -536,6 +562,82 @@ impl FilesProxy {
         self.protocol_version.replace(version);
     }
 
+    /// Sends the [`FileRequest`] to the agent and saves its metadata, so that we can match the
+    /// response later.
+    ///
+    /// Uses [`IdentifiedFileRequest`]s when the [`mirrord_protocol`] version allows for them,
+    /// otherwise relies on the agent answering in order.
+    async fn send_request(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        additional_data: AdditionalRequestData,
+        request: FileRequest,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        if self.identify_requests() {
+            let id = self.next_request_id;
+            self.next_request_id += 1;
+            self.identified_requests
+                .insert(id, (message_id, layer_id, additional_data));
+            message_bus
+                .send_agent(ClientMessage::IdentifiedFileRequest(
+                    IdentifiedFileRequest { id, request },
+                ))
+                .await;
+        } else {
+            self.request_queue
+                .push_back_with_data(message_id, layer_id, additional_data);
+            message_bus
+                .send_agent(ClientMessage::FileRequest(request))
+                .await;
+        }
+    }
+
+    /// Sends a [`FileRequest`] that does not get any response from the agent, e.g.
+    /// [`FileRequest::Close`].
+    ///
+    /// Still uses an [`IdentifiedFileRequest`] when possible, so that the agent does not have to
+    /// execute it in order with other requests.
+    async fn send_untracked(&mut self, request: FileRequest, message_bus: &mut MessageBus<Self>) {
+        let message = if self.identify_requests() {
+            let id = self.next_request_id;
+            self.next_request_id += 1;
+            ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest { id, request })
+        } else {
+            ClientMessage::FileRequest(request)
+        };
+
+        message_bus.send_agent(message).await;
+    }
+
+    /// Takes metadata of the request answered by the agent.
+    ///
+    /// `id` is [`None`] for responses to requests sent without an id.
+    fn take_request(
+        &mut self,
+        id: Option<u64>,
+    ) -> Option<(MessageId, LayerId, AdditionalRequestData)> {
+        let (message_id, layer_id, additional_data) = match id {
+            Some(id) => self.identified_requests.remove(&id),
+            None => self.request_queue.pop_front_with_data(),
+        }?;
+
+        self.reconnect_tracker.request_done(layer_id, message_id);
+
+        Some((message_id, layer_id, additional_data))
+    }
+
+    /// Produces the error for an agent response that does not match any outstanding request.
+    fn unexpected_response(id: Option<u64>, response: FileResponse) -> UnexpectedAgentMessage {
+        let message = match id {
+            Some(id) => DaemonMessage::IdentifiedFile(IdentifiedFileResponse { id, response }),
+            None => DaemonMessage::File(response),
+        };
+
+        UnexpectedAgentMessage(message.into())
+    }
+
     /// Checks if the mirrord protocol version supports this [`FileRequest`].
     #[tracing::instrument(level = Level::TRACE, skip(self), ret, err(level = Level::WARN, Debug))]
     fn is_request_supported(&self, request: &FileRequest) -> Result<(), FileResponse> {
@@ -584,7 +686,8 @@ impl FilesProxy {
     }
 
     /// Handles the [`FileRequest`], converting a [`FilesProxyMessage`]  to a
-    /// [`ClientMessage::FileRequest`] and sending it on the [`MessageBus`]`.
+    /// [`ClientMessage::FileRequest`] (or [`ClientMessage::IdentifiedFileRequest`]) and sending it
+    /// on the [`MessageBus`]`.
     ///
     /// - Has a catch-all `other` for [`FileRequest`]s.
     ///
@@ -600,6 +703,7 @@ impl FilesProxy {
     ) {
         // Not supported in old `mirrord-protocol` versions.
         if let Err(response) = self.is_request_supported(&request) {
+            self.reconnect_tracker.request_done(layer_id, message_id);
             message_bus
                 .send(ToLayer {
                     message_id,
@@ -615,8 +719,7 @@ impl FilesProxy {
             FileRequest::Close(close) => {
                 if self.remote_files.remove(layer_id, close.fd) {
                     self.buffered_files.remove(&close.fd);
-                    message_bus
-                        .send_agent(ClientMessage::FileRequest(FileRequest::Close(close)))
+                    self.send_untracked(FileRequest::Close(close), message_bus)
                         .await;
                 }
             }
@@ -625,8 +728,7 @@ impl FilesProxy {
             FileRequest::CloseDir(close) => {
                 if self.remote_dirs.remove(layer_id, close.remote_fd) {
                     self.buffered_dirs.remove(&close.remote_fd);
-                    message_bus
-                        .send_agent(ClientMessage::FileRequest(FileRequest::CloseDir(close)))
+                    self.send_untracked(FileRequest::CloseDir(close), message_bus)
                         .await;
                 }
             }
@@ -638,11 +740,14 @@ impl FilesProxy {
                 } else {
                     Default::default()
                 };
-                self.request_queue
-                    .push_back_with_data(message_id, layer_id, additional_data);
-                message_bus
-                    .send_agent(ClientMessage::FileRequest(FileRequest::Open(open)))
-                    .await;
+                self.send_request(
+                    message_id,
+                    layer_id,
+                    additional_data,
+                    FileRequest::Open(open),
+                    message_bus,
+                )
+                .await;
             }
 
             // May require storing additional data in the request queue.
@@ -652,11 +757,14 @@ impl FilesProxy {
                 } else {
                     Default::default()
                 };
-                self.request_queue
-                    .push_back_with_data(message_id, layer_id, additional_data);
-                message_bus
-                    .send_agent(ClientMessage::FileRequest(FileRequest::OpenRelative(open)))
-                    .await;
+                self.send_request(
+                    message_id,
+                    layer_id,
+                    additional_data,
+                    FileRequest::OpenRelative(open),
+                    message_bus,
+                )
+                .await;
             }
 
             // Try to use local buffer if possible.
@@ -667,6 +775,7 @@ impl FilesProxy {
                     if let Some(from_buffer) = from_buffer {
                         let bytes = from_buffer.to_vec();
                         data.fd_position += read.buffer_size;
+                        self.reconnect_tracker.request_done(layer_id, message_id);
                         message_bus
                             .send(ToLayer {
                                 message_id,
@@ -685,32 +794,32 @@ impl FilesProxy {
                             requested_amount: read.buffer_size,
                             update_fd_position: true,
                         };
-                        self.request_queue.push_back_with_data(
+                        let request = FileRequest::ReadLimited(ReadLimitedFileRequest {
+                            remote_fd: read.remote_fd,
+                            buffer_size: std::cmp::max(read.buffer_size, self.file_buffer_size),
+                            start_from: data.fd_position,
+                        });
+                        self.send_request(
                             message_id,
                             layer_id,
                             additional_data,
-                        );
-                        message_bus
-                            .send_agent(ClientMessage::FileRequest(FileRequest::ReadLimited(
-                                ReadLimitedFileRequest {
-                                    remote_fd: read.remote_fd,
-                                    buffer_size: std::cmp::max(
-                                        read.buffer_size,
-                                        self.file_buffer_size,
-                                    ),
-                                    start_from: data.fd_position,
-                                },
-                            )))
-                            .await;
+                            request,
+                            message_bus,
+                        )
+                        .await;
                     }
                 }
 
                 // File is not buffered.
                 None => {
-                    self.request_queue.push_back(message_id, layer_id);
-                    message_bus
-                        .send_agent(ClientMessage::FileRequest(FileRequest::Read(read)))
-                        .await;
+                    self.send_request(
+                        message_id,
+                        layer_id,
+                        Default::default(),
+                        FileRequest::Read(read),
+                        message_bus,
+                    )
+                    .await;
                 }
             },
 
@@ -721,6 +830,7 @@ impl FilesProxy {
                     let from_buffer = data.read_from_buffer(read.buffer_size, read.start_from);
                     if let Some(from_buffer) = from_buffer {
                         let bytes = from_buffer.to_vec();
+                        self.reconnect_tracker.request_done(layer_id, message_id);
                         message_bus
                             .send(ToLayer {
                                 message_id,
@@ -739,32 +849,31 @@ impl FilesProxy {
                             requested_amount: read.buffer_size,
                             update_fd_position: false,
                         };
-                        self.request_queue.push_back_with_data(
+                        self.send_request(
                             message_id,
                             layer_id,
                             additional_data,
-                        );
-                        message_bus
-                            .send_agent(ClientMessage::FileRequest(FileRequest::ReadLimited(
-                                ReadLimitedFileRequest {
-                                    remote_fd: read.remote_fd,
-                                    buffer_size: std::cmp::max(
-                                        read.buffer_size,
-                                        self.file_buffer_size,
-                                    ),
-                                    start_from: read.start_from,
-                                },
-                            )))
-                            .await;
+                            FileRequest::ReadLimited(ReadLimitedFileRequest {
+                                remote_fd: read.remote_fd,
+                                buffer_size: std::cmp::max(read.buffer_size, self.file_buffer_size),
+                                start_from: read.start_from,
+                            }),
+                            message_bus,
+                        )
+                        .await;
                     }
                 }
 
                 // File is not buffered.
                 None => {
-                    self.request_queue.push_back(message_id, layer_id);
-                    message_bus
-                        .send_agent(ClientMessage::FileRequest(FileRequest::ReadLimited(read)))
-                        .await;
+                    self.send_request(
+                        message_id,
+                        layer_id,
+                        Default::default(),
+                        FileRequest::ReadLimited(read),
+                        message_bus,
+                    )
+                    .await;
                 }
             },
 
@@ -774,6 +883,7 @@ impl FilesProxy {
                 // Directory is buffered.
                 Some(data) => {
                     if let Some(direntry) = data.buffered_entries.next() {
+                        self.reconnect_tracker.request_done(layer_id, message_id);
                         message_bus
                             .send(ToLayer {
                                 message_id,
@@ -786,24 +896,30 @@ impl FilesProxy {
                             })
                             .await;
                     } else {
-                        self.request_queue.push_back(message_id, layer_id);
-                        message_bus
-                            .send_agent(ClientMessage::FileRequest(FileRequest::ReadDirBatch(
-                                ReadDirBatchRequest {
-                                    remote_fd: read_dir.remote_fd,
-                                    amount: Self::READDIR_BATCH_SIZE,
-                                },
-                            )))
-                            .await;
+                        self.send_request(
+                            message_id,
+                            layer_id,
+                            Default::default(),
+                            FileRequest::ReadDirBatch(ReadDirBatchRequest {
+                                remote_fd: read_dir.remote_fd,
+                                amount: Self::READDIR_BATCH_SIZE,
+                            }),
+                            message_bus,
+                        )
+                        .await;
                     }
                 }
 
                 // Directory is not buffered.
                 None => {
-                    self.request_queue.push_back(message_id, layer_id);
-                    message_bus
-                        .send_agent(ClientMessage::FileRequest(FileRequest::ReadDir(read_dir)))
-                        .await;
+                    self.send_request(
+                        message_id,
+                        layer_id,
+                        Default::default(),
+                        FileRequest::ReadDir(read_dir),
+                        message_bus,
+                    )
+                    .await;
                 }
             },
 
@@ -821,6 +937,7 @@ impl FilesProxy {
                             match result {
                                 Ok(offset) => seek.seek_from = SeekFromInternal::Start(offset),
                                 Err(..) => {
+                                    self.reconnect_tracker.request_done(layer_id, message_id);
                                     message_bus
                                         .send(ToLayer {
                                             message_id,
@@ -843,11 +960,14 @@ impl FilesProxy {
                         _ => AdditionalRequestData::Other,
                     };
 
-                self.request_queue
-                    .push_back_with_data(message_id, layer_id, additional_data);
-                message_bus
-                    .send_agent(ClientMessage::FileRequest(FileRequest::Seek(seek)))
-                    .await;
+                self.send_request(
+                    message_id,
+                    layer_id,
+                    additional_data,
+                    FileRequest::Seek(seek),
+                    message_bus,
+                )
+                .await;
             }
             FileRequest::StatFsV2(statfs_v2)
                 if self
@@ -855,12 +975,14 @@ impl FilesProxy {
                     .as_ref()
                     .is_none_or(|version| !STATFS_V2_VERSION.matches(version)) =>
             {
-                self.request_queue.push_back(message_id, layer_id);
-                message_bus
-                    .send_agent(ClientMessage::FileRequest(FileRequest::StatFs(
-                        statfs_v2.into(),
-                    )))
-                    .await;
+                self.send_request(
+                    message_id,
+                    layer_id,
+                    Default::default(),
+                    FileRequest::StatFs(statfs_v2.into()),
+                    message_bus,
+                )
+                .await;
             }
             FileRequest::XstatFsV2(xstatfs_v2)
                 if self
@@ -868,19 +990,19 @@ impl FilesProxy {
                     .as_ref()
                     .is_none_or(|version| !STATFS_V2_VERSION.matches(version)) =>
             {
-                self.request_queue.push_back(message_id, layer_id);
-                message_bus
-                    .send_agent(ClientMessage::FileRequest(FileRequest::XstatFs(
-                        xstatfs_v2.into(),
-                    )))
-                    .await;
+                self.send_request(
+                    message_id,
+                    layer_id,
+                    Default::default(),
+                    FileRequest::XstatFs(xstatfs_v2.into()),
+                    message_bus,
+                )
+                .await;
             }
 
             // Doesn't require any special logic.
             other => {
-                self.request_queue.push_back(message_id, layer_id);
-                message_bus
-                    .send_agent(ClientMessage::FileRequest(other))
+                self.send_request(message_id, layer_id, Default::default(), other, message_bus)
                     .await;
             }
         }
@@ -889,6 +1011,7 @@ impl FilesProxy {
     #[tracing::instrument(level = Level::TRACE, skip(message_bus), ret, err)]
     async fn file_response(
         &mut self,
+        id: Option<u64>,
         response: FileResponse,
         message_bus: &mut MessageBus<Self>,
     ) -> Result<(), FilesProxyError> {
@@ -896,10 +1019,8 @@ impl FilesProxy {
             // Update file maps.
             FileResponse::Open(Ok(open)) => {
                 let (message_id, layer_id, additional_data) =
-                    self.request_queue.pop_front_with_data().ok_or_else(|| {
-                        UnexpectedAgentMessage(
-                            DaemonMessage::File(FileResponse::Open(Ok(open.clone()))).into(),
-                        )
+                    self.take_request(id).ok_or_else(|| {
+                        Self::unexpected_response(id, FileResponse::Open(Ok(open.clone())))
                     })?;
 
                 self.remote_files.add(layer_id, open.fd);
@@ -919,10 +1040,8 @@ impl FilesProxy {
 
             // Update dir maps.
             FileResponse::OpenDir(Ok(open)) => {
-                let (message_id, layer_id) = self.request_queue.pop_front().ok_or_else(|| {
-                    UnexpectedAgentMessage(
-                        DaemonMessage::File(FileResponse::OpenDir(Ok(open.clone()))).into(),
-                    )
+                let (message_id, layer_id, _) = self.take_request(id).ok_or_else(|| {
+                    Self::unexpected_response(id, FileResponse::OpenDir(Ok(open.clone())))
                 })?;
 
                 self.remote_dirs.add(layer_id, open.fd);
@@ -943,10 +1062,8 @@ impl FilesProxy {
             // If the file is buffered, update `files_data`.
             FileResponse::ReadLimited(Ok(read)) => {
                 let (message_id, layer_id, additional_data) =
-                    self.request_queue.pop_front_with_data().ok_or_else(|| {
-                        UnexpectedAgentMessage(
-                            DaemonMessage::File(FileResponse::ReadLimited(Ok(read.clone()))).into(),
-                        )
+                    self.take_request(id).ok_or_else(|| {
+                        Self::unexpected_response(id, FileResponse::ReadLimited(Ok(read.clone())))
                     })?;
 
                 let AdditionalRequestData::ReadBuffered {
@@ -1015,11 +1132,8 @@ impl FilesProxy {
                 // need to ensure that if a Read request was sent by layer, a Read response is
                 // returned containing the error rather than a ReadLimited
                 let (message_id, layer_id, additional_data) =
-                    self.request_queue.pop_front_with_data().ok_or_else(|| {
-                        UnexpectedAgentMessage(
-                            DaemonMessage::File(FileResponse::ReadLimited(Err(error.clone())))
-                                .into(),
-                        )
+                    self.take_request(id).ok_or_else(|| {
+                        Self::unexpected_response(id, FileResponse::ReadLimited(Err(error.clone())))
                     })?;
 
                 let message = match additional_data {
@@ -1041,10 +1155,8 @@ impl FilesProxy {
             // If the file is buffered, update `files_data`.
             FileResponse::Seek(Ok(seek)) => {
                 let (message_id, layer_id, additional_data) =
-                    self.request_queue.pop_front_with_data().ok_or_else(|| {
-                        UnexpectedAgentMessage(
-                            DaemonMessage::File(FileResponse::Seek(Ok(seek.clone()))).into(),
-                        )
+                    self.take_request(id).ok_or_else(|| {
+                        Self::unexpected_response(id, FileResponse::Seek(Ok(seek.clone())))
                     })?;
 
                 if let AdditionalRequestData::SeekBuffered { fd } = additional_data {
@@ -1076,10 +1188,8 @@ impl FilesProxy {
 
             // Store extra entries in `dirs_data`.
             FileResponse::ReadDirBatch(Ok(batch)) => {
-                let (message_id, layer_id) = self.request_queue.pop_front().ok_or_else(|| {
-                    UnexpectedAgentMessage(
-                        DaemonMessage::File(FileResponse::ReadDirBatch(Ok(batch.clone()))).into(),
-                    )
+                let (message_id, layer_id, _) = self.take_request(id).ok_or_else(|| {
+                    Self::unexpected_response(id, FileResponse::ReadDirBatch(Ok(batch.clone())))
                 })?;
 
                 let Some(data) = self.buffered_dirs.get_mut(&batch.fd) else {
@@ -1112,10 +1222,8 @@ impl FilesProxy {
             }
             // Convert to XstatFsV2 so that the layer doesn't ever need to deal with the old type.
             FileResponse::XstatFs(res) => {
-                let (message_id, layer_id) = self.request_queue.pop_front().ok_or_else(|| {
-                    UnexpectedAgentMessage(
-                        DaemonMessage::File(FileResponse::XstatFs(res.clone())).into(),
-                    )
+                let (message_id, layer_id, _) = self.take_request(id).ok_or_else(|| {
+                    Self::unexpected_response(id, FileResponse::XstatFs(res.clone()))
                 })?;
                 message_bus
                     .send(ToLayer {
@@ -1130,9 +1238,9 @@ impl FilesProxy {
 
             // Doesn't require any special logic.
             other => {
-                let (message_id, layer_id) = self.request_queue.pop_front().ok_or_else(|| {
-                    UnexpectedAgentMessage(DaemonMessage::File(other.clone()).into())
-                })?;
+                let (message_id, layer_id, _) = self
+                    .take_request(id)
+                    .ok_or_else(|| Self::unexpected_response(id, other.clone()))?;
                 message_bus
                     .send(ToLayer {
                         message_id,
@@ -1182,6 +1290,9 @@ impl FilesProxy {
                 for response in responses {
                     message_bus.send(ToLayer::from(response)).await;
                 }
+                // Responses to these will never come, the layer already got the errors above.
+                self.request_queue = Default::default();
+                self.identified_requests.clear();
                 // Reset protocol version since we'll need another negotiation
                 // round for the new connection.
                 self.protocol_version = None;
@@ -1220,7 +1331,11 @@ impl BackgroundTask for FilesProxy {
                 }
                 FilesProxyMessage::FileRes(response) => {
                     let response = self.reconnect_tracker.map_response(response);
-                    self.file_response(response, message_bus).await?;
+                    self.file_response(None, response, message_bus).await?;
+                }
+                FilesProxyMessage::IdentifiedFileRes(IdentifiedFileResponse { id, response }) => {
+                    let response = self.reconnect_tracker.map_response(response);
+                    self.file_response(Some(id), response, message_bus).await?;
                 }
                 FilesProxyMessage::LayerClosed(closed) => {
                     self.layer_closed(closed, message_bus).await;
@@ -1245,7 +1360,8 @@ mod tests {
 
     use mirrord_intproxy_protocol::{LayerId, ProxyToLayerMessage};
     use mirrord_protocol::{
-        ClientMessage, ErrorKindInternal, FileRequest, FileResponse, RemoteIOError, ResponseError,
+        ClientMessage, ErrorKindInternal, FileRequest, FileResponse, IdentifiedFileRequest,
+        IdentifiedFileResponse, RemoteIOError, ResponseError,
         file::{
             FdOpenDirRequest, OpenDirResponse, OpenFileRequest, OpenFileResponse,
             OpenOptionsInternal, ReadDirBatchRequest, ReadDirBatchResponse, ReadDirRequest,
@@ -1265,6 +1381,11 @@ mod tests {
         main_tasks::{MainTaskId, ProxyMessage, ToLayer},
     };
 
+    /// Last [`mirrord_protocol`] version without [`IdentifiedFileRequest`]s.
+    ///
+    /// Most tests here use it, so that they can match requests and responses by their order.
+    const IN_ORDER_VERSION: Version = Version::new(1, 26, 0);
+
     #[derive(Debug, PartialEq)]
     enum Either<A, B> {
         Left(A),
@@ -1585,7 +1706,7 @@ mod tests {
     #[tokio::test]
     async fn reading_from_unbuffered_file(#[case] readonly: bool, #[case] buffering_enabled: bool) {
         let (proxy, mut tasks, out) = setup_proxy(
-            mirrord_protocol::VERSION.clone(),
+            IN_ORDER_VERSION,
             if buffering_enabled {
                 4096
             } else {
@@ -1645,7 +1766,7 @@ mod tests {
 
     #[tokio::test]
     async fn reading_from_buffered_file() {
-        let (proxy, mut tasks, out) = setup_proxy(mirrord_protocol::VERSION.clone(), 4096).await;
+        let (proxy, mut tasks, out) = setup_proxy(IN_ORDER_VERSION, 4096).await;
 
         let fd = open_file(&proxy, &mut tasks, &out, true).await;
         let contents = std::iter::repeat(0_u8..=255).flatten();
@@ -1774,7 +1895,7 @@ mod tests {
 
     #[tokio::test]
     async fn seeking_in_buffered_file() {
-        let (proxy, mut tasks, out) = setup_proxy(mirrord_protocol::VERSION.clone(), 4096).await;
+        let (proxy, mut tasks, out) = setup_proxy(IN_ORDER_VERSION, 4096).await;
 
         let fd = open_file(&proxy, &mut tasks, &out, true).await;
         let contents = std::iter::repeat(0_u8..=255).flatten();
@@ -1875,7 +1996,7 @@ mod tests {
         // relevant ticket: MBE-717: intproxy crashes when attempting to `cat` a remote dir
         // test that a ReadLimited response from agent is sent to the layer the same variant as the
         // original request type
-        let (proxy, mut tasks, out) = setup_proxy(mirrord_protocol::VERSION.clone(), 4096).await;
+        let (proxy, mut tasks, out) = setup_proxy(IN_ORDER_VERSION, 4096).await;
 
         // create dir - use empty file
         let fd = open_file(&proxy, &mut tasks, &out, true).await;
@@ -1904,4 +2025,113 @@ mod tests {
             ProxyToLayerMessage::File(FileResponse::Read(Err(res_error))),
         );
     }
+
+    /// Verifies that with [`IdentifiedFileRequest`]s the agent's responses are matched with the
+    /// layer's requests by their ids, not by their order.
+    #[tokio::test]
+    async fn identified_responses_out_of_order() {
+        let (proxy, mut tasks, out) = setup_proxy(mirrord_protocol::VERSION.clone(), 0).await;
+
+        let mut request_ids = Vec::new();
+        for (message_id, path) in [(1, "/slow/file"), (2, "/fast/file")] {
+            let request = FileRequest::Open(OpenFileRequest {
+                path: PathBuf::from(path),
+                open_options: OpenOptionsInternal {
+                    read: true,
+                    ..Default::default()
+                },
+            });
+            proxy
+                .send(FilesProxyMessage::FileReq(
+                    message_id,
+                    LayerId(0),
+                    request.clone(),
+                ))
+                .await;
+
+            match out.next().await.unwrap() {
+                ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest {
+                    id,
+                    request: sent,
+                }) => {
+                    assert_eq!(sent, request);
+                    request_ids.push(id);
+                }
+                other => panic!("unexpected message to the agent: {other:?}"),
+            }
+        }
+        assert_ne!(request_ids[0], request_ids[1]);
+
+        for (id, message_id, fd) in [(request_ids[1], 2, 0xfa57), (request_ids[0], 1, 0x5104)] {
+            let response = FileResponse::Open(Ok(OpenFileResponse { fd }));
+            proxy
+                .send(FilesProxyMessage::IdentifiedFileRes(
+                    IdentifiedFileResponse {
+                        id,
+                        response: response.clone(),
+                    },
+                ))
+                .await;
+
+            let update = tasks.next().await.unwrap().1.unwrap_message();
+            assert_eq!(
+                update,
+                ProxyMessage::ToLayer(ToLayer {
+                    message_id,
+                    layer_id: LayerId(0),
+                    message: ProxyToLayerMessage::File(response),
+                })
+            );
+        }
+    }
+
+    /// Verifies that without [`IdentifiedFileRequest`]s the agent's responses are matched with the
+    /// layer's requests by their order.
+    #[tokio::test]
+    async fn unidentified_responses_in_order() {
+        let (proxy, mut tasks, out) = setup_proxy(IN_ORDER_VERSION, 0).await;
+
+        for (message_id, path) in [(1, "/first/file"), (2, "/second/file")] {
+            let request = FileRequest::Open(OpenFileRequest {
+                path: PathBuf::from(path),
+                open_options: OpenOptionsInternal {
+                    read: true,
+                    ..Default::default()
+                },
+            });
+            proxy
+                .send(FilesProxyMessage::FileReq(
+                    message_id,
+                    LayerId(0),
+                    request.clone(),
+                ))
+                .await;
+
+            let update = out.next().await.unwrap();
+            assert_eq!(update, ClientMessage::FileRequest(request));
+        }
+
+        for (message_id, fd) in [(1, 0xf125), (2, 0x5ec0)] {
+            let response = FileResponse::Open(Ok(OpenFileResponse { fd }));
+            proxy
+                .send(FilesProxyMessage::FileRes(response.clone()))
+                .await;
+
+            let update = tasks.next().await.unwrap().1.unwrap_message();
+            assert_eq!(
+                update,
+                ProxyMessage::ToLayer(ToLayer {
+                    message_id,
+                    layer_id: LayerId(0),
+                    message: ProxyToLayerMessage::File(response),
+                })
+            );
+        }
+
+        drop(proxy);
+        let results = tasks.results().await;
+        for (_, result) in results {
+            assert!(result.is_ok(), "{result:?}");
+        }
+    }
 }
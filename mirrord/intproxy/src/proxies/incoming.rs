@@ -6,7 +6,14 @@
 //!    until connection becomes readable (is TCP) or receives an http request.
 //! 2. HttpSender -
 
-use std::{collections::HashMap, io, net::SocketAddr, ops::Not, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    ops::Not,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bound_socket::BoundTcpSocket;
 use futures::future::Either;
@@ -22,9 +29,8 @@ use mirrord_protocol::{
     ClientMessage, ConnectionId, RequestId, ResponseError,
     tcp::{
         ChunkedRequest, ChunkedRequestBodyV1, ChunkedRequestErrorV1, ChunkedRequestErrorV2,
-        DaemonTcp, HttpRequest, HttpRequestMetadata, IncomingTrafficTransportType,
-        InternalHttpBodyFrame, InternalHttpRequest, LayerTcp, LayerTcpSteal, NewTcpConnectionV1,
-        NewTcpConnectionV2,
+        DaemonTcp, HttpRequest, IncomingTrafficTransportType, InternalHttpBodyFrame,
+        InternalHttpRequest, LayerTcp, LayerTcpSteal, NewTcpConnectionV1, NewTcpConnectionV2,
     },
 };
 use semver::Version;
@@ -48,7 +54,7 @@ mod bound_socket;
 mod http;
 mod http_gateway;
 mod metadata_store;
-mod port_subscription_ext;
+pub(crate) mod port_subscription_ext;
 mod subscriptions;
 mod tasks;
 mod tcp_proxy;
@@ -203,6 +209,8 @@ pub struct IncomingProxy {
 impl IncomingProxy {
     /// Used when registering new tasks in the internal [`BackgroundTasks`] instance.
     const CHANNEL_SIZE: usize = 512;
+    /// How often retained subscriptions are checked for expiry.
+    const RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
     pub fn new(
         idle_local_http_connection_timeout: Duration,
@@ -226,6 +234,13 @@ impl IncomingProxy {
         }
     }
 
+    /// Retains port subscriptions of closed layers in the agent for the given period, so that a
+    /// restarted process can reuse them. Zero disables retention.
+    pub fn with_subscription_retention(mut self, retention: Duration) -> Self {
+        self.subscriptions = SubscriptionsManager::with_retention(retention);
+        self
+    }
+
     /// Starts a new [`HttpGatewayTask`] to handle the given request.
     ///
     /// If we don't have a [`PortSubscription`] for the port, the task is not started.
@@ -240,6 +255,7 @@ impl IncomingProxy {
         request: HttpRequest<StreamingBody>,
         body_tx: Option<mpsc::Sender<InternalHttpBodyFrame>>,
         transport: IncomingTrafficTransportType,
+        trace_context: Option<HashMap<String, String>>,
         is_steal: bool,
         message_bus: &MessageBus<Self>,
     ) {
@@ -298,7 +314,8 @@ impl IncomingProxy {
                 is_steal.then_some(self.response_mode),
                 server_addr,
                 transport,
-            ),
+            )
+            .with_trace_context(trace_context),
             if is_steal {
                 InProxyTask::StealHttpGateway(id)
             } else {
@@ -420,6 +437,7 @@ impl IncomingProxy {
                     request,
                     Some(body_tx),
                     IncomingTrafficTransportType::Tcp,
+                    None,
                     is_steal,
                     message_bus,
                 )
@@ -438,8 +456,8 @@ impl IncomingProxy {
                 };
 
                 let transport = request.transport;
+                let trace_context = request.metadata.trace_context().cloned();
 
-                let HttpRequestMetadata::V1 { destination, .. } = request.metadata;
                 let request = HttpRequest {
                     connection_id: request.connection_id,
                     request_id: request.request_id,
@@ -450,11 +468,18 @@ impl IncomingProxy {
                         version: request.request.version,
                         body,
                     },
-                    port: destination.port(),
+                    port: request.metadata.destination().port(),
                 };
 
-                self.start_http_gateway(request, body_tx, transport, is_steal, message_bus)
-                    .await;
+                self.start_http_gateway(
+                    request,
+                    body_tx,
+                    transport,
+                    trace_context,
+                    is_steal,
+                    message_bus,
+                )
+                .await;
             }
 
             ChunkedRequest::Body(ChunkedRequestBodyV1 {
@@ -587,6 +612,7 @@ impl IncomingProxy {
                     request.map_body(From::from),
                     None,
                     IncomingTrafficTransportType::Tcp,
+                    None,
                     is_steal,
                     message_bus,
                 )
@@ -598,6 +624,7 @@ impl IncomingProxy {
                     request.map_body(From::from),
                     None,
                     IncomingTrafficTransportType::Tcp,
+                    None,
                     is_steal,
                     message_bus,
                 )
@@ -648,17 +675,18 @@ impl IncomingProxy {
         match message {
             IncomingProxyMessage::LayerRequest(message_id, layer_id, req) => match req {
                 IncomingRequest::PortSubscribe(subscribe) => {
-                    let msg = self.subscriptions.layer_subscribed(
+                    let msgs = self.subscriptions.layer_subscribed(
                         layer_id,
                         message_id,
                         subscribe,
                         self.protocol_version.as_ref(),
                     );
-                    match msg {
-                        Some(Either::Left(m)) => message_bus.send(m).await,
-                        Some(Either::Right(m)) => message_bus.send_agent(m).await,
-                        None => (),
-                    };
+                    for msg in msgs {
+                        match msg {
+                            Either::Left(m) => message_bus.send(m).await,
+                            Either::Right(m) => message_bus.send_agent(m).await,
+                        }
+                    }
                 }
 
                 IncomingRequest::PortUnsubscribe(unsubscribe) => {
@@ -733,6 +761,7 @@ impl IncomingProxy {
                         // round for the new connection.
                         self.protocol_version = None;
                         self.restore_subscriptions_on_protocol_version_switch = true;
+                        self.subscriptions.clear_retained();
                     }
                     ConnectionRefresh::End(tx_handle) => {
                         message_bus.set_agent_tx(tx_handle);
@@ -900,6 +929,9 @@ impl BackgroundTask for IncomingProxy {
             None => self.tasks = Some(BackgroundTasks::new(message_bus.clone_agent_tx())),
         };
 
+        let mut retention_check = tokio::time::interval(Self::RETENTION_CHECK_INTERVAL);
+        retention_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
         loop {
             tokio::select! {
                 msg = message_bus.recv() => match msg {
@@ -924,6 +956,12 @@ impl BackgroundTask for IncomingProxy {
                         self.handle_http_gateway_update(id, true, update, message_bus).await;
                     }
                 },
+
+                _ = retention_check.tick(), if self.subscriptions.has_retained() => {
+                    for msg in self.subscriptions.expire_retained(Instant::now()) {
+                        message_bus.send_agent(msg).await;
+                    }
+                }
             }
         }
     }
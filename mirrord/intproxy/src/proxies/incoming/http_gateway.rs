@@ -1,5 +1,5 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     convert::Infallible,
     error::Report,
     fmt,
@@ -9,7 +9,12 @@ use std::{
 };
 
 use http_body_util::BodyExt;
-use hyper::{StatusCode, body::Incoming, http::response::Parts};
+use hyper::{
+    HeaderMap, StatusCode,
+    body::Incoming,
+    header::{HeaderName, HeaderValue},
+    http::response::Parts,
+};
 use mirrord_protocol::{
     ClientMessage, Payload,
     batched_body::BatchedBody,
@@ -19,9 +24,12 @@ use mirrord_protocol::{
         InternalHttpResponse, LayerTcpSteal,
     },
 };
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry_http::HeaderInjector;
 use tokio::time;
 use tokio_retry::strategy::ExponentialBackoff;
 use tracing::Level;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use super::{
     http::{ClientStore, LocalHttpError, ResponseMode, StreamingBody, mirrord_error_response},
@@ -47,6 +55,8 @@ pub struct HttpGatewayTask {
     server_addr: SocketAddr,
     /// How to transport the HTTP request to the server.
     transport: IncomingTrafficTransportType,
+    /// Trace context of the request, propagated from the agent.
+    trace_context: Option<HashMap<String, String>>,
 }
 
 impl fmt::Debug for HttpGatewayTask {
@@ -56,6 +66,7 @@ impl fmt::Debug for HttpGatewayTask {
             .field("response_mode", &self.response_mode)
             .field("server_addr", &self.server_addr)
             .field("transport", &self.transport)
+            .field("trace_context", &self.trace_context)
             .finish()
     }
 }
@@ -75,6 +86,44 @@ impl HttpGatewayTask {
             response_mode,
             server_addr,
             transport,
+            trace_context: None,
+        }
+    }
+
+    /// Makes this task continue the trace of the request, see
+    /// [`HttpRequestMetadata::trace_context`](mirrord_protocol::tcp::HttpRequestMetadata::trace_context).
+    pub fn with_trace_context(mut self, trace_context: Option<HashMap<String, String>>) -> Self {
+        self.trace_context = trace_context;
+        self
+    }
+
+    /// Passes the trace context of the request to the user application, in the W3C
+    /// `traceparent` and `tracestate` headers.
+    ///
+    /// When we export traces, the context of the current span is used. Otherwise, the context
+    /// received from the agent is passed as is.
+    fn inject_trace_context(&self, headers: &mut HeaderMap) {
+        let Some(trace_context) = self.trace_context.as_ref() else {
+            return;
+        };
+
+        let context = tracing::Span::current().context();
+        if context.span().span_context().is_valid() {
+            opentelemetry::global::get_text_map_propagator(|propagator| {
+                propagator.inject_context(&context, &mut HeaderInjector(headers))
+            });
+            return;
+        }
+
+        for (name, value) in trace_context {
+            let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) else {
+                continue;
+            };
+
+            headers.insert(name, value);
         }
     }
 
@@ -236,7 +285,9 @@ impl HttpGatewayTask {
                 &self.request.internal_request.uri,
             )
             .await?;
-        let mut response = client.send_request(self.request.clone()).await?;
+        let mut request = self.request.clone();
+        self.inject_trace_context(&mut request.internal_request.headers);
+        let mut response = client.send_request(request).await?;
         let on_upgrade = (response.status() == StatusCode::SWITCHING_PROTOCOLS).then(|| {
             tracing::debug!("Detected an HTTP upgrade");
             hyper::upgrade::on(&mut response)
@@ -361,6 +412,13 @@ impl BackgroundTask for HttpGatewayTask {
             .max_delay(Duration::from_millis(500))
             .take(9);
 
+        if let Some(trace_context) = self.trace_context.as_ref() {
+            let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+                propagator.extract(trace_context)
+            });
+            tracing::Span::current().set_parent(parent);
+        }
+
         let closed_token = message_bus.closed_token().clone();
 
         let mut attempt = 0;
@@ -1122,4 +1180,38 @@ mod test {
             }
         }
     }
+
+    /// Verifies that [`HttpGatewayTask`] passes the trace context received from the agent to the
+    /// user application, when the intproxy does not export traces.
+    #[tokio::test]
+    async fn passes_agent_trace_context() {
+        const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+        let request = HttpRequest {
+            connection_id: 0,
+            request_id: 0,
+            port: 80,
+            internal_request: InternalHttpRequest {
+                method: Method::GET,
+                uri: "/".parse().unwrap(),
+                headers: Default::default(),
+                version: Version::HTTP_11,
+                body: Default::default(),
+            },
+        };
+        let gateway = HttpGatewayTask::new(
+            request,
+            ClientStore::new_with_timeout(Duration::from_secs(1), Default::default()),
+            Some(ResponseMode::Basic),
+            "127.0.0.1:80".parse().unwrap(),
+            IncomingTrafficTransportType::Tcp,
+        )
+        .with_trace_context(Some(
+            [("traceparent".to_string(), TRACEPARENT.to_string())].into(),
+        ));
+
+        let mut headers = HeaderMap::new();
+        gateway.inject_trace_context(&mut headers);
+        assert_eq!(headers.get("traceparent").unwrap(), TRACEPARENT);
+    }
 }
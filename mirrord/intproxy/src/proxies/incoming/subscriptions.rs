@@ -1,11 +1,13 @@
 use std::{
     collections::{HashMap, hash_map::Entry},
     net::SocketAddr,
+    time::{Duration, Instant},
 };
 
 use futures::future::Either;
 use mirrord_intproxy_protocol::{
-    IncomingResponse, LayerId, MessageId, PortSubscribe, PortUnsubscribe, ProxyToLayerMessage,
+    IncomingResponse, LayerId, MessageId, PortSubscribe, PortSubscription, PortUnsubscribe,
+    ProxyToLayerMessage,
 };
 use mirrord_protocol::{BlockedAction, ClientMessage, Port, RemoteResult, ResponseError};
 use semver::Version;
@@ -56,6 +58,26 @@ impl Subscription {
         )
     }
 
+    /// Creates a new subscription from the given [`Source`], reusing a confirmed subscription that
+    /// is still active in the agent.
+    /// Additionally returns a message to be sent to the layer.
+    fn reused(source: Source) -> (Self, ToLayer) {
+        let message = ToLayer {
+            message_id: source.message,
+            layer_id: source.layer,
+            message: ProxyToLayerMessage::Incoming(IncomingResponse::PortSubscribe(Ok(()))),
+        };
+
+        (
+            Self {
+                queued_sources: Default::default(),
+                active_source: source,
+                confirmed: true,
+            },
+            message,
+        )
+    }
+
     /// Overwrites the active subscription [`Source`].
     /// Returns a message to be sent to the layer.
     /// Returns [`None`] if this subscription is still waiting for confirmation.
@@ -160,6 +182,15 @@ impl Subscription {
     }
 }
 
+/// A confirmed subscription that lost its last [`Source`] when a layer closed.
+///
+/// It is kept in the agent for a while, so that a restarted process can reuse it.
+#[derive(Debug)]
+struct RetainedSubscription {
+    subscription: PortSubscription,
+    retained_at: Instant,
+}
+
 /// Manages port subscriptions across all connected layers.
 /// Logic of this struct is a bit complicated for several reasons:
 /// 1. Layer can subscribe to a single port multiple times (e.g. with `port_mapping`)
@@ -168,13 +199,27 @@ impl Subscription {
 ///    subscription requests
 /// 4. Subscription response from the agent most of the time cannot be tracked down to its
 ///    subscription request
+/// 5. Subscriptions of a closed layer can be retained for a while, so that a restarted process
+///    (e.g. with `mirrord exec --watch`) does not wait for the agent again
 #[derive(Default)]
 pub struct SubscriptionsManager {
     remote_ports: RemoteResources<(Port, SocketAddr)>,
     subscriptions: HashMap<Port, Subscription>,
+    retained: HashMap<Port, RetainedSubscription>,
+    /// How long subscriptions of closed layers are retained, zero disables retention.
+    retention: Duration,
 }
 
 impl SubscriptionsManager {
+    /// Creates a new instance that retains subscriptions of closed layers for the given
+    /// `retention` period.
+    pub fn with_retention(retention: Duration) -> Self {
+        Self {
+            retention,
+            ..Default::default()
+        }
+    }
+
     /// Returns active [`PortSubscribe`] request for the given [`Port`].
     pub fn get(&self, port: Port) -> Option<&PortSubscribe> {
         self.subscriptions
@@ -183,11 +228,14 @@ impl SubscriptionsManager {
     }
 
     /// Registers a new port subscription in this struct.
-    /// Optionally returns a message to be sent.
+    /// Returns messages to be sent.
     ///
     /// Subsequent subscriptions of the same port will take precedence over previous ones, meaning
     /// that new connections will be routed to the listener from the most recent [`PortSubscribe`]
     /// request.
+    ///
+    /// A retained subscription of the same port is reused when it matches the request, otherwise
+    /// it is replaced in the agent.
     #[tracing::instrument(level = Level::INFO, skip(self), ret)]
     pub fn layer_subscribed(
         &mut self,
@@ -195,7 +243,7 @@ impl SubscriptionsManager {
         message_id: MessageId,
         request: PortSubscribe,
         protocol_version: Option<&Version>,
-    ) -> Option<Either<ProxyMessage, ClientMessage>> {
+    ) -> Vec<Either<ProxyMessage, ClientMessage>> {
         self.remote_ports.add(
             layer_id,
             (request.subscription.port(), request.listening_on),
@@ -212,12 +260,27 @@ impl SubscriptionsManager {
             Entry::Occupied(mut e) => e
                 .get_mut()
                 .push_source(source)
-                .map(|m| Either::Left(ProxyMessage::ToLayer(m))),
-            Entry::Vacant(e) => {
-                let (subscription, message) = Subscription::new(source, protocol_version);
-                e.insert(subscription);
-                Some(Either::Right(message))
-            }
+                .map(|m| Either::Left(ProxyMessage::ToLayer(m)))
+                .into_iter()
+                .collect(),
+            Entry::Vacant(e) => match self.retained.remove(&port) {
+                Some(retained) if retained.subscription == source.request.subscription => {
+                    let (subscription, message) = Subscription::reused(source);
+                    e.insert(subscription);
+                    vec![Either::Left(ProxyMessage::ToLayer(message))]
+                }
+                retained => {
+                    let unsubscribe = retained.map(|retained| {
+                        Either::Right(retained.subscription.wrap_agent_unsubscribe())
+                    });
+                    let (subscription, message) = Subscription::new(source, protocol_version);
+                    e.insert(subscription);
+                    unsubscribe
+                        .into_iter()
+                        .chain(std::iter::once(Either::Right(message)))
+                        .collect()
+                }
+            },
         }
     }
 
@@ -317,22 +380,73 @@ impl SubscriptionsManager {
 
     /// Notifies this struct about layer closing.
     /// Returns messages to be sent to the agent.
+    ///
+    /// If retention is enabled, confirmed subscriptions that lose their last source are retained
+    /// instead of being unsubscribed, see [`Self::expire_retained`].
     pub fn layer_closed(&mut self, layer_id: LayerId) -> Vec<ClientMessage> {
+        let now = Instant::now();
+
         self.remote_ports
             .remove_all(layer_id)
             .filter_map(|(port, listening_on)| {
                 let subscription = self.subscriptions.remove(&port)?;
-                match subscription.remove_source(listening_on) {
-                    Ok(subscription) => {
+                let retainable = (subscription.confirmed && !self.retention.is_zero())
+                    .then(|| subscription.active_source.request.subscription.clone());
+
+                match (subscription.remove_source(listening_on), retainable) {
+                    (Ok(subscription), _) => {
                         self.subscriptions.insert(port, subscription);
                         None
                     }
-                    Err(message) => Some(*message),
+                    (Err(..), Some(subscription)) => {
+                        tracing::debug!(port, "Retaining subscription of a closed layer");
+                        self.retained.insert(
+                            port,
+                            RetainedSubscription {
+                                subscription,
+                                retained_at: now,
+                            },
+                        );
+                        None
+                    }
+                    (Err(message), None) => Some(*message),
                 }
             })
             .collect()
     }
 
+    /// Returns whether there are any retained subscriptions, see [`Self::layer_closed`].
+    pub fn has_retained(&self) -> bool {
+        !self.retained.is_empty()
+    }
+
+    /// Drops retained subscriptions that were not reused within the retention period.
+    /// Returns messages to be sent to the agent.
+    pub fn expire_retained(&mut self, now: Instant) -> Vec<ClientMessage> {
+        let retention = self.retention;
+        let mut messages = vec![];
+
+        self.retained.retain(|port, retained| {
+            if now.saturating_duration_since(retained.retained_at) < retention {
+                return true;
+            }
+
+            tracing::debug!(port, "Retained subscription expired");
+            messages.push(retained.subscription.wrap_agent_unsubscribe());
+            false
+        });
+
+        messages
+    }
+
+    /// Forgets all retained subscriptions, without unsubscribing them in the agent.
+    ///
+    /// Used when the agent connection is refreshed, as the new connection starts without any
+    /// subscriptions.
+    pub fn clear_retained(&mut self) {
+        self.retained.clear();
+    }
+
     /// Notifies this struct about layer forking.
     pub fn layer_forked(&mut self, parent: LayerId, child: LayerId) {
         self.remote_ports.clone_all(parent, child);
@@ -346,7 +460,7 @@ impl SubscriptionsManager {
 #[cfg(test)]
 mod test {
     use mirrord_intproxy_protocol::PortSubscription;
-    use mirrord_protocol::tcp::{LayerTcp, MirrorType};
+    use mirrord_protocol::tcp::{LayerTcp, LayerTcpSteal, MirrorType, StealType};
 
     use super::*;
 
@@ -368,10 +482,10 @@ mod test {
         );
         assert!(
             matches!(
-                response,
-                Some(Either::Right(ClientMessage::Tcp(LayerTcp::PortSubscribe(
+                response.as_slice(),
+                [Either::Right(ClientMessage::Tcp(LayerTcp::PortSubscribe(
                     80
-                ))))
+                )))]
             ),
             "{response:?}"
         );
@@ -385,7 +499,7 @@ mod test {
             },
             None,
         );
-        assert!(response.is_none(), "{response:?}");
+        assert!(response.is_empty(), "{response:?}");
 
         let mut responses = manager.agent_responded(Ok(80)).unwrap();
         assert_eq!(responses.len(), 2, "{responses:?}");
@@ -449,10 +563,10 @@ mod test {
         );
         assert!(
             matches!(
-                response,
-                Some(Either::Right(ClientMessage::Tcp(LayerTcp::PortSubscribe(
+                response.as_slice(),
+                [Either::Right(ClientMessage::Tcp(LayerTcp::PortSubscribe(
                     80
-                ))))
+                )))]
             ),
             "{response:?}"
         );
@@ -516,10 +630,10 @@ mod test {
         );
         assert!(
             matches!(
-                response,
-                Some(Either::Right(ClientMessage::Tcp(LayerTcp::PortSubscribe(
+                response.as_slice(),
+                [Either::Right(ClientMessage::Tcp(LayerTcp::PortSubscribe(
                     80
-                ))))
+                )))]
             ),
             "{response:?}"
         );
@@ -545,4 +659,113 @@ mod test {
             .unwrap();
         assert!(responses.is_empty(), "{responses:?}");
     }
+
+    #[test]
+    fn with_retention() {
+        let listening_on = "127.0.0.1:1111".parse().unwrap();
+        let subscription = PortSubscription::Mirror(MirrorType::All(80));
+
+        let mut manager = SubscriptionsManager::with_retention(Duration::from_secs(10));
+
+        let response = manager.layer_subscribed(
+            LayerId(0),
+            0,
+            PortSubscribe {
+                listening_on,
+                subscription: subscription.clone(),
+            },
+            None,
+        );
+        assert_eq!(response.len(), 1, "{response:?}");
+        manager.agent_responded(Ok(80)).unwrap();
+
+        let messages = manager.layer_closed(LayerId(0));
+        assert!(messages.is_empty(), "{messages:?}");
+        assert!(manager.has_retained());
+        assert!(manager.get(80).is_none());
+
+        // The restarted process gets an immediate response, without the agent.
+        let response = manager.layer_subscribed(
+            LayerId(1),
+            0,
+            PortSubscribe {
+                listening_on,
+                subscription,
+            },
+            None,
+        );
+        assert!(
+            matches!(
+                response.as_slice(),
+                [Either::Left(ProxyMessage::ToLayer(ToLayer {
+                    layer_id: LayerId(1),
+                    message: ProxyToLayerMessage::Incoming(IncomingResponse::PortSubscribe(Ok(()))),
+                    message_id: 0,
+                }))]
+            ),
+            "{response:?}"
+        );
+        assert!(!manager.has_retained());
+        assert_eq!(manager.get(80).unwrap().listening_on, listening_on);
+
+        let messages = manager.layer_closed(LayerId(1));
+        assert!(messages.is_empty(), "{messages:?}");
+
+        let messages = manager.expire_retained(Instant::now());
+        assert!(messages.is_empty(), "{messages:?}");
+
+        let messages = manager.expire_retained(Instant::now() + Duration::from_secs(10));
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [ClientMessage::Tcp(LayerTcp::PortUnsubscribe(80))]
+            ),
+            "{messages:?}"
+        );
+        assert!(!manager.has_retained());
+    }
+
+    #[test]
+    fn with_retention_replaced() {
+        let listening_on = "127.0.0.1:1111".parse().unwrap();
+
+        let mut manager = SubscriptionsManager::with_retention(Duration::from_secs(10));
+
+        manager.layer_subscribed(
+            LayerId(0),
+            0,
+            PortSubscribe {
+                listening_on,
+                subscription: PortSubscription::Mirror(MirrorType::All(80)),
+            },
+            None,
+        );
+        manager.agent_responded(Ok(80)).unwrap();
+        manager.layer_closed(LayerId(0));
+
+        // The restarted process steals instead of mirroring, so the retained subscription is
+        // replaced.
+        let response = manager.layer_subscribed(
+            LayerId(1),
+            0,
+            PortSubscribe {
+                listening_on,
+                subscription: PortSubscription::Steal(StealType::All(80)),
+            },
+            None,
+        );
+        assert!(
+            matches!(
+                response.as_slice(),
+                [
+                    Either::Right(ClientMessage::Tcp(LayerTcp::PortUnsubscribe(80))),
+                    Either::Right(ClientMessage::TcpSteal(LayerTcpSteal::PortSubscribe(
+                        StealType::All(80)
+                    ))),
+                ]
+            ),
+            "{response:?}"
+        );
+        assert!(!manager.has_retained());
+    }
 }
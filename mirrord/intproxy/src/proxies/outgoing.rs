@@ -3,9 +3,11 @@
 use std::{collections::HashMap, fmt, io, net::SocketAddr, time::Instant};
 
 use bytes::Bytes;
+use mirrord_config::feature::network::dns::DnsOverrides;
 use mirrord_intproxy_protocol::{
-    LayerId, MessageId, NetProtocol, OutgoingConnMetadataResponse, OutgoingConnectRequest,
-    OutgoingConnectResponse, OutgoingRequest, OutgoingResponse, ProxyToLayerMessage,
+    HostnameInspection, LayerId, MessageId, NetProtocol, OutgoingConnMetadataResponse,
+    OutgoingConnectRequest, OutgoingConnectResponse, OutgoingRequest, OutgoingResponse,
+    ProxyToLayerMessage,
 };
 use mirrord_protocol::{
     ConnectionId, DaemonMessage, RemoteResult, ResponseError,
@@ -19,7 +21,12 @@ use semver::Version;
 use thiserror::Error;
 use tracing::Level;
 
-use self::interceptor::Interceptor;
+use self::{
+    capture::{Direction, OutgoingCapture},
+    dns::{DnsOverrideAction, DnsOverrideInterceptor},
+    inspector::Inspector,
+    interceptor::Interceptor,
+};
 use crate::{
     ProxyMessage,
     background_tasks::{
@@ -28,14 +35,17 @@ use crate::{
     error::{UnexpectedAgentMessage, agent_lost_io_error},
     main_tasks::{ConnectionRefresh, LayerClosed, LayerForked, ToLayer},
     proxies::outgoing::{
-        busy_tcp_listener::{BusyListenerMethod, BusyTcpListener},
-        net_protocol_ext::{NetProtocolExt, PreparedSocket},
+        busy_tcp_listener::BusyListenerMethod,
+        net_protocol_ext::{ConnectedSocket, NetProtocolExt, PreparedSocket},
     },
     remote_resources::RemoteResources,
     request_queue::RequestQueue,
 };
 
 mod busy_tcp_listener;
+pub mod capture;
+mod dns;
+mod inspector;
 mod interceptor;
 mod net_protocol_ext;
 
@@ -95,7 +105,8 @@ impl From<AgentLostOutgoingResponse> for ToLayer {
 
 #[derive(Debug)]
 struct ConnectInProgress {
-    prepared_socket: Option<BusyTcpListener>,
+    /// Socket prepared before the agent responded, the layer already knows its address.
+    prepared_socket: Option<PreparedSocket>,
     remote_address: SocketAddress,
     requested_at: Instant,
     layer_id: LayerId,
@@ -127,17 +138,34 @@ struct ConnectInProgress {
 /// 1. Proxy receives an [`OutgoingConnectRequest`] from the layer.
 /// 2. Proxy sends a corresponding [`LayerConnect`](mirrord_protocol::outgoing::LayerConnect) to the
 ///    agent.
-/// 3. Proxy creates a new [`BusyTcpListener`], and sends confirmation to the layer.
+/// 3. Proxy creates a new [`BusyTcpListener`](busy_tcp_listener::BusyTcpListener), and sends
+///    confirmation to the layer.
 /// 4. The layer starts connecting to the socket. Because we prepare the socket in a special way
-///    (see [`BusyTcpListener`] doc), this connect attempt will hang.
+///    (see [`BusyTcpListener`](busy_tcp_listener::BusyTcpListener) doc), this connect attempt will
+///    hang.
 /// 5. Proxy receives a confirmation from the agent.
 /// 6. Proxy starts a new outgoing [`Interceptor`] background task to manage the connection.
-/// 7. The [`Interceptor`] calls [`BusyTcpListener::accept`], and the layer socket finally connects
-///    to our socket.
+/// 7. The [`Interceptor`] calls
+///    [`BusyTcpListener::accept`](busy_tcp_listener::BusyTcpListener::accept), and the layer socket
+///    finally connects to our socket.
 /// 8. The proxy passes the data between the agent and the [`Interceptor`] task.
 /// 9. If the layer closes the connection, the [`Interceptor`] exits and the proxy notifies the
 ///    agent. If the agent closes the connection, the proxy shuts down the [`Interceptor`].
 ///
+/// # Hostname inspection flow
+///
+/// Used when the [`OutgoingConnectRequest`] contains a [`HostnameInspection`].
+///
+/// 1. Proxy receives an [`OutgoingConnectRequest`] from the layer.
+/// 2. Proxy creates a new socket, starts a new [`Inspector`] background task to manage it, and
+///    sends confirmation to the layer.
+/// 3. The layer connects to the socket, and the [`Inspector`] looks for the TLS SNI or HTTP `Host`
+///    in the first bytes sent through it.
+/// 4. If the connection should be made locally, the [`Inspector`] makes it and proxies the data
+///    until it's closed.
+/// 5. Otherwise, the [`Inspector`] passes the accepted socket back to the proxy, and the flow
+///    continues like the TCP non blocking flow, from step 2.
+///
 /// ## Why?
 ///
 /// In the regular flow, the user app's thread is unconditionally **blocked** during intproxy's
@@ -180,6 +208,11 @@ pub struct OutgoingProxy {
     /// For managing [`Interceptor`] tasks.
     background_tasks: Option<BackgroundTasks<InterceptorId, Bytes, io::Error>>,
 
+    /// Connections handled by active [`Inspector`] tasks, by outgoing connection local IDs.
+    inspections: HashMap<u128, (ConnectInProgress, TaskSender<Inspector>)>,
+    /// For managing [`Inspector`] tasks.
+    inspectors: Option<BackgroundTasks<u128, ConnectedSocket, io::Error>>,
+
     /// Whether TCP connect requests should be handled in a non-blocking way.
     ///
     /// See struct level docs for more info.
@@ -195,6 +228,11 @@ pub struct OutgoingProxy {
     connections_in_layers: RemoteResources<u128>,
     /// Maps outgoing connection local IDs to local addresses of corresponding agent sockets.
     agent_local_addresses: HashMap<u128, SocketAddr>,
+
+    /// Writes the traffic of the remote connections to a file, see [`OutgoingCapture`].
+    capture: Option<OutgoingCapture>,
+    /// Answers the DNS queries sent to the nameserver for overridden hostnames.
+    dns: DnsOverrideInterceptor,
 }
 
 impl OutgoingProxy {
@@ -219,13 +257,30 @@ impl OutgoingProxy {
             v2_reqs: Default::default(),
             txs: Default::default(),
             background_tasks: Default::default(),
+            inspections: Default::default(),
+            inspectors: Default::default(),
             non_blocking_tcp_connect,
             protocol_version: Default::default(),
             connections_in_layers: Default::default(),
             agent_local_addresses: Default::default(),
+            capture: None,
+            dns: Default::default(),
         }
     }
 
+    /// Captures the traffic of the remote connections with the given [`OutgoingCapture`].
+    pub fn with_capture(mut self, capture: Option<OutgoingCapture>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Answers the DNS queries sent through UDP connections to port `53` with the given
+    /// [`DnsOverrides`], see [`DnsOverrideInterceptor`].
+    pub fn with_dns_overrides(mut self, overrides: DnsOverrides) -> Self {
+        self.dns = DnsOverrideInterceptor::new(overrides);
+        self
+    }
+
     /// Retrieves correct [`RequestQueue`] for the given [`NetProtocol`].
     fn queue(&mut self, protocol: NetProtocol) -> &mut RequestQueue<ConnectInProgress> {
         match protocol {
@@ -260,7 +315,12 @@ impl OutgoingProxy {
             return Ok(());
         };
 
-        interceptor.send(bytes.0).await;
+        if let Some(capture) = &mut self.capture {
+            capture.data(id, Direction::Received, &bytes.0);
+        }
+
+        let bytes = self.dns.received(id, bytes.0);
+        interceptor.send(bytes).await;
 
         Ok(())
     }
@@ -344,12 +404,23 @@ impl OutgoingProxy {
             }
         };
 
+        let id = InterceptorId {
+            connection_id,
+            protocol,
+        };
+
+        self.dns.connected(id, &remote_address);
+
         if let SocketAddress::Ip(addr) = &local_address {
             self.agent_local_addresses.insert(in_progress.id, *addr);
+
+            if let (Some(capture), SocketAddress::Ip(peer)) = (&mut self.capture, &remote_address) {
+                capture.connected(id, *addr, *peer);
+            }
         }
 
         let prepared_socket = match in_progress.prepared_socket {
-            Some(socket) => PreparedSocket::BusyTcpListener(socket),
+            Some(socket) => socket,
             None => {
                 let prepared_socket = protocol.prepare_socket(remote_address).await?;
                 let layer_address = prepared_socket.local_address()?;
@@ -372,11 +443,6 @@ impl OutgoingProxy {
             }
         };
 
-        let id = InterceptorId {
-            connection_id,
-            protocol,
-        };
-
         tracing::debug!(
             %id,
             remote_address = %in_progress.remote_address,
@@ -400,13 +466,29 @@ impl OutgoingProxy {
         request: OutgoingConnectRequest,
         message_bus: &mut MessageBus<Self>,
     ) -> Result<(), OutgoingProxyError> {
+        if let Some(inspection) = request.inspect
+            && request.protocol == NetProtocol::Stream
+        {
+            return self
+                .start_inspection(
+                    message_id,
+                    session_id,
+                    request.remote_address,
+                    inspection,
+                    message_bus,
+                )
+                .await;
+        }
+
         let prepared_socket = if self.non_blocking_tcp_connect
             && matches!(&request.remote_address, SocketAddress::Ip(..))
             && request.protocol == NetProtocol::Stream
         {
             if let Some(method) = BusyListenerMethod::recommended().await {
                 let ipv4 = matches!(&request.remote_address, SocketAddress::Ip(ip) if ip.is_ipv4());
-                Some(method.prepare_socket(ipv4).await?)
+                Some(PreparedSocket::BusyTcpListener(
+                    method.prepare_socket(ipv4).await?,
+                ))
             } else {
                 tracing::warn!(
                     remote_address = %request.remote_address,
@@ -423,14 +505,13 @@ impl OutgoingProxy {
         self.connections_in_layers.add(session_id, connection_id);
 
         if let Some(socket) = &prepared_socket {
-            let addr = socket.local_addr()?;
             let to_layer = ToLayer {
                 message_id,
                 layer_id: session_id,
                 message: ProxyToLayerMessage::Outgoing(OutgoingResponse::Connect(Ok(
                     OutgoingConnectResponse {
                         connection_id,
-                        layer_address: addr.into(),
+                        layer_address: socket.local_address()?,
                         in_cluster_address: None,
                     },
                 ))),
@@ -438,48 +519,120 @@ impl OutgoingProxy {
             message_bus.send(to_layer).await;
         }
 
+        let in_progress = ConnectInProgress {
+            id: connection_id,
+            prepared_socket,
+            remote_address: request.remote_address,
+            requested_at: Instant::now(),
+            layer_id: session_id,
+            message_id,
+        };
+        self.send_connect_request(in_progress, request.protocol, message_bus)
+            .await;
+
+        Ok(())
+    }
+
+    /// Sends the connection request to the agent, and saves it until the agent responds.
+    async fn send_connect_request(
+        &mut self,
+        in_progress: ConnectInProgress,
+        protocol: NetProtocol,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let remote_address = in_progress.remote_address.clone();
+
         let uid = if self
             .protocol_version
             .as_ref()
             .is_some_and(|version| OUTGOING_CONNECT_V2.matches(version))
         {
             let request_uid = Uid::new_v4();
-            self.v2_reqs.insert(
-                (request_uid, request.protocol),
-                ConnectInProgress {
-                    prepared_socket,
-                    remote_address: request.remote_address.clone(),
-                    requested_at: Instant::now(),
-                    layer_id: session_id,
-                    message_id,
-                    id: connection_id,
-                },
-            );
+            self.v2_reqs.insert((request_uid, protocol), in_progress);
             Some(request_uid)
         } else {
-            self.queue(request.protocol).push_back_with_data(
-                message_id,
-                session_id,
-                ConnectInProgress {
-                    id: connection_id,
-                    prepared_socket,
-                    remote_address: request.remote_address.clone(),
-                    requested_at: Instant::now(),
-                    layer_id: session_id,
-                    message_id,
-                },
+            self.queue(protocol).push_back_with_data(
+                in_progress.message_id,
+                in_progress.layer_id,
+                in_progress,
             );
             None
         };
 
-        let msg = request
-            .protocol
-            .wrap_agent_connect(request.remote_address, uid);
+        let msg = protocol.wrap_agent_connect(remote_address, uid);
         message_bus.send_agent(msg).await;
+    }
+
+    /// Prepares a local socket for the layer's TCP connection, and starts an [`Inspector`] task
+    /// that decides whether it should be made remotely. Replies to the layer's request.
+    #[tracing::instrument(level = Level::DEBUG, skip(self, message_bus), err)]
+    async fn start_inspection(
+        &mut self,
+        message_id: MessageId,
+        layer_id: LayerId,
+        remote_address: SocketAddress,
+        inspection: HostnameInspection,
+        message_bus: &mut MessageBus<Self>,
+    ) -> Result<(), OutgoingProxyError> {
+        let socket = NetProtocol::Stream
+            .prepare_socket(remote_address.clone())
+            .await?;
+        let layer_address = socket.local_address()?;
+
+        // The chance for collision here is negligible.
+        let connection_id = rand::random::<u128>();
+        self.connections_in_layers.add(layer_id, connection_id);
+
+        let inspector = self.inspectors.as_mut().unwrap().register(
+            Inspector::new(connection_id, socket, inspection),
+            connection_id,
+            1,
+        );
+        let in_progress = ConnectInProgress {
+            id: connection_id,
+            prepared_socket: None,
+            remote_address,
+            requested_at: Instant::now(),
+            layer_id,
+            message_id,
+        };
+        self.inspections
+            .insert(connection_id, (in_progress, inspector));
+
+        message_bus
+            .send(ToLayer {
+                message_id,
+                layer_id,
+                message: ProxyToLayerMessage::Outgoing(OutgoingResponse::Connect(Ok(
+                    OutgoingConnectResponse {
+                        connection_id,
+                        layer_address,
+                        in_cluster_address: None,
+                    },
+                ))),
+            })
+            .await;
 
         Ok(())
     }
 
+    /// Handles the socket accepted by an [`Inspector`] task, when the connection should be made
+    /// remotely.
+    async fn handle_inspected(
+        &mut self,
+        id: u128,
+        socket: ConnectedSocket,
+        message_bus: &mut MessageBus<Self>,
+    ) {
+        let Some((mut in_progress, _)) = self.inspections.remove(&id) else {
+            return;
+        };
+
+        in_progress.prepared_socket = Some(PreparedSocket::Accepted(socket));
+        self.send_connect_request(in_progress, NetProtocol::Stream, message_bus)
+            .await;
+    }
+
     #[tracing::instrument(level = Level::INFO, skip_all, ret)]
     async fn handle_connection_refresh(
         &mut self,
@@ -491,6 +644,12 @@ impl OutgoingProxy {
                 tracing::debug!("Closing all local connections");
                 self.txs.clear();
                 self.background_tasks.as_mut().unwrap().clear();
+                self.inspections.clear();
+                self.inspectors.as_mut().unwrap().clear();
+                if let Some(capture) = &mut self.capture {
+                    capture.clear();
+                }
+                self.dns.clear();
                 self.protocol_version = None;
 
                 tracing::debug!(
@@ -603,6 +762,10 @@ impl BackgroundTask for OutgoingProxy {
                 self.background_tasks = Some(BackgroundTasks::new(message_bus.clone_agent_tx()))
             }
         };
+        match &mut self.inspectors {
+            Some(tasks) => tasks.set_agent_tx(message_bus.clone_agent_tx()),
+            None => self.inspectors = Some(BackgroundTasks::new(message_bus.clone_agent_tx())),
+        };
 
         loop {
             tokio::select! {
@@ -615,6 +778,9 @@ impl BackgroundTask for OutgoingProxy {
                         DaemonTcpOutgoing::Close(close) => {
                             let id = InterceptorId { connection_id: close, protocol: NetProtocol::Stream};
                             self.txs.remove(&id);
+                            if let Some(capture) = &mut self.capture {
+                                capture.closed(id);
+                            }
                         },
                         DaemonTcpOutgoing::Read(read) => self.handle_agent_read(read, NetProtocol::Stream).await?,
                         DaemonTcpOutgoing::Connect(connect) => self.handle_connect_response(connect, NetProtocol::Stream, None, message_bus).await?,
@@ -629,6 +795,10 @@ impl BackgroundTask for OutgoingProxy {
                         DaemonUdpOutgoing::Close(close) => {
                             let id = InterceptorId { connection_id: close, protocol: NetProtocol::Datagrams};
                             self.txs.remove(&id);
+                            self.dns.closed(id);
+                            if let Some(capture) = &mut self.capture {
+                                capture.closed(id);
+                            }
                         }
                         DaemonUdpOutgoing::Read(read) => self.handle_agent_read(read, NetProtocol::Datagrams).await?,
                         DaemonUdpOutgoing::Connect(connect) => self.handle_connect_response(connect, NetProtocol::Datagrams, None, message_bus).await?,
@@ -657,10 +827,20 @@ impl BackgroundTask for OutgoingProxy {
                 },
 
                 Some(task_update) = self.background_tasks.as_mut().unwrap().next() => match task_update {
-                    (id, TaskUpdate::Message(bytes)) => {
-                        let msg = id.protocol.wrap_agent_write(id.connection_id, bytes);
-                        message_bus.send_agent(msg).await;
-                    }
+                    (id, TaskUpdate::Message(bytes)) => match self.dns.sent(id, bytes) {
+                        DnsOverrideAction::Forward(bytes) => {
+                            if let Some(capture) = &mut self.capture {
+                                capture.data(id, Direction::Sent, &bytes);
+                            }
+                            let msg = id.protocol.wrap_agent_write(id.connection_id, bytes);
+                            message_bus.send_agent(msg).await;
+                        }
+                        DnsOverrideAction::Respond(response) => {
+                            if let Some(interceptor) = self.txs.get(&id) {
+                                interceptor.send(response).await;
+                            }
+                        }
+                    },
                     (id, TaskUpdate::Finished(res)) => {
                         match res {
                             Ok(()) => tracing::debug!(%id, "Interceptor finished"),
@@ -678,6 +858,28 @@ impl BackgroundTask for OutgoingProxy {
                             let _ = message_bus.send_agent(msg).await;
                             self.txs.remove(&id);
                         }
+
+                        self.dns.closed(id);
+                        if let Some(capture) = &mut self.capture {
+                            capture.closed(id);
+                        }
+                    }
+                },
+
+                Some(task_update) = self.inspectors.as_mut().unwrap().next() => match task_update {
+                    (id, TaskUpdate::Message(socket)) => self.handle_inspected(id, socket, message_bus).await,
+                    (id, TaskUpdate::Finished(res)) => {
+                        match res {
+                            Ok(()) => tracing::debug!(id, "Inspector finished"),
+                            Err(TaskError::Error(error)) => {
+                                tracing::warn!(id, %error, "Inspector failed");
+                            }
+                            Err(TaskError::Panic) => {
+                                tracing::error!(id, "Inspector panicked");
+                            }
+                        }
+
+                        self.inspections.remove(&id);
                     }
                 },
             }
@@ -726,6 +928,7 @@ mod test {
                     OutgoingRequest::Connect(OutgoingConnectRequest {
                         remote_address: SocketAddress::Ip(peer_addr),
                         protocol: NetProtocol::Stream,
+                        inspect: None,
                     }),
                     i,
                     LayerId(0),
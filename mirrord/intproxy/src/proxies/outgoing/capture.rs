@@ -0,0 +1,518 @@
+//! Writes the remote outgoing traffic to a [pcap-ng](https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-03.html)
+//! file, see [`OutgoingCapture`].
+//!
+//! The file is written from a blocking task, see [`write_blocks`].
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use mirrord_intproxy_protocol::NetProtocol;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::InterceptorId;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// Raw IPv4/IPv6 packets, without the link layer.
+const LINKTYPE_RAW: u16 = 101;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+/// Payload of a single synthesized packet, so that its length fits in the IP header.
+const MAX_PAYLOAD: usize = 65_000;
+
+/// Direction of the data in a captured connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From the user application to the peer.
+    Sent,
+    /// From the peer to the user application.
+    Received,
+}
+
+/// State of a single captured connection.
+#[derive(Debug)]
+struct CapturedStream {
+    protocol: NetProtocol,
+    /// Address of the agent's socket, in the cluster.
+    local: SocketAddr,
+    /// Address of the peer.
+    peer: SocketAddr,
+    /// Next TCP sequence number of the local side.
+    local_seq: u32,
+    /// Next TCP sequence number of the peer.
+    peer_seq: u32,
+    local_fin: bool,
+    peer_fin: bool,
+}
+
+/// Writes the traffic of the outgoing connections made through the agent to a pcap-ng file, so that
+/// it can be inspected with tools like Wireshark.
+///
+/// We don't see the real packets, so we synthesize IP packets with the real addresses of the
+/// connections (from [`DaemonConnect`](mirrord_protocol::outgoing::DaemonConnect)), including the
+/// TCP handshake and shutdown.
+///
+/// The packets are sent to a blocking task that writes them to the file, so that the proxy never
+/// waits for the disk. Dropping this struct closes the file.
+pub struct OutgoingCapture {
+    /// Sends pcap-ng blocks to the [`write_blocks`] task.
+    ///
+    /// [`None`] after the task exits on a write failure.
+    blocks: Option<UnboundedSender<Vec<u8>>>,
+    streams: HashMap<InterceptorId, CapturedStream>,
+}
+
+impl OutgoingCapture {
+    /// Creates the capture file at the given `path`, truncating it if it exists, and starts the
+    /// task that writes to it.
+    ///
+    /// Must be called from within a [`tokio`] runtime.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let mut section_header = Vec::with_capacity(16);
+        section_header.extend(BYTE_ORDER_MAGIC.to_le_bytes());
+        // Version 1.0.
+        section_header.extend(1u16.to_le_bytes());
+        section_header.extend(0u16.to_le_bytes());
+        // Unknown section length.
+        section_header.extend((-1i64).to_le_bytes());
+        write_block(&mut writer, BLOCK_SECTION_HEADER, &section_header)?;
+
+        let mut interface = Vec::with_capacity(8);
+        interface.extend(LINKTYPE_RAW.to_le_bytes());
+        interface.extend(0u16.to_le_bytes());
+        // No snapshot length limit.
+        interface.extend(0u32.to_le_bytes());
+        write_block(&mut writer, BLOCK_INTERFACE_DESCRIPTION, &interface)?;
+
+        writer.flush()?;
+
+        let (blocks, rx) = mpsc::unbounded_channel();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || write_blocks(writer, rx, path));
+
+        Ok(Self {
+            blocks: Some(blocks),
+            streams: Default::default(),
+        })
+    }
+
+    /// Starts capturing a new connection. For TCP, writes the handshake.
+    pub fn connected(&mut self, id: InterceptorId, local: SocketAddr, peer: SocketAddr) {
+        let (local, peer) = same_family(local, peer);
+        let mut stream = CapturedStream {
+            protocol: id.protocol,
+            local,
+            peer,
+            local_seq: rand::random(),
+            peer_seq: rand::random(),
+            local_fin: false,
+            peer_fin: false,
+        };
+
+        if stream.protocol == NetProtocol::Stream {
+            let syn = tcp_segment(&stream, Direction::Sent, TCP_SYN, &[]);
+            stream.local_seq = stream.local_seq.wrapping_add(1);
+            let syn_ack = tcp_segment(&stream, Direction::Received, TCP_SYN | TCP_ACK, &[]);
+            stream.peer_seq = stream.peer_seq.wrapping_add(1);
+            let ack = tcp_segment(&stream, Direction::Sent, TCP_ACK, &[]);
+
+            self.write_packets([syn, syn_ack, ack]);
+        }
+
+        self.streams.insert(id, stream);
+    }
+
+    /// Captures data of a connection. Empty data means that the side shut down writing.
+    pub fn data(&mut self, id: InterceptorId, direction: Direction, bytes: &[u8]) {
+        let Some(stream) = self.streams.get_mut(&id) else {
+            return;
+        };
+
+        let mut packets = Vec::new();
+        match stream.protocol {
+            NetProtocol::Datagrams => {
+                packets.extend(
+                    bytes
+                        .chunks(MAX_PAYLOAD)
+                        .map(|chunk| udp_datagram(stream, direction, chunk)),
+                );
+            }
+            NetProtocol::Stream if bytes.is_empty() => packets.extend(tcp_fin(stream, direction)),
+            NetProtocol::Stream => {
+                for chunk in bytes.chunks(MAX_PAYLOAD) {
+                    packets.push(tcp_segment(stream, direction, TCP_PSH | TCP_ACK, chunk));
+                    let seq = match direction {
+                        Direction::Sent => &mut stream.local_seq,
+                        Direction::Received => &mut stream.peer_seq,
+                    };
+                    *seq = seq.wrapping_add(chunk.len() as u32);
+                }
+            }
+        }
+
+        self.write_packets(packets);
+    }
+
+    /// Stops capturing a connection. For TCP, writes the missing shutdowns.
+    pub fn closed(&mut self, id: InterceptorId) {
+        let Some(mut stream) = self.streams.remove(&id) else {
+            return;
+        };
+
+        if stream.protocol == NetProtocol::Stream {
+            let packets = [Direction::Sent, Direction::Received]
+                .into_iter()
+                .filter_map(|direction| tcp_fin(&mut stream, direction))
+                .collect::<Vec<_>>();
+            self.write_packets(packets);
+        }
+    }
+
+    /// Forgets all captured connections, e.g. when the agent connection is lost.
+    pub fn clear(&mut self) {
+        self.streams.clear();
+    }
+
+    fn write_packets<I: IntoIterator<Item = Vec<u8>>>(&mut self, packets: I) {
+        let Some(blocks) = self.blocks.as_ref() else {
+            return;
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let result = packets.into_iter().try_for_each(|packet| {
+            let mut body = Vec::with_capacity(20 + packet.len());
+            // Interface id.
+            body.extend(0u32.to_le_bytes());
+            body.extend(((timestamp >> 32) as u32).to_le_bytes());
+            body.extend((timestamp as u32).to_le_bytes());
+            // Captured and original length.
+            body.extend((packet.len() as u32).to_le_bytes());
+            body.extend((packet.len() as u32).to_le_bytes());
+            body.extend(packet);
+
+            let mut block = Vec::with_capacity(12 + body.len() + 3);
+            write_block(&mut block, BLOCK_ENHANCED_PACKET, &body)
+                .expect("writing to a Vec never fails");
+            blocks.send(block)
+        });
+
+        // The task already logged the error.
+        if result.is_err() {
+            self.blocks = None;
+        }
+    }
+}
+
+/// Writes the blocks received from the [`OutgoingCapture`] to the file, until the
+/// [`OutgoingCapture`] is dropped or a write fails.
+///
+/// The file is flushed whenever there are no more blocks waiting, and before returning.
+fn write_blocks(
+    mut writer: BufWriter<File>,
+    mut blocks: UnboundedReceiver<Vec<u8>>,
+    path: PathBuf,
+) {
+    let result = (|| {
+        while let Some(block) = blocks.blocking_recv() {
+            writer.write_all(&block)?;
+
+            if blocks.is_empty() {
+                writer.flush()?;
+            }
+        }
+
+        writer.flush()
+    })();
+
+    if let Err(error) = result {
+        tracing::warn!(
+            %error,
+            path = %path.display(),
+            "Failed to write the outgoing traffic capture, no more traffic will be captured",
+        );
+    }
+}
+
+/// Writes a pcap-ng block, padding the `body` to 32 bits.
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let padding = body.len().next_multiple_of(4) - body.len();
+    let total_length = (12 + body.len() + padding) as u32;
+
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&total_length.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&[0; 3][..padding])?;
+    writer.write_all(&total_length.to_le_bytes())
+}
+
+/// IP packets can't mix address families, so we map IPv4 addresses to IPv6 when needed.
+fn same_family(local: SocketAddr, peer: SocketAddr) -> (SocketAddr, SocketAddr) {
+    let to_ipv6 = |addr: SocketAddr| match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(ip.to_ipv6_mapped().into(), addr.port()),
+        IpAddr::V6(..) => addr,
+    };
+
+    if local.is_ipv4() == peer.is_ipv4() {
+        (local, peer)
+    } else {
+        (to_ipv6(local), to_ipv6(peer))
+    }
+}
+
+/// Returns the source and destination of a packet going in the given `direction`.
+fn endpoints(stream: &CapturedStream, direction: Direction) -> (SocketAddr, SocketAddr) {
+    match direction {
+        Direction::Sent => (stream.local, stream.peer),
+        Direction::Received => (stream.peer, stream.local),
+    }
+}
+
+/// Creates a FIN segment from the given side, unless it was already sent.
+fn tcp_fin(stream: &mut CapturedStream, direction: Direction) -> Option<Vec<u8>> {
+    let fin_sent = match direction {
+        Direction::Sent => &mut stream.local_fin,
+        Direction::Received => &mut stream.peer_fin,
+    };
+    if *fin_sent {
+        return None;
+    }
+    *fin_sent = true;
+
+    let packet = tcp_segment(stream, direction, TCP_FIN | TCP_ACK, &[]);
+    let seq = match direction {
+        Direction::Sent => &mut stream.local_seq,
+        Direction::Received => &mut stream.peer_seq,
+    };
+    *seq = seq.wrapping_add(1);
+
+    Some(packet)
+}
+
+fn tcp_segment(
+    stream: &CapturedStream,
+    direction: Direction,
+    flags: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let (src, dst) = endpoints(stream, direction);
+    let (seq, ack) = match direction {
+        Direction::Sent => (stream.local_seq, stream.peer_seq),
+        Direction::Received => (stream.peer_seq, stream.local_seq),
+    };
+    let ack = if flags & TCP_ACK != 0 { ack } else { 0 };
+
+    let mut segment = Vec::with_capacity(20 + payload.len());
+    segment.extend(src.port().to_be_bytes());
+    segment.extend(dst.port().to_be_bytes());
+    segment.extend(seq.to_be_bytes());
+    segment.extend(ack.to_be_bytes());
+    // Data offset of 5 words, no options.
+    segment.push(5 << 4);
+    segment.push(flags);
+    // Window size, checksum, urgent pointer.
+    segment.extend(u16::MAX.to_be_bytes());
+    segment.extend([0; 4]);
+    segment.extend(payload);
+
+    ip_packet(src.ip(), dst.ip(), IPPROTO_TCP, segment, 16)
+}
+
+fn udp_datagram(stream: &CapturedStream, direction: Direction, payload: &[u8]) -> Vec<u8> {
+    let (src, dst) = endpoints(stream, direction);
+
+    let mut datagram = Vec::with_capacity(8 + payload.len());
+    datagram.extend(src.port().to_be_bytes());
+    datagram.extend(dst.port().to_be_bytes());
+    datagram.extend(((8 + payload.len()) as u16).to_be_bytes());
+    // Checksum.
+    datagram.extend([0; 2]);
+    datagram.extend(payload);
+
+    ip_packet(src.ip(), dst.ip(), IPPROTO_UDP, datagram, 6)
+}
+
+/// Wraps the transport `segment` in an IP header, filling its checksum at `checksum_offset`.
+fn ip_packet(
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    mut segment: Vec<u8>,
+    checksum_offset: usize,
+) -> Vec<u8> {
+    let length = segment.len();
+
+    let mut pseudo_header = Vec::with_capacity(40);
+    let mut packet = Vec::with_capacity(40 + length);
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            pseudo_header.extend(src.octets());
+            pseudo_header.extend(dst.octets());
+            pseudo_header.extend([0, protocol]);
+            pseudo_header.extend((length as u16).to_be_bytes());
+
+            // Version and header length, DSCP.
+            packet.extend([0x45, 0]);
+            packet.extend(((20 + length) as u16).to_be_bytes());
+            // Identification, don't fragment.
+            packet.extend([0, 0, 0x40, 0]);
+            // TTL, protocol, checksum.
+            packet.extend([64, protocol, 0, 0]);
+            packet.extend(src.octets());
+            packet.extend(dst.octets());
+
+            let checksum = checksum([&packet[..]]);
+            packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        }
+        (src, dst) => {
+            let to_ipv6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            let (src, dst) = (to_ipv6(src), to_ipv6(dst));
+
+            pseudo_header.extend(src.octets());
+            pseudo_header.extend(dst.octets());
+            pseudo_header.extend((length as u32).to_be_bytes());
+            pseudo_header.extend([0, 0, 0, protocol]);
+
+            // Version, traffic class and flow label.
+            packet.extend([0x60, 0, 0, 0]);
+            packet.extend((length as u16).to_be_bytes());
+            // Next header, hop limit.
+            packet.extend([protocol, 64]);
+            packet.extend(src.octets());
+            packet.extend(dst.octets());
+        }
+    }
+
+    let checksum = match checksum([&pseudo_header[..], &segment[..]]) {
+        // Zero means no checksum in UDP.
+        0 => u16::MAX,
+        checksum => checksum,
+    };
+    segment[checksum_offset..checksum_offset + 2].copy_from_slice(&checksum.to_be_bytes());
+
+    packet.extend(segment);
+    packet
+}
+
+/// The internet checksum ([RFC 1071](https://www.rfc-editor.org/rfc/rfc1071)) of the concatenated
+/// `parts`. All parts but the last must have an even length.
+fn checksum<'a, I: IntoIterator<Item = &'a [u8]>>(parts: I) -> u16 {
+    let mut sum = parts
+        .into_iter()
+        .flat_map(|part| part.chunks(2))
+        .map(|word| {
+            u32::from(u16::from_be_bytes([
+                word[0],
+                word.get(1).copied().unwrap_or(0),
+            ]))
+        })
+        .sum::<u32>();
+
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stream(protocol: NetProtocol) -> CapturedStream {
+        CapturedStream {
+            protocol,
+            local: "10.0.0.5:41000".parse().unwrap(),
+            peer: "10.0.0.9:443".parse().unwrap(),
+            local_seq: 100,
+            peer_seq: 500,
+            local_fin: false,
+            peer_fin: false,
+        }
+    }
+
+    /// Valid checksums sum up to zero, including the checksum itself.
+    #[test]
+    fn checksums() {
+        let packet = tcp_segment(
+            &stream(NetProtocol::Stream),
+            Direction::Sent,
+            TCP_PSH | TCP_ACK,
+            b"hello",
+        );
+        assert_eq!(packet.len(), 20 + 20 + 5);
+        assert_eq!(checksum([&packet[..20]]), 0);
+
+        let pseudo_header = [
+            &packet[12..20],
+            &[0, IPPROTO_TCP][..],
+            &25u16.to_be_bytes()[..],
+        ]
+        .concat();
+        assert_eq!(checksum([&pseudo_header[..], &packet[20..]]), 0);
+    }
+
+    #[test]
+    fn tcp_sequence() {
+        let mut stream = stream(NetProtocol::Stream);
+
+        let fin = tcp_fin(&mut stream, Direction::Received).unwrap();
+        assert_eq!(&fin[20..22], &443u16.to_be_bytes());
+        assert_eq!(&fin[24..28], &500u32.to_be_bytes());
+        assert_eq!(&fin[28..32], &100u32.to_be_bytes());
+        assert_eq!(fin[33], TCP_FIN | TCP_ACK);
+        assert_eq!(stream.peer_seq, 501);
+
+        assert!(tcp_fin(&mut stream, Direction::Received).is_none());
+    }
+
+    #[test]
+    fn mixed_families() {
+        let (local, peer) = same_family(
+            "10.0.0.5:41000".parse().unwrap(),
+            "[fd00::9]:443".parse().unwrap(),
+        );
+        assert_eq!(local, "[::ffff:10.0.0.5]:41000".parse().unwrap());
+        assert_eq!(peer, "[fd00::9]:443".parse().unwrap());
+
+        let mut stream = stream(NetProtocol::Datagrams);
+        stream.local = local;
+        stream.peer = peer;
+        let packet = udp_datagram(&stream, Direction::Sent, b"query");
+        assert_eq!(packet[0] >> 4, 6);
+        assert_eq!(packet.len(), 40 + 8 + 5);
+    }
+
+    #[test]
+    fn blocks_are_padded() {
+        let mut block = Vec::new();
+        write_block(&mut block, BLOCK_ENHANCED_PACKET, &[1, 2, 3, 4, 5]).unwrap();
+
+        assert_eq!(block.len(), 20);
+        assert_eq!(&block[4..8], &20u32.to_le_bytes());
+        assert_eq!(&block[16..20], &20u32.to_le_bytes());
+    }
+}
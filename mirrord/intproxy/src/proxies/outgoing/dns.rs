@@ -0,0 +1,230 @@
+//! Answers the DNS queries sent through outgoing UDP connections for hostnames configured in
+//! `feature.network.dns.overrides`, see [`DnsOverrideInterceptor`].
+
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+use mirrord_config::feature::network::dns::{
+    DnsOverrideConfig, DnsOverrides,
+    message::{self, DnsQuery},
+};
+use mirrord_intproxy_protocol::NetProtocol;
+use mirrord_protocol::outgoing::SocketAddress;
+
+use super::InterceptorId;
+
+const DNS_PORT: u16 = 53;
+
+/// What to do with the data sent by the layer through an outgoing connection.
+#[derive(Debug)]
+pub enum DnsOverrideAction {
+    /// Send this data to the agent.
+    Forward(Bytes),
+    /// Send this response back to the layer.
+    Respond(Bytes),
+}
+
+/// Answers the DNS queries that the user application sends directly to the nameserver (e.g. Go
+/// programs built with `CGO_ENABLED=0`), for hostnames configured in
+/// `feature.network.dns.overrides`.
+///
+/// Addresses are returned right away. For a CNAME, the query is sent for the CNAME target
+/// instead, and the addresses from the nameserver's response are returned with the CNAME record.
+#[derive(Debug, Default)]
+pub struct DnsOverrideInterceptor {
+    overrides: DnsOverrides,
+    /// UDP connections to port `53`.
+    connections: HashSet<InterceptorId>,
+    /// Queries sent for their CNAME target, by connection and query id.
+    pending: HashMap<(InterceptorId, u16), (DnsQuery, String)>,
+}
+
+impl DnsOverrideInterceptor {
+    pub fn new(overrides: DnsOverrides) -> Self {
+        Self {
+            overrides,
+            ..Default::default()
+        }
+    }
+
+    /// Starts checking the data sent through the connection, if it's made to a nameserver.
+    pub fn connected(&mut self, id: InterceptorId, remote_address: &SocketAddress) {
+        if self.overrides.is_empty() || id.protocol != NetProtocol::Datagrams {
+            return;
+        }
+
+        if matches!(remote_address, SocketAddress::Ip(address) if address.port() == DNS_PORT) {
+            self.connections.insert(id);
+        }
+    }
+
+    pub fn closed(&mut self, id: InterceptorId) {
+        if self.connections.remove(&id) {
+            self.pending.retain(|(connection, _), _| *connection != id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.connections.clear();
+        self.pending.clear();
+    }
+
+    /// Checks the data sent by the layer through the connection.
+    pub fn sent(&mut self, id: InterceptorId, bytes: Bytes) -> DnsOverrideAction {
+        if !self.connections.contains(&id) {
+            return DnsOverrideAction::Forward(bytes);
+        }
+        let Some(query) = DnsQuery::parse(&bytes) else {
+            return DnsOverrideAction::Forward(bytes);
+        };
+        let Some(dns_override) = self.overrides.resolve(query.name()) else {
+            return DnsOverrideAction::Forward(bytes);
+        };
+        tracing::debug!(%id, name = %query.name(), ?dns_override, "Overriding DNS query");
+
+        let response = match dns_override {
+            DnsOverrideConfig::Addresses(addresses) => query.response(None, addresses),
+            DnsOverrideConfig::Cname { cname } if query.is_address_query() => {
+                let Some(request) = query.with_name(cname) else {
+                    return DnsOverrideAction::Forward(bytes);
+                };
+                self.pending
+                    .insert((id, query.id()), (query, cname.clone()));
+                return DnsOverrideAction::Forward(request.into());
+            }
+            DnsOverrideConfig::Cname { cname } => query.response(Some(cname), &[]),
+        };
+
+        match response {
+            Some(response) => DnsOverrideAction::Respond(response.into()),
+            None => DnsOverrideAction::Forward(bytes),
+        }
+    }
+
+    /// Checks the data received from the agent through the connection, returns the data that
+    /// should be sent to the layer.
+    pub fn received(&mut self, id: InterceptorId, bytes: Bytes) -> Bytes {
+        if self.pending.is_empty() {
+            return bytes;
+        }
+        let Some((query_id, addresses)) = message::response_addresses(&bytes) else {
+            return bytes;
+        };
+        let Some((query, cname)) = self.pending.remove(&(id, query_id)) else {
+            return bytes;
+        };
+
+        query
+            .response(Some(&cname), &addresses)
+            .map(Bytes::from)
+            .unwrap_or(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, net::IpAddr};
+
+    use mirrord_config::util::VecOrSingle;
+
+    use super::*;
+
+    const NAMESERVER: &str = "10.96.0.10:53";
+
+    /// An `A` query for `name`, with id `7`.
+    fn query(name: &str) -> Vec<u8> {
+        let mut message = vec![0, 7, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            message.push(label.len() as u8);
+            message.extend(label.as_bytes());
+        }
+        message.extend([0, 0, 1, 0, 1]);
+        message
+    }
+
+    fn interceptor() -> (DnsOverrideInterceptor, InterceptorId) {
+        let overrides = BTreeMap::from([
+            (
+                "payments".to_string(),
+                DnsOverrideConfig::Addresses(VecOrSingle::Multiple(vec![
+                    "127.0.0.1".parse().unwrap(),
+                    "::1".parse().unwrap(),
+                ])),
+            ),
+            (
+                "legacy".to_string(),
+                DnsOverrideConfig::Cname {
+                    cname: "gateway.default".to_string(),
+                },
+            ),
+        ]);
+        let mut interceptor = DnsOverrideInterceptor::new(DnsOverrides::new(&overrides).unwrap());
+
+        let id = InterceptorId {
+            connection_id: 0,
+            protocol: NetProtocol::Datagrams,
+        };
+        interceptor.connected(id, &SocketAddress::Ip(NAMESERVER.parse().unwrap()));
+
+        (interceptor, id)
+    }
+
+    #[test]
+    fn answers_addresses() {
+        let (mut interceptor, id) = interceptor();
+
+        let DnsOverrideAction::Respond(response) = interceptor.sent(id, query("payments").into())
+        else {
+            panic!("query should be answered");
+        };
+        assert_eq!(
+            message::response_addresses(&response),
+            Some((7, vec!["127.0.0.1".parse::<IpAddr>().unwrap()]))
+        );
+
+        assert!(matches!(
+            interceptor.sent(id, query("orders").into()),
+            DnsOverrideAction::Forward(..)
+        ));
+    }
+
+    #[test]
+    fn resolves_cname() {
+        let (mut interceptor, id) = interceptor();
+
+        let DnsOverrideAction::Forward(request) = interceptor.sent(id, query("legacy").into())
+        else {
+            panic!("query should be sent to the nameserver");
+        };
+        let request = DnsQuery::parse(&request).unwrap();
+        assert_eq!(request.name(), "gateway.default");
+
+        let upstream = request
+            .response(None, &["10.0.0.1".parse().unwrap()])
+            .unwrap();
+        let response = interceptor.received(id, upstream.into());
+        // The question is the original one.
+        let original = query("legacy");
+        assert_eq!(&response[12..original.len()], &original[12..]);
+        assert_eq!(
+            message::response_addresses(&response),
+            Some((7, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]))
+        );
+    }
+
+    /// Other connections are not checked.
+    #[test]
+    fn ignores_other_connections() {
+        let (mut interceptor, _) = interceptor();
+        let id = InterceptorId {
+            connection_id: 1,
+            protocol: NetProtocol::Datagrams,
+        };
+        interceptor.connected(id, &SocketAddress::Ip("10.0.0.1:5353".parse().unwrap()));
+
+        assert!(matches!(
+            interceptor.sent(id, query("payments").into()),
+            DnsOverrideAction::Forward(..)
+        ));
+    }
+}
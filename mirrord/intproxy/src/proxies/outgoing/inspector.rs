@@ -0,0 +1,346 @@
+//! [`BackgroundTask`] used by [`OutgoingProxy`](super::OutgoingProxy) to decide where an
+//! intercepted TCP connection should be made, based on the hostname the layer sends in it.
+
+use std::{io, time::Duration};
+
+use mirrord_intproxy_protocol::HostnameInspection;
+use tokio::{io::AsyncWriteExt, net::TcpStream, time::Instant};
+use tracing::Level;
+
+use super::net_protocol_ext::{ConnectedSocket, PreparedSocket};
+use crate::background_tasks::{BackgroundTask, MessageBus};
+
+/// How long we wait for the layer to send enough data to find the hostname.
+///
+/// Protocols where the server speaks first never send anything, so this should be short.
+const INSPECTION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// We give up looking for the hostname after peeking this many bytes.
+const MAX_INSPECTED_BYTES: usize = 16 * 1024;
+
+/// Result of looking for a hostname in the first bytes of a connection.
+#[derive(Debug, PartialEq, Eq)]
+enum Hostname {
+    Found(String),
+    Missing,
+    /// More data is needed.
+    Incomplete,
+}
+
+/// Looks for the TLS SNI or the HTTP `Host` header in the first bytes of a connection.
+fn parse_hostname(data: &[u8]) -> Hostname {
+    match data.first() {
+        None => Hostname::Incomplete,
+        Some(&TLS_HANDSHAKE) => parse_sni(data),
+        Some(byte) if byte.is_ascii_uppercase() => parse_http_host(data),
+        Some(..) => Hostname::Missing,
+    }
+}
+
+const TLS_HANDSHAKE: u8 = 0x16;
+const TLS_CLIENT_HELLO: u8 = 0x01;
+const TLS_EXTENSION_SNI: u16 = 0x0000;
+const TLS_SNI_HOST_NAME: u8 = 0x00;
+
+/// Reads the SNI from the TLS ClientHello in the first record.
+fn parse_sni(data: &[u8]) -> Hostname {
+    // Record header: content type (1), legacy version (2), length (2).
+    let Some(&[_, _, _, length_hi, length_lo]) = data.get(..5) else {
+        return Hostname::Incomplete;
+    };
+    let record_length = usize::from(u16::from_be_bytes([length_hi, length_lo]));
+    let Some(record) = data.get(5..5 + record_length) else {
+        return Hostname::Incomplete;
+    };
+
+    find_sni(record).map_or(Hostname::Missing, Hostname::Found)
+}
+
+/// Cursor over the bytes of a TLS handshake message.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.0.split_at_checked(count)?;
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Takes a vector prefixed with its 1 byte length.
+    fn vec8(&mut self) -> Option<Reader<'a>> {
+        let length = self.u8()?;
+        self.take(length.into()).map(Reader)
+    }
+
+    /// Takes a vector prefixed with its 2 byte length.
+    fn vec16(&mut self) -> Option<Reader<'a>> {
+        let length = self.u16()?;
+        self.take(length.into()).map(Reader)
+    }
+}
+
+fn find_sni(handshake: &[u8]) -> Option<String> {
+    let mut reader = Reader(handshake);
+
+    if reader.u8()? != TLS_CLIENT_HELLO {
+        return None;
+    }
+    // Handshake length (3), legacy version (2), random (32).
+    reader.take(3 + 2 + 32)?;
+    // Session id, cipher suites, compression methods.
+    reader.vec8()?;
+    reader.vec16()?;
+    reader.vec8()?;
+
+    let mut extensions = reader.vec16()?;
+    while !extensions.0.is_empty() {
+        let extension_type = extensions.u16()?;
+        let mut extension = extensions.vec16()?;
+        if extension_type != TLS_EXTENSION_SNI {
+            continue;
+        }
+
+        let mut names = extension.vec16()?;
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name = names.vec16()?;
+            if name_type == TLS_SNI_HOST_NAME {
+                return std::str::from_utf8(name.0).ok().map(str::to_string);
+            }
+        }
+    }
+
+    None
+}
+
+/// Reads the `Host` header from an HTTP/1 request.
+fn parse_http_host(data: &[u8]) -> Hostname {
+    let Some(headers_end) = data.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return Hostname::Incomplete;
+    };
+    let Ok(head) = std::str::from_utf8(&data[..headers_end]) else {
+        return Hostname::Missing;
+    };
+
+    let mut lines = head.split("\r\n");
+    let is_http = lines
+        .next()
+        .is_some_and(|request_line| request_line.contains(" HTTP/1."));
+    if !is_http {
+        return Hostname::Missing;
+    }
+
+    lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| {
+            let value = value.trim();
+            // Strip the port, minding IPv6 literals like `[::1]:80`.
+            match value.rsplit_once(':') {
+                Some((host, port))
+                    if !host.ends_with(':') && port.bytes().all(|b| b.is_ascii_digit()) =>
+                {
+                    host
+                }
+                _ => value,
+            }
+            .to_string()
+        })
+        .map_or(Hostname::Missing, Hostname::Found)
+}
+
+/// Checks if the `hostname` found in a connection is the `filter` hostname.
+fn hostname_matches(filter: &str, hostname: &str) -> bool {
+    filter
+        .trim_end_matches('.')
+        .eq_ignore_ascii_case(hostname.trim_end_matches('.'))
+}
+
+/// Accepts one connection from the layer and looks for a hostname in its first bytes, as
+/// described by [`HostnameInspection`].
+///
+/// When the connection should be made remotely, the accepted [`ConnectedSocket`] is sent through
+/// the [`MessageBus`], so that [`OutgoingProxy`](super::OutgoingProxy) can connect through the
+/// agent. Otherwise, this task makes the connection locally and proxies it until it's closed.
+pub struct Inspector {
+    id: u128,
+    socket: Option<PreparedSocket>,
+    inspection: HostnameInspection,
+}
+
+impl Inspector {
+    pub fn new(id: u128, socket: PreparedSocket, inspection: HostnameInspection) -> Self {
+        Self {
+            id,
+            socket: Some(socket),
+            inspection,
+        }
+    }
+
+    /// Peeks the data sent by the layer, until we find the hostname or give up.
+    async fn find_hostname(&self, socket: &mut ConnectedSocket) -> io::Result<Option<String>> {
+        let deadline = Instant::now() + INSPECTION_TIMEOUT;
+        let mut peeked = 0;
+
+        loop {
+            let Ok(data) = tokio::time::timeout_at(deadline, socket.peek()).await else {
+                return Ok(None);
+            };
+            let data = data?;
+
+            match parse_hostname(data) {
+                Hostname::Found(hostname) => break Ok(Some(hostname)),
+                Hostname::Missing => break Ok(None),
+                // No more data, the layer shut down writing.
+                Hostname::Incomplete if data.len() == peeked => break Ok(None),
+                Hostname::Incomplete if data.len() >= MAX_INSPECTED_BYTES => break Ok(None),
+                Hostname::Incomplete => peeked = data.len(),
+            }
+        }
+    }
+}
+
+impl BackgroundTask for Inspector {
+    type Error = io::Error;
+    type MessageIn = ();
+    type MessageOut = ConnectedSocket;
+
+    #[tracing::instrument(
+        level = Level::DEBUG,
+        name = "outgoing_inspector_main_loop",
+        skip_all, fields(id = self.id),
+        ret, err(level = Level::WARN),
+    )]
+    async fn run(&mut self, message_bus: &mut MessageBus<Self>) -> Result<(), Self::Error> {
+        let Some(socket) = self.socket.take() else {
+            return Ok(());
+        };
+
+        let mut socket = tokio::select! {
+            socket = socket.accept() => socket?,
+            _ = message_bus.closed_token().cancelled() => return Ok(()),
+        };
+
+        let hostname = self.find_hostname(&mut socket).await?;
+        let matched = hostname.as_deref().is_some_and(|hostname| {
+            self.inspection
+                .hostnames
+                .iter()
+                .any(|filter| hostname_matches(filter, hostname))
+        });
+        let remote = matched == self.inspection.remote_on_match;
+        tracing::debug!(?hostname, matched, remote, "Inspected outgoing connection");
+
+        if remote {
+            message_bus.send(socket).await;
+            return Ok(());
+        }
+
+        let Some((mut layer_stream, peeked)) = socket.into_tcp_stream() else {
+            return Err(io::Error::other("inspected connection is not a TCP stream"));
+        };
+        let mut local_stream = TcpStream::connect(self.inspection.local_address).await?;
+        local_stream.write_all(&peeked).await?;
+
+        tokio::select! {
+            result = tokio::io::copy_bidirectional(&mut layer_stream, &mut local_stream) => {
+                result.map(|_| ())
+            },
+            _ = message_bus.closed_token().cancelled() => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A minimal ClientHello record with the SNI extension.
+    fn client_hello(server_name: &str) -> Vec<u8> {
+        let name = server_name.as_bytes();
+
+        let mut sni = vec![TLS_SNI_HOST_NAME];
+        sni.extend((name.len() as u16).to_be_bytes());
+        sni.extend(name);
+        let mut sni_list = (sni.len() as u16).to_be_bytes().to_vec();
+        sni_list.extend(sni);
+
+        let mut extensions = Vec::new();
+        // Some other extension first (supported_versions).
+        extensions.extend([0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+        extensions.extend(TLS_EXTENSION_SNI.to_be_bytes());
+        extensions.extend((sni_list.len() as u16).to_be_bytes());
+        extensions.extend(sni_list);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend([0; 32]);
+        // Session id, cipher suites, compression methods.
+        body.extend([0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        body.extend((extensions.len() as u16).to_be_bytes());
+        body.extend(extensions);
+
+        let mut handshake = vec![TLS_CLIENT_HELLO];
+        handshake.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend(body);
+
+        let mut record = vec![TLS_HANDSHAKE, 0x03, 0x01];
+        record.extend((handshake.len() as u16).to_be_bytes());
+        record.extend(handshake);
+        record
+    }
+
+    #[test]
+    fn tls_sni() {
+        let hello = client_hello("api.example.com");
+
+        assert_eq!(
+            parse_hostname(&hello),
+            Hostname::Found("api.example.com".into())
+        );
+        assert_eq!(parse_hostname(&hello[..40]), Hostname::Incomplete);
+    }
+
+    #[test]
+    fn http_host() {
+        assert_eq!(
+            parse_hostname(b"GET / HTTP/1.1\r\nAccept: */*\r\nhost: api.example.com:8080\r\n\r\n"),
+            Hostname::Found("api.example.com".into())
+        );
+        assert_eq!(
+            parse_hostname(b"GET / HTTP/1.1\r\nHost: [::1]\r\n\r\n"),
+            Hostname::Found("[::1]".into())
+        );
+        assert_eq!(
+            parse_hostname(b"GET / HTTP/1.1\r\nHost: api.exa"),
+            Hostname::Incomplete
+        );
+        assert_eq!(parse_hostname(b"GET / HTTP/1.0\r\n\r\n"), Hostname::Missing);
+    }
+
+    #[test]
+    fn other_protocols() {
+        assert_eq!(
+            parse_hostname(b"\x00\x00\x00\x08\x04\xd2\x16\x2f"),
+            Hostname::Missing
+        );
+        assert_eq!(
+            parse_hostname(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"),
+            Hostname::Missing
+        );
+    }
+
+    #[test]
+    fn matching() {
+        assert!(hostname_matches("API.example.com", "api.example.com."));
+        assert!(!hostname_matches("example.com", "api.example.com"));
+    }
+}
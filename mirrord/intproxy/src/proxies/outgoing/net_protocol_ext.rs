@@ -153,6 +153,8 @@ pub enum PreparedSocket {
     BusyTcpListener(BusyTcpListener),
     #[cfg(not(target_os = "windows"))]
     UnixListener(UnixListener),
+    /// The connection was already accepted, e.g. to inspect its first bytes.
+    Accepted(ConnectedSocket),
 }
 
 impl PreparedSocket {
@@ -183,6 +185,7 @@ impl PreparedSocket {
                 let pathname = addr.as_pathname().unwrap().to_path_buf();
                 SocketAddress::Unix(UnixAddr::Pathname(pathname))
             }
+            Self::Accepted(socket) => socket.local_address()?,
         };
 
         Ok(address)
@@ -206,6 +209,7 @@ impl PreparedSocket {
                 let (stream, _) = listener.accept().await?;
                 (InnerConnectedSocket::UnixStream(stream), true)
             }
+            Self::Accepted(socket) => return Ok(socket),
         };
 
         Ok(ConnectedSocket {
@@ -216,6 +220,7 @@ impl PreparedSocket {
     }
 }
 
+#[derive(Debug)]
 enum InnerConnectedSocket {
     UdpSocket(UdpSocket),
     TcpStream(TcpStream),
@@ -224,6 +229,7 @@ enum InnerConnectedSocket {
 }
 
 /// A socket for intercepted connection with the layer.
+#[derive(Debug)]
 pub struct ConnectedSocket {
     inner: InnerConnectedSocket,
     /// Meaningful only when `inner` is [`InnerConnectedSocket::UdpSocket`].
@@ -250,7 +256,62 @@ impl ConnectedSocket {
         }
     }
 
+    /// Returns the local address of this socket.
+    fn local_address(&self) -> io::Result<SocketAddress> {
+        let address = match &self.inner {
+            InnerConnectedSocket::UdpSocket(socket) => socket.local_addr()?.into(),
+            InnerConnectedSocket::TcpStream(stream) => stream.local_addr()?.into(),
+            #[cfg(not(target_os = "windows"))]
+            InnerConnectedSocket::UnixStream(stream) => {
+                let addr = stream.local_addr()?;
+                let pathname = addr.as_pathname().unwrap().to_path_buf();
+                SocketAddress::Unix(UnixAddr::Pathname(pathname))
+            }
+        };
+
+        Ok(address)
+    }
+
+    /// Reads more data from the layer, without consuming it. The data is returned from the
+    /// following [`Self::receive`] calls.
+    ///
+    /// Returns all data peeked so far.
+    ///
+    /// # Note
+    ///
+    /// Not supported for UDP sockets.
+    pub async fn peek(&mut self) -> io::Result<&[u8]> {
+        match &mut self.inner {
+            InnerConnectedSocket::TcpStream(stream) => {
+                stream.read_buf(&mut self.buffer).await?;
+            }
+            #[cfg(not(target_os = "windows"))]
+            InnerConnectedSocket::UnixStream(stream) => {
+                stream.read_buf(&mut self.buffer).await?;
+            }
+            InnerConnectedSocket::UdpSocket(..) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "peeking is not supported for UDP sockets",
+                ));
+            }
+        }
+
+        Ok(&self.buffer)
+    }
+
+    /// Returns the inner [`TcpStream`], along with the data that was [peeked](Self::peek), but not
+    /// received yet.
+    pub fn into_tcp_stream(self) -> Option<(TcpStream, BytesMut)> {
+        match self.inner {
+            InnerConnectedSocket::TcpStream(stream) => Some((stream, self.buffer)),
+            _ => None,
+        }
+    }
+
     /// Receives some data from the layer.
+    ///
+    /// Returns the [peeked](Self::peek) data first, if there is any.
     pub async fn receive(&mut self) -> io::Result<Vec<u8>> {
         match &mut self.inner {
             InnerConnectedSocket::UdpSocket(socket) => {
@@ -266,14 +327,18 @@ impl ConnectedSocket {
                 Ok(bytes)
             }
             InnerConnectedSocket::TcpStream(stream) => {
-                stream.read_buf(&mut self.buffer).await?;
+                if self.buffer.is_empty() {
+                    stream.read_buf(&mut self.buffer).await?;
+                }
                 let bytes = self.buffer.to_vec();
                 self.buffer.clear();
                 Ok(bytes)
             }
             #[cfg(not(target_os = "windows"))]
             InnerConnectedSocket::UnixStream(stream) => {
-                stream.read_buf(&mut self.buffer).await?;
+                if self.buffer.is_empty() {
+                    stream.read_buf(&mut self.buffer).await?;
+                }
                 let bytes = self.buffer.to_vec();
                 self.buffer.clear();
                 Ok(bytes)
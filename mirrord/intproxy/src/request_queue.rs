@@ -8,6 +8,10 @@
 //! on this behavior and stores [`MessageId`]s of layer's requests in multiple queues. Upon
 //! receiving a response from the agent, correct [`MessageId`] is taken from the right queue.
 //!
+//! File requests are the exception when the agent supports
+//! [`IdentifiedFileRequest`](mirrord_protocol::codec::IdentifiedFileRequest)s, as they're matched
+//! by their ids instead, see `FilesProxy`.
+//!
 //! Additionaly, single internal proxy handles multiple layer
 //! instances (coming from forks). This fifo stores their [`LayerId`]s as well.
 
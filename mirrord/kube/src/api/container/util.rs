@@ -64,6 +64,18 @@ pub(super) fn agent_env(agent: &AgentConfig, params: &ContainerParams) -> Vec<En
         env.push(envs::DNS_TIMEOUT.as_k8s_spec(&timeout));
     };
 
+    if let Some(max_pending_bytes) = agent.client_limits.max_pending_bytes {
+        env.push(envs::CLIENT_MAX_PENDING_BYTES.as_k8s_spec(&max_pending_bytes));
+    }
+
+    if let Some(max_in_flight_requests) = agent.client_limits.max_in_flight_requests {
+        env.push(envs::CLIENT_MAX_IN_FLIGHT_REQUESTS.as_k8s_spec(&max_in_flight_requests));
+    }
+
+    if let Some(file_workers) = agent.client_limits.file_workers {
+        env.push(envs::CLIENT_FILE_WORKERS.as_k8s_spec(&file_workers));
+    }
+
     if let Some(pod_ips) = &params.pod_ips {
         env.push(envs::POD_IPS.as_k8s_spec(pod_ips));
     }
@@ -72,6 +84,10 @@ pub(super) fn agent_env(agent: &AgentConfig, params: &ContainerParams) -> Vec<En
         env.push(envs::METRICS.as_k8s_spec(metrics_address));
     }
 
+    if let Some(otlp_endpoint) = agent.otlp_endpoint.as_ref() {
+        env.push(envs::OTLP_ENDPOINT.as_k8s_spec(otlp_endpoint));
+    }
+
     if let Some(cert) = &params.tls_cert {
         env.push(envs::OPERATOR_CERT.as_k8s_spec(cert));
     }
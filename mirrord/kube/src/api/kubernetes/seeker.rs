@@ -5,7 +5,7 @@ use itertools::join;
 use k8s_openapi::{
     ClusterResourceScope, Metadata, NamespaceResourceScope,
     api::{
-        apps::v1::{Deployment, ReplicaSet, StatefulSet},
+        apps::v1::{DaemonSet, Deployment, ReplicaSet, StatefulSet},
         batch::v1::{CronJob, Job},
         core::v1::{Pod, Service},
     },
@@ -27,18 +27,20 @@ pub struct KubeResourceSeeker<'a> {
 
 impl KubeResourceSeeker<'_> {
     /// Returns all resource types that don't require the operator to operate ie. [`Pod`],
-    /// [`Deployment`] and [`Rollout`]
+    /// [`Deployment`], [`Rollout`] and [`DaemonSet`]
     pub async fn all_open_source(&self) -> Result<Vec<String>> {
-        let (pods, deployments, rollouts) = tokio::try_join!(
+        let (pods, deployments, rollouts, daemonsets) = tokio::try_join!(
             self.pods(),
             self.deployments(),
-            self.simple_list_resource::<Rollout>("rollout")
+            self.simple_list_resource::<Rollout>("rollout"),
+            self.simple_list_resource::<DaemonSet>("daemonset"),
         )?;
 
         Ok(pods
             .into_iter()
             .chain(deployments)
             .chain(rollouts)
+            .chain(daemonsets)
             .collect())
     }
 
@@ -50,9 +52,20 @@ impl KubeResourceSeeker<'_> {
     /// 5. [`Job`]s
     /// 6. [`Service`]s
     /// 7. [`ReplicaSet`]s
-    /// 8. [`Pod`]s
+    /// 8. [`DaemonSet`]s
+    /// 9. [`Pod`]s
     pub async fn all(&self) -> Result<Vec<String>> {
-        let (pods, deployments, rollouts, jobs, cronjobs, statefulsets, services, replicasets) = tokio::try_join!(
+        let (
+            pods,
+            deployments,
+            rollouts,
+            jobs,
+            cronjobs,
+            statefulsets,
+            services,
+            replicasets,
+            daemonsets,
+        ) = tokio::try_join!(
             self.pods(),
             self.simple_list_resource::<Deployment>("deployment"),
             self.simple_list_resource::<Rollout>("rollout"),
@@ -61,6 +74,7 @@ impl KubeResourceSeeker<'_> {
             self.simple_list_resource::<StatefulSet>("statefulset"),
             self.simple_list_resource::<Service>("service"),
             self.simple_list_resource::<ReplicaSet>("replicaset"),
+            self.simple_list_resource::<DaemonSet>("daemonset"),
         )?;
 
         Ok(deployments
@@ -71,6 +85,7 @@ impl KubeResourceSeeker<'_> {
             .chain(jobs)
             .chain(services)
             .chain(replicasets)
+            .chain(daemonsets)
             .chain(pods)
             .collect())
     }
@@ -120,6 +135,7 @@ impl KubeResourceSeeker<'_> {
             TargetType::ReplicaSet if operator_active => {
                 self.simple_list_resource::<ReplicaSet>("replicaset").await
             }
+            TargetType::DaemonSet => self.simple_list_resource::<DaemonSet>("daemonset").await,
             TargetType::Targetless => Err(KubeApiError::InvalidTargetType(resource_type)),
             resource_type if !operator_active => {
                 Err(KubeApiError::TargetTypeRequiresOperator(resource_type))
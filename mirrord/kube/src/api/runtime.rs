@@ -1,6 +1,7 @@
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
     convert::Infallible,
     fmt::{self, Display, Formatter},
     future::Future,
@@ -16,7 +17,11 @@ use k8s_openapi::{
 };
 use kube::{Api, Client, Resource, api::ListParams};
 use mirrord_agent_env::mesh::MeshVendor;
-use mirrord_config::target::Target;
+use mirrord_config::target::{
+    Target,
+    pod::PodTarget,
+    selector::{TargetPick, TargetSelector},
+};
 use serde::de::DeserializeOwned;
 use thiserror::Error;
 use tracing::Level;
@@ -35,6 +40,7 @@ use crate::{
 };
 
 pub mod cron_job;
+pub mod daemon_set;
 pub mod deployment;
 pub mod job;
 pub mod pod;
@@ -383,7 +389,8 @@ pub trait RuntimeDataProvider {
 ///
 /// Implementors are provided with an implementation of [`RuntimeDataProvider`].
 /// When resolving [`RuntimeData`], the set of pods is fetched and [`RuntimeData`] is extracted from
-/// the first pod on the list. If the set is empty, resolution fails.
+/// the first pod on the list (that runs on [`RuntimeDataFromLabels::node`], if given). If the set
+/// is empty, resolution fails.
 pub trait RuntimeDataFromLabels {
     type Resource: Resource<DynamicType = (), Scope = NamespaceResourceScope>
         + Clone
@@ -430,6 +437,11 @@ pub trait RuntimeDataFromLabels {
     fn name(&self) -> Cow<'_, str>;
 
     fn container(&self) -> Option<&str>;
+
+    /// Name of the node the selected pod must be running on.
+    fn node(&self) -> Option<&str> {
+        None
+    }
 }
 
 impl<T> RuntimeDataProvider for T
@@ -450,13 +462,24 @@ where
         }
 
         pods.iter()
+            .filter(|pod| {
+                self.node().is_none_or(|node| {
+                    pod.spec.as_ref().and_then(|spec| spec.node_name.as_deref()) == Some(node)
+                })
+            })
             .filter_map(|pod| RuntimeData::from_pod(pod, self.container()).ok())
             .next()
-            .ok_or_else(|| {
-                KubeApiError::invalid_state(
+            .ok_or_else(|| match self.node() {
+                Some(node) => KubeApiError::invalid_state(
+                    &resource,
+                    format_args!(
+                        "no pod matching the labels is ready to be targeted on node `{node}`"
+                    ),
+                ),
+                None => KubeApiError::invalid_state(
                     &resource,
                     "no pod matching the labels is ready to be targeted",
-                )
+                ),
             })
     }
 }
@@ -472,6 +495,7 @@ impl RuntimeDataProvider for Target {
             Target::StatefulSet(target) => target.runtime_data(client, namespace).await,
             Target::Service(target) => target.runtime_data(client, namespace).await,
             Target::ReplicaSet(target) => target.runtime_data(client, namespace).await,
+            Target::DaemonSet(target) => target.runtime_data(client, namespace).await,
             Target::Targetless => Err(KubeApiError::MissingRuntimeData),
         }
     }
@@ -488,11 +512,148 @@ impl RuntimeDataProvider for ResolvedTarget<true> {
             Self::StatefulSet(target) => target.runtime_data(client, namespace).await,
             Self::Service(target) => target.runtime_data(client, namespace).await,
             Self::ReplicaSet(target) => target.runtime_data(client, namespace).await,
+            Self::DaemonSet(target) => target.runtime_data(client, namespace).await,
             Self::Targetless(_) => Err(KubeApiError::MissingRuntimeData),
         }
     }
 }
 
+/// Resolves the given [`TargetSelector`] to a ready [`Pod`] in the given `namespace`.
+///
+/// Candidates are the pods that match [`TargetSelector::labels`] and pass the readiness check of
+/// [`RuntimeData::from_pod`]. They are sorted by name before [`TargetSelector::pick`] is applied,
+/// so ties are always broken the same way.
+///
+/// For [`TargetPick::LeastLoaded`], mirrord agents are looked up in `agent_namespace`, see
+/// [`count_attached_agents`].
+#[tracing::instrument(level = Level::DEBUG, skip(client), ret, err)]
+pub async fn resolve_target_selector(
+    client: &Client,
+    selector: &TargetSelector,
+    namespace: Option<&str>,
+    agent_namespace: Option<&str>,
+) -> Result<PodTarget> {
+    let pod_api: Api<Pod> = get_k8s_resource_api(client, namespace);
+    let mut candidates = pod_api
+        .list(&ListParams::default().labels(&selector.labels))
+        .await?
+        .items
+        .into_iter()
+        .filter(|pod| RuntimeData::from_pod(pod, selector.container.as_deref()).is_ok())
+        .collect::<Vec<_>>();
+
+    if candidates.is_empty() {
+        return Err(KubeApiError::NoReadyPodForSelector(selector.labels.clone()));
+    }
+
+    candidates.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name));
+
+    let created_at = |pod: &Pod| pod.metadata.creation_timestamp.clone().map(|time| time.0);
+    let index = match selector.pick {
+        TargetPick::Newest => candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, pod)| Reverse(created_at(pod)))
+            .map(|(index, _)| index),
+        TargetPick::Oldest => candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, pod)| created_at(pod))
+            .map(|(index, _)| index),
+        TargetPick::Random => Some(rand::random_range(0..candidates.len())),
+        TargetPick::LeastLoaded => count_attached_agents(client, &candidates, agent_namespace)
+            .await?
+            .into_iter()
+            .enumerate()
+            .min_by_key(|(_, agents)| *agents)
+            .map(|(index, _)| index),
+    }
+    .unwrap_or_default();
+
+    let pod = candidates.swap_remove(index);
+    let Name(pod_name) = FromResource::from_resource(&pod, &())?;
+
+    Ok(PodTarget {
+        pod: pod_name.to_owned(),
+        container: selector.container.clone(),
+    })
+}
+
+/// Counts mirrord agents attached to each of the given pods, which are:
+///
+/// 1. running ephemeral agent containers in the pod;
+/// 2. running agent pods in `agent_namespace` that target one of the pod's containers.
+///
+/// Returns the counts in the order of `pods`.
+async fn count_attached_agents(
+    client: &Client,
+    pods: &[Pod],
+    agent_namespace: Option<&str>,
+) -> Result<Vec<usize>> {
+    let agent_api: Api<Pod> = get_k8s_resource_api(client, agent_namespace);
+    let agent_pods = agent_api
+        .list(
+            &ListParams::default()
+                .labels("app=mirrord")
+                .fields("status.phase=Running"),
+        )
+        .await?
+        .items;
+
+    let mut agents_per_container: HashMap<String, usize> = HashMap::new();
+    for command in agent_pods
+        .iter()
+        .filter_map(|pod| pod.spec.as_ref())
+        .flat_map(|spec| &spec.containers)
+        .filter_map(|container| container.command.as_ref())
+    {
+        if let Some(container_id) = command
+            .iter()
+            .skip_while(|arg| *arg != "--container-id")
+            .nth(1)
+        {
+            *agents_per_container
+                .entry(container_id.clone())
+                .or_default() += 1;
+        }
+    }
+
+    let counts = pods
+        .iter()
+        .map(|pod| {
+            let Some(status) = pod.status.as_ref() else {
+                return 0;
+            };
+
+            let ephemeral_agents = status
+                .ephemeral_container_statuses
+                .iter()
+                .flatten()
+                .filter(|status| status.name.starts_with("mirrord-agent"))
+                .filter(|status| {
+                    status
+                        .state
+                        .as_ref()
+                        .is_some_and(|state| state.running.is_some())
+                })
+                .count();
+
+            let targeting_agents = status
+                .container_statuses
+                .iter()
+                .flatten()
+                .filter_map(|status| status.container_id.as_deref())
+                .filter_map(|container_id| container_id.split_once("://"))
+                .filter_map(|(_, container_id)| agents_per_container.get(container_id))
+                .sum::<usize>();
+
+            ephemeral_agents + targeting_agents
+        })
+        .collect();
+
+    Ok(counts)
+}
+
 #[cfg(test)]
 mod tests {
     use mirrord_config::target::{
@@ -0,0 +1,429 @@
+//! Resolving environment variables straight from the Kubernetes API, as opposed to reading them
+//! from the target process in the agent.
+//!
+//! All requests are made with the client we're given, so the user needs RBAC permissions to get
+//! the referenced [`Secret`]s and [`ConfigMap`]s.
+
+use std::collections::{BTreeMap, HashMap};
+
+use k8s_openapi::{
+    ByteString,
+    api::core::v1::{
+        ConfigMap, ConfigMapEnvSource, Container, EnvFromSource, EnvVar, Secret, SecretEnvSource,
+    },
+};
+use kube::{Api, Client};
+use mirrord_config::feature::env::EnvSource;
+use tracing::Level;
+
+use crate::{api::kubernetes::get_k8s_resource_api, error::KubeApiError};
+
+/// Resolves the environment of the given [`Container`] spec, in the same order as the kubelet
+/// does: [`Container::env_from`] first, then [`Container::env`] on top.
+///
+/// Variables that come from the downward API (`fieldRef` and `resourceFieldRef`) are skipped,
+/// as only the running process knows their values. `$(VAR)` references are not expanded.
+#[tracing::instrument(level = Level::DEBUG, skip_all, fields(container = %container.name), err)]
+pub async fn resolve_container_env(
+    client: &Client,
+    namespace: Option<&str>,
+    container: &Container,
+) -> Result<HashMap<String, String>, KubeApiError> {
+    let mut env = load_env_from(
+        client,
+        namespace,
+        container.env_from.as_deref().unwrap_or_default(),
+    )
+    .await?;
+
+    for var in container.env.as_deref().unwrap_or_default() {
+        if let Some(value) = resolve_env_var(client, namespace, var).await? {
+            env.insert(var.name.clone(), value);
+        }
+    }
+
+    Ok(env)
+}
+
+/// Loads all keys of the given [`EnvFromSource`]s as environment variables.
+///
+/// Later sources take precedence, like in [`Container::env_from`].
+#[tracing::instrument(level = Level::DEBUG, skip(client), err)]
+pub async fn load_env_from(
+    client: &Client,
+    namespace: Option<&str>,
+    sources: &[EnvFromSource],
+) -> Result<HashMap<String, String>, KubeApiError> {
+    let mut env = HashMap::new();
+
+    for source in sources {
+        let data = if let Some(secret_ref) = &source.secret_ref {
+            get_secret_data(
+                client,
+                namespace,
+                &secret_ref.name,
+                secret_ref.optional.unwrap_or_default(),
+            )
+            .await?
+        } else if let Some(config_map_ref) = &source.config_map_ref {
+            get_config_map_data(
+                client,
+                namespace,
+                &config_map_ref.name,
+                config_map_ref.optional.unwrap_or_default(),
+            )
+            .await?
+        } else {
+            continue;
+        };
+
+        let prefix = source.prefix.as_deref().unwrap_or_default();
+        env.extend(
+            data.into_iter()
+                .map(|(key, value)| (format!("{prefix}{key}"), value)),
+        );
+    }
+
+    Ok(env)
+}
+
+/// Loads environment variables from the user's [`EnvSource`]s, see [`load_env_from`].
+pub async fn load_env_sources(
+    client: &Client,
+    namespace: Option<&str>,
+    sources: &[EnvSource],
+) -> Result<HashMap<String, String>, KubeApiError> {
+    let sources = sources
+        .iter()
+        .map(|source| match source {
+            EnvSource::Secret { name, prefix } => EnvFromSource {
+                secret_ref: Some(SecretEnvSource {
+                    name: name.clone(),
+                    optional: None,
+                }),
+                prefix: prefix.clone(),
+                ..Default::default()
+            },
+            EnvSource::ConfigMap { name, prefix } => EnvFromSource {
+                config_map_ref: Some(ConfigMapEnvSource {
+                    name: name.clone(),
+                    optional: None,
+                }),
+                prefix: prefix.clone(),
+                ..Default::default()
+            },
+        })
+        .collect::<Vec<_>>();
+
+    load_env_from(client, namespace, &sources).await
+}
+
+/// Resolves the value of a single [`EnvVar`].
+///
+/// Returns [`None`] if the value cannot be resolved from the API, or comes from an optional
+/// reference that does not exist.
+async fn resolve_env_var(
+    client: &Client,
+    namespace: Option<&str>,
+    var: &EnvVar,
+) -> Result<Option<String>, KubeApiError> {
+    let Some(value_from) = &var.value_from else {
+        return Ok(Some(var.value.clone().unwrap_or_default()));
+    };
+
+    let (mut data, key) = if let Some(selector) = &value_from.secret_key_ref {
+        let optional = selector.optional.unwrap_or_default();
+        let data = get_secret_data(client, namespace, &selector.name, optional).await?;
+        if !optional && !data.contains_key(&selector.key) {
+            return Err(KubeApiError::MissingEnvSource(format!(
+                "key `{}` in Secret `{}`",
+                selector.key, selector.name
+            )));
+        }
+
+        (data, &selector.key)
+    } else if let Some(selector) = &value_from.config_map_key_ref {
+        let optional = selector.optional.unwrap_or_default();
+        let data = get_config_map_data(client, namespace, &selector.name, optional).await?;
+        if !optional && !data.contains_key(&selector.key) {
+            return Err(KubeApiError::MissingEnvSource(format!(
+                "key `{}` in ConfigMap `{}`",
+                selector.key, selector.name
+            )));
+        }
+
+        (data, &selector.key)
+    } else {
+        tracing::debug!(
+            name = %var.name,
+            "Skipping environment variable that comes from the downward API"
+        );
+        return Ok(None);
+    };
+
+    Ok(data.remove(key))
+}
+
+/// Fetches the [`Secret`] and decodes its data.
+///
+/// Returns an empty map if the [`Secret`] does not exist and `optional` is set.
+async fn get_secret_data(
+    client: &Client,
+    namespace: Option<&str>,
+    name: &str,
+    optional: bool,
+) -> Result<BTreeMap<String, String>, KubeApiError> {
+    let api: Api<Secret> = get_k8s_resource_api(client, namespace);
+
+    match api.get_opt(name).await? {
+        Some(secret) => Ok(secret
+            .data
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(key, ByteString(value))| match String::from_utf8(value) {
+                Ok(value) => Some((key, value)),
+                Err(..) => {
+                    tracing::warn!(secret = name, %key, "Skipping non UTF-8 value in a Secret");
+                    None
+                }
+            })
+            .collect()),
+        None if optional => Ok(Default::default()),
+        None => Err(KubeApiError::MissingEnvSource(format!("Secret `{name}`"))),
+    }
+}
+
+/// Fetches the [`ConfigMap`] data.
+///
+/// Returns an empty map if the [`ConfigMap`] does not exist and `optional` is set.
+async fn get_config_map_data(
+    client: &Client,
+    namespace: Option<&str>,
+    name: &str,
+    optional: bool,
+) -> Result<BTreeMap<String, String>, KubeApiError> {
+    let api: Api<ConfigMap> = get_k8s_resource_api(client, namespace);
+
+    match api.get_opt(name).await? {
+        Some(config_map) => Ok(config_map.data.unwrap_or_default()),
+        None if optional => Ok(Default::default()),
+        None => Err(KubeApiError::MissingEnvSource(format!(
+            "ConfigMap `{name}`"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use http::{Request, Response, StatusCode};
+    use k8s_openapi::api::core::v1::{
+        ConfigMapKeySelector, EnvVarSource, ObjectFieldSelector, SecretKeySelector,
+    };
+    use kube::client::Body;
+    use serde_json::json;
+    use tower::service_fn;
+
+    use super::*;
+
+    /// [`Client`] of a fake API server, with the Secret `db` and the ConfigMap `app` in the
+    /// `default` namespace.
+    fn fake_client() -> Client {
+        let service = service_fn(|request: Request<Body>| async move {
+            let (status, body) = match request.uri().path() {
+                "/api/v1/namespaces/default/secrets/db" => (
+                    StatusCode::OK,
+                    json!({
+                        "apiVersion": "v1",
+                        "kind": "Secret",
+                        "metadata": { "name": "db" },
+                        // `admin` and `hunter2`.
+                        "data": { "USER": "YWRtaW4=", "PASSWORD": "aHVudGVyMg==" },
+                    }),
+                ),
+                "/api/v1/namespaces/default/configmaps/app" => (
+                    StatusCode::OK,
+                    json!({
+                        "apiVersion": "v1",
+                        "kind": "ConfigMap",
+                        "metadata": { "name": "app" },
+                        "data": { "USER": "guest", "LOG_LEVEL": "debug" },
+                    }),
+                ),
+                _ => (
+                    StatusCode::NOT_FOUND,
+                    json!({
+                        "apiVersion": "v1",
+                        "kind": "Status",
+                        "metadata": {},
+                        "status": "Failure",
+                        "message": "not found",
+                        "reason": "NotFound",
+                        "code": 404,
+                    }),
+                ),
+            };
+
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .status(status)
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                    .unwrap(),
+            )
+        });
+
+        Client::new(service, "default")
+    }
+
+    fn env_var(name: &str, value_from: EnvVarSource) -> EnvVar {
+        EnvVar {
+            name: name.into(),
+            value: None,
+            value_from: Some(value_from),
+        }
+    }
+
+    fn secret_key(name: &str, key: &str, optional: Option<bool>) -> EnvVarSource {
+        EnvVarSource {
+            secret_key_ref: Some(SecretKeySelector {
+                name: name.into(),
+                key: key.into(),
+                optional,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// `env` takes precedence over `envFrom`, and later `envFrom` sources over earlier ones.
+    #[tokio::test]
+    async fn container_env_precedence() {
+        let container = Container {
+            name: "main".into(),
+            env_from: Some(vec![
+                EnvFromSource {
+                    config_map_ref: Some(ConfigMapEnvSource {
+                        name: "app".into(),
+                        optional: None,
+                    }),
+                    ..Default::default()
+                },
+                EnvFromSource {
+                    secret_ref: Some(SecretEnvSource {
+                        name: "db".into(),
+                        optional: None,
+                    }),
+                    ..Default::default()
+                },
+            ]),
+            env: Some(vec![
+                EnvVar {
+                    name: "LOG_LEVEL".into(),
+                    value: Some("trace".into()),
+                    value_from: None,
+                },
+                env_var("DB_PASSWORD", secret_key("db", "PASSWORD", None)),
+                env_var(
+                    "LEVEL",
+                    EnvVarSource {
+                        config_map_key_ref: Some(ConfigMapKeySelector {
+                            name: "app".into(),
+                            key: "LOG_LEVEL".into(),
+                            optional: None,
+                        }),
+                        ..Default::default()
+                    },
+                ),
+                env_var(
+                    "NODE_NAME",
+                    EnvVarSource {
+                        field_ref: Some(ObjectFieldSelector {
+                            api_version: None,
+                            field_path: "spec.nodeName".into(),
+                        }),
+                        ..Default::default()
+                    },
+                ),
+                env_var("TOKEN", secret_key("db", "TOKEN", Some(true))),
+                env_var("API_KEY", secret_key("missing", "KEY", Some(true))),
+            ]),
+            ..Default::default()
+        };
+
+        let env = resolve_container_env(&fake_client(), None, &container)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            env,
+            HashMap::from(
+                [
+                    ("USER", "admin"),
+                    ("PASSWORD", "hunter2"),
+                    ("LOG_LEVEL", "trace"),
+                    ("DB_PASSWORD", "hunter2"),
+                    ("LEVEL", "debug"),
+                ]
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+            )
+        );
+    }
+
+    /// Prefixes are prepended to all keys, and missing optional sources are skipped.
+    #[tokio::test]
+    async fn env_from_prefix_and_optional() {
+        let sources = [
+            EnvFromSource {
+                secret_ref: Some(SecretEnvSource {
+                    name: "db".into(),
+                    optional: None,
+                }),
+                prefix: Some("DB_".into()),
+                ..Default::default()
+            },
+            EnvFromSource {
+                config_map_ref: Some(ConfigMapEnvSource {
+                    name: "missing".into(),
+                    optional: Some(true),
+                }),
+                ..Default::default()
+            },
+        ];
+
+        let env = load_env_from(&fake_client(), None, &sources).await.unwrap();
+
+        assert_eq!(
+            env,
+            HashMap::from(
+                [("DB_USER", "admin"), ("DB_PASSWORD", "hunter2")]
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+            )
+        );
+    }
+
+    /// Missing sources and keys fail the resolution, unless they're optional.
+    #[tokio::test]
+    async fn missing_sources_fail() {
+        let client = fake_client();
+
+        let container = Container {
+            name: "main".into(),
+            env: Some(vec![env_var("TOKEN", secret_key("db", "TOKEN", None))]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            resolve_container_env(&client, None, &container).await,
+            Err(KubeApiError::MissingEnvSource(..))
+        ));
+
+        let sources = [EnvSource::ConfigMap {
+            name: "missing".into(),
+            prefix: None,
+        }];
+        assert!(matches!(
+            load_env_sources(&client, None, &sources).await,
+            Err(KubeApiError::MissingEnvSource(..))
+        ));
+    }
+}
@@ -84,6 +84,14 @@ pub enum KubeApiError {
     /// Spawned agent pod was deleted during startup.
     #[error("Agent pod was unexpectedly deleted")]
     AgentPodDeleted,
+
+    /// None of the pods matching the target label selector is ready to be targeted.
+    #[error("no pod matching the label selector `{0}` is ready to be targeted")]
+    NoReadyPodForSelector(String),
+
+    /// A non-optional reference in the target's environment could not be resolved.
+    #[error("{0} referenced in the environment was not found")]
+    MissingEnvSource(String),
 }
 
 impl KubeApiError {
@@ -17,6 +17,7 @@
 pub use kube;
 
 pub mod api;
+pub mod env;
 pub mod error;
 pub mod extract;
 pub mod resolved;
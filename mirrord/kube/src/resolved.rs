@@ -1,7 +1,10 @@
-use std::{borrow::Cow, collections::BTreeMap};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+};
 
 use k8s_openapi::api::{
-    apps::v1::{Deployment, ReplicaSet, StatefulSet},
+    apps::v1::{DaemonSet, Deployment, ReplicaSet, StatefulSet},
     batch::v1::{CronJob, Job},
     core::v1::{EnvFromSource, EnvVar, Pod, PodSpec, Service},
 };
@@ -13,9 +16,13 @@ use super::{
     api::{kubernetes::get_k8s_resource_api, runtime::RuntimeData},
     error::KubeApiError,
 };
-use crate::api::{kubernetes::rollout::Rollout, runtime::RuntimeDataFromLabels};
+use crate::{
+    api::{container::SKIP_NAMES, kubernetes::rollout::Rollout, runtime::RuntimeDataFromLabels},
+    env::resolve_container_env,
+};
 
 pub mod cron_job;
+pub mod daemon_set;
 pub mod deployment;
 pub mod job;
 pub mod pod;
@@ -43,6 +50,7 @@ pub enum ResolvedTarget<const CHECKED: bool> {
     StatefulSet(ResolvedResource<StatefulSet>),
     Service(ResolvedResource<Service>),
     ReplicaSet(ResolvedResource<ReplicaSet>),
+    DaemonSet(ResolvedResource<DaemonSet>),
 
     /// [`Pod`] is a special case, in that it does not implement [`RuntimeDataFromLabels`],
     /// and instead we implement a `runtime_data` method directly in its
@@ -101,6 +109,9 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
             ResolvedTarget::ReplicaSet(ResolvedResource { resource, .. }) => {
                 resource.metadata.name.as_deref()
             }
+            ResolvedTarget::DaemonSet(ResolvedResource { resource, .. }) => {
+                resource.metadata.name.as_deref()
+            }
             ResolvedTarget::Targetless(_) => None,
         }
     }
@@ -115,6 +126,7 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
             ResolvedTarget::StatefulSet(ResolvedResource { resource, .. }) => resource.name_any(),
             ResolvedTarget::Service(ResolvedResource { resource, .. }) => resource.name_any(),
             ResolvedTarget::ReplicaSet(ResolvedResource { resource, .. }) => resource.name_any(),
+            ResolvedTarget::DaemonSet(ResolvedResource { resource, .. }) => resource.name_any(),
             ResolvedTarget::Targetless(..) => "targetless".to_string(),
         }
     }
@@ -145,6 +157,9 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
             ResolvedTarget::ReplicaSet(ResolvedResource { resource, .. }) => {
                 resource.metadata.namespace.as_deref()
             }
+            ResolvedTarget::DaemonSet(ResolvedResource { resource, .. }) => {
+                resource.metadata.namespace.as_deref()
+            }
             ResolvedTarget::Targetless(namespace) => Some(namespace),
         }
     }
@@ -166,6 +181,9 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
             ResolvedTarget::ReplicaSet(ResolvedResource { resource, .. }) => {
                 resource.metadata.labels
             }
+            ResolvedTarget::DaemonSet(ResolvedResource { resource, .. }) => {
+                resource.metadata.labels
+            }
             ResolvedTarget::Targetless(_) => None,
         }
     }
@@ -180,6 +198,7 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
             ResolvedTarget::StatefulSet(_) => "statefulset",
             ResolvedTarget::Service(_) => "service",
             ResolvedTarget::ReplicaSet(_) => "replicaset",
+            ResolvedTarget::DaemonSet(_) => "daemonset",
             ResolvedTarget::Targetless(_) => "targetless",
         }
     }
@@ -194,9 +213,8 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
             | ResolvedTarget::StatefulSet(ResolvedResource { container, .. })
             | ResolvedTarget::Service(ResolvedResource { container, .. })
             | ResolvedTarget::Pod(ResolvedResource { container, .. })
-            | ResolvedTarget::ReplicaSet(ResolvedResource { container, .. }) => {
-                container.as_deref()
-            }
+            | ResolvedTarget::ReplicaSet(ResolvedResource { container, .. })
+            | ResolvedTarget::DaemonSet(ResolvedResource { container, .. }) => container.as_deref(),
             ResolvedTarget::Targetless(..) => None,
         }
     }
@@ -292,6 +310,16 @@ impl ResolvedTarget<false> {
                         container: target.container.clone(),
                     })
                 }),
+            Target::DaemonSet(target) => get_k8s_resource_api::<DaemonSet>(client, namespace)
+                .get(&target.daemon_set)
+                .await
+                .map(Box::new)
+                .map(|resource| {
+                    ResolvedTarget::DaemonSet(ResolvedResource {
+                        resource,
+                        container: target.container.clone(),
+                    })
+                }),
             Target::Targetless => Ok(ResolvedTarget::Targetless(
                 namespace.unwrap_or("default").to_string(),
             )),
@@ -306,8 +334,8 @@ impl ResolvedTarget<false> {
     ///
     /// Performs only basic checks:
     /// 1. [`ResolvedTarget::Deployment`], [`ResolvedTarget::Rollout`],
-    ///    [`ResolvedTarget::StatefulSet`] - the target container, if specified, is found in the
-    ///    spec
+    ///    [`ResolvedTarget::StatefulSet`], [`ResolvedTarget::ReplicaSet`],
+    ///    [`ResolvedTarget::DaemonSet`] - the target container, if specified, is found in the spec
     /// 2. [`ResolvedTarget::Pod`] - passes target-readiness check, see [`RuntimeData::from_pod`].
     /// 3. [`ResolvedTarget::Job`] and [`ResolvedTarget::CronJob`] - error, as this is `copy_target`
     ///    exclusive
@@ -470,6 +498,32 @@ impl ResolvedTarget<false> {
                 }))
             }
 
+            ResolvedTarget::DaemonSet(ResolvedResource {
+                resource,
+                container,
+            }) => {
+                if let Some(container) = &container {
+                    // verify that the container exists
+                    resource
+                        .spec
+                        .as_ref()
+                        .ok_or_else(|| KubeApiError::missing_field(resource.as_ref(), ".spec"))?
+                        .template
+                        .spec
+                        .as_ref()
+                        .ok_or_else(|| KubeApiError::missing_field(resource.as_ref(), ".spec.template.spec"))?
+                        .containers
+                        .iter()
+                        .find(|c| c.name == *container)
+                        .ok_or_else(|| KubeApiError::invalid_state(resource.as_ref(), format_args!("specified pod template does not contain target container `{container}`")))?;
+                }
+
+                Ok(ResolvedTarget::DaemonSet(ResolvedResource {
+                    resource,
+                    container,
+                }))
+            }
+
             ResolvedTarget::Targetless(namespace) => {
                 // no check needed here
                 Ok(ResolvedTarget::Targetless(namespace))
@@ -517,6 +571,9 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
                 .as_ref()?
                 .spec
                 .as_ref(),
+            ResolvedTarget::DaemonSet(inner) => {
+                inner.resource.spec.as_ref()?.template.spec.as_ref()
+            }
             ResolvedTarget::Pod(inner) => inner.resource.spec.as_ref(),
             ResolvedTarget::Targetless(..) => None,
         }
@@ -627,4 +684,37 @@ impl<const CHECKED: bool> ResolvedTarget<CHECKED> {
 
         Ok(None)
     }
+
+    /// Resolves the environment of the target container from its pod spec, including the
+    /// referenced [`Secret`](k8s_openapi::api::core::v1::Secret)s and
+    /// [`ConfigMap`](k8s_openapi::api::core::v1::ConfigMap)s.
+    ///
+    /// When no container is specified, picks it the same way as
+    /// [`choose_container`](crate::api::container::choose_container). Returns an empty map for
+    /// targets without a pod spec.
+    pub async fn resolve_env(
+        &self,
+        client: &Client,
+    ) -> Result<HashMap<String, String>, KubeApiError> {
+        let Some(pod_spec) = self.resolve_pod_spec(client).await? else {
+            return Ok(Default::default());
+        };
+
+        let container = match self.container() {
+            Some(name) => pod_spec
+                .containers
+                .iter()
+                .find(|container| container.name == name),
+            None => pod_spec
+                .containers
+                .iter()
+                .find(|container| !SKIP_NAMES.contains(container.name.as_str()))
+                .or_else(|| pod_spec.containers.first()),
+        };
+        let Some(container) = container else {
+            return Ok(Default::default());
+        };
+
+        resolve_container_env(client, self.namespace(), container).await
+    }
 }
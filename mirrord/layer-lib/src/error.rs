@@ -426,8 +426,9 @@ pub type LayerResult<T, E = LayerError> = std::result::Result<T, E>;
 pub type HookResult<T, E = HookError> = std::result::Result<T, E>;
 pub type ProxyResult<T, E = ProxyError> = std::result::Result<T, E>;
 
+/// Converts the error to the errno that is reported to the user application.
 #[cfg(unix)]
-fn get_platform_errno(fail: HookError) -> i32 {
+pub fn get_platform_errno(fail: HookError) -> i32 {
     match fail {
         HookError::Null(_) => libc::EINVAL,
         HookError::TryFromInt(_) => libc::EINVAL,
@@ -500,8 +501,9 @@ fn get_platform_errno(fail: HookError) -> i32 {
     }
 }
 
+/// Converts the error to the error code that is reported to the user application.
 #[cfg(target_os = "windows")]
-fn get_platform_errno(fail: HookError) -> u32 {
+pub fn get_platform_errno(fail: HookError) -> u32 {
     use winapi::shared::winerror::*;
     match fail {
         HookError::Null(_) => WSAEINVAL,
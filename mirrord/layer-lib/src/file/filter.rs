@@ -80,6 +80,7 @@ pub struct FileFilter {
     pub read_write: RegexSet,
     pub local: RegexSet,
     pub not_found: RegexSet,
+    pub overlay: RegexSet,
     pub default_local: RegexSet,
     pub default_remote_ro: RegexSet,
     pub default_not_found: RegexSet,
@@ -107,6 +108,7 @@ impl FileFilter {
             local,
             mode,
             not_found,
+            overlay,
             ..
         } = fs_config;
 
@@ -117,6 +119,7 @@ impl FileFilter {
         let local = Self::make_regex_set(local).expect("building local path regex set failed");
         let not_found =
             Self::make_regex_set(not_found).expect("building not-found regex set failed");
+        let overlay = Self::make_regex_set(overlay).expect("building overlay regex set failed");
 
         let default_local = generate_local_set();
         let default_remote_ro = generate_remote_ro_set();
@@ -127,6 +130,7 @@ impl FileFilter {
             read_write,
             local,
             not_found,
+            overlay,
             default_local,
             default_remote_ro,
             default_not_found,
@@ -139,7 +143,10 @@ impl FileFilter {
 
         match self.mode {
             FsModeConfig::Local => Some(FileMode::Local(false)),
-            FsModeConfig::Read | FsModeConfig::Write | FsModeConfig::LocalWithOverrides => {
+            FsModeConfig::Read
+            | FsModeConfig::Write
+            | FsModeConfig::Overlay
+            | FsModeConfig::LocalWithOverrides => {
                 if self.not_found.is_match(path) {
                     Some(FileMode::NotFound(false))
                 } else if self.read_write.is_match(path) {
@@ -161,6 +168,15 @@ impl FileFilter {
         }
     }
 
+    /// Checks if writes to this remote path should go to the local copy-on-write overlay.
+    ///
+    /// Only meaningful for paths that are accessed remotely.
+    pub fn is_overlay<T: AsRef<str>>(&self, path: T) -> bool {
+        let path = path.as_ref();
+
+        self.overlay.is_match(path) || (self.mode.is_overlay() && !self.read_write.is_match(path))
+    }
+
     pub fn check_not_found(&self, path: &Path) -> bool {
         matches!(
             self.check(path.to_str().unwrap_or_default()),
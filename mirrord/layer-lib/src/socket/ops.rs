@@ -206,6 +206,7 @@ where
         let request = OutgoingConnectRequest {
             remote_address: remote_address.clone(),
             protocol,
+            inspect: None,
         };
 
         let response = match proxy_request_fn(request) {
@@ -350,6 +351,7 @@ pub fn create_outgoing_request(
     OutgoingConnectRequest {
         remote_address: remote_address.into(),
         protocol,
+        inspect: None,
     }
 }
 
@@ -23,8 +23,11 @@ use mirrord_protocol::file::{
 use mirrord_protocol::file::{GetDEnts64Request, GetDEnts64Response};
 
 pub(crate) mod hooks;
+#[cfg(target_os = "linux")]
+pub(crate) mod io_uring;
 pub(crate) mod open_dirs;
 pub(crate) mod ops;
+pub(crate) mod overlay;
 
 type RemoteFd = u64;
 type LocalFd = RawFd;
@@ -275,7 +275,7 @@ pub(super) unsafe extern "C" fn sendfile_detour(
 
 /// Hook for [`libc::ftruncate`].
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn ftruncate_detour(fd: c_int, length: off_t) -> c_int {
+pub(crate) unsafe extern "C" fn ftruncate_detour(fd: c_int, length: off_t) -> c_int {
     ftruncate(fd, length)
         .map(|()| 0)
         .unwrap_or_bypass_with(|_| unsafe { FN_FTRUNCATE(fd, length) })
@@ -283,7 +283,7 @@ pub(super) unsafe extern "C" fn ftruncate_detour(fd: c_int, length: off_t) -> c_
 
 /// Hook for [`libc::futimens`].
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn futimens_detour(fd: c_int, raw_times: *const timespec) -> c_int {
+pub(crate) unsafe extern "C" fn futimens_detour(fd: c_int, raw_times: *const timespec) -> c_int {
     unsafe {
         let times = if !raw_times.is_null() {
             let [first, second] = slice::from_raw_parts(raw_times, 2) else {
@@ -311,7 +311,7 @@ pub(super) unsafe extern "C" fn futimens_detour(fd: c_int, raw_times: *const tim
 
 /// Hook for [`libc::fchown`].
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn fchown_detour(fd: c_int, owner: uid_t, group: gid_t) -> c_int {
+pub(crate) unsafe extern "C" fn fchown_detour(fd: c_int, owner: uid_t, group: gid_t) -> c_int {
     fchown(fd, owner, group)
         .map(|()| 0)
         .unwrap_or_bypass_with(|_| unsafe { FN_FCHOWN(fd, owner, group) })
@@ -319,7 +319,7 @@ pub(super) unsafe extern "C" fn fchown_detour(fd: c_int, owner: uid_t, group: gi
 
 /// Hook for [`libc::fchmod`].
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn fchmod_detour(fd: c_int, mode: mode_t) -> c_int {
+pub(crate) unsafe extern "C" fn fchmod_detour(fd: c_int, mode: mode_t) -> c_int {
     // mode_t is u16 on MacOS but u32 on Linux so clippy warns that the u32 cast is useless when
     // targetting linux but we want to keep it to explicitly handle both platforms in a
     // single expr
@@ -1691,6 +1691,8 @@ pub(crate) unsafe fn enable_file_hooks(hook_manager: &mut HookManager, state: &L
                 FnStatfs64,
                 FN_STATFS64
             );
+
+            super::io_uring::enable_io_uring_hooks(hook_manager, state.experimental().io_uring);
         }
 
         #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
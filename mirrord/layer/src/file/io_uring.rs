@@ -0,0 +1,743 @@
+//! `io_uring` support, see [`IoUringMode`].
+//!
+//! `io_uring` performs I/O without going through any of the hooked [`libc`] functions, so reads of
+//! remote files would silently return local data. There are no [`libc`] wrappers for the
+//! `io_uring` syscalls, so we hook the `syscall` function, which is used by the `io-uring` crate
+//! (tokio-uring) and libuv. liburing (since 2.2) issues the syscalls inline, so its rings are not
+//! detected.
+//!
+//! In [`IoUringMode::Disable`], every `io_uring_setup` fails with `ENOSYS`, and runtimes fall back
+//! to classic syscalls.
+//!
+//! In [`IoUringMode::Emulate`], we map the rings of every set up ring again, in the layer. Before
+//! `io_uring_enter` submits new entries, the ones that target files from
+//! [`OPEN_FILES`](super::OPEN_FILES) are carried out with [`file::ops`](super::ops), and replaced
+//! in the ring with `IORING_OP_NOP`s. The `user_data` of these NOPs is a token of ours, as the
+//! application's `user_data` need not be unique. Once the kernel posts completions of these NOPs,
+//! we restore the application's `user_data`, and overwrite their results with the results of the
+//! emulated operations.
+//!
+//! The NOPs are not linked to the previous entries, nor drained or punted to the async workers, so
+//! the kernel completes them within the `io_uring_enter` that submits them, before we patch the
+//! completions. The emulated operations are done before the submission anyway.
+
+use std::{
+    collections::HashMap,
+    io,
+    os::unix::io::RawFd,
+    ptr, slice,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use libc::{c_char, c_long, iovec};
+use mirrord_config::experimental::IoUringMode;
+use mirrord_layer_macro::hook_fn;
+use mirrord_protocol::file::{ReadFileResponse, WriteFileResponse};
+use nix::errno::Errno;
+
+use super::{
+    OPEN_FILES, OpenOptionsInternalExt,
+    ops::{fsync, openat, pread, pwrite, read, write},
+};
+use crate::{
+    FN_CLOSE, close_layer_fd,
+    common::CheckedInto,
+    detour::{Detour, DetourGuard},
+    hooks::HookManager,
+    replace,
+};
+
+/// Offsets of the `mmap`s of a ring, from `linux/io_uring.h`.
+const IORING_OFF_SQ_RING: i64 = 0;
+const IORING_OFF_CQ_RING: i64 = 0x8000000;
+const IORING_OFF_SQES: i64 = 0x10000000;
+
+const IORING_SETUP_SQPOLL: u32 = 1 << 1;
+const IORING_SETUP_SQE128: u32 = 1 << 10;
+const IORING_SETUP_CQE32: u32 = 1 << 11;
+const IORING_SETUP_NO_MMAP: u32 = 1 << 14;
+const IORING_SETUP_NO_SQARRAY: u32 = 1 << 16;
+
+const IORING_FEAT_SINGLE_MMAP: u32 = 1 << 0;
+
+const IORING_ENTER_REGISTERED_RING: u32 = 1 << 4;
+
+const IOSQE_FIXED_FILE: u8 = 1 << 0;
+const IOSQE_IO_DRAIN: u8 = 1 << 1;
+const IOSQE_IO_LINK: u8 = 1 << 2;
+const IOSQE_IO_HARDLINK: u8 = 1 << 3;
+const IOSQE_ASYNC: u8 = 1 << 4;
+const IOSQE_BUFFER_SELECT: u8 = 1 << 5;
+const IOSQE_CQE_SKIP_SUCCESS: u8 = 1 << 6;
+
+const IORING_OP_NOP: u8 = 0;
+const IORING_OP_READV: u8 = 1;
+const IORING_OP_WRITEV: u8 = 2;
+const IORING_OP_FSYNC: u8 = 3;
+const IORING_OP_OPENAT: u8 = 18;
+const IORING_OP_CLOSE: u8 = 19;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+
+/// High bits of the `user_data` of NOPs that replaced emulated entries, the low bits are a counter.
+const EMULATED_USER_DATA: u64 = 0x6d69_7272_0000_0000;
+
+/// `struct io_sqring_offsets`.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug, Default, Clone, Copy)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+/// `struct io_cqring_offsets`.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug, Default, Clone, Copy)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+/// `struct io_uring_params`, filled by the kernel in `io_uring_setup`.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug)]
+struct IoUringParams {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+/// `struct io_uring_sqe`, only the fields we use are named after the kernel ones.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug, Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    op_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    file_index: u32,
+    addr3: u64,
+    pad: u64,
+}
+
+impl Sqe {
+    /// Replaces the entry with a `IORING_OP_NOP` that completes with the given `user_data`.
+    ///
+    /// The NOP succeeds, so `IOSQE_CQE_SKIP_SUCCESS` is kept only if the emulated operation
+    /// succeeded as well. Links to the next entries are kept.
+    fn make_nop(&mut self, result: i32, user_data: u64) {
+        let mut flags =
+            self.flags & !(IOSQE_FIXED_FILE | IOSQE_BUFFER_SELECT | IOSQE_IO_DRAIN | IOSQE_ASYNC);
+        if result < 0 {
+            flags &= !IOSQE_CQE_SKIP_SUCCESS;
+        }
+
+        *self = Self {
+            opcode: IORING_OP_NOP,
+            flags,
+            fd: -1,
+            user_data,
+            ..Default::default()
+        };
+    }
+
+    /// Makes the kernel start the next entry without waiting for this one.
+    fn unlink_next(&mut self) {
+        self.flags &= !(IOSQE_IO_LINK | IOSQE_IO_HARDLINK);
+    }
+
+    fn skips_completion(&self) -> bool {
+        self.flags & IOSQE_CQE_SKIP_SUCCESS != 0
+    }
+}
+
+/// `struct io_uring_cqe`.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// Rings set up in [`IoUringMode::Emulate`], by ring fd.
+static RINGS: LazyLock<Mutex<HashMap<RawFd, Arc<Ring>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Our own mapping of a ring shared with the kernel and the application.
+#[derive(Debug)]
+struct Ring {
+    sq: *mut u8,
+    sq_size: usize,
+    /// Same as `sq` with `IORING_FEAT_SINGLE_MMAP`.
+    cq: *mut u8,
+    cq_size: usize,
+    sqes: *mut u8,
+    sqes_size: usize,
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+    sqe_size: usize,
+    cqe_size: usize,
+    no_sq_array: bool,
+    /// Emulated entries waiting for completions of their NOPs, by the NOP `user_data`, with the
+    /// application's `user_data` and the result.
+    pending: Mutex<HashMap<u64, (u64, i32)>>,
+    /// Counter for the `user_data` of NOPs, see [`EMULATED_USER_DATA`].
+    next_nop: AtomicU32,
+}
+
+// SAFETY: the mappings are shared memory that stays valid until the ring is dropped, and the
+// application synchronizes access to the submission queue.
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    /// Maps the rings of `fd` again, see `io_uring_setup(2)` for the layout.
+    unsafe fn map(fd: RawFd, params: &IoUringParams) -> io::Result<Self> {
+        unsafe {
+            let sqe_size = if params.flags & IORING_SETUP_SQE128 != 0 {
+                128
+            } else {
+                size_of::<Sqe>()
+            };
+            let cqe_size = if params.flags & IORING_SETUP_CQE32 != 0 {
+                32
+            } else {
+                size_of::<Cqe>()
+            };
+            let no_sq_array = params.flags & IORING_SETUP_NO_SQARRAY != 0;
+            let single_mmap = params.features & IORING_FEAT_SINGLE_MMAP != 0;
+
+            let mut sq_size = if no_sq_array {
+                let SqRingOffsets {
+                    head,
+                    tail,
+                    ring_mask,
+                    flags,
+                    dropped,
+                    ..
+                } = params.sq_off;
+                [head, tail, ring_mask, flags, dropped]
+                    .into_iter()
+                    .max()
+                    .unwrap_or_default() as usize
+                    + size_of::<u32>()
+            } else {
+                params.sq_off.array as usize + params.sq_entries as usize * size_of::<u32>()
+            };
+            let mut cq_size = params.cq_off.cqes as usize + params.cq_entries as usize * cqe_size;
+            if single_mmap {
+                sq_size = sq_size.max(cq_size);
+                cq_size = sq_size;
+            }
+            let sqes_size = params.sq_entries as usize * sqe_size;
+
+            let sq = map_shared(fd, sq_size, IORING_OFF_SQ_RING)?;
+            let cq = if single_mmap {
+                sq
+            } else {
+                map_shared(fd, cq_size, IORING_OFF_CQ_RING).inspect_err(|_| {
+                    libc::munmap(sq.cast(), sq_size);
+                })?
+            };
+            let sqes = map_shared(fd, sqes_size, IORING_OFF_SQES).inspect_err(|_| {
+                libc::munmap(sq.cast(), sq_size);
+                if !single_mmap {
+                    libc::munmap(cq.cast(), cq_size);
+                }
+            })?;
+
+            Ok(Self {
+                sq,
+                sq_size,
+                cq,
+                cq_size,
+                sqes,
+                sqes_size,
+                sq_off: params.sq_off,
+                cq_off: params.cq_off,
+                sqe_size,
+                cqe_size,
+                no_sq_array,
+                pending: Default::default(),
+                next_nop: Default::default(),
+            })
+        }
+    }
+
+    unsafe fn sq_u32(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*self.sq.add(offset as usize).cast::<AtomicU32>() }
+    }
+
+    unsafe fn cq_u32(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*self.cq.add(offset as usize).cast::<AtomicU32>() }
+    }
+
+    /// Emulates up to `to_submit` entries that the kernel is about to consume.
+    unsafe fn emulate_submissions(&self, to_submit: u32) {
+        unsafe {
+            // The kernel owns the head, the application wrote the tail before `io_uring_enter`.
+            let head = self.sq_u32(self.sq_off.head).load(Ordering::Acquire);
+            let tail = self.sq_u32(self.sq_off.tail).load(Ordering::Acquire);
+            let mask = self.sq_u32(self.sq_off.ring_mask).load(Ordering::Relaxed);
+            let count = tail.wrapping_sub(head).min(to_submit);
+
+            let mut previous: Option<*mut Sqe> = None;
+            for i in 0..count {
+                let position = head.wrapping_add(i) & mask;
+                let index = if self.no_sq_array {
+                    position
+                } else {
+                    self.sq
+                        .add(self.sq_off.array as usize)
+                        .cast::<u32>()
+                        .add(position as usize)
+                        .read_volatile()
+                };
+                let sqe_ptr = self.sqes.add(index as usize * self.sqe_size).cast::<Sqe>();
+                let sqe = &mut *sqe_ptr;
+                let previous = previous.replace(sqe_ptr);
+
+                let Some(result) = emulate(sqe) else {
+                    continue;
+                };
+
+                // The operation is already done, the NOP must not wait for the previous entry.
+                if let Some(previous) = previous {
+                    (*previous).unlink_next();
+                }
+
+                let user_data = sqe.user_data;
+                let nop_user_data =
+                    EMULATED_USER_DATA | u64::from(self.next_nop.fetch_add(1, Ordering::Relaxed));
+                sqe.make_nop(result, nop_user_data);
+                if (result < 0 || !sqe.skips_completion())
+                    && let Ok(mut pending) = self.pending.lock()
+                {
+                    pending.insert(nop_user_data, (user_data, result));
+                }
+            }
+        }
+    }
+
+    /// Overwrites results in completions of NOPs that replaced emulated entries.
+    unsafe fn patch_completions(&self) {
+        let Ok(mut pending) = self.pending.lock() else {
+            return;
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        unsafe {
+            // The application owns the head, the kernel owns the tail.
+            let head = self.cq_u32(self.cq_off.head).load(Ordering::Acquire);
+            let tail = self.cq_u32(self.cq_off.tail).load(Ordering::Acquire);
+            let mask = self.cq_u32(self.cq_off.ring_mask).load(Ordering::Relaxed);
+
+            let cqes = self.cq.add(self.cq_off.cqes as usize);
+            for i in 0..tail.wrapping_sub(head) {
+                let position = head.wrapping_add(i) & mask;
+                let cqe = &mut *cqes.add(position as usize * self.cqe_size).cast::<Cqe>();
+
+                if let Some((user_data, result)) = pending.remove(&cqe.user_data) {
+                    cqe.user_data = user_data;
+                    cqe.res = result;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.sqes.cast(), self.sqes_size);
+            if self.cq != self.sq {
+                libc::munmap(self.cq.cast(), self.cq_size);
+            }
+            libc::munmap(self.sq.cast(), self.sq_size);
+        }
+    }
+}
+
+unsafe fn map_shared(fd: RawFd, size: usize, offset: i64) -> io::Result<*mut u8> {
+    let address = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED | libc::MAP_POPULATE,
+            fd,
+            offset,
+        )
+    };
+
+    if address == libc::MAP_FAILED {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(address.cast())
+    }
+}
+
+/// Converts the outcome of a file operation to a completion result.
+///
+/// [`Detour::Bypass`] means that the kernel should carry out the entry.
+fn completion_result<T: TryInto<i32>>(detour: Detour<T>) -> Option<i32> {
+    match detour {
+        Detour::Success(value) => Some(value.try_into().unwrap_or(i32::MAX)),
+        Detour::Bypass(..) => None,
+        Detour::Error(error) => {
+            // Sets `errno`, like for the other hooks.
+            let _ = i64::from(error);
+            Some(-Errno::last_raw())
+        }
+    }
+}
+
+/// Carries out the entry with [`file::ops`](super::ops) if it targets a file managed by mirrord.
+///
+/// Returns the result for the completion, or [`None`] if the entry should go to the kernel.
+unsafe fn emulate(sqe: &Sqe) -> Option<i32> {
+    if sqe.opcode == IORING_OP_OPENAT {
+        // Fixed files are not supported, the kernel would install the local file in the table.
+        if sqe.file_index != 0 {
+            return None;
+        }
+
+        let path = (sqe.addr as *const c_char).checked_into();
+        let open_options = OpenOptionsInternalExt::from_flags(sqe.op_flags as i32);
+        return completion_result(openat(sqe.fd, path, open_options));
+    }
+
+    if sqe.flags & IOSQE_FIXED_FILE != 0 || !OPEN_FILES.lock().ok()?.contains_key(&sqe.fd) {
+        return None;
+    }
+    if sqe.flags & IOSQE_BUFFER_SELECT != 0 {
+        return Some(-libc::EOPNOTSUPP);
+    }
+
+    let fd = sqe.fd;
+    // `-1` offset means the current file position.
+    let offset = (sqe.off != u64::MAX).then_some(sqe.off);
+
+    unsafe {
+        match sqe.opcode {
+            IORING_OP_READ => {
+                let buffer = slice::from_raw_parts_mut(sqe.addr as *mut u8, sqe.len as usize);
+                completion_result(read_into(fd, offset, &mut [buffer]))
+            }
+            IORING_OP_READV => {
+                let mut buffers = iovecs(sqe)
+                    .iter()
+                    .map(|iov| slice::from_raw_parts_mut(iov.iov_base.cast::<u8>(), iov.iov_len))
+                    .collect::<Vec<_>>();
+                completion_result(read_into(fd, offset, &mut buffers))
+            }
+            IORING_OP_WRITE => {
+                let buffer = slice::from_raw_parts(sqe.addr as *const u8, sqe.len as usize);
+                completion_result(write_from(fd, offset, buffer.to_vec()))
+            }
+            IORING_OP_WRITEV => {
+                let bytes = iovecs(sqe)
+                    .iter()
+                    .flat_map(|iov| slice::from_raw_parts(iov.iov_base.cast::<u8>(), iov.iov_len))
+                    .copied()
+                    .collect::<Vec<_>>();
+                completion_result(write_from(fd, offset, bytes))
+            }
+            IORING_OP_FSYNC => completion_result(fsync(fd)),
+            IORING_OP_CLOSE => {
+                let result = FN_CLOSE(fd);
+                close_layer_fd(fd);
+                Some(if result == 0 { 0 } else { -Errno::last_raw() })
+            }
+            // The local fd is only a placeholder, the kernel can't do anything useful with it.
+            _ => Some(-libc::EOPNOTSUPP),
+        }
+    }
+}
+
+unsafe fn iovecs(sqe: &Sqe) -> &[iovec] {
+    if sqe.addr == 0 {
+        return &[];
+    }
+
+    unsafe { slice::from_raw_parts(sqe.addr as *const iovec, sqe.len as usize) }
+}
+
+/// Reads from a remote file into the buffers, returns the number of bytes read.
+fn read_into(fd: RawFd, offset: Option<u64>, buffers: &mut [&mut [u8]]) -> Detour<usize> {
+    let size = buffers.iter().map(|buffer| buffer.len() as u64).sum();
+    let ReadFileResponse { bytes, .. } = match offset {
+        Some(offset) => pread(fd, size, offset)?,
+        None => read(fd, size)?,
+    };
+
+    let mut remaining = &bytes[..];
+    for buffer in buffers {
+        let length = buffer.len().min(remaining.len());
+        buffer[..length].copy_from_slice(&remaining[..length]);
+        remaining = &remaining[length..];
+    }
+
+    Detour::Success(bytes.len())
+}
+
+/// Writes to a remote file, returns the number of bytes written.
+fn write_from(fd: RawFd, offset: Option<u64>, bytes: Vec<u8>) -> Detour<isize> {
+    match offset {
+        Some(offset) => {
+            let WriteFileResponse { written_amount } = pwrite(fd, &bytes, offset)?;
+            Detour::Success(written_amount.try_into()?)
+        }
+        None => write(fd, Some(bytes)),
+    }
+}
+
+/// Handles `io_uring_setup` according to the [`IoUringMode`].
+unsafe fn io_uring_setup(params: *mut IoUringParams, setup: impl FnOnce() -> c_long) -> c_long {
+    let mode = crate::setup().experimental().io_uring;
+    let unsupported = match mode {
+        IoUringMode::Allow => return setup(),
+        IoUringMode::Disable => true,
+        IoUringMode::Emulate => unsafe {
+            params.is_null() || (*params).flags & (IORING_SETUP_SQPOLL | IORING_SETUP_NO_MMAP) != 0
+        },
+    };
+    if unsupported {
+        tracing::debug!(
+            ?mode,
+            "Failing `io_uring_setup`, remote files are not available through io_uring"
+        );
+        Errno::ENOSYS.set();
+        return -1;
+    }
+
+    let fd = setup();
+    if fd < 0 {
+        return fd;
+    }
+
+    let fd = fd as RawFd;
+    match unsafe { Ring::map(fd, &*params) } {
+        Ok(ring) => {
+            if let Ok(mut rings) = RINGS.lock() {
+                rings.insert(fd, Arc::new(ring));
+            }
+            fd as c_long
+        }
+        Err(error) => {
+            tracing::warn!(%error, "Failed to map io_uring rings, failing `io_uring_setup`");
+            unsafe { FN_CLOSE(fd) };
+            Errno::ENOSYS.set();
+            -1
+        }
+    }
+}
+
+/// Handles `io_uring_enter` of rings set up in [`IoUringMode::Emulate`].
+unsafe fn io_uring_enter(
+    fd: RawFd,
+    to_submit: u32,
+    flags: u32,
+    enter: impl FnOnce() -> c_long,
+) -> c_long {
+    // With a registered ring, `fd` is an index in the ring table of the thread.
+    let ring = (flags & IORING_ENTER_REGISTERED_RING == 0)
+        .then(|| RINGS.lock().ok()?.get(&fd).cloned())
+        .flatten();
+    let Some(ring) = ring else {
+        return enter();
+    };
+
+    unsafe {
+        ring.emulate_submissions(to_submit);
+        let result = enter();
+        ring.patch_completions();
+        result
+    }
+}
+
+/// Forgets the ring with this fd, if there is one.
+pub(crate) fn close_ring(fd: RawFd) {
+    if let Ok(mut rings) = RINGS.lock() {
+        rings.remove(&fd);
+    }
+}
+
+/// Hook for `libc::syscall`, intercepts only the `io_uring` syscalls.
+#[hook_fn]
+pub(super) unsafe extern "C" fn syscall_detour(nr: c_long, mut args: ...) -> c_long {
+    unsafe {
+        let a1: c_long = args.arg();
+        let a2: c_long = args.arg();
+        let a3: c_long = args.arg();
+        let a4: c_long = args.arg();
+        let a5: c_long = args.arg();
+        let a6: c_long = args.arg();
+        let original = || FN_SYSCALL(nr, a1, a2, a3, a4, a5, a6);
+
+        // Other syscalls are frequent (e.g. `futex`), and must not pay for the guard.
+        if nr != libc::SYS_io_uring_setup && nr != libc::SYS_io_uring_enter {
+            return original();
+        }
+
+        let guard = DetourGuard::new();
+        if guard.is_none() {
+            return original();
+        }
+
+        if nr == libc::SYS_io_uring_setup {
+            io_uring_setup(a2 as *mut IoUringParams, original)
+        } else {
+            io_uring_enter(a1 as RawFd, a2 as u32, a4 as u32, original)
+        }
+    }
+}
+
+pub(crate) unsafe fn enable_io_uring_hooks(hook_manager: &mut HookManager, mode: IoUringMode) {
+    if mode == IoUringMode::Allow {
+        return;
+    }
+
+    unsafe {
+        replace!(
+            hook_manager,
+            "syscall",
+            syscall_detour,
+            FnSyscall,
+            FN_SYSCALL
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_struct_layout() {
+        assert_eq!(size_of::<Sqe>(), 64);
+        assert_eq!(size_of::<Cqe>(), 16);
+        assert_eq!(size_of::<IoUringParams>(), 120);
+    }
+
+    #[test]
+    fn nop_keeps_links() {
+        let mut sqe = Sqe {
+            opcode: IORING_OP_READ,
+            flags: IOSQE_IO_LINK | IOSQE_ASYNC | IOSQE_CQE_SKIP_SUCCESS,
+            fd: 7,
+            addr: 0xdead,
+            len: 4096,
+            user_data: 42,
+            ..Default::default()
+        };
+
+        sqe.make_nop(-libc::ENOENT, EMULATED_USER_DATA);
+        assert_eq!(sqe.opcode, IORING_OP_NOP);
+        assert_eq!(sqe.fd, -1);
+        assert_eq!(sqe.addr, 0);
+        assert_eq!(sqe.user_data, EMULATED_USER_DATA);
+        // The error must be reported in a completion.
+        assert_eq!(sqe.flags, IOSQE_IO_LINK);
+    }
+
+    #[test]
+    fn completions_are_matched_by_nop() {
+        // Head, tail and mask of the completion queue, followed by 4 completions.
+        let mut memory = [0u64; 16];
+        let base = memory.as_mut_ptr().cast::<u8>();
+        let cq_off = CqRingOffsets {
+            head: 0,
+            tail: 4,
+            ring_mask: 8,
+            ring_entries: 12,
+            cqes: 64,
+            ..Default::default()
+        };
+        let ring = Ring {
+            sq: base,
+            sq_size: 0,
+            cq: base,
+            cq_size: 0,
+            sqes: ptr::null_mut(),
+            sqes_size: 0,
+            sq_off: Default::default(),
+            cq_off,
+            sqe_size: size_of::<Sqe>(),
+            cqe_size: size_of::<Cqe>(),
+            no_sq_array: false,
+            pending: Default::default(),
+            next_nop: Default::default(),
+        };
+
+        // A real completion and an emulated one, with the same application `user_data`.
+        let cqes = unsafe { base.add(cq_off.cqes as usize).cast::<Cqe>() };
+        unsafe {
+            ring.cq_u32(cq_off.tail).store(2, Ordering::Release);
+            ring.cq_u32(cq_off.ring_mask).store(3, Ordering::Release);
+            cqes.write(Cqe {
+                user_data: 0,
+                res: 4096,
+                flags: 0,
+            });
+            cqes.add(1).write(Cqe {
+                user_data: EMULATED_USER_DATA,
+                res: 0,
+                flags: 0,
+            });
+        }
+        ring.pending
+            .lock()
+            .unwrap()
+            .insert(EMULATED_USER_DATA, (0, -libc::ENOENT));
+
+        unsafe { ring.patch_completions() };
+
+        let (real, emulated) = unsafe { (&*cqes, &*cqes.add(1)) };
+        assert_eq!((real.user_data, real.res), (0, 4096));
+        assert_eq!((emulated.user_data, emulated.res), (0, -libc::ENOENT));
+        assert!(ring.pending.lock().unwrap().is_empty());
+
+        // The memory is not mapped.
+        std::mem::forget(ring);
+    }
+}
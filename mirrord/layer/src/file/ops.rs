@@ -34,7 +34,12 @@ use rand::distr::{Alphanumeric, SampleString};
 use tracing::Level;
 use tracing::{error, trace};
 
-use super::{hooks::FN_OPEN, open_dirs::OPEN_DIRS, *};
+use super::{
+    hooks::FN_OPEN,
+    open_dirs::OPEN_DIRS,
+    overlay::{FsOverlay, OverlayOp},
+    *,
+};
 #[cfg(target_os = "linux")]
 use crate::common::CheckedInto;
 use crate::{
@@ -44,7 +49,7 @@ use crate::{
 };
 
 /// 1 Megabyte. Large read requests can lead to timeouts.
-const MAX_READ_SIZE: u64 = 1024 * 1024;
+pub(crate) const MAX_READ_SIZE: u64 = 1024 * 1024;
 
 /// Convenience extension for verifying that a [`Path`] is not relative.
 trait PathExt {
@@ -79,6 +84,7 @@ pub fn ensure_remote(file_filter: &FileFilter, path: &Path, write: bool) -> Deto
             Detour::Error(HookError::FileNotFound(text.to_string()))
         }
         _ if file_filter.read_write.is_match(text) => Detour::Success(()),
+        _ if file_filter.overlay.is_match(text) => Detour::Success(()),
         _ if file_filter.read_only.is_match(text) => {
             if write {
                 Detour::Bypass(Bypass::ignored_file(text))
@@ -93,21 +99,54 @@ pub fn ensure_remote(file_filter: &FileFilter, path: &Path, write: bool) -> Deto
         _ if file_filter.default_remote_ro.is_match(text) && !write => Detour::Success(()),
         _ if file_filter.default_local.is_match(text) => Detour::Bypass(Bypass::ignored_file(text)),
         FsModeConfig::LocalWithOverrides => Detour::Bypass(Bypass::ignored_file(text)),
-        FsModeConfig::Write => Detour::Success(()),
+        FsModeConfig::Write | FsModeConfig::Overlay => Detour::Success(()),
         FsModeConfig::Read if write => Detour::Bypass(Bypass::ReadOnly(text.into())),
         FsModeConfig::Read => Detour::Success(()),
     }
 }
 
+/// The local copy-on-write overlay, if the remote `path` uses it.
+fn overlay_of(path: &Path) -> Option<&'static FsOverlay> {
+    let setup = crate::setup();
+    let path_str = path.to_str().unwrap_or_default();
+
+    setup
+        .fs_overlay()
+        .filter(|_| setup.file_filter().is_overlay(path_str))
+}
+
+/// Bypasses the operation to the local copy-on-write overlay, if the remote `path` uses it.
+fn overlay_redirect(path: &Path, op: OverlayOp) -> Detour<()> {
+    match overlay_of(path) {
+        Some(overlay) => overlay.redirect(path, op),
+        None => Detour::Success(()),
+    }
+}
+
 /// Performs standard verification of paths accessed by the user application.
 ///
 /// Operations in order:
 /// 1. Bypass if the path is not relative and not present in the `fs.not_found` filters.
 /// 2. Remap the file according to the config.
 /// 3. Bypass if the new path should be accessed locally.
+/// 4. Bypass if the new path is in the fs overlay, see [`FsOverlay`].
 ///
 /// Returns the remapped path.
 fn common_path_check(path: PathBuf, write: bool) -> Detour<PathBuf> {
+    let path = remote_path_check(path, write)?;
+
+    let op = if write {
+        OverlayOp::Write
+    } else {
+        OverlayOp::Read
+    };
+    overlay_redirect(&path, op)?;
+
+    Detour::Success(path)
+}
+
+/// Same as [`common_path_check`], but leaves the fs overlay to the caller.
+fn remote_path_check(path: PathBuf, write: bool) -> Detour<PathBuf> {
     path.ensure_not_relative_or_not_found()?;
 
     let path = crate::setup().file_remapper().change_path(path);
@@ -432,7 +471,10 @@ pub(crate) fn mkdirat(dirfd: RawFd, path: Detour<PathBuf>, mode: u32) -> Detour<
 
 #[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
 pub(crate) fn rmdir(path: Detour<PathBuf>) -> Detour<()> {
-    let path = common_path_check(path?, true)?;
+    let path = remote_path_check(path?, true)?;
+    if let Some(overlay) = overlay_of(&path) {
+        return overlay.remove(&path, true);
+    }
 
     let rmdir = RemoveDirRequest { pathname: path };
 
@@ -446,7 +488,10 @@ pub(crate) fn rmdir(path: Detour<PathBuf>) -> Detour<()> {
 
 #[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
 pub(crate) fn unlink(path: Detour<PathBuf>) -> Detour<()> {
-    let path = common_path_check(path?, true)?;
+    let path = remote_path_check(path?, true)?;
+    if let Some(overlay) = overlay_of(&path) {
+        return overlay.remove(&path, false);
+    }
 
     let unlink = UnlinkRequest { pathname: path };
 
@@ -469,6 +514,9 @@ pub(crate) fn unlinkat(dirfd: RawFd, path: Detour<PathBuf>, flags: u32) -> Detou
     if path.is_absolute() {
         path = crate::setup().file_remapper().change_path(path);
         ensure_remote(crate::setup().file_filter(), &path, true)?;
+        if let Some(overlay) = overlay_of(&path) {
+            return overlay.remove(&path, flags & libc::AT_REMOVEDIR as u32 != 0);
+        }
     }
 
     let unlink = if path.is_absolute() || dirfd == AT_FDCWD {
@@ -604,6 +652,7 @@ pub(crate) fn xstat(
                 } else if path.is_absolute() {
                     path = crate::setup().file_remapper().change_path(path);
                     ensure_remote(crate::setup().file_filter(), &path, true)?;
+                    overlay_redirect(&path, OverlayOp::Read)?;
                     None
                 } else {
                     Some(get_remote_fd(fd)?)
@@ -855,8 +904,27 @@ pub(crate) fn realpath(path: Detour<PathBuf>) -> Detour<PathBuf> {
 ///   paths remapped.
 #[mirrord_layer_macro::instrument(level = Level::TRACE, ret)]
 pub(crate) fn rename(old_path: Detour<PathBuf>, new_path: Detour<PathBuf>) -> Detour<()> {
-    let old_path = common_path_check(old_path?, false);
-    let new_path = common_path_check(new_path?, false);
+    let old_path = remote_path_check(old_path?, false);
+    let new_path = remote_path_check(new_path?, false);
+
+    // The fs overlay is like a separate filesystem: renames within it are done locally, and
+    // renames between it and the target fail with `EXDEV`, so that the application copies the
+    // file instead. Local files can still be moved into it.
+    let overlay_of_checked = |path: &Detour<PathBuf>| match path {
+        Detour::Success(path) => overlay_of(path),
+        _ => None,
+    };
+    let new_path = match (overlay_of_checked(&old_path), overlay_of_checked(&new_path)) {
+        (Some(overlay), Some(_)) => return overlay.rename(&old_path?, &new_path?),
+        (None, None) => new_path,
+        (None, Some(_)) if !matches!(old_path, Detour::Success(..)) => new_path
+            .and_then(|new_path| overlay_redirect(&new_path, OverlayOp::Write).map(|()| new_path)),
+        _ => {
+            return Detour::Error(HookError::ResponseError(ResponseError::from(
+                std::io::Error::from_raw_os_error(libc::EXDEV),
+            )));
+        }
+    };
 
     // We need to remap both `old_path` and `new_path` on bypass.
     let (old_path, new_path) = match (old_path, new_path) {
@@ -1151,6 +1219,14 @@ mod test {
         false,
         DetourKind::Error
     )]
+    #[case(FsModeConfig::Overlay, "/a/test.a", true, DetourKind::Success)]
+    #[case(
+        FsModeConfig::Overlay,
+        "/pain/read_only/test.a",
+        true,
+        DetourKind::Bypass
+    )]
+    #[case(FsModeConfig::Overlay, "/pain/local/test.a", true, DetourKind::Bypass)]
     fn include_complex_configuration(
         #[case] mode: FsModeConfig,
         #[case] path: &str,
@@ -1173,8 +1249,8 @@ mod test {
             local,
             not_found,
             mode,
-            mapping: None,
             readonly_file_buffer: READONLY_FILE_BUFFER_DEFAULT,
+            ..Default::default()
         };
 
         let file_filter = FileFilter::new(fs_config);
@@ -0,0 +1,554 @@
+//! Local copy-on-write overlay for remote files, see
+//! [`FsConfig::overlay`](mirrord_config::feature::fs::FsConfig::overlay).
+//!
+//! Remote paths are stored under the overlay directory with the same structure, e.g.
+//! `/app/config.yaml` is stored in `<overlay_dir>/app/config.yaml`. Once a path is in the overlay,
+//! all operations on it are bypassed to the local copy.
+//!
+//! Removed paths are marked with an empty `.wh.<name>` file next to them (like whiteouts in OCI
+//! image layers), so that they're not found even though they still exist in the target. A local
+//! copy takes precedence over the whiteout, so the whiteout is written only once the local copy is
+//! removed, and dropped only once a new local copy is created. A remote directory can be removed
+//! only after all of its entries are.
+//!
+//! Directories in the overlay don't hide the remote ones, so directory listings always come from
+//! the target.
+
+use std::{
+    fs::{self, File, Metadata, Permissions},
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use mirrord_protocol::{
+    ResponseError,
+    error::{ErrorKindInternal, RemoteIOError},
+    file::{
+        CloseDirRequest, FdOpenDirRequest, MetadataInternal, OpenDirResponse, OpenFileRequest,
+        OpenFileResponse, OpenOptionsInternal, ReadDirRequest, ReadDirResponse, XstatRequest,
+        XstatResponse,
+    },
+};
+
+use super::ops::{MAX_READ_SIZE, RemoteFile};
+use crate::{
+    common,
+    detour::{Bypass, Detour},
+    error::HookError,
+};
+
+/// Prefix of the whiteout files, which mark paths removed in the overlay.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// Bypasses the operation to the given local path.
+fn bypass_to(local_path: &Path) -> Detour<()> {
+    Detour::Bypass(Bypass::ignored_file(
+        local_path.as_os_str().as_encoded_bytes(),
+    ))
+}
+
+/// Fails the operation with the error of a local filesystem operation, as if it came from the
+/// target.
+fn local_error(error: io::Error) -> HookError {
+    HookError::ResponseError(ResponseError::from(error))
+}
+
+fn not_found(path: &Path) -> HookError {
+    HookError::FileNotFound(path.to_string_lossy().into())
+}
+
+/// What the user application wants to do with a path in the overlay, see [`FsOverlay::redirect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OverlayOp {
+    /// Read the file or its metadata.
+    Read,
+    /// Create or modify the file.
+    Write,
+}
+
+/// State of a remote path in the overlay.
+#[derive(Debug)]
+struct OverlayEntry {
+    local_path: PathBuf,
+    /// Metadata of the local copy, if there is one.
+    local: Option<Metadata>,
+    /// The path was removed in the overlay, and not created again.
+    removed: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct FsOverlay {
+    dir: PathBuf,
+}
+
+impl FsOverlay {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Path of the local copy of the remote `path`.
+    fn local_path(&self, path: &Path) -> PathBuf {
+        self.dir.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    /// Path of the whiteout file that marks the remote `path` as removed.
+    fn whiteout_path(&self, path: &Path) -> Option<PathBuf> {
+        let name = path.file_name()?;
+        let mut whiteout = WHITEOUT_PREFIX.to_string();
+        whiteout.push_str(&name.to_string_lossy());
+
+        Some(self.local_path(path).with_file_name(whiteout))
+    }
+
+    /// Looks up the remote `path` in the overlay.
+    ///
+    /// Drops the whiteout of a path that was created again after it was removed, as the local copy
+    /// hides it anyway.
+    fn entry(&self, path: &Path) -> OverlayEntry {
+        let local_path = self.local_path(path);
+        let local = local_path.symlink_metadata().ok();
+        let whiteout = self
+            .whiteout_path(path)
+            .filter(|whiteout| whiteout.exists());
+
+        let removed = match whiteout {
+            Some(whiteout) if local.is_some() => {
+                if let Err(error) = fs::remove_file(&whiteout) {
+                    tracing::debug!(%error, ?whiteout, "Failed to remove a stale whiteout");
+                }
+                false
+            }
+            Some(..) => true,
+            None => false,
+        };
+
+        OverlayEntry {
+            local_path,
+            local,
+            removed,
+        }
+    }
+
+    /// Decides where the operation on the remote `path` happens.
+    ///
+    /// Returns [`Detour::Success`] when the operation should go to the target, and
+    /// [`Bypass::IgnoredFile`] with the path of the local copy when it should be done locally.
+    /// Copies the remote file to the overlay when it's about to change.
+    #[mirrord_layer_macro::instrument(level = "trace", ret)]
+    pub(crate) fn redirect(&self, path: &Path, op: OverlayOp) -> Detour<()> {
+        let entry = self.entry(path);
+
+        match op {
+            OverlayOp::Read => {
+                if entry.local.is_some_and(|metadata| !metadata.is_dir()) {
+                    return bypass_to(&entry.local_path);
+                }
+                if entry.removed {
+                    return Detour::Error(not_found(path));
+                }
+
+                Detour::Success(())
+            }
+
+            OverlayOp::Write => {
+                // A removed file is created anew, its whiteout is dropped once the local file
+                // exists.
+                if entry.local.is_none() && !entry.removed {
+                    self.copy_up(path, &entry.local_path)?;
+                }
+                if let Some(parent) = entry.local_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                bypass_to(&entry.local_path)
+            }
+        }
+    }
+
+    /// Removes the remote file (or directory, if `dir`) at `path` in the overlay.
+    ///
+    /// The local copy is removed, if there is one, and then the whiteout is written. Remote files
+    /// are not copied, we only check that they exist.
+    #[mirrord_layer_macro::instrument(level = "trace", ret)]
+    pub(crate) fn remove(&self, path: &Path, dir: bool) -> Detour<()> {
+        let Some(whiteout_path) = self.whiteout_path(path) else {
+            return Detour::Error(not_found(path));
+        };
+        let entry = self.entry(path);
+        if entry.removed {
+            return Detour::Error(not_found(path));
+        }
+
+        let is_dir = match &entry.local {
+            Some(metadata) => metadata.is_dir(),
+            None => {
+                let Some(metadata) = remote_metadata(path)? else {
+                    return Detour::Error(not_found(path));
+                };
+                metadata.mode & u32::from(libc::S_IFMT) == u32::from(libc::S_IFDIR)
+            }
+        };
+        match (dir, is_dir) {
+            (true, false) => {
+                return Detour::Error(local_error(io::Error::from_raw_os_error(libc::ENOTDIR)));
+            }
+            (false, true) => {
+                return Detour::Error(local_error(io::Error::from_raw_os_error(libc::EISDIR)));
+            }
+            _ => {}
+        }
+
+        if is_dir {
+            self.prepare_dir_removal(path, &entry.local_path)?;
+        }
+        if entry.local.is_some() {
+            let removed = if is_dir {
+                fs::remove_dir(&entry.local_path)
+            } else {
+                fs::remove_file(&entry.local_path)
+            };
+            removed.map_err(local_error)?;
+        }
+
+        if let Some(parent) = whiteout_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        File::create(whiteout_path)?;
+
+        Detour::Success(())
+    }
+
+    /// Renames the remote `old_path` to `new_path` in the overlay.
+    ///
+    /// The old path is copied to the overlay if needed, and marked as removed once its local copy
+    /// is renamed.
+    #[mirrord_layer_macro::instrument(level = "trace", ret)]
+    pub(crate) fn rename(&self, old_path: &Path, new_path: &Path) -> Detour<()> {
+        let Some(whiteout_path) = self.whiteout_path(old_path) else {
+            return Detour::Error(not_found(old_path));
+        };
+        let old = self.entry(old_path);
+        if old.removed || (old.local.is_none() && !self.copy_up(old_path, &old.local_path)?) {
+            return Detour::Error(not_found(old_path));
+        }
+
+        let new = self.entry(new_path);
+        if new.local.is_none() && !new.removed {
+            self.copy_up(new_path, &new.local_path)?;
+        }
+        if let Some(parent) = new.local_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::rename(&old.local_path, &new.local_path).map_err(local_error)?;
+        File::create(whiteout_path)?;
+
+        Detour::Success(())
+    }
+
+    /// Copies the remote file or directory (without its content) at `path` to `local_path`.
+    ///
+    /// Returns `false` if there is no such remote file.
+    fn copy_up(&self, path: &Path, local_path: &Path) -> Detour<bool> {
+        let Some(metadata) = remote_metadata(path)? else {
+            return Detour::Success(false);
+        };
+
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if metadata.mode & u32::from(libc::S_IFMT) == u32::from(libc::S_IFDIR) {
+            fs::create_dir_all(local_path)?;
+            return Detour::Success(true);
+        }
+
+        let remote_fd = RemoteFile::remote_open(
+            path.to_path_buf(),
+            OpenOptionsInternal {
+                read: true,
+                ..Default::default()
+            },
+        )?
+        .fd;
+
+        let copied = (|| -> Detour<()> {
+            let mut file = File::create(local_path)?;
+            loop {
+                let response = RemoteFile::remote_read(remote_fd, MAX_READ_SIZE)?;
+                if response.bytes.is_empty() {
+                    break;
+                }
+                file.write_all(&response.bytes)?;
+            }
+            file.set_permissions(Permissions::from_mode(metadata.mode & 0o7777))?;
+
+            Detour::Success(())
+        })();
+
+        RemoteFile::remote_close(remote_fd)?;
+        copied?;
+
+        tracing::debug!(?path, ?local_path, "Copied remote file to the fs overlay");
+
+        Detour::Success(true)
+    }
+
+    /// Checks that the directory at `path` has no entries left, apart from the ones removed in the
+    /// overlay, and drops their whiteouts from `local_path`, so that it can be removed.
+    ///
+    /// Fails with `ENOTEMPTY` otherwise, as the local copy of a remote directory starts out empty.
+    fn prepare_dir_removal(&self, path: &Path, local_path: &Path) -> Detour<()> {
+        let not_empty = || {
+            Detour::Error(HookError::ResponseError(ResponseError::RemoteIO(
+                RemoteIOError {
+                    raw_os_error: Some(libc::ENOTEMPTY),
+                    kind: ErrorKindInternal::DirectoryNotEmpty,
+                },
+            )))
+        };
+
+        // Without a local copy, none of its entries were removed yet.
+        let entries = match fs::read_dir(local_path) {
+            Ok(entries) => entries.collect::<io::Result<Vec<_>>>()?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Detour::Error(error.into()),
+        };
+
+        let mut whiteouts = Vec::new();
+        for entry in entries {
+            let name = entry.file_name();
+            match name
+                .to_str()
+                .and_then(|name| name.strip_prefix(WHITEOUT_PREFIX))
+            {
+                Some(removed) => whiteouts.push((removed.to_string(), entry.path())),
+                None => return not_empty(),
+            }
+        }
+
+        let remote_entries = remote_dir_entries(path)?;
+        if remote_entries
+            .iter()
+            .any(|name| !whiteouts.iter().any(|(removed, _)| removed == name))
+        {
+            return not_empty();
+        }
+
+        for (_, whiteout) in whiteouts {
+            fs::remove_file(whiteout)?;
+        }
+
+        Detour::Success(())
+    }
+}
+
+/// Metadata of the remote file at `path`, [`None`] if there is no such file.
+fn remote_metadata(path: &Path) -> Detour<Option<MetadataInternal>> {
+    match common::make_proxy_request_with_response(XstatRequest {
+        path: Some(path.to_path_buf()),
+        fd: None,
+        follow_symlink: true,
+    })? {
+        Ok(XstatResponse { metadata }) => Detour::Success(Some(metadata)),
+        Err(ResponseError::RemoteIO(error)) if error.kind == ErrorKindInternal::NotFound => {
+            Detour::Success(None)
+        }
+        Err(error) => Detour::Error(error.into()),
+    }
+}
+
+/// Names of the entries in the remote directory at `path`.
+///
+/// Returns no entries if there is no such remote directory, e.g. when it was created in the
+/// overlay.
+fn remote_dir_entries(path: &Path) -> Detour<Vec<String>> {
+    let remote_fd = match common::make_proxy_request_with_response(OpenFileRequest {
+        path: path.to_path_buf(),
+        open_options: OpenOptionsInternal {
+            read: true,
+            ..Default::default()
+        },
+    })? {
+        Ok(OpenFileResponse { fd }) => fd,
+        Err(ResponseError::RemoteIO(error)) if error.kind == ErrorKindInternal::NotFound => {
+            return Detour::Success(Vec::new());
+        }
+        Err(error) => return Detour::Error(error.into()),
+    };
+
+    let listed = (|| -> Detour<Vec<String>> {
+        let OpenDirResponse { fd: dir_fd } =
+            common::make_proxy_request_with_response(FdOpenDirRequest { remote_fd })??;
+
+        let mut names = Vec::new();
+        let read = (|| -> Detour<()> {
+            while let ReadDirResponse {
+                direntry: Some(entry),
+            } =
+                common::make_proxy_request_with_response(ReadDirRequest { remote_fd: dir_fd })??
+            {
+                if entry.name != "." && entry.name != ".." {
+                    names.push(entry.name);
+                }
+            }
+
+            Detour::Success(())
+        })();
+
+        common::make_proxy_request_no_response(CloseDirRequest { remote_fd: dir_fd })?;
+        read?;
+
+        Detour::Success(names)
+    })();
+
+    RemoteFile::remote_close(remote_fd)?;
+
+    listed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_paths() {
+        let overlay = FsOverlay::new("/tmp/overlay".into());
+
+        assert_eq!(
+            overlay.local_path(Path::new("/app/config.yaml")),
+            Path::new("/tmp/overlay/app/config.yaml")
+        );
+        assert_eq!(
+            overlay.whiteout_path(Path::new("/app/config.yaml")),
+            Some(PathBuf::from("/tmp/overlay/app/.wh.config.yaml"))
+        );
+        assert_eq!(overlay.whiteout_path(Path::new("/")), None);
+    }
+
+    fn is_bypassed_to(detour: Detour<()>, local_path: &Path) -> bool {
+        matches!(
+            detour,
+            Detour::Bypass(Bypass::IgnoredFile(path))
+                if path.as_bytes() == local_path.as_os_str().as_encoded_bytes()
+        )
+    }
+
+    /// Operations on paths that are already in the overlay don't reach the remote.
+    #[test]
+    fn redirect_local_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let overlay = FsOverlay::new(dir.path().into());
+        let path = Path::new("/app/config.yaml");
+        let local_path = dir.path().join("app/config.yaml");
+        fs::create_dir_all(local_path.parent().unwrap()).unwrap();
+        fs::write(&local_path, "changed").unwrap();
+
+        assert!(is_bypassed_to(
+            overlay.redirect(path, OverlayOp::Read),
+            &local_path
+        ));
+        assert!(is_bypassed_to(
+            overlay.redirect(path, OverlayOp::Write),
+            &local_path
+        ));
+
+        assert!(matches!(overlay.remove(path, false), Detour::Success(())));
+        assert!(!local_path.exists());
+        assert!(dir.path().join("app/.wh.config.yaml").exists());
+        assert!(matches!(
+            overlay.redirect(path, OverlayOp::Read),
+            Detour::Error(HookError::FileNotFound(_))
+        ));
+    }
+
+    /// Removed paths are not found, until they're created again.
+    #[test]
+    fn redirect_whiteout() {
+        let dir = tempfile::tempdir().unwrap();
+        let overlay = FsOverlay::new(dir.path().into());
+        let path = Path::new("/app/config.yaml");
+        let local_path = dir.path().join("app/config.yaml");
+        let whiteout = dir.path().join("app/.wh.config.yaml");
+        fs::create_dir_all(whiteout.parent().unwrap()).unwrap();
+        File::create(&whiteout).unwrap();
+
+        assert!(matches!(
+            overlay.redirect(path, OverlayOp::Read),
+            Detour::Error(HookError::FileNotFound(_))
+        ));
+        assert!(matches!(
+            overlay.remove(path, false),
+            Detour::Error(HookError::FileNotFound(_))
+        ));
+
+        // The whiteout stays until the local file is actually created.
+        assert!(is_bypassed_to(
+            overlay.redirect(path, OverlayOp::Write),
+            &local_path
+        ));
+        assert!(whiteout.exists());
+        assert!(matches!(
+            overlay.redirect(path, OverlayOp::Read),
+            Detour::Error(HookError::FileNotFound(_))
+        ));
+
+        fs::write(&local_path, "created").unwrap();
+        assert!(is_bypassed_to(
+            overlay.redirect(path, OverlayOp::Read),
+            &local_path
+        ));
+        assert!(!whiteout.exists());
+    }
+
+    /// The old path is marked as removed only once the rename succeeds.
+    #[test]
+    fn rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let overlay = FsOverlay::new(dir.path().into());
+        let old_path = Path::new("/app/config.yaml");
+        let old_local_path = dir.path().join("app/config.yaml");
+        let old_whiteout = dir.path().join("app/.wh.config.yaml");
+        fs::create_dir_all(dir.path().join("app/busy")).unwrap();
+        fs::write(dir.path().join("app/busy/file.txt"), "busy").unwrap();
+        fs::write(&old_local_path, "changed").unwrap();
+        // Removed, so that it's not copied from the remote.
+        File::create(dir.path().join("app/.wh.renamed.yaml")).unwrap();
+
+        assert!(matches!(
+            overlay.rename(old_path, Path::new("/app/busy")),
+            Detour::Error(HookError::ResponseError(ResponseError::RemoteIO(..)))
+        ));
+        assert!(old_local_path.exists());
+        assert!(!old_whiteout.exists());
+
+        assert!(matches!(
+            overlay.rename(old_path, Path::new("/app/renamed.yaml")),
+            Detour::Success(())
+        ));
+        assert!(!old_local_path.exists());
+        assert!(old_whiteout.exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("app/renamed.yaml")).unwrap(),
+            "changed"
+        );
+    }
+
+    /// Directories with entries in the overlay can't be removed.
+    #[test]
+    fn remove_non_empty_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let overlay = FsOverlay::new(dir.path().into());
+        fs::create_dir_all(dir.path().join("app/data")).unwrap();
+        fs::write(dir.path().join("app/data/new.txt"), "new").unwrap();
+
+        assert!(matches!(
+            overlay.remove(Path::new("/app/data"), true),
+            Detour::Error(HookError::ResponseError(ResponseError::RemoteIO(error)))
+                if error.raw_os_error == Some(libc::ENOTEMPTY)
+        ));
+        assert!(dir.path().join("app/data/new.txt").exists());
+        assert!(!dir.path().join("app/.wh.data").exists());
+    }
+}
@@ -47,6 +47,30 @@ unsafe extern "C" fn c_abi_syscall6_handler(
             libc::SYS_accept => accept_detour(param1 as _, param2 as _, param3 as _) as i64,
             libc::SYS_close => close_detour(param1 as _) as i64,
             libc::SYS_connect => connect_detour(param1 as _, param2 as _, param3 as _) as i64,
+            libc::SYS_getsockname => {
+                getsockname_detour(param1 as _, param2 as _, param3 as _) as i64
+            }
+            libc::SYS_getpeername => {
+                getpeername_detour(param1 as _, param2 as _, param3 as _) as i64
+            }
+            libc::SYS_sendto => send_to_detour(
+                param1 as _,
+                param2 as _,
+                param3 as _,
+                param4 as _,
+                param5 as _,
+                param6 as _,
+            ) as i64,
+            libc::SYS_recvfrom => recv_from_detour(
+                param1 as _,
+                param2 as _,
+                param3 as _,
+                param4 as _,
+                param5 as _,
+                param6 as _,
+            ) as i64,
+            libc::SYS_sendmsg => sendmsg_detour(param1 as _, param2 as _, param3 as _) as i64,
+            libc::SYS_recvmsg => recvmsg_detour(param1 as _, param2 as _, param3 as _) as i64,
 
             _ if crate::setup().fs_config().is_active() => {
                 match syscall {
@@ -84,23 +108,8 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                     libc::SYS_newfstatat => {
                         fstatat_logic(param1 as _, param2 as _, param3 as _, param4 as _)
                             .unwrap_or_bypass_with(|_| {
-                                let (Ok(result) | Err(result)) = syscalls::syscall!(
-                                    syscalls::Sysno::from(syscall as i32),
-                                    param1,
-                                    param2,
-                                    param3,
-                                    param4,
-                                    param5,
-                                    param6
-                                )
-                                .map(|success| success as i64)
-                                .map_err(|fail| {
-                                    let raw_errno = fail.into_raw();
-                                    Errno::set_raw(raw_errno);
-
-                                    -(raw_errno as i64)
-                                });
-                                result as i32
+                                raw_syscall(syscall, param1, param2, param3, param4, param5, param6)
+                                    as i32
                             })
                             .into()
                     }
@@ -116,8 +125,40 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                     libc::SYS_getdents64 => {
                         getdents64_detour(param1 as _, param2 as _, param3 as _) as i64
                     }
+                    // `rename_detour` falls back to libc `rename`, which is only hooked with
+                    // `experimental.hook_rename`, same as for C programs.
+                    #[cfg(all(target_os = "linux", not(target_arch = "aarch64")))]
+                    libc::SYS_rename if crate::setup().experimental().hook_rename => {
+                        rename_detour(param1 as _, param2 as _) as i64
+                    }
+                    // Go uses `renameat` on x86_64, and `renameat2` with no flags on aarch64.
                     #[cfg(all(target_os = "linux", not(target_arch = "aarch64")))]
-                    libc::SYS_rename => rename_detour(param1 as _, param2 as _) as i64,
+                    libc::SYS_renameat
+                        if crate::setup().experimental().hook_rename
+                            && at_cwd(param1, param2)
+                            && at_cwd(param3, param4) =>
+                    {
+                        rename_detour(param2 as _, param4 as _) as i64
+                    }
+                    libc::SYS_renameat2
+                        if crate::setup().experimental().hook_rename
+                            && param5 == 0
+                            && at_cwd(param1, param2)
+                            && at_cwd(param3, param4) =>
+                    {
+                        rename_detour(param2 as _, param4 as _) as i64
+                    }
+                    libc::SYS_readlinkat if at_cwd(param1, param2) => {
+                        readlink_detour(param2 as _, param3 as _, param4 as _) as i64
+                    }
+                    libc::SYS_fchmod => fchmod_detour(param1 as _, param2 as _) as i64,
+                    libc::SYS_fchown => fchown_detour(param1 as _, param2 as _, param3 as _) as i64,
+                    libc::SYS_ftruncate => ftruncate_detour(param1 as _, param2 as _) as i64,
+                    // With a null path, `utimensat` changes the times of `dirfd` itself, which is
+                    // what libc `futimens` does. There is no remote operation for paths.
+                    libc::SYS_utimensat if param2 == 0 && param4 == 0 => {
+                        futimens_detour(param1 as _, param3 as _) as i64
+                    }
 
                     #[cfg(all(target_os = "linux", not(target_arch = "aarch64")))]
                     libc::SYS_mkdir => mkdir_detour(param1 as _, param2 as _) as i64,
@@ -131,46 +172,10 @@ unsafe extern "C" fn c_abi_syscall6_handler(
                     libc::SYS_unlinkat => {
                         unlinkat_detour(param1 as _, param2 as _, param3 as _) as i64
                     }
-                    _ => {
-                        let (Ok(result) | Err(result)) = syscalls::syscall!(
-                            syscalls::Sysno::from(syscall as i32),
-                            param1,
-                            param2,
-                            param3,
-                            param4,
-                            param5,
-                            param6
-                        )
-                        .map(|success| success as i64)
-                        .map_err(|fail| {
-                            let raw_errno = fail.into_raw();
-                            Errno::set_raw(raw_errno);
-
-                            -(raw_errno as i64)
-                        });
-                        result
-                    }
+                    _ => raw_syscall(syscall, param1, param2, param3, param4, param5, param6),
                 }
             }
-            _ => {
-                let (Ok(result) | Err(result)) = syscalls::syscall!(
-                    syscalls::Sysno::from(syscall as i32),
-                    param1,
-                    param2,
-                    param3,
-                    param4,
-                    param5,
-                    param6
-                )
-                .map(|success| success as i64)
-                .map_err(|fail| {
-                    let raw_errno = fail.into_raw();
-                    Errno::set_raw(raw_errno);
-
-                    -(raw_errno as i64)
-                });
-                result
-            }
+            _ => raw_syscall(syscall, param1, param2, param3, param4, param5, param6),
         };
 
         if syscall_result.is_negative() {
@@ -182,6 +187,46 @@ unsafe extern "C" fn c_abi_syscall6_handler(
     }
 }
 
+/// Makes the syscall without any of our logic, setting `errno` on failure.
+unsafe fn raw_syscall(
+    syscall: i64,
+    param1: i64,
+    param2: i64,
+    param3: i64,
+    param4: i64,
+    param5: i64,
+    param6: i64,
+) -> i64 {
+    let (Ok(result) | Err(result)) = unsafe {
+        syscalls::syscall!(
+            syscalls::Sysno::from(syscall as i32),
+            param1,
+            param2,
+            param3,
+            param4,
+            param5,
+            param6
+        )
+    }
+    .map(|success| success as i64)
+    .map_err(|fail| {
+        let raw_errno = fail.into_raw();
+        Errno::set_raw(raw_errno);
+
+        -(raw_errno as i64)
+    });
+    result
+}
+
+/// Whether an `*at` syscall with these `dirfd` and `path` resolves the path like the libc
+/// function without the `at` suffix, so that we can handle it with the same detour.
+///
+/// That's the case when `dirfd` is [`libc::AT_FDCWD`], or when the path is absolute.
+unsafe fn at_cwd(dirfd: i64, path: i64) -> bool {
+    dirfd == libc::AT_FDCWD as i64
+        || (path != 0 && unsafe { *(path as *const libc::c_char) } == b'/' as libc::c_char)
+}
+
 /// Handler for `rawVforkSyscall` calls.
 ///
 /// Removes the [`libc::CLONE_VM`] flag from the clone flags.
@@ -588,6 +588,9 @@ fn sip_only_layer_start(
         read_only: None,
         local: None,
         not_found: None,
+        overlay: None,
+        overlay_dir: None,
+        overlay_export: None,
         mapping: None,
         readonly_file_buffer: READONLY_FILE_BUFFER_DEFAULT,
     };
@@ -744,6 +747,9 @@ pub(crate) fn close_layer_fd(fd: c_int) {
                     .expect("OPEN_FILES lock failed")
                     .remove(&fd);
             }
+
+            #[cfg(target_os = "linux")]
+            file::io_uring::close_ring(fd);
         }
     }
 }
@@ -28,6 +28,7 @@ use regex::RegexSet;
 
 use crate::{
     debugger_ports::DebuggerPorts,
+    file::overlay::FsOverlay,
     socket::{OutgoingSelector, dns_selector::DnsSelector},
 };
 
@@ -39,6 +40,7 @@ pub struct LayerSetup {
     config: LayerConfig,
     file_filter: FileFilter,
     file_remapper: FileRemapper,
+    fs_overlay: Option<FsOverlay>,
     debugger_ports: DebuggerPorts,
     remote_unix_streams: RegexSet,
     outgoing_selector: OutgoingSelector,
@@ -60,6 +62,17 @@ impl LayerSetup {
         let file_filter = FileFilter::new(config.feature.fs.clone());
         let file_remapper =
             FileRemapper::new(config.feature.fs.mapping.clone().unwrap_or_default());
+        // The CLI always sets the directory, the default is only a safety net.
+        let fs_overlay = config.feature.fs.uses_overlay().then(|| {
+            FsOverlay::new(
+                config
+                    .feature
+                    .fs
+                    .overlay_dir
+                    .clone()
+                    .unwrap_or_else(|| std::env::temp_dir().join("mirrord-fs-overlay")),
+            )
+        });
 
         let remote_unix_streams = config
             .feature
@@ -92,6 +105,7 @@ impl LayerSetup {
             config,
             file_filter,
             file_remapper,
+            fs_overlay,
             debugger_ports,
             remote_unix_streams,
             outgoing_selector,
@@ -124,6 +138,10 @@ impl LayerSetup {
         &self.file_remapper
     }
 
+    pub(crate) fn fs_overlay(&self) -> Option<&FsOverlay> {
+        self.fs_overlay.as_ref()
+    }
+
     pub fn incoming_config(&self) -> &IncomingConfig {
         &self.config.feature.network.incoming
     }
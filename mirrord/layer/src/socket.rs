@@ -15,7 +15,9 @@ use mirrord_config::feature::network::{
     filter::{AddressFilter, ProtocolAndAddressFilter, ProtocolFilter},
     outgoing::{OutgoingConfig, OutgoingFilterConfig},
 };
-use mirrord_intproxy_protocol::{NetProtocol, OutgoingConnCloseRequest, PortUnsubscribe};
+use mirrord_intproxy_protocol::{
+    HostnameInspection, NetProtocol, OutgoingConnCloseRequest, PortUnsubscribe,
+};
 use mirrord_protocol::{
     DnsLookupError, ResolveErrorKindInternal, ResponseError, outgoing::SocketAddress,
 };
@@ -399,6 +401,52 @@ impl OutgoingSelector {
         }
     }
 
+    /// Decides whether the internal proxy should look for the TLS SNI or HTTP `Host` of the TCP
+    /// connection to `address`, before deciding where to make it, see
+    /// [`OutgoingConfig::inspect_hostnames`].
+    ///
+    /// - `through`: the result of [`Self::get_connection_through`] for `address`.
+    ///
+    /// Only connections that didn't match any filter by their address are inspected, and only
+    /// when some hostname filter could match them by port.
+    #[mirrord_layer_macro::instrument(level = "trace", ret)]
+    fn hostname_inspection(
+        &self,
+        address: SocketAddr,
+        protocol: NetProtocol,
+        through: ConnectionThrough,
+    ) -> HookResult<Option<HostnameInspection>> {
+        if !crate::setup().outgoing_config().inspect_hostnames || protocol != NetProtocol::Stream {
+            return Ok(None);
+        }
+
+        let (filters, remote_on_match) = match (self, through) {
+            (Self::Remote(filters), ConnectionThrough::Local(..)) => (filters, true),
+            (Self::Local(filters), ConnectionThrough::Remote(..)) => (filters, false),
+            _ => return Ok(None),
+        };
+
+        let hostnames = filters
+            .iter()
+            .filter_map(|filter| filter.inspected_hostname(address.port()))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if hostnames.is_empty() {
+            return Ok(None);
+        }
+
+        let local_address = match through {
+            ConnectionThrough::Local(local_address) => local_address,
+            ConnectionThrough::Remote(..) => Self::get_local_address_to_connect(address)?,
+        };
+
+        Ok(Some(HostnameInspection {
+            hostnames,
+            remote_on_match,
+            local_address,
+        }))
+    }
+
     /// Helper function that looks into the [`REMOTE_DNS_REVERSE_MAPPING`] for `address`, so we can
     /// retrieve the hostname and resolve it locally (when applicable).
     ///
@@ -1,7 +1,7 @@
 use std::{net::IpAddr, ops::Deref};
 
 use mirrord_config::feature::network::{
-    dns::{DnsConfig, DnsFilterConfig},
+    dns::{DnsConfig, DnsFilterConfig, DnsOverrideConfig, DnsOverrides},
     filter::AddressFilter,
 };
 use tracing::Level;
@@ -9,19 +9,27 @@ use tracing::Level;
 use crate::detour::{Bypass, Detour};
 
 /// Generated from [`DnsConfig`] provided in the [`LayerConfig`](mirrord_config::LayerConfig).
-/// Decides whether DNS queries are done locally or remotely.
+/// Decides whether DNS queries are done locally or remotely, and which queries are answered with
+/// the configured [`DnsOverrides`].
 #[derive(Debug)]
 pub struct DnsSelector {
     /// Filters provided in the config.
     filters: Vec<AddressFilter>,
     /// Whether a query matching one of [`Self::filters`] should be done locally.
     filter_is_local: bool,
+    /// Overrides provided in the config.
+    overrides: DnsOverrides,
 }
 
 impl DnsSelector {
     /// Bypasses queries that should be done locally.
     #[tracing::instrument(level = Level::DEBUG, ret)]
     pub fn check_query(&self, node: &str, port: u16) -> Detour<()> {
+        // Overridden queries are answered in `remote_getaddrinfo`, regardless of the filters.
+        if self.overrides.resolve(node).is_some() {
+            return Detour::Success(());
+        }
+
         let matched = self
             .filters
             .iter()
@@ -51,6 +59,11 @@ impl DnsSelector {
             Detour::Success(())
         }
     }
+
+    /// Returns the configured override for the given hostname, if any.
+    pub fn dns_override(&self, node: &str) -> Option<&DnsOverrideConfig> {
+        self.overrides.resolve(node)
+    }
 }
 
 impl From<&DnsConfig> for DnsSelector {
@@ -59,6 +72,7 @@ impl From<&DnsConfig> for DnsSelector {
             return Self {
                 filters: Default::default(),
                 filter_is_local: false,
+                overrides: Default::default(),
             };
         }
 
@@ -78,9 +92,16 @@ impl From<&DnsConfig> for DnsSelector {
             })
             .collect();
 
+        // Verified in the CLI, but a bad override is not worth crashing the user application.
+        let (overrides, errors) = DnsOverrides::new_lossy(&value.overrides);
+        for error in errors {
+            tracing::warn!(%error, "Skipping an invalid DNS override");
+        }
+
         Self {
             filters,
             filter_is_local,
+            overrides,
         }
     }
 }
@@ -79,7 +79,7 @@ pub(super) unsafe extern "C" fn _connect_nocancel_detour(
 }
 
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn getpeername_detour(
+pub(crate) unsafe extern "C" fn getpeername_detour(
     sockfd: RawFd,
     address: *mut sockaddr,
     address_len: *mut socklen_t,
@@ -369,7 +369,7 @@ unsafe extern "C" fn freeaddrinfo_detour(addrinfo: *mut libc::addrinfo) {
 
 /// Not a faithful reproduction of what [`libc::recvmsg`] is supposed to do, see [`recv_from`].
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn recv_from_detour(
+pub(crate) unsafe extern "C" fn recv_from_detour(
     sockfd: i32,
     out_buffer: *mut c_void,
     buffer_length: size_t,
@@ -403,7 +403,7 @@ pub(super) unsafe extern "C" fn recv_from_detour(
 
 /// Not a faithful reproduction of what [`libc::sendto`] is supposed to do, see [`send_to`].
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn send_to_detour(
+pub(crate) unsafe extern "C" fn send_to_detour(
     sockfd: RawFd,
     raw_message: *const c_void,
     message_length: size_t,
@@ -442,7 +442,7 @@ pub(super) unsafe extern "C" fn send_to_detour(
 ///
 /// TODO(alex): We are ignoring the control message header [`libc::cmsghdr`].
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn recvmsg_detour(
+pub(crate) unsafe extern "C" fn recvmsg_detour(
     sockfd: i32,
     message_header: *mut libc::msghdr,
     flags: c_int,
@@ -469,7 +469,7 @@ pub(super) unsafe extern "C" fn recvmsg_detour(
 //
 // TODO(alex): We are ignoring the control message header `libc::cmsghdr`.
 #[hook_guard_fn]
-pub(super) unsafe extern "C" fn sendmsg_detour(
+pub(crate) unsafe extern "C" fn sendmsg_detour(
     sockfd: RawFd,
     message_header: *const libc::msghdr,
     flags: c_int,
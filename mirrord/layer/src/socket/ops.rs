@@ -15,10 +15,13 @@ use std::{
 };
 
 use libc::{AF_UNIX, c_int, c_void, hostent, sockaddr, socklen_t};
-use mirrord_config::feature::network::incoming::{IncomingConfig, IncomingMode};
+use mirrord_config::feature::network::{
+    dns::DnsOverrideConfig,
+    incoming::{IncomingConfig, IncomingMode},
+};
 use mirrord_intproxy_protocol::{
-    ConnMetadataRequest, ConnMetadataResponse, NetProtocol, OutgoingConnMetadataRequest,
-    OutgoingConnectRequest, OutgoingConnectResponse, PortSubscribe,
+    ConnMetadataRequest, ConnMetadataResponse, HostnameInspection, NetProtocol,
+    OutgoingConnMetadataRequest, OutgoingConnectRequest, OutgoingConnectResponse, PortSubscribe,
 };
 use mirrord_protocol::{
     dns::{AddressFamily, GetAddrInfoRequestV2, LookupRecord, SockType},
@@ -532,13 +535,14 @@ fn connect_outgoing<const CALL_CONNECT: bool>(
     protocol: NetProtocol,
 ) -> Detour<ConnectResult> {
     // Closure that performs the connection with mirrord messaging.
-    let remote_connection = |remote_address: SockAddr| {
+    let remote_connection = |remote_address: SockAddr, inspect: Option<HostnameInspection>| {
         // Prepare this socket to be intercepted.
         let remote_address = SocketAddress::try_from(remote_address).unwrap();
 
         let request = OutgoingConnectRequest {
             remote_address: remote_address.clone(),
             protocol,
+            inspect,
         };
         let response = common::make_proxy_request_with_response(request)??;
 
@@ -641,18 +645,26 @@ fn connect_outgoing<const CALL_CONNECT: bool>(
     };
 
     if remote_address.is_unix() {
-        let connect_result = remote_connection(remote_address)?;
+        let connect_result = remote_connection(remote_address, None)?;
         Detour::Success(connect_result)
     } else {
+        let address = remote_address.as_socket()?;
+        let selector = crate::setup().outgoing_selector();
+
         // Can't just connect to whatever `remote_address` is, as it might be a remotely resolved
         // address, in a local connection context (or vice versa), so we let `remote_connection`
         // handle this address trickery.
-        match crate::setup()
-            .outgoing_selector()
-            .get_connection_through(remote_address.as_socket()?, protocol)?
-        {
+        let through = selector.get_connection_through(address, protocol)?;
+
+        // The internal proxy decides where the connection is made.
+        if let Some(inspection) = selector.hostname_inspection(address, protocol, through)? {
+            let connect_result = remote_connection(SockAddr::from(address), Some(inspection))?;
+            return Detour::Success(connect_result);
+        }
+
+        match through {
             ConnectionThrough::Remote(addr) => {
-                let connect_result = remote_connection(SockAddr::from(addr))?;
+                let connect_result = remote_connection(SockAddr::from(addr), None)?;
                 Detour::Success(connect_result)
             }
             ConnectionThrough::Local(addr) => {
@@ -1038,6 +1050,9 @@ pub(super) fn dup<const SWITCH_MAP: bool>(fd: c_int, dup_fd: i32) -> Result<(),
 /// Handles the remote communication part of [`getaddrinfo`], call this if you want to resolve a DNS
 /// through the agent, but don't need to deal with all the [`libc::getaddrinfo`] stuff.
 ///
+/// Hostnames configured in `feature.network.dns.overrides` are answered with the configured
+/// addresses, or replaced with the configured CNAME before the request is sent.
+///
 /// # Note
 ///
 /// This function updates the mapping in [`REMOTE_DNS_REVERSE_MAPPING`].
@@ -1050,6 +1065,23 @@ pub(super) fn remote_getaddrinfo(
     socktype: c_int,
     protocol: c_int,
 ) -> HookResult<Vec<(String, IpAddr)>> {
+    let node = match crate::setup().dns_selector().dns_override(&node) {
+        // Not added to `REMOTE_DNS_REVERSE_MAPPING`, these are often local addresses.
+        Some(DnsOverrideConfig::Addresses(addresses)) => {
+            return Ok(addresses
+                .iter()
+                .filter(|address| match family {
+                    libc::AF_INET => address.is_ipv4(),
+                    libc::AF_INET6 => address.is_ipv6(),
+                    _ => true,
+                })
+                .map(|address| (node.clone(), *address))
+                .collect());
+        }
+        Some(DnsOverrideConfig::Cname { cname }) => cname.clone(),
+        None => node,
+    };
+
     let family = match family {
         libc::AF_INET => AddressFamily::Ipv4Only,
         libc::AF_INET6 => AddressFamily::Ipv6Only,
@@ -0,0 +1,52 @@
+#include <arpa/inet.h>
+#include <assert.h>
+#include <netdb.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+
+/// Resolves `node` with the given address `family`, and checks that the only address returned is
+/// of that family.
+struct addrinfo *resolve_single(const char *node, int family)
+{
+  struct addrinfo hints = {0};
+  hints.ai_family = family;
+  hints.ai_socktype = SOCK_STREAM;
+
+  struct addrinfo *result = NULL;
+  int error = getaddrinfo(node, "80", &hints, &result);
+  assert(error == 0);
+  assert(result != NULL);
+  assert(result->ai_next == NULL);
+  assert(result->ai_family == family);
+
+  return result;
+}
+
+/// Resolves `node` to IPv4 addresses, and checks that the only address returned is `expected`.
+void expect_ipv4_address(const char *node, const char *expected)
+{
+  struct addrinfo *result = resolve_single(node, AF_INET);
+
+  char address[INET_ADDRSTRLEN] = {0};
+  struct sockaddr_in *ipv4 = (struct sockaddr_in *)result->ai_addr;
+  inet_ntop(AF_INET, &ipv4->sin_addr, address, sizeof(address));
+  printf("%s resolved to %s\n", node, address);
+  assert(strcmp(address, expected) == 0);
+
+  freeaddrinfo(result);
+}
+
+/// Test `feature.network.dns.overrides`.
+///
+/// - `payments` is overridden with an IPv4 and an IPv6 address, only the one of the requested
+///   family is returned;
+/// - `legacy` is overridden with a CNAME, which is resolved remotely instead.
+int main()
+{
+  expect_ipv4_address("payments", "127.0.0.1");
+  freeaddrinfo(resolve_single("payments", AF_INET6));
+  expect_ipv4_address("legacy", "10.0.0.1");
+
+  return 0;
+}
@@ -0,0 +1,43 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+/// Test the fs overlay (`feature.fs.mode = "overlay"`).
+///
+/// - renames a remote file, which copies it to the overlay and marks the old path as removed;
+/// - reads the renamed file from the overlay;
+/// - fails to open the old path;
+/// - fails to remove a remote directory with a file in it;
+/// - removes the file and then the directory.
+int main()
+{
+  int rename_result = rename("/app/config.txt", "/app/renamed.txt");
+  assert(rename_result == 0);
+
+  char buffer[32] = {0};
+  int fd = open("/app/renamed.txt", O_RDONLY);
+  assert(fd >= 0);
+  ssize_t amount_read = read(fd, buffer, sizeof(buffer) - 1);
+  assert(amount_read >= 0);
+  assert(strcmp(buffer, "remote contents") == 0);
+  close(fd);
+
+  int removed_fd = open("/app/config.txt", O_RDONLY);
+  assert(removed_fd == -1);
+  assert(errno == ENOENT);
+
+  int rmdir_result = rmdir("/app/data");
+  assert(rmdir_result == -1);
+  assert(errno == ENOTEMPTY);
+
+  int unlink_result = unlink("/app/data/file.txt");
+  assert(unlink_result == 0);
+
+  rmdir_result = rmdir("/app/data");
+  assert(rmdir_result == 0);
+
+  return 0;
+}
@@ -0,0 +1,3 @@
+module rename_readlink_go
+
+go 1.20
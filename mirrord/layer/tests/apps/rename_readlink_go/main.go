@@ -0,0 +1,28 @@
+//go:build !windows
+
+package main
+
+import (
+	"C"
+	"syscall"
+)
+
+func main() {
+	// Tests: SYS_readlinkat
+	// Readlink calls readlinkat with _AT_FDCWD
+	buffer := make([]byte, 256)
+	length, err := syscall.Readlink("/app/link.txt", buffer)
+	if err != nil {
+		panic(err)
+	}
+	if target := string(buffer[:length]); target != "/gatos/rajado.txt" {
+		panic("unexpected link target " + target)
+	}
+
+	// Tests: SYS_renameat
+	// Rename calls renameat with _AT_FDCWD for both paths
+	err = syscall.Rename("/app/test.txt", "/app/renamed.txt")
+	if err != nil {
+		panic(err)
+	}
+}
@@ -0,0 +1,72 @@
+//go:build !windows
+
+package main
+
+import (
+	"C"
+	"bytes"
+	"syscall"
+)
+
+// The remote peer, port `53` makes the layer send the packets through the agent.
+var peer = &syscall.SockaddrInet4{Port: 53, Addr: [4]byte{1, 2, 3, 4}}
+
+// Opens a UDP socket bound to an ephemeral localhost port.
+func udpSocket() int {
+	fd, err := syscall.Socket(syscall.AF_INET, syscall.SOCK_DGRAM, 0)
+	if err != nil {
+		panic(err)
+	}
+
+	err = syscall.Bind(fd, &syscall.SockaddrInet4{Addr: [4]byte{127, 0, 0, 1}})
+	if err != nil {
+		panic(err)
+	}
+
+	return fd
+}
+
+// Checks that the packet was echoed by the peer.
+func expectEcho(sent []byte, received []byte, from syscall.Sockaddr) {
+	if !bytes.Equal(sent, received) {
+		panic("unexpected data " + string(received))
+	}
+
+	source, ok := from.(*syscall.SockaddrInet4)
+	if !ok || source.Addr != peer.Addr || source.Port != peer.Port {
+		panic("unexpected source address")
+	}
+}
+
+func main() {
+	// Tests: SYS_sendto, SYS_recvfrom
+	fd := udpSocket()
+	message := []byte("sendto")
+	err := syscall.Sendto(fd, message, 0, peer)
+	if err != nil {
+		panic(err)
+	}
+
+	buffer := make([]byte, 256)
+	length, from, err := syscall.Recvfrom(fd, buffer, 0)
+	if err != nil {
+		panic(err)
+	}
+	expectEcho(message, buffer[:length], from)
+	syscall.Close(fd)
+
+	// Tests: SYS_sendmsg, SYS_recvmsg
+	fd = udpSocket()
+	message = []byte("sendmsg")
+	_, err = syscall.SendmsgN(fd, message, nil, peer, 0)
+	if err != nil {
+		panic(err)
+	}
+
+	length, _, _, from, err = syscall.Recvmsg(fd, buffer, nil, 0)
+	if err != nil {
+		panic(err)
+	}
+	expectEcho(message, buffer[:length], from)
+	syscall.Close(fd)
+}
@@ -20,6 +20,7 @@ use mirrord_config::{
     LayerConfig, LayerFileConfig, MIRRORD_LAYER_INTPROXY_ADDR,
     config::{ConfigContext, MirrordConfig},
     experimental::ExperimentalFileConfig,
+    feature::network::dns::DnsOverrides,
 };
 use mirrord_intproxy::{IntProxy, agent_conn::AgentConnection};
 use mirrord_protocol::{
@@ -57,6 +58,13 @@ pub const RUST_OUTGOING_LOCAL: &str = "4.4.4.4:4444";
 #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
 static MIRRORD_MACOS_ARM64_LIBRARY: OnceLock<PathBuf> = OnceLock::new();
 
+/// Highest [`mirrord_protocol`] version that [`TestIntProxy`] agrees on by default.
+///
+/// Most tests answer file requests in order, with [`DaemonMessage::File`], so it must not
+/// enable [`ClientMessage::IdentifiedFileRequest`]s. Tests of the newer file protocol use
+/// [`TestIntProxy::with_protocol_version`].
+const TEST_AGENT_PROTOCOL_VERSION: semver::Version = semver::Version::new(1, 26, 0);
+
 /// Initializes tracing for the current thread, allowing us to have multiple tracing subscribers
 /// writin logs to different files.
 ///
@@ -121,6 +129,9 @@ pub fn init_tracing() -> DefaultGuard {
 pub struct TestIntProxy {
     codec: Framed<TcpStream, DaemonCodec>,
     num_connections: u64,
+    /// Highest [`mirrord_protocol`] version that we agree on, see
+    /// [`TEST_AGENT_PROTOCOL_VERSION`].
+    protocol_version: semver::Version,
 }
 
 impl TestIntProxy {
@@ -128,17 +139,22 @@ impl TestIntProxy {
         let fake_agent_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
         let fake_agent_address = fake_agent_listener.local_addr().unwrap();
         let mut context = ConfigContext::default();
-        let experimental_config = match config {
+        let (experimental_config, dns_overrides) = match config {
             Some(path) => {
-                LayerFileConfig::from_path(path, &mut context)
+                let config = LayerFileConfig::from_path(path, &mut context)
                     .unwrap()
                     .generate_config(&mut context)
-                    .unwrap()
-                    .experimental
+                    .unwrap();
+                let dns_overrides =
+                    DnsOverrides::new(&config.feature.network.dns.overrides).unwrap();
+                (config.experimental, dns_overrides)
             }
-            None => ExperimentalFileConfig::default()
-                .generate_config(&mut context)
-                .unwrap(),
+            None => (
+                ExperimentalFileConfig::default()
+                    .generate_config(&mut context)
+                    .unwrap(),
+                Default::default(),
+            ),
         };
 
         tokio::spawn(async move {
@@ -151,6 +167,9 @@ impl TestIntProxy {
                 0,
                 Default::default(),
                 Duration::from_secs(60),
+                Duration::ZERO,
+                None,
+                dns_overrides,
                 &experimental_config,
             );
             intproxy
@@ -166,9 +185,19 @@ impl TestIntProxy {
         Self {
             codec,
             num_connections: 0,
+            protocol_version: TEST_AGENT_PROTOCOL_VERSION,
         }
     }
 
+    /// Agree on up to the given [`mirrord_protocol`] version, instead of
+    /// [`TEST_AGENT_PROTOCOL_VERSION`].
+    ///
+    /// Must be called before the first message is received from the intproxy.
+    pub fn with_protocol_version(mut self, version: semver::Version) -> Self {
+        self.protocol_version = version;
+        self
+    }
+
     pub async fn recv(&mut self) -> ClientMessage {
         self.try_recv().await.expect("intproxy connection closed")
     }
@@ -248,8 +277,10 @@ impl TestIntProxy {
                     self.send(DaemonMessage::Pong).await;
                 }
                 ClientMessage::SwitchProtocolVersion(version) => {
-                    self.send(DaemonMessage::SwitchProtocolVersionResponse(version))
-                        .await;
+                    self.send(DaemonMessage::SwitchProtocolVersionResponse(
+                        version.min(self.protocol_version.clone()),
+                    ))
+                    .await;
                 }
                 ClientMessage::ReadyForLogs => {}
                 other => break Some(other),
@@ -955,6 +986,8 @@ pub enum Application {
     GoWrite(GoVersion),
     GoLSeek(GoVersion),
     GoFAccessAt(GoVersion),
+    GoRenameReadlink(GoVersion),
+    GoUdp(GoVersion),
     GoSelfOpen(GoVersion),
     RustOutgoingUdp,
     RustOutgoingTcp {
@@ -980,6 +1013,8 @@ pub enum Application {
     CIssue2178,
     RustIssue2058,
     Realpath,
+    FsOverlay,
+    DnsOverrides,
     NodeIssue2283,
     RustIssue2204,
     RustIssue2438,
@@ -1039,6 +1074,8 @@ impl Application {
             Application::StatfsFstatfs => String::from("tests/apps/statfs_fstatfs/out.c_test_app"),
             Application::MkdirRmdir => String::from("tests/apps/mkdir_rmdir/out.c_test_app"),
             Application::Realpath => String::from("tests/apps/realpath/out.c_test_app"),
+            Application::FsOverlay => String::from("tests/apps/fs_overlay/out.c_test_app"),
+            Application::DnsOverrides => String::from("tests/apps/dns_overrides/out.c_test_app"),
             Application::NodeHTTP
             | Application::NodeIssue2283
             | Application::NodeIssue2807
@@ -1078,6 +1115,12 @@ impl Application {
             Application::GoFAccessAt(version) => {
                 format!("tests/apps/faccessat_go/{version}.go_test_app")
             }
+            Application::GoRenameReadlink(version) => {
+                format!("tests/apps/rename_readlink_go/{version}.go_test_app")
+            }
+            Application::GoUdp(version) => {
+                format!("tests/apps/udp_go/{version}.go_test_app")
+            }
             Application::GoSelfOpen(version) => {
                 format!("tests/apps/self_open/{version}.go_test_app")
             }
@@ -1274,11 +1317,15 @@ impl Application {
             | Application::GoWrite(..)
             | Application::GoLSeek(..)
             | Application::GoFAccessAt(..)
+            | Application::GoRenameReadlink(..)
+            | Application::GoUdp(..)
             | Application::Fork
             | Application::ReadLink
             | Application::StatfsFstatfs
             | Application::MkdirRmdir
             | Application::Realpath
+            | Application::FsOverlay
+            | Application::DnsOverrides
             | Application::RustFileOps
             | Application::RustIssue1123
             | Application::RustIssue1054
@@ -1367,11 +1414,15 @@ impl Application {
             | Application::StatfsFstatfs
             | Application::MkdirRmdir
             | Application::Realpath
+            | Application::FsOverlay
+            | Application::DnsOverrides
             | Application::GoIssue834(..)
             | Application::GoRead(..)
             | Application::GoWrite(..)
             | Application::GoLSeek(..)
             | Application::GoFAccessAt(..)
+            | Application::GoRenameReadlink(..)
+            | Application::GoUdp(..)
             | Application::GoDirBypass(..)
             | Application::GoSelfOpen(..)
             | Application::GoDir(..)
@@ -0,0 +1,76 @@
+#![feature(assert_matches)]
+#![warn(clippy::indexing_slicing)]
+
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{
+    ClientMessage, DaemonMessage,
+    dns::{AddressFamily, DnsLookup, GetAddrInfoRequestV2, GetAddrInfoResponse, LookupRecord},
+};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Test `feature.network.dns.overrides` in `getaddrinfo`: overridden addresses are returned
+/// without asking the agent, filtered by the requested family, and a CNAME is resolved remotely
+/// instead of the queried name.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn dns_overrides(dylib_path: &Path) {
+    let _tracing = init_tracing();
+
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("dns_overrides.json");
+    std::fs::write(
+        &config_path,
+        serde_json::json!({
+            "feature": {
+                "network": {
+                    "dns": {
+                        "overrides": {
+                            "payments": ["127.0.0.1", "::1"],
+                            "legacy": { "cname": "gateway.default.svc.cluster.local" },
+                        }
+                    }
+                }
+            }
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let (mut test_process, mut intproxy) = Application::DnsOverrides
+        .start_process_with_layer(dylib_path, Default::default(), Some(&config_path))
+        .await;
+
+    // Only `legacy` is resolved remotely, through its CNAME.
+    let message = intproxy.recv().await;
+    let ClientMessage::GetAddrInfoRequestV2(GetAddrInfoRequestV2 { node, family, .. }) = message
+    else {
+        panic!("unexpected message from the layer: {message:?}");
+    };
+    assert_eq!(node, "gateway.default.svc.cluster.local");
+    assert_eq!(family, AddressFamily::Ipv4Only);
+
+    intproxy
+        .send(DaemonMessage::GetAddrInfoResponse(GetAddrInfoResponse(Ok(
+            DnsLookup(vec![LookupRecord {
+                name: node,
+                ip: "10.0.0.1".parse().unwrap(),
+            }]),
+        ))))
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+    test_process
+        .assert_stdout_contains("payments resolved to 127.0.0.1")
+        .await;
+    test_process
+        .assert_stdout_contains("legacy resolved to 10.0.0.1")
+        .await;
+}
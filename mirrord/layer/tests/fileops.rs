@@ -217,6 +217,97 @@ async fn pwrite(#[values(Application::RustFileOps)] application: Application, dy
     );
 }
 
+/// Verifies `pwrite` with [`mirrord_protocol`] versions that allow for
+/// [`ClientMessage::IdentifiedFileRequest`]s, answering each request with the id it was sent with.
+#[cfg(target_os = "linux")]
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn pwrite_identified(
+    #[values(semver::Version::new(1, 27, 0), mirrord_protocol::VERSION.clone())]
+    protocol_version: semver::Version,
+    dylib_path: &Path,
+) {
+    let _tracing = init_tracing();
+
+    assert!(FILE_REQUEST_ID_VERSION.matches(&protocol_version));
+
+    let (mut test_process, intproxy) = Application::RustFileOps
+        .start_process_with_layer(
+            dylib_path,
+            vec![("MIRRORD_FILE_READ_WRITE_PATTERN", "/tmp/test_file.txt")],
+            None,
+        )
+        .await;
+    let mut intproxy = intproxy.with_protocol_version(protocol_version);
+
+    let fd = 1;
+
+    let ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest {
+        id: open_id,
+        request: FileRequest::Open(OpenFileRequest { path, open_options }),
+    }) = intproxy.recv().await
+    else {
+        panic!("expected an identified open request");
+    };
+    assert_eq!(path, PathBuf::from("/tmp/test_file.txt"));
+    assert_eq!(
+        open_options,
+        OpenOptionsInternal {
+            read: false,
+            write: true,
+            append: false,
+            truncate: false,
+            create: true,
+            create_new: false,
+        }
+    );
+    intproxy
+        .send(DaemonMessage::IdentifiedFile(IdentifiedFileResponse {
+            id: open_id,
+            response: FileResponse::Open(Ok(OpenFileResponse { fd })),
+        }))
+        .await;
+
+    let ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest {
+        id: write_id,
+        request:
+            FileRequest::WriteLimited(WriteLimitedFileRequest {
+                remote_fd,
+                start_from,
+                write_bytes,
+            }),
+    }) = intproxy.recv().await
+    else {
+        panic!("expected an identified pwrite request");
+    };
+    assert_ne!(write_id, open_id);
+    assert_eq!(remote_fd, fd);
+    assert_eq!(start_from, 0);
+    assert_eq!(
+        write_bytes.as_ref(),
+        b"Hello, I am the file you're writing!\0".as_slice()
+    );
+    intproxy
+        .send(DaemonMessage::IdentifiedFile(IdentifiedFileResponse {
+            id: write_id,
+            response: FileResponse::WriteLimited(Ok(WriteFileResponse { written_amount: 37 })),
+        }))
+        .await;
+
+    assert_matches!(
+        intproxy.recv().await,
+        ClientMessage::IdentifiedFileRequest(IdentifiedFileRequest {
+            request: FileRequest::Close(CloseFileRequest { fd: 1 }),
+            ..
+        })
+    );
+
+    // Assert all clear
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+}
+
 /// Verifies `pwrite` - if opening a file in write mode and writing to it at an offset of zero
 /// matches the expected bytes written.
 #[rstest]
@@ -696,3 +787,36 @@ async fn faccessat_go(
     test_process.wait_assert_success().await;
     test_process.assert_no_error_in_stderr().await;
 }
+
+/// Test go `readlinkat` and `renameat`.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(10))]
+async fn rename_readlink_go(
+    #[values(GoVersion::GO_1_23, GoVersion::GO_1_24, GoVersion::GO_1_25)] go_version: GoVersion,
+    dylib_path: &Path,
+) {
+    let _tracing = init_tracing();
+
+    let (mut test_process, mut intproxy) = Application::GoRenameReadlink(go_version)
+        .start_process_with_layer(
+            dylib_path,
+            vec![
+                ("MIRRORD_FILE_MODE", "localwithoverrides"),
+                ("MIRRORD_FILE_READ_WRITE_PATTERN", "^/app/"),
+            ],
+            None,
+        )
+        .await;
+
+    intproxy.expect_read_link("/app/link.txt").await;
+    intproxy
+        .expect_file_rename("/app/test.txt", "/app/renamed.txt")
+        .await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    // Assert all clear
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+}
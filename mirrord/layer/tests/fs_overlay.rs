@@ -0,0 +1,186 @@
+#![feature(assert_matches)]
+use std::{path::Path, time::Duration};
+
+use mirrord_protocol::{file::*, *};
+use rstest::rstest;
+
+mod common;
+pub use common::*;
+
+/// Answers the requests made when copying the remote file at `path` to the overlay.
+async fn expect_copy_up(intproxy: &mut TestIntProxy, path: &str, fd: u64, contents: &str) {
+    intproxy
+        .expect_xstat_with_metadata(
+            Some(path.into()),
+            None,
+            MetadataInternal {
+                mode: libc::S_IFREG as u32 | 0o644,
+                size: contents.len() as u64,
+                ..Default::default()
+            },
+        )
+        .await;
+    intproxy
+        .expect_file_open_with_whatever_options(path, fd)
+        .await;
+
+    intproxy.expect_only_file_read(fd).await;
+    intproxy
+        .answer_file_read(contents.as_bytes().to_vec())
+        .await;
+    if !contents.is_empty() {
+        intproxy.expect_only_file_read(fd).await;
+        intproxy.answer_file_read(Vec::new()).await;
+    }
+
+    intproxy.expect_file_close(fd).await;
+}
+
+/// Answers the requests made when listing the remote directory at `path`.
+async fn expect_dir_listing(
+    intproxy: &mut TestIntProxy,
+    path: &str,
+    fd: u64,
+    dir_fd: u64,
+    entries: &[&str],
+) {
+    intproxy
+        .expect_file_open_with_whatever_options(path, fd)
+        .await;
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::FdOpenDir(FdOpenDirRequest { remote_fd: fd }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::OpenDir(Ok(
+            OpenDirResponse { fd: dir_fd },
+        ))))
+        .await;
+
+    let dir_entries = entries
+        .iter()
+        .enumerate()
+        .map(|(position, name)| DirEntryInternal {
+            inode: position as u64 + 1,
+            position: position as u64,
+            name: name.to_string(),
+            file_type: libc::DT_REG,
+        })
+        .collect::<Vec<_>>();
+    for dir_entries in [dir_entries, Vec::new()] {
+        assert_eq!(
+            intproxy.recv().await,
+            ClientMessage::FileRequest(FileRequest::ReadDirBatch(ReadDirBatchRequest {
+                remote_fd: dir_fd,
+                amount: 128
+            }))
+        );
+        intproxy
+            .send(DaemonMessage::File(FileResponse::ReadDirBatch(Ok(
+                ReadDirBatchResponse {
+                    fd: dir_fd,
+                    dir_entries,
+                },
+            ))))
+            .await;
+    }
+
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::CloseDir(CloseDirRequest { remote_fd: dir_fd }))
+    );
+    intproxy.expect_file_close(fd).await;
+}
+
+/// Test the local copy-on-write fs overlay: remote files are copied to the overlay before they're
+/// changed, removed files are hidden with whiteouts (without being copied), and remote directories
+/// with files in them can't be removed.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(60))]
+async fn fs_overlay(dylib_path: &Path) {
+    let _tracing = init_tracing();
+
+    let dir = tempfile::tempdir().unwrap();
+    let overlay_dir = dir.path().join("overlay");
+    let config_path = dir.path().join("fs_overlay.json");
+    std::fs::write(
+        &config_path,
+        serde_json::json!({
+            "feature": {
+                "fs": {
+                    "mode": "overlay",
+                    "overlay_dir": overlay_dir,
+                }
+            }
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let (mut test_process, mut intproxy) = Application::FsOverlay
+        .start_process_with_layer(dylib_path, Default::default(), Some(&config_path))
+        .await;
+
+    // `rename` copies the old file to the overlay, and checks that the new one is not remote.
+    expect_copy_up(&mut intproxy, "/app/config.txt", 1, "remote contents").await;
+    assert_eq!(
+        intproxy.recv().await,
+        ClientMessage::FileRequest(FileRequest::Xstat(XstatRequest {
+            path: Some("/app/renamed.txt".into()),
+            fd: None,
+            follow_symlink: true,
+        }))
+    );
+    intproxy
+        .send(DaemonMessage::File(FileResponse::Xstat(Err(
+            ResponseError::RemoteIO(RemoteIOError {
+                raw_os_error: Some(libc::ENOENT),
+                kind: ErrorKindInternal::NotFound,
+            }),
+        ))))
+        .await;
+
+    // The first `rmdir` fails, as there's a file in the remote directory.
+    intproxy
+        .expect_xstat_with_metadata(
+            Some("/app/data".into()),
+            None,
+            MetadataInternal {
+                mode: libc::S_IFDIR as u32 | 0o755,
+                ..Default::default()
+            },
+        )
+        .await;
+    expect_dir_listing(&mut intproxy, "/app/data", 2, 3, &["file.txt"]).await;
+
+    // `unlink` only checks that the remote file exists, it's not copied to the overlay.
+    intproxy
+        .expect_xstat_with_metadata(
+            Some("/app/data/file.txt".into()),
+            None,
+            MetadataInternal {
+                mode: libc::S_IFREG as u32 | 0o644,
+                ..Default::default()
+            },
+        )
+        .await;
+
+    // The second `rmdir` succeeds, as the file was removed in the overlay.
+    expect_dir_listing(&mut intproxy, "/app/data", 4, 5, &["file.txt"]).await;
+
+    assert_eq!(intproxy.try_recv().await, None);
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+
+    assert_eq!(
+        std::fs::read_to_string(overlay_dir.join("app/renamed.txt")).unwrap(),
+        "remote contents"
+    );
+    assert!(!overlay_dir.join("app/config.txt").exists());
+    assert!(overlay_dir.join("app/.wh.config.txt").exists());
+    assert!(overlay_dir.join("app/.wh.data").exists());
+    assert!(!overlay_dir.join("app/data").exists());
+}
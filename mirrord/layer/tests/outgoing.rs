@@ -88,6 +88,70 @@ async fn outgoing_udp(dylib_path: &Path) {
     test_process.wait_assert_success().await;
 }
 
+/// Test outgoing UDP from Go, through the `sendto`, `recvfrom`, `sendmsg` and `recvmsg` raw
+/// syscalls.
+///
+/// The application sends a packet to `1.2.3.4:53` from 2 sockets, and expects the peer to send
+/// the same data back.
+#[rstest]
+#[tokio::test]
+#[timeout(Duration::from_secs(15))]
+async fn outgoing_udp_go(
+    #[values(GoVersion::GO_1_23, GoVersion::GO_1_24, GoVersion::GO_1_25)] go_version: GoVersion,
+    dylib_path: &Path,
+) {
+    let _tracing = init_tracing();
+
+    let (mut test_process, mut intproxy) = Application::GoUdp(go_version)
+        .start_process_with_layer(dylib_path, vec![("MIRRORD_FILE_MODE", "local")], None)
+        .await;
+
+    for (connection_id, expected) in [b"sendto".as_slice(), b"sendmsg"].into_iter().enumerate() {
+        let connection_id = connection_id as u64;
+
+        let (uid, addr) = intproxy.recv_udp_connect().await;
+        assert_eq!(addr, "1.2.3.4:53".parse().unwrap());
+        intproxy
+            .send_udp_connect_ok(
+                uid,
+                connection_id,
+                addr,
+                RUST_OUTGOING_LOCAL.parse().unwrap(),
+            )
+            .await;
+
+        let msg = intproxy.recv().await;
+        let ClientMessage::UdpOutgoing(LayerUdpOutgoing::Write(LayerWrite {
+            connection_id: response_connection_id,
+            bytes,
+        })) = msg
+        else {
+            panic!("Invalid message received from layer: {msg:?}");
+        };
+
+        assert_eq!(response_connection_id, connection_id);
+        assert_eq!(&bytes[..], expected);
+
+        intproxy
+            .send(DaemonMessage::UdpOutgoing(DaemonUdpOutgoing::Read(Ok(
+                DaemonRead {
+                    connection_id,
+                    bytes,
+                },
+            ))))
+            .await;
+
+        intproxy
+            .send(DaemonMessage::UdpOutgoing(DaemonUdpOutgoing::Close(
+                connection_id,
+            )))
+            .await;
+    }
+
+    test_process.wait_assert_success().await;
+    test_process.assert_no_error_in_stderr().await;
+}
+
 /// Test outgoing TCP.
 /// Application, for each remote peer in [`RUST_OUTGOING_PEERS`]:
 /// 1. Opens a TCP port at [`RUST_OUTGOING_LOCAL`]
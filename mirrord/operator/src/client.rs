@@ -141,6 +141,9 @@ pub struct OperatorSession {
     pub operator_protocol_version: Option<Version>,
     /// Allow the layer to attempt reconnection
     pub allow_reconnect: bool,
+    /// The operator supports compression of the connection, see
+    /// [`NewOperatorFeature::ProtocolCompression`].
+    pub allow_compression: bool,
 }
 
 impl fmt::Debug for OperatorSession {
@@ -156,6 +159,7 @@ impl fmt::Debug for OperatorSession {
             .field("operator_protocol_version", &self.operator_protocol_version)
             .field("operator_version", &self.operator_version)
             .field("allow_reconnect", &self.allow_reconnect)
+            .field("allow_compression", &self.allow_compression)
             .finish()
     }
 }
@@ -1020,6 +1024,11 @@ impl OperatorApi<PreparedClientCert> {
             .spec
             .supported_features()
             .contains(&NewOperatorFeature::LayerReconnect);
+        let allow_compression = self
+            .operator
+            .spec
+            .supported_features()
+            .contains(&NewOperatorFeature::ProtocolCompression);
 
         Ok(OperatorSession {
             id,
@@ -1029,6 +1038,7 @@ impl OperatorApi<PreparedClientCert> {
             operator_protocol_version,
             operator_version,
             allow_reconnect,
+            allow_compression,
         })
     }
 
@@ -1476,7 +1486,10 @@ impl OperatorApi<PreparedClientCert> {
                 mirrord_config::feature::database_branches::DatabaseBranchConfig::Pg(pg_config) => {
                     Some(pg_config.base.creation_timeout_secs)
                 }
-                mirrord_config::feature::database_branches::DatabaseBranchConfig::Redis(_) => None,
+                mirrord_config::feature::database_branches::DatabaseBranchConfig::Redis(_)
+                | mirrord_config::feature::database_branches::DatabaseBranchConfig::Mongodb(_) => {
+                    None
+                }
             })
             .max()
             .unwrap_or(default_creation_timeout_secs());
@@ -1547,7 +1560,10 @@ impl OperatorApi<PreparedClientCert> {
                 mirrord_config::feature::database_branches::DatabaseBranchConfig::Pg(pg_config) => {
                     Some(pg_config.base.creation_timeout_secs)
                 }
-                mirrord_config::feature::database_branches::DatabaseBranchConfig::Redis(_) => None,
+                mirrord_config::feature::database_branches::DatabaseBranchConfig::Redis(_)
+                | mirrord_config::feature::database_branches::DatabaseBranchConfig::Mongodb(_) => {
+                    None
+                }
             })
             .max()
             .unwrap_or(default_creation_timeout_secs());
@@ -10,8 +10,8 @@ use kube::{
 };
 use mirrord_config::{
     feature::database_branches::{
-        ConnectionSource, ConnectionSourceKind, DatabaseBranchConfig, DatabaseBranchesConfig,
-        MysqlBranchConfig, PgBranchConfig,
+        ConnectionSource, ConnectionSourceKind, DatabaseBranchConfig, DatabaseBranchLocation,
+        DatabaseBranchesConfig, MysqlBranchConfig, PgBranchConfig,
     },
     target::{Target, TargetDisplay},
 };
@@ -314,10 +314,20 @@ impl DatabaseBranchParams {
     /// Create branch database parameters.
     ///
     /// We generate unique database IDs unless the user explicitly specifies them.
+    ///
+    /// Branches with [`DatabaseBranchLocation::Local`] are spawned by the CLI, so they are skipped
+    /// here.
     pub(crate) fn new(config: &DatabaseBranchesConfig, target: &Target) -> Self {
         let mut mysql = HashMap::new();
         let mut pg = HashMap::new();
         for branch_db_config in config.0.iter() {
+            if branch_db_config
+                .sql_base()
+                .is_some_and(|base| base.location == DatabaseBranchLocation::Local)
+            {
+                continue;
+            }
+
             match branch_db_config {
                 DatabaseBranchConfig::Mysql(mysql_config) => {
                     let id = if let Some(id) = mysql_config.base.id.clone() {
@@ -337,7 +347,7 @@ impl DatabaseBranchParams {
                     let params = PgBranchParams::new(id.as_ref(), pg_config, target);
                     pg.insert(id, params);
                 }
-                DatabaseBranchConfig::Redis(_) => {}
+                DatabaseBranchConfig::Redis(_) | DatabaseBranchConfig::Mongodb(_) => {}
             };
         }
         Self { mysql, pg }
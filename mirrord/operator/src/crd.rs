@@ -76,6 +76,7 @@ impl TargetCrd {
             Target::StatefulSet(target) => ("statefulset", &target.stateful_set, &target.container),
             Target::Service(target) => ("service", &target.service, &target.container),
             Target::ReplicaSet(target) => ("replicaset", &target.replica_set, &target.container),
+            Target::DaemonSet(target) => ("daemonset", &target.daemon_set, &target.container),
             Target::Targetless => return TARGETLESS_TARGET_NAME.to_string(),
         };
 
@@ -103,6 +104,8 @@ impl TryFrom<TargetCrd> for TargetConfig {
         Ok(TargetConfig {
             path: Some(Target::try_from(crd.spec.target)?),
             namespace: crd.metadata.namespace,
+            additional: Vec::new(),
+            selector: None,
         })
     }
 }
@@ -398,6 +401,10 @@ pub enum NewOperatorFeature {
     /// operator's license gets updated (on validity extension, for example).
     BypassCiCertificateVerification,
 
+    /// The operator passes [`ClientMessage::SwitchCompression`](mirrord_protocol::ClientMessage)
+    /// to the agent, and compresses its own side of the connection accordingly.
+    ProtocolCompression,
+
     /// This variant is what a client sees when the operator includes a feature the client is not
     /// yet aware of, because it was introduced in a version newer than the client's.
     #[schemars(skip)]
@@ -427,6 +434,7 @@ impl Display for NewOperatorFeature {
             NewOperatorFeature::BypassCiCertificateVerification => {
                 "BypassCiCertificateVerification"
             }
+            NewOperatorFeature::ProtocolCompression => "protocol compression",
             NewOperatorFeature::Unknown => "unknown feature",
         };
         f.write_str(name)
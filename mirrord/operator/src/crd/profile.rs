@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use kube::CustomResource;
-use schemars::JsonSchema;
+use schemars::{JsonSchema, r#gen::SchemaGenerator, schema::Schema};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -24,9 +24,25 @@ use serde_json::Value;
 pub struct MirrordClusterProfileSpec {
     /// A list of adjustments to be made in the user's feature config.
     ///
-    /// The adjustments are applied in order.
+    /// The adjustments are applied in order, after the [`Self::config_patch`].
+    #[serde(default)]
     pub feature_adjustments: Vec<FeatureAdjustment>,
 
+    /// A JSON merge patch ([RFC 7386](https://datatracker.ietf.org/doc/html/rfc7386)) to be
+    /// applied to the user's config file.
+    ///
+    /// Precedence, from the lowest:
+    /// 1. the user's config file (and the files it extends);
+    /// 2. this patch;
+    /// 3. environment variables and CLI flags;
+    /// 4. [`Self::feature_adjustments`].
+    ///
+    /// The patched config is validated against the mirrord config schema.
+    /// The patch cannot change `profile` nor `extends`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "config_patch_schema")]
+    pub config_patch: Option<Value>,
+
     /// For future compatibility.
     ///
     /// The CLI should error when the profile contains unknown fields.
@@ -57,9 +73,25 @@ pub struct MirrordClusterProfileSpec {
 pub struct MirrordProfileSpec {
     /// A list of adjustments to be made in the user's feature config.
     ///
-    /// The adjustments are applied in order.
+    /// The adjustments are applied in order, after the [`Self::config_patch`].
+    #[serde(default)]
     pub feature_adjustments: Vec<FeatureAdjustment>,
 
+    /// A JSON merge patch ([RFC 7386](https://datatracker.ietf.org/doc/html/rfc7386)) to be
+    /// applied to the user's config file.
+    ///
+    /// Precedence, from the lowest:
+    /// 1. the user's config file (and the files it extends);
+    /// 2. this patch;
+    /// 3. environment variables and CLI flags;
+    /// 4. [`Self::feature_adjustments`].
+    ///
+    /// The patched config is validated against the mirrord config schema.
+    /// The patch cannot change `profile` nor `extends`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "config_patch_schema")]
+    pub config_patch: Option<Value>,
+
     /// For future compatibility.
     ///
     /// The CLI should error when the profile contains unknown fields.
@@ -68,6 +100,18 @@ pub struct MirrordProfileSpec {
     pub unknown_fields: HashMap<String, Value>,
 }
 
+/// Schema of the `configPatch` field, any JSON object.
+///
+/// Kubernetes would prune the unknown fields otherwise.
+fn config_patch_schema(_: &mut SchemaGenerator) -> Schema {
+    serde_json::from_value(serde_json::json!({
+        "type": "object",
+        "nullable": true,
+        "x-kubernetes-preserve-unknown-fields": true,
+    }))
+    .expect("config patch schema should be valid")
+}
+
 /// A single adjustment to the mirrord config's `feature` section.
 #[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -633,6 +633,7 @@ impl OperatorClusterRole {
                     "statefulsets/scale".to_owned(),
                     "services".to_owned(),
                     "replicasets".to_owned(),
+                    "daemonsets".to_owned(),
                 ]),
                 verbs: vec!["get".to_owned(), "list".to_owned(), "watch".to_owned()],
                 ..Default::default()
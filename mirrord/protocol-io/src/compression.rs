@@ -0,0 +1,339 @@
+//! Negotiated compression of mirrord-protocol messages.
+//!
+//! Compression is switched on separately for each direction of the connection, by the
+//! [`ClientMessage::SwitchCompression`] and [`DaemonMessage::SwitchCompressionResponse`]
+//! messages. Every message sent after the switching one is framed as described in
+//! [`CompressionAlgorithm`]. Since both the encoder and the decoder switch right after processing
+//! the switching message, no additional synchronization is required.
+
+use std::{io, marker::PhantomData};
+
+use actix_codec::{Decoder, Encoder};
+use bincode::error::DecodeError;
+use bytes::{Buf, BufMut, BytesMut};
+use mirrord_protocol::{ClientMessage, CompressionAlgorithm, DaemonMessage};
+
+/// Implemented by message types that can switch on compression, see the module docs.
+pub trait SwitchesCompression {
+    /// Returns the algorithm used for all messages sent after this one, if this message switches
+    /// compression on.
+    fn switches_compression(&self) -> Option<CompressionAlgorithm>;
+}
+
+impl SwitchesCompression for ClientMessage {
+    fn switches_compression(&self) -> Option<CompressionAlgorithm> {
+        match self {
+            Self::SwitchCompression(algorithm) => Some(*algorithm),
+            _ => None,
+        }
+    }
+}
+
+impl SwitchesCompression for DaemonMessage {
+    fn switches_compression(&self) -> Option<CompressionAlgorithm> {
+        match self {
+            Self::SwitchCompressionResponse(algorithm) => Some(*algorithm),
+            _ => None,
+        }
+    }
+}
+
+/// Encoded messages shorter than this are always sent uncompressed, as compressing small control
+/// messages costs more than it saves.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Upper bound for the uncompressed size of a single message.
+///
+/// Protects us from allocating huge buffers when the peer sends garbage.
+const MAX_MESSAGE_SIZE: usize = 512 * 1024 * 1024;
+
+/// We favor speed over the compression ratio.
+const ZSTD_LEVEL: i32 = 1;
+
+const UNCOMPRESSED_TAG: u8 = 0;
+const COMPRESSED_TAG: u8 = 1;
+
+/// Tag byte, compressed length and uncompressed length.
+const COMPRESSED_HEADER_LEN: usize = 9;
+
+/// Compression state of both directions of a connection.
+#[derive(Debug, Default)]
+pub(crate) struct Compression {
+    incoming: Option<CompressionAlgorithm>,
+    outgoing: Option<CompressionAlgorithm>,
+}
+
+impl Compression {
+    /// Decodes the next incoming message from `src`.
+    ///
+    /// Returns [`None`] if `src` does not contain a whole message yet.
+    pub(crate) fn decode<I>(&mut self, src: &mut BytesMut) -> Result<Option<I>, DecodeError>
+    where
+        I: bincode::Decode<()> + SwitchesCompression,
+    {
+        let message = match self.incoming {
+            None => decode_plain(src, 0)?,
+            Some(algorithm) => match src.first() {
+                None => None,
+                Some(&UNCOMPRESSED_TAG) => decode_plain(src, 1)?,
+                Some(&COMPRESSED_TAG) => decode_compressed(algorithm, src)?,
+                Some(tag) => {
+                    return Err(DecodeError::OtherString(format!(
+                        "invalid message tag {tag}"
+                    )));
+                }
+            },
+        };
+
+        if let Some(algorithm) = message
+            .as_ref()
+            .and_then(SwitchesCompression::switches_compression)
+        {
+            self.incoming = Some(algorithm);
+        }
+
+        Ok(message)
+    }
+
+    /// Appends the given bincode-encoded outgoing message to `dst`.
+    ///
+    /// `switches_compression` should come from [`SwitchesCompression`] implementation of the
+    /// message, it is applied to the messages that follow.
+    pub(crate) fn encode(
+        &mut self,
+        message: &[u8],
+        switches_compression: Option<CompressionAlgorithm>,
+        dst: &mut BytesMut,
+    ) {
+        match self.outgoing {
+            None => dst.extend_from_slice(message),
+            Some(algorithm) => match compress(algorithm, message) {
+                Some(compressed) => {
+                    dst.reserve(COMPRESSED_HEADER_LEN + compressed.len());
+                    dst.put_u8(COMPRESSED_TAG);
+                    dst.put_u32_le(compressed.len() as u32);
+                    dst.put_u32_le(message.len() as u32);
+                    dst.extend_from_slice(&compressed);
+                }
+                None => {
+                    dst.reserve(1 + message.len());
+                    dst.put_u8(UNCOMPRESSED_TAG);
+                    dst.extend_from_slice(message);
+                }
+            },
+        }
+
+        if let Some(algorithm) = switches_compression {
+            self.outgoing = Some(algorithm);
+        }
+    }
+}
+
+/// Decodes a plain bincode message, that starts at `offset` in `src`.
+fn decode_plain<I: bincode::Decode<()>>(
+    src: &mut BytesMut,
+    offset: usize,
+) -> Result<Option<I>, DecodeError> {
+    match bincode::decode_from_slice(
+        src.get(offset..).unwrap_or_default(),
+        bincode::config::standard(),
+    ) {
+        Ok((message, read)) => {
+            src.advance(offset + read);
+            Ok(Some(message))
+        }
+        Err(DecodeError::UnexpectedEnd { .. }) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Decodes a compressed message, `src` starts with [`COMPRESSED_TAG`].
+fn decode_compressed<I: bincode::Decode<()>>(
+    algorithm: CompressionAlgorithm,
+    src: &mut BytesMut,
+) -> Result<Option<I>, DecodeError> {
+    let Some(mut header) = src.get(1..COMPRESSED_HEADER_LEN) else {
+        return Ok(None);
+    };
+    let compressed_len = header.get_u32_le() as usize;
+    let len = header.get_u32_le() as usize;
+
+    if len.max(compressed_len) > MAX_MESSAGE_SIZE {
+        return Err(DecodeError::OtherString(format!(
+            "compressed message is too big ({compressed_len} bytes, {len} uncompressed)"
+        )));
+    }
+
+    let frame_len = COMPRESSED_HEADER_LEN + compressed_len;
+    let Some(compressed) = src.get(COMPRESSED_HEADER_LEN..frame_len) else {
+        src.reserve(frame_len - src.len());
+        return Ok(None);
+    };
+
+    let decompressed = match algorithm {
+        CompressionAlgorithm::Zstd => {
+            zstd::bulk::decompress(compressed, len).map_err(|error| error.to_string())
+        }
+        CompressionAlgorithm::Lz4 => {
+            lz4_flex::block::decompress(compressed, len).map_err(|error| error.to_string())
+        }
+    }
+    .map_err(|error| {
+        DecodeError::OtherString(format!(
+            "failed to decompress {algorithm:?} message: {error}"
+        ))
+    })?;
+
+    let (message, _) = bincode::decode_from_slice(&decompressed, bincode::config::standard())?;
+    src.advance(frame_len);
+
+    Ok(Some(message))
+}
+
+/// Compresses the message, if it's worth it.
+fn compress(algorithm: CompressionAlgorithm, message: &[u8]) -> Option<Vec<u8>> {
+    if message.len() < COMPRESSION_THRESHOLD || message.len() > MAX_MESSAGE_SIZE {
+        return None;
+    }
+
+    let compressed = match algorithm {
+        CompressionAlgorithm::Zstd => zstd::bulk::compress(message, ZSTD_LEVEL)
+            .inspect_err(|error| tracing::warn!(%error, "failed to compress a message"))
+            .ok()?,
+        CompressionAlgorithm::Lz4 => lz4_flex::block::compress(message),
+    };
+
+    (compressed.len() < message.len()).then_some(compressed)
+}
+
+/// Same as [`ProtocolCodec`](mirrord_protocol::ProtocolCodec), but supports compression.
+///
+/// Used on the agent side of the connection, e.g. `CompressionCodec<ClientMessage,
+/// DaemonMessage>`.
+pub struct CompressionCodec<I, O> {
+    compression: Compression,
+    _phantom: PhantomData<fn(O) -> I>,
+}
+
+impl<I, O> Default for CompressionCodec<I, O> {
+    fn default() -> Self {
+        Self {
+            compression: Default::default(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, O> Decoder for CompressionCodec<I, O>
+where
+    I: bincode::Decode<()> + SwitchesCompression,
+{
+    type Item = I;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        self.compression.decode(src).map_err(io::Error::other)
+    }
+}
+
+impl<I, O> Encoder<O> for CompressionCodec<I, O>
+where
+    O: bincode::Encode + SwitchesCompression,
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: O, dst: &mut BytesMut) -> io::Result<()> {
+        let switches_compression = msg.switches_compression();
+        let encoded =
+            bincode::encode_to_vec(msg, bincode::config::standard()).map_err(io::Error::other)?;
+        self.compression.encode(&encoded, switches_compression, dst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mirrord_protocol::{
+        FileRequest, FileResponse,
+        file::{CloseFileRequest, ReadFileResponse},
+    };
+    use rstest::rstest;
+
+    use super::*;
+
+    type AgentCodec = CompressionCodec<ClientMessage, DaemonMessage>;
+    type ClientCodec = CompressionCodec<DaemonMessage, ClientMessage>;
+
+    fn big_read() -> DaemonMessage {
+        DaemonMessage::File(FileResponse::Read(Ok(ReadFileResponse {
+            bytes: vec![7; 64 * 1024].into(),
+            read_amount: 64 * 1024,
+        })))
+    }
+
+    /// Messages before and after the switch survive the round trip, big ones get compressed.
+    #[rstest]
+    fn round_trip(
+        #[values(CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4)]
+        algorithm: CompressionAlgorithm,
+    ) {
+        let mut agent = AgentCodec::default();
+        let mut client = ClientCodec::default();
+        let mut buf = BytesMut::new();
+
+        let switch = ClientMessage::SwitchCompression(algorithm);
+        let close = ClientMessage::FileRequest(FileRequest::Close(CloseFileRequest { fd: 1 }));
+        client.encode(switch.clone(), &mut buf).unwrap();
+        client.encode(close.clone(), &mut buf).unwrap();
+        assert_eq!(agent.decode(&mut buf).unwrap(), Some(switch));
+        assert_eq!(agent.decode(&mut buf).unwrap(), Some(close));
+        assert_eq!(agent.decode(&mut buf).unwrap(), None);
+
+        let response = DaemonMessage::SwitchCompressionResponse(algorithm);
+        agent.encode(response.clone(), &mut buf).unwrap();
+        assert_eq!(client.decode(&mut buf).unwrap(), Some(response));
+
+        agent.encode(big_read(), &mut buf).unwrap();
+        assert!(buf.len() < 1024, "message was not compressed");
+        assert_eq!(buf.first(), Some(&COMPRESSED_TAG));
+        assert_eq!(client.decode(&mut buf).unwrap(), Some(big_read()));
+
+        agent.encode(DaemonMessage::Pong, &mut buf).unwrap();
+        assert_eq!(buf.first(), Some(&UNCOMPRESSED_TAG));
+        assert_eq!(client.decode(&mut buf).unwrap(), Some(DaemonMessage::Pong));
+        assert!(buf.is_empty());
+    }
+
+    /// Compressed messages that arrive in pieces are decoded only once complete.
+    #[test]
+    fn partial_frames() {
+        let mut agent = AgentCodec::default();
+        let mut client = ClientCodec::default();
+        let mut encoded = BytesMut::new();
+
+        agent
+            .encode(
+                DaemonMessage::SwitchCompressionResponse(CompressionAlgorithm::Zstd),
+                &mut encoded,
+            )
+            .unwrap();
+        agent.encode(big_read(), &mut encoded).unwrap();
+
+        let mut buf = BytesMut::new();
+        let mut decoded = Vec::new();
+        for byte in encoded {
+            buf.put_u8(byte);
+            if let Some(message) = client.decode(&mut buf).unwrap() {
+                decoded.push(message);
+            }
+        }
+
+        assert_eq!(
+            decoded,
+            vec![
+                DaemonMessage::SwitchCompressionResponse(CompressionAlgorithm::Zstd),
+                big_read()
+            ]
+        );
+    }
+}
@@ -1,5 +1,8 @@
 //! This module implements mirrord-protocol's wire-level IO.
 
+pub mod compression;
+pub mod priority;
+
 use std::{
     collections::{HashMap, VecDeque},
     fmt,
@@ -14,13 +17,19 @@ use std::{
 
 use actix_codec::{AsyncRead, AsyncWrite, Decoder, Encoder, Framed};
 use bincode::error::DecodeError;
-use bytes::{BufMut, BytesMut};
-use futures::{Sink, SinkExt, Stream, StreamExt, future::Either};
-use mirrord_protocol::{ClientMessage, DaemonMessage, ProtocolCodec};
+use bytes::BytesMut;
+use compression::{Compression, SwitchesCompression};
+use futures::{
+    Sink, SinkExt, Stream, StreamExt,
+    future::{self, Either},
+};
+use mirrord_protocol::{ClientMessage, CompressionAlgorithm, DaemonMessage};
+use priority::{Prioritized, Priority};
 use rand::seq::IteratorRandom;
 use tokio::{
     pin, select,
     sync::{Notify, futures::OwnedNotified, mpsc},
+    task::JoinHandle,
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, instrument};
@@ -43,8 +52,8 @@ impl<T, I, O> Transport<I, O> for T where
 ///
 /// Implemented by [`Client`] and [`Agent`].
 pub trait ProtocolEndpoint: 'static + Sized + Clone {
-    type InMsg: bincode::Decode<()> + Send + fmt::Debug;
-    type OutMsg: bincode::Encode + Send + fmt::Debug;
+    type InMsg: bincode::Decode<()> + SwitchesCompression + Send + fmt::Debug;
+    type OutMsg: bincode::Encode + SwitchesCompression + Prioritized + Send + fmt::Debug;
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -71,23 +80,35 @@ impl ProtocolEndpoint for Agent {
     type OutMsg = DaemonMessage;
 }
 
-// Same as protocolCodec but outputs raw Vec<u8>s
-struct Codec<I>(PhantomData<I>);
+/// An already encoded message, waiting in one of the [`SharedState`] queues.
+#[derive(Debug)]
+struct Outgoing {
+    encoded: Vec<u8>,
+    /// From the [`SwitchesCompression`] implementation of the message.
+    switches_compression: Option<CompressionAlgorithm>,
+    priority: Priority,
+}
+
+// Same as protocolCodec but takes already encoded messages
+struct Codec<I> {
+    compression: Compression,
+    _phantom: PhantomData<I>,
+}
 
-impl<I: bincode::Decode<()>> Decoder for Codec<I> {
+impl<I: bincode::Decode<()> + SwitchesCompression> Decoder for Codec<I> {
     type Item = I;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
-        ProtocolCodec::<I, ()>::default().decode(src)
+        self.compression.decode(src).map_err(io::Error::other)
     }
 }
 
-impl<I> Encoder<Vec<u8>> for Codec<I> {
+impl<I> Encoder<Outgoing> for Codec<I> {
     type Error = io::Error;
-    fn encode(&mut self, encoded: Vec<u8>, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        dst.reserve(encoded.len());
-        dst.put(&encoded[..]);
+    fn encode(&mut self, outgoing: Outgoing, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.compression
+            .encode(&outgoing.encoded, outgoing.switches_compression, dst);
         Ok(())
     }
 }
@@ -103,12 +124,15 @@ impl<I> Encoder<Vec<u8>> for Codec<I> {
 /// derived from it.
 ///
 /// The impl works by randomly picking a message from one of the
-/// nonempty queues and sending it over the wire. Future versions will
-/// use message chunking to fairly split the available bandwidth
-/// between all queues (as messages can vary in size).
+/// nonempty queues with the most urgent [`Priority`] message at their
+/// front, and sending it over the wire. Large messages are split into
+/// chunks when possible (see [`Prioritized::into_chunks`]), so that
+/// control messages (e.g. pings) are not stuck behind bulk transfers.
 pub struct Connection<Type: ProtocolEndpoint> {
     rx: mpsc::Receiver<Type::InMsg>,
     shared_state: Arc<SharedState<Type>>,
+    /// [`None`] for [`Self::dummy`] connections.
+    io_task: Option<JoinHandle<()>>,
 }
 
 impl<Type: ProtocolEndpoint> Connection<Type> {
@@ -118,13 +142,19 @@ impl<Type: ProtocolEndpoint> Connection<Type> {
     where
         IO: AsyncIO,
     {
-        let framed = Framed::new(inner, Codec::<Type::InMsg>(PhantomData));
+        let framed = Framed::new(
+            inner,
+            Codec::<Type::InMsg> {
+                compression: Default::default(),
+                _phantom: PhantomData,
+            },
+        );
 
         let (inbound_tx, inbound_rx) = mpsc::channel(64);
 
         let shared_state = Arc::new(SharedState::new(inbound_tx.downgrade(), None));
 
-        tokio::spawn(io_task::<_, Type>(
+        let io_task = tokio::spawn(io_task::<_, Type>(
             framed,
             Arc::clone(&shared_state),
             inbound_tx,
@@ -133,6 +163,7 @@ impl<Type: ProtocolEndpoint> Connection<Type> {
         Ok(Self {
             rx: inbound_rx,
             shared_state,
+            io_task: Some(io_task),
         })
     }
 
@@ -147,13 +178,24 @@ impl<Type: ProtocolEndpoint> Connection<Type> {
         Filter: Fn(Type::OutMsg) -> Either<Type::OutMsg, Type::InMsg> + Send + Sync + 'static,
         C::Error: From<DecodeError> + std::error::Error + Send + 'static,
     {
-        let framed = channel.map(|msg| {
-            msg.and_then(|e| {
-                bincode::decode_from_slice::<Type::InMsg, _>(&e, bincode::config::standard())
-                    .map(|(msg, _)| msg)
-                    .map_err(<C::Error as From<DecodeError>>::from)
+        // Every message is sent in a separate frame of the channel, but we still need to track
+        // compression of each direction.
+        let mut incoming = Compression::default();
+        let mut outgoing = Compression::default();
+        let framed = channel
+            .map(move |msg| {
+                msg.and_then(|e| {
+                    incoming
+                        .decode::<Type::InMsg>(&mut BytesMut::from(&e[..]))
+                        .and_then(|msg| msg.ok_or(DecodeError::UnexpectedEnd { additional: 1 }))
+                        .map_err(<C::Error as From<DecodeError>>::from)
+                })
             })
-        });
+            .with(move |msg: Outgoing| {
+                let mut encoded = BytesMut::new();
+                outgoing.encode(&msg.encoded, msg.switches_compression, &mut encoded);
+                future::ready(Ok::<_, C::Error>(encoded.to_vec()))
+            });
 
         let (inbound_tx, inbound_rx) = mpsc::channel(64);
 
@@ -162,7 +204,7 @@ impl<Type: ProtocolEndpoint> Connection<Type> {
             filter.map(|f| Box::new(f) as _),
         ));
 
-        tokio::spawn(io_task::<_, Type>(
+        let io_task = tokio::spawn(io_task::<_, Type>(
             framed,
             Arc::clone(&shared_state),
             inbound_tx,
@@ -171,6 +213,7 @@ impl<Type: ProtocolEndpoint> Connection<Type> {
         Ok(Self {
             rx: inbound_rx,
             shared_state,
+            io_task: Some(io_task),
         })
     }
 
@@ -183,6 +226,7 @@ impl<Type: ProtocolEndpoint> Connection<Type> {
         let connection = Connection {
             rx: inbound_rx,
             shared_state: Arc::clone(&shared_state),
+            io_task: None,
         };
 
         (
@@ -227,6 +271,26 @@ impl<Type: ProtocolEndpoint> Connection<Type> {
     pub fn is_closed(&self) -> bool {
         self.rx.is_closed()
     }
+
+    /// Closes the connection, waiting until all queued messages are sent.
+    ///
+    /// Dropping the connection closes it as well, but doesn't wait for the queues to be flushed.
+    pub async fn close(mut self) {
+        self.shared_state.cancel.cancel();
+
+        let Some(mut io_task) = self.io_task.take() else {
+            return;
+        };
+
+        loop {
+            select! {
+                _ = &mut io_task => break,
+                // Incoming messages are discarded, so that the IO task doesn't get stuck on a full
+                // channel.
+                _ = self.rx.recv() => {}
+            }
+        }
+    }
 }
 
 impl<Type: ProtocolEndpoint> Drop for Connection<Type> {
@@ -250,7 +314,7 @@ where
     Type::OutMsg: bincode::Decode<()>,
 {
     pub async fn next(&self) -> Option<Type::OutMsg> {
-        bincode::decode_from_slice(&self.0.next().await, bincode::config::standard())
+        bincode::decode_from_slice(&self.0.next().await.encoded, bincode::config::standard())
             .ok()
             .map(|e| e.0)
     }
@@ -263,7 +327,7 @@ async fn io_task<Channel, Type>(
     tx: mpsc::Sender<Type::InMsg>,
 ) where
     Type: ProtocolEndpoint,
-    Channel: Transport<Type::InMsg, Vec<u8>>,
+    Channel: Transport<Type::InMsg, Outgoing>,
     Channel::Error: std::error::Error + Send,
 {
     pin!(framed);
@@ -312,7 +376,7 @@ async fn io_task<Channel, Type>(
 
 #[derive(Debug, Default)]
 struct OutQueue {
-    messages: VecDeque<Vec<u8>>,
+    messages: VecDeque<Outgoing>,
     used_bytes: usize,
 
     free: Arc<Notify>,
@@ -363,6 +427,8 @@ impl<Type: ProtocolEndpoint> fmt::Debug for SharedState<Type> {
 
 impl<Type: ProtocolEndpoint> SharedState<Type> {
     const MAX_CAPACITY: usize = 1024 * 16;
+    /// Messages that carry more data than this are split, see [`Prioritized::into_chunks`].
+    const MAX_CHUNK_SIZE: usize = 1024 * 16;
     fn new(
         in_tx: mpsc::WeakSender<Type::InMsg>,
         out_filter: Option<Box<FilterFn<Type::InMsg, Type::OutMsg>>>,
@@ -388,8 +454,8 @@ impl<Type: ProtocolEndpoint> SharedState<Type> {
     fn try_push(
         &self,
         queue_id: QueueId,
-        encoded: Vec<u8>,
-    ) -> Result<(), (Vec<u8>, OwnedNotified)> {
+        encoded: Outgoing,
+    ) -> Result<(), (Outgoing, OwnedNotified)> {
         let mut lock = self.queues.lock().unwrap();
 
         // Garbage-collect unused queues
@@ -405,7 +471,7 @@ impl<Type: ProtocolEndpoint> SharedState<Type> {
             return Err((encoded, queue.free.clone().notified_owned()));
         }
 
-        queue.used_bytes += encoded.len();
+        queue.used_bytes += encoded.encoded.len();
         queue.messages.push_back(encoded);
 
         if queue.messages.len() == 1 {
@@ -443,30 +509,49 @@ impl<Type: ProtocolEndpoint> SharedState<Type> {
             }
         }
 
-        let mut encoded = bincode::encode_to_vec(msg, bincode::config::standard()).unwrap();
+        for chunk in msg.into_chunks(Self::MAX_CHUNK_SIZE) {
+            let switches_compression = chunk.switches_compression();
+            let priority = chunk.priority();
+            let mut encoded = Outgoing {
+                encoded: bincode::encode_to_vec(chunk, bincode::config::standard()).unwrap(),
+                switches_compression,
+                priority,
+            };
 
-        loop {
-            match self.try_push(id, encoded) {
-                Ok(()) => break,
-                Err((r, notify)) => {
-                    encoded = r;
-                    notify.await;
+            loop {
+                match self.try_push(id, encoded) {
+                    Ok(()) => break,
+                    Err((r, notify)) => {
+                        encoded = r;
+                        notify.await;
+                    }
                 }
             }
         }
     }
 
-    /// Check for enqueued messages and return one from a
-    /// randomly-picked nonempty queue.
-    fn poll_next(&self) -> Option<Vec<u8>> {
+    /// Check for enqueued messages and return one from a randomly-picked
+    /// nonempty queue, among the ones with the most urgent message at
+    /// their front.
+    fn poll_next(&self) -> Option<Outgoing> {
         let mut lock = self.queues.lock().unwrap();
+        let Queues { queues, ready } = &mut *lock;
+
+        let front_priority = |key: &QueueId| {
+            queues
+                .get(key)
+                .and_then(|queue| queue.messages.front())
+                .map(|message| message.priority)
+        };
 
         // If `ready` is empty then we have nothing to do.
-        let key_idx = (0..lock.ready.len()).choose(&mut rand::rng())?;
-        let key = *lock.ready.get(key_idx).unwrap();
+        let priority = ready.iter().filter_map(front_priority).max()?;
+        let key_idx = (0..ready.len())
+            .filter(|idx| ready.get(*idx).and_then(front_priority) == Some(priority))
+            .choose(&mut rand::rng())?;
+        let key = *ready.get(key_idx).unwrap();
 
-        let queue = lock
-            .queues
+        let queue = queues
             .get_mut(&key)
             .expect("key was in Queues::ready but the corresponding queue was not found");
 
@@ -477,21 +562,21 @@ impl<Type: ProtocolEndpoint> SharedState<Type> {
 
         let was_full = queue.used_bytes >= Self::MAX_CAPACITY;
 
-        queue.used_bytes -= next.len();
+        queue.used_bytes -= next.encoded.len();
 
         if was_full && queue.used_bytes < Self::MAX_CAPACITY {
             queue.free.notify_waiters();
         }
 
         if queue.messages.is_empty() {
-            lock.ready.swap_remove(key_idx);
+            ready.swap_remove(key_idx);
         }
 
         Some(next)
     }
 
     /// Wait for a new message to be enqueued and return it.
-    async fn next(&self) -> Vec<u8> {
+    async fn next(&self) -> Outgoing {
         loop {
             match self.poll_next() {
                 Some(msg) => break msg,
@@ -584,6 +669,18 @@ mod tests {
         }
     }
 
+    impl SwitchesCompression for Message {
+        fn switches_compression(&self) -> Option<CompressionAlgorithm> {
+            None
+        }
+    }
+
+    impl Prioritized for Message {
+        fn priority(&self) -> Priority {
+            Priority::Normal
+        }
+    }
+
     #[derive(Clone)]
     struct Test;
     impl ProtocolEndpoint for Test {
@@ -646,4 +743,148 @@ mod tests {
             assert_eq!(seq.next(), None);
         }
     }
+
+    /// Both ends of a byte stream connection switch compression on in the right moment.
+    #[tokio::test]
+    #[rstest]
+    #[timeout(Duration::from_secs(5))]
+    async fn negotiates_compression(
+        #[values(CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4)]
+        algorithm: CompressionAlgorithm,
+    ) {
+        use mirrord_protocol::{
+            FileRequest, FileResponse,
+            file::{ReadFileResponse, WriteFileRequest},
+        };
+
+        let (client_stream, agent_stream) = tokio::io::duplex(1024);
+        let mut client = Connection::<Client>::from_stream(client_stream)
+            .await
+            .unwrap();
+        let mut agent = Connection::<Agent>::from_stream(agent_stream)
+            .await
+            .unwrap();
+
+        let write = ClientMessage::FileRequest(FileRequest::Write(WriteFileRequest {
+            fd: 1,
+            write_bytes: vec![1; 64 * 1024].into(),
+        }));
+        client
+            .send(ClientMessage::SwitchCompression(algorithm))
+            .await;
+        client.send(write.clone()).await;
+        client.send(ClientMessage::Ping).await;
+
+        assert_eq!(
+            agent.recv().await.unwrap(),
+            ClientMessage::SwitchCompression(algorithm)
+        );
+        assert_eq!(agent.recv().await.unwrap(), write);
+        assert_eq!(agent.recv().await.unwrap(), ClientMessage::Ping);
+
+        let read = DaemonMessage::File(FileResponse::Read(Ok(ReadFileResponse {
+            bytes: vec![2; 64 * 1024].into(),
+            read_amount: 64 * 1024,
+        })));
+        agent
+            .send(DaemonMessage::SwitchCompressionResponse(algorithm))
+            .await;
+        agent.send(read.clone()).await;
+        agent.send(DaemonMessage::Pong).await;
+
+        assert_eq!(
+            client.recv().await.unwrap(),
+            DaemonMessage::SwitchCompressionResponse(algorithm)
+        );
+        assert_eq!(client.recv().await.unwrap(), read);
+        assert_eq!(client.recv().await.unwrap(), DaemonMessage::Pong);
+    }
+
+    /// Control messages overtake bulk transfers, and big messages are split into chunks.
+    #[tokio::test]
+    #[rstest]
+    #[timeout(Duration::from_secs(5))]
+    async fn control_messages_go_first() {
+        use mirrord_protocol::outgoing::{LayerWrite, tcp::LayerTcpOutgoing};
+
+        let write = |len: usize| {
+            ClientMessage::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+                connection_id: 0,
+                bytes: vec![1; len].into(),
+            }))
+        };
+
+        let (connection, _inbound_tx, output) = Connection::<Client>::dummy();
+
+        for _ in 0..10 {
+            let tx = connection.tx_handle();
+            for _ in 0..3 {
+                tx.send(write(4096)).await;
+            }
+        }
+        connection.send(ClientMessage::Ping).await;
+
+        assert_eq!(output.next().await, Some(ClientMessage::Ping));
+        for _ in 0..30 {
+            assert_eq!(output.next().await, Some(write(4096)));
+        }
+
+        let tx = connection.tx_handle();
+        let big = tokio::spawn(async move { tx.send(write(100 * 1024)).await });
+        let mut received = 0;
+        while received < 100 * 1024 {
+            match output.next().await.unwrap() {
+                ClientMessage::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+                    bytes, ..
+                })) => {
+                    assert!(bytes.len() <= SharedState::<Client>::MAX_CHUNK_SIZE);
+                    received += bytes.len();
+                }
+                other => panic!("unexpected message {other:?}"),
+            }
+        }
+        big.await.unwrap();
+    }
+
+    /// The agent's pongs are not stuck behind a stream of big reads, which are split into chunks.
+    #[tokio::test]
+    #[rstest]
+    #[timeout(Duration::from_secs(5))]
+    async fn pong_overtakes_daemon_reads() {
+        use mirrord_protocol::outgoing::{DaemonRead, tcp::DaemonTcpOutgoing};
+
+        let (connection, _inbound_tx, output) = Connection::<Agent>::dummy();
+
+        let tx = connection.tx_handle();
+        let reads = tokio::spawn(async move {
+            for _ in 0..10 {
+                tx.send(DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Read(Ok(
+                    DaemonRead {
+                        connection_id: 0,
+                        bytes: vec![1; 64 * 1024].into(),
+                    },
+                ))))
+                .await;
+            }
+        });
+
+        let next_read = async || match output.next().await.unwrap() {
+            DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Read(Ok(DaemonRead {
+                bytes, ..
+            }))) => {
+                assert!(bytes.len() <= SharedState::<Agent>::MAX_CHUNK_SIZE);
+                bytes.len()
+            }
+            other => panic!("unexpected message {other:?}"),
+        };
+
+        let mut received = next_read().await;
+        connection.send(DaemonMessage::Pong).await;
+        assert_eq!(output.next().await, Some(DaemonMessage::Pong));
+
+        while received < 10 * 64 * 1024 {
+            received += next_read().await;
+        }
+        reads.await.unwrap();
+    }
 }
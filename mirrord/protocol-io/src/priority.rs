@@ -0,0 +1,392 @@
+//! Priority classes and chunking of outgoing messages, see [`Prioritized`].
+
+use mirrord_protocol::{
+    ClientMessage, DaemonMessage, FileRequest, FileResponse, IdentifiedFileRequest,
+    IdentifiedFileResponse, Payload,
+    outgoing::{
+        DaemonRead, LayerWrite,
+        tcp::{DaemonTcpOutgoing, LayerTcpOutgoing},
+        udp::{DaemonUdpOutgoing, LayerUdpOutgoing},
+    },
+    tcp::{
+        ChunkedRequest, ChunkedRequestBodyV1, ChunkedResponse, DaemonTcp, InternalHttpBodyFrame,
+        LayerTcpSteal, TcpData,
+    },
+    vpn::{ClientVpn, ServerVpn},
+};
+
+/// Priority class of an outgoing message.
+///
+/// When picking the next message to send, [`Connection`](crate::Connection) only considers the
+/// queues with the most urgent message at their front. Messages sent through the same queue are
+/// still delivered in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Messages that carry data, e.g. file reads and writes, TCP data and HTTP bodies.
+    Bulk,
+    /// Everything else.
+    Normal,
+    /// Small messages that keep the session alive or block the application, e.g. pings, DNS
+    /// and port subscriptions.
+    Control,
+}
+
+/// Implemented by outgoing message types, allows [`Connection`](crate::Connection) to interleave
+/// control traffic with bulk transfers.
+pub trait Prioritized: Sized {
+    /// Priority class of this message.
+    fn priority(&self) -> Priority;
+
+    /// Splits this message into a sequence of messages with the same meaning, each carrying at
+    /// most `max_chunk` bytes of data.
+    ///
+    /// Messages that can't be split are returned as they are.
+    fn into_chunks(self, _max_chunk: usize) -> Vec<Self> {
+        vec![self]
+    }
+}
+
+impl Prioritized for ClientMessage {
+    fn priority(&self) -> Priority {
+        match self {
+            Self::Close
+            | Self::Ping
+            | Self::OperatorPong(..)
+            | Self::SwitchProtocolVersion(..)
+            | Self::SwitchCompression(..)
+            | Self::ReadyForLogs
+            | Self::PauseTargetRequest(..)
+            | Self::GetEnvVarsRequest(..)
+            | Self::GetAddrInfoRequest(..)
+            | Self::GetAddrInfoRequestV2(..)
+            | Self::ReverseDnsLookup(..)
+            | Self::Tcp(..)
+            | Self::TcpSteal(
+                LayerTcpSteal::PortSubscribe(..)
+                | LayerTcpSteal::PortUnsubscribe(..)
+                | LayerTcpSteal::ConnectionUnsubscribe(..),
+            )
+            | Self::Vpn(ClientVpn::GetNetworkConfiguration | ClientVpn::OpenSocket) => {
+                Priority::Control
+            }
+            Self::TcpSteal(..)
+            | Self::TcpOutgoing(LayerTcpOutgoing::Write(..))
+            | Self::UdpOutgoing(LayerUdpOutgoing::Write(..))
+            | Self::FileRequest(FileRequest::Write(..) | FileRequest::WriteLimited(..))
+            | Self::IdentifiedFileRequest(IdentifiedFileRequest {
+                request: FileRequest::Write(..) | FileRequest::WriteLimited(..),
+                ..
+            })
+            | Self::Vpn(ClientVpn::Packet(..)) => Priority::Bulk,
+            _ => Priority::Normal,
+        }
+    }
+
+    fn into_chunks(self, max_chunk: usize) -> Vec<Self> {
+        match self {
+            Self::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+                connection_id,
+                bytes,
+            })) if bytes.len() > max_chunk => split_payload(bytes, max_chunk)
+                .into_iter()
+                .map(|bytes| {
+                    Self::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+                        connection_id,
+                        bytes,
+                    }))
+                })
+                .collect(),
+            Self::TcpSteal(LayerTcpSteal::Data(TcpData {
+                connection_id,
+                bytes,
+            })) if bytes.len() > max_chunk => split_payload(bytes, max_chunk)
+                .into_iter()
+                .map(|bytes| {
+                    Self::TcpSteal(LayerTcpSteal::Data(TcpData {
+                        connection_id,
+                        bytes,
+                    }))
+                })
+                .collect(),
+            Self::TcpSteal(LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Body(body))) => {
+                split_body(body, max_chunk)
+                    .into_iter()
+                    .map(|body| {
+                        Self::TcpSteal(LayerTcpSteal::HttpResponseChunked(ChunkedResponse::Body(
+                            body,
+                        )))
+                    })
+                    .collect()
+            }
+            other => vec![other],
+        }
+    }
+}
+
+impl Prioritized for DaemonMessage {
+    fn priority(&self) -> Priority {
+        match self {
+            Self::Close(..)
+            | Self::Pong
+            | Self::OperatorPing(..)
+            | Self::SwitchProtocolVersionResponse(..)
+            | Self::SwitchCompressionResponse(..)
+            | Self::PauseTarget(..)
+            | Self::GetEnvVarsResponse(..)
+            | Self::GetAddrInfoResponse(..)
+            | Self::ReverseDnsLookup(..)
+            | Self::Tcp(DaemonTcp::SubscribeResult(..))
+            | Self::TcpSteal(DaemonTcp::SubscribeResult(..))
+            | Self::Vpn(ServerVpn::NetworkConfiguration(..)) => Priority::Control,
+            Self::Tcp(
+                DaemonTcp::Data(..)
+                | DaemonTcp::HttpRequest(..)
+                | DaemonTcp::HttpRequestFramed(..)
+                | DaemonTcp::HttpRequestChunked(ChunkedRequest::Body(..)),
+            )
+            | Self::TcpSteal(
+                DaemonTcp::Data(..)
+                | DaemonTcp::HttpRequest(..)
+                | DaemonTcp::HttpRequestFramed(..)
+                | DaemonTcp::HttpRequestChunked(ChunkedRequest::Body(..)),
+            )
+            | Self::TcpOutgoing(DaemonTcpOutgoing::Read(..))
+            | Self::UdpOutgoing(DaemonUdpOutgoing::Read(..))
+            | Self::File(FileResponse::Read(..) | FileResponse::ReadLimited(..))
+            | Self::IdentifiedFile(IdentifiedFileResponse {
+                response: FileResponse::Read(..) | FileResponse::ReadLimited(..),
+                ..
+            })
+            | Self::Vpn(ServerVpn::Packet(..)) => Priority::Bulk,
+            _ => Priority::Normal,
+        }
+    }
+
+    fn into_chunks(self, max_chunk: usize) -> Vec<Self> {
+        match self {
+            Self::Tcp(DaemonTcp::Data(TcpData {
+                connection_id,
+                bytes,
+            })) if bytes.len() > max_chunk => split_payload(bytes, max_chunk)
+                .into_iter()
+                .map(|bytes| {
+                    Self::Tcp(DaemonTcp::Data(TcpData {
+                        connection_id,
+                        bytes,
+                    }))
+                })
+                .collect(),
+            Self::TcpSteal(DaemonTcp::Data(TcpData {
+                connection_id,
+                bytes,
+            })) if bytes.len() > max_chunk => split_payload(bytes, max_chunk)
+                .into_iter()
+                .map(|bytes| {
+                    Self::TcpSteal(DaemonTcp::Data(TcpData {
+                        connection_id,
+                        bytes,
+                    }))
+                })
+                .collect(),
+            Self::TcpOutgoing(DaemonTcpOutgoing::Read(Ok(DaemonRead {
+                connection_id,
+                bytes,
+            }))) if bytes.len() > max_chunk => split_payload(bytes, max_chunk)
+                .into_iter()
+                .map(|bytes| {
+                    Self::TcpOutgoing(DaemonTcpOutgoing::Read(Ok(DaemonRead {
+                        connection_id,
+                        bytes,
+                    })))
+                })
+                .collect(),
+            Self::TcpSteal(DaemonTcp::HttpRequestChunked(ChunkedRequest::Body(body))) => {
+                split_body(body, max_chunk)
+                    .into_iter()
+                    .map(|body| {
+                        Self::TcpSteal(DaemonTcp::HttpRequestChunked(ChunkedRequest::Body(body)))
+                    })
+                    .collect()
+            }
+            // UDP datagrams and file reads can't be split without changing their meaning.
+            other => vec![other],
+        }
+    }
+}
+
+/// Splits the payload into pieces of at most `max_chunk` bytes, without copying.
+///
+/// Never produces empty pieces from a non-empty payload, as empty data usually means EOF.
+fn split_payload(payload: Payload, max_chunk: usize) -> Vec<Payload> {
+    if payload.len() <= max_chunk {
+        return vec![payload];
+    }
+
+    let bytes = payload.0;
+    (0..bytes.len())
+        .step_by(max_chunk)
+        .map(|start| Payload(bytes.slice(start..bytes.len().min(start + max_chunk))))
+        .collect()
+}
+
+/// Splits the frames of a chunked HTTP body between multiple body messages, each carrying at most
+/// `max_chunk` bytes of data. Only the last message inherits [`ChunkedRequestBodyV1::is_last`].
+fn split_body(body: ChunkedRequestBodyV1, max_chunk: usize) -> Vec<ChunkedRequestBodyV1> {
+    let data_len: usize = body
+        .frames
+        .iter()
+        .map(|frame| match frame {
+            InternalHttpBodyFrame::Data(data) => data.len(),
+            InternalHttpBodyFrame::Trailers(..) => 0,
+        })
+        .sum();
+    if data_len <= max_chunk {
+        return vec![body];
+    }
+
+    let ChunkedRequestBodyV1 {
+        frames,
+        is_last,
+        connection_id,
+        request_id,
+    } = body;
+
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    let mut current_len = 0;
+    for frame in frames {
+        match frame {
+            InternalHttpBodyFrame::Data(data) => {
+                for piece in split_payload(data, max_chunk) {
+                    if current_len + piece.len() > max_chunk && !current.is_empty() {
+                        chunks.push(std::mem::take(&mut current));
+                        current_len = 0;
+                    }
+                    current_len += piece.len();
+                    current.push(InternalHttpBodyFrame::Data(piece));
+                }
+            }
+            trailers @ InternalHttpBodyFrame::Trailers(..) => current.push(trailers),
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    let count = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, frames)| ChunkedRequestBodyV1 {
+            frames,
+            is_last: is_last && index + 1 == count,
+            connection_id,
+            request_id,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Written data is split without losing or reordering any bytes.
+    #[test]
+    fn split_write() {
+        let data = (0..100_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let message = ClientMessage::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+            connection_id: 3,
+            bytes: data.clone().into(),
+        }));
+        assert_eq!(message.priority(), Priority::Bulk);
+
+        let chunks = message.into_chunks(16 * 1024);
+        assert_eq!(chunks.len(), 7);
+
+        let mut reassembled = Vec::new();
+        for chunk in chunks {
+            let ClientMessage::TcpOutgoing(LayerTcpOutgoing::Write(LayerWrite {
+                connection_id: 3,
+                bytes,
+            })) = &chunk
+            else {
+                panic!("unexpected chunk {chunk:?}");
+            };
+            assert!(!bytes.is_empty() && bytes.len() <= 16 * 1024);
+            reassembled.extend_from_slice(&bytes);
+        }
+        assert_eq!(reassembled, data);
+    }
+
+    /// Only the last body message is marked as last, and trailers stay at the end.
+    #[test]
+    fn split_http_body() {
+        let body = ChunkedRequestBodyV1 {
+            frames: vec![
+                InternalHttpBodyFrame::Data(vec![1; 10].into()),
+                InternalHttpBodyFrame::Data(vec![2; 50].into()),
+                InternalHttpBodyFrame::Trailers(Default::default()),
+            ],
+            is_last: true,
+            connection_id: 1,
+            request_id: 2,
+        };
+
+        let chunks = split_body(body, 32);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(
+            chunks.iter().map(|chunk| chunk.is_last).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+        assert!(matches!(
+            chunks.last().unwrap().frames.last(),
+            Some(InternalHttpBodyFrame::Trailers(..))
+        ));
+
+        let data = chunks
+            .iter()
+            .flat_map(|chunk| &chunk.frames)
+            .filter_map(|frame| match frame {
+                InternalHttpBodyFrame::Data(data) => Some(data.to_vec()),
+                InternalHttpBodyFrame::Trailers(..) => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(data, [vec![1; 10], vec![2; 50]].concat());
+    }
+
+    /// Data read from outgoing connections is split, and the pieces stay behind pongs.
+    #[test]
+    fn split_daemon_read() {
+        let message = DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Read(Ok(DaemonRead {
+            connection_id: 1,
+            bytes: vec![0; 40 * 1024].into(),
+        })));
+        assert_eq!(message.priority(), Priority::Bulk);
+        assert!(DaemonMessage::Pong.priority() > Priority::Bulk);
+
+        let chunks = message.into_chunks(16 * 1024);
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| match chunk {
+                    DaemonMessage::TcpOutgoing(DaemonTcpOutgoing::Read(Ok(read))) =>
+                        read.bytes.len(),
+                    other => panic!("unexpected chunk {other:?}"),
+                })
+                .collect::<Vec<_>>(),
+            vec![16 * 1024, 16 * 1024, 8 * 1024]
+        );
+    }
+
+    /// Small messages are never split.
+    #[test]
+    fn small_messages_stay_whole() {
+        let message = ClientMessage::TcpSteal(LayerTcpSteal::Data(TcpData {
+            connection_id: 1,
+            bytes: vec![0; 1024].into(),
+        }));
+        assert_eq!(message.clone().into_chunks(16 * 1024), vec![message]);
+        assert_eq!(ClientMessage::Ping.priority(), Priority::Control);
+    }
+}
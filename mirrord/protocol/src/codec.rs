@@ -118,6 +118,45 @@ pub enum FileRequest {
     Fchmod(FchmodRequest),
 }
 
+/// A [`FileRequest`] tagged with a client-chosen id.
+///
+/// The agent may execute these concurrently, and answers each one with an
+/// [`IdentifiedFileResponse`] carrying the same id, in no particular order. Requests that don't
+/// produce a response ([`FileRequest::Close`] and [`FileRequest::CloseDir`]) are never answered.
+///
+/// Requests that refer to the same remote fd are still executed in the order they were sent.
+///
+/// Allowed since [`FILE_REQUEST_ID_VERSION`](crate::file::FILE_REQUEST_ID_VERSION).
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct IdentifiedFileRequest {
+    pub id: u64,
+    pub request: FileRequest,
+}
+
+/// Response to an [`IdentifiedFileRequest`] with the same id.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
+pub struct IdentifiedFileResponse {
+    pub id: u64,
+    pub response: FileResponse,
+}
+
+/// Algorithm used to compress the messages that follow [`ClientMessage::SwitchCompression`] or
+/// [`DaemonMessage::SwitchCompressionResponse`].
+///
+/// Once compression is switched on, every message sent in that direction is prefixed with a tag
+/// byte. `0` means that a plain bincode-encoded message follows. `1` means that the message is
+/// compressed, and is preceded by its compressed and uncompressed lengths (both as little-endian
+/// `u32`). Small messages can still be sent uncompressed, at the discretion of the sender.
+#[derive(Encode, Decode, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum CompressionAlgorithm {
+    Zstd,
+    Lz4,
+}
+
+/// Minimal mirrord-protocol version that allows [`ClientMessage::SwitchCompression`].
+pub static COMPRESSION_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.28.0".parse().expect("Bad Identifier"));
+
 /// Minimal mirrord-protocol version that allows `ClientMessage::ReadyForLogs` message.
 pub static CLIENT_READY_FOR_LOGS: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.3.1".parse().expect("Bad Identifier"));
@@ -166,6 +205,15 @@ pub enum ClientMessage {
     ///
     /// Sent by the operator when enforcing hostname-based outgoing network policies.
     ReverseDnsLookup(ReverseDnsLookupRequest),
+    /// File request that can be answered out of order, see [`IdentifiedFileRequest`].
+    IdentifiedFileRequest(IdentifiedFileRequest),
+    /// Compresses all following client messages with the given algorithm, see
+    /// [`CompressionAlgorithm`].
+    ///
+    /// The agent answers with [`DaemonMessage::SwitchCompressionResponse`].
+    ///
+    /// Allowed since [`COMPRESSION_VERSION`].
+    SwitchCompression(CompressionAlgorithm),
 }
 
 /// Type alias for `Result`s that should be returned from mirrord-agent to mirrord-layer.
@@ -230,6 +278,11 @@ pub enum DaemonMessage {
     ///
     /// Sent by the agent in response to [`ClientMessage::ReverseDnsLookup`].
     ReverseDnsLookup(RemoteResult<ReverseDnsLookupResponse>),
+    /// Response to [`ClientMessage::IdentifiedFileRequest`].
+    IdentifiedFile(IdentifiedFileResponse),
+    /// Response to [`ClientMessage::SwitchCompression`], compresses all following daemon
+    /// messages with the given algorithm.
+    SwitchCompressionResponse(CompressionAlgorithm),
 }
 
 #[derive(Encode, Decode, PartialEq, Eq, Clone, From, Into, Deref)]
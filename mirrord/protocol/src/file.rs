@@ -49,6 +49,14 @@ pub static RENAME_VERSION: LazyLock<VersionReq> =
 pub static COPYFILE_VERSION: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.24.0".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows [`IdentifiedFileRequest`]s, and with them
+/// out-of-order [`IdentifiedFileResponse`]s.
+///
+/// [`IdentifiedFileRequest`]: crate::codec::IdentifiedFileRequest
+/// [`IdentifiedFileResponse`]: crate::codec::IdentifiedFileResponse
+pub static FILE_REQUEST_ID_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.27.0".parse().expect("Bad Identifier"));
+
 /// Internal version of Metadata across operating system (macOS, Linux)
 /// Only mutual attributes
 #[derive(Encode, Decode, Debug, PartialEq, Clone, Copy, Eq, Default)]
@@ -1,6 +1,6 @@
 use core::fmt::Display;
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     convert::Infallible,
     fmt,
     net::{IpAddr, SocketAddr},
@@ -170,6 +170,38 @@ pub enum HttpRequestMetadata {
         source: SocketAddr,
         destination: SocketAddr,
     },
+    /// Sent only to clients matching [`HTTP_TRACE_CONTEXT_VERSION`].
+    V2 {
+        source: SocketAddr,
+        destination: SocketAddr,
+        /// [W3C trace context](https://www.w3.org/TR/trace-context/) of the agent's span for
+        /// this request (`traceparent` and `tracestate` entries).
+        ///
+        /// Empty when the agent does not export traces.
+        trace_context: HashMap<String, String>,
+    },
+}
+
+impl HttpRequestMetadata {
+    pub fn source(&self) -> SocketAddr {
+        match self {
+            Self::V1 { source, .. } | Self::V2 { source, .. } => *source,
+        }
+    }
+
+    pub fn destination(&self) -> SocketAddr {
+        match self {
+            Self::V1 { destination, .. } | Self::V2 { destination, .. } => *destination,
+        }
+    }
+
+    /// Returns the trace context propagated from the agent, if any.
+    pub fn trace_context(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            Self::V1 { .. } => None,
+            Self::V2 { trace_context, .. } => Some(trace_context).filter(|ctx| !ctx.is_empty()),
+        }
+    }
 }
 
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
@@ -601,6 +633,11 @@ pub static MIRROR_HTTP_FILTER_VERSION: LazyLock<VersionReq> =
 pub static HTTP_BODY_JSON_FILTER_VERSION: LazyLock<VersionReq> =
     LazyLock::new(|| ">=1.23.0".parse().expect("Bad Identifier"));
 
+/// Minimal mirrord-protocol version that allows [`HttpRequestMetadata::V2`], which carries the
+/// trace context of the request.
+pub static HTTP_TRACE_CONTEXT_VERSION: LazyLock<VersionReq> =
+    LazyLock::new(|| ">=1.26.0".parse().expect("Bad Identifier"));
+
 /// Protocol break - on version 2, please add source port, dest/src IP to the message
 /// so we can avoid losing this information.
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
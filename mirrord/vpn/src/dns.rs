@@ -0,0 +1,204 @@
+//! Answers the DNS queries sent through the tunnel for hostnames configured in
+//! `feature.network.dns.overrides`, see [`DnsOverrideResponder`].
+
+use std::{collections::VecDeque, net::IpAddr};
+
+use mirrord_config::feature::network::dns::{DnsOverrideConfig, DnsOverrides, message::DnsQuery};
+use mirrord_protocol::dns::{GetAddrInfoRequest, GetAddrInfoResponse};
+use pnet_packet::{
+    MutablePacket as _, Packet as _,
+    ip::IpNextHeaderProtocols,
+    ipv4::{self, Ipv4Packet, MutableIpv4Packet},
+    ipv6::{Ipv6Packet, MutableIpv6Packet},
+    udp::{self, MutableUdpPacket, UdpPacket},
+};
+
+const DNS_PORT: u16 = 53;
+
+/// Addresses and ports of a UDP packet.
+#[derive(Debug, Clone, Copy)]
+struct Endpoints {
+    source: IpAddr,
+    source_port: u16,
+    destination: IpAddr,
+    destination_port: u16,
+}
+
+/// Parses an IP packet, returns [`None`] if it's not a DNS query sent to port `53`.
+fn parse_query(packet: &[u8]) -> Option<(Endpoints, DnsQuery)> {
+    let (source, destination, protocol, payload) = match packet.first()? >> 4 {
+        4 => {
+            let packet = Ipv4Packet::new(packet)?;
+            (
+                IpAddr::V4(packet.get_source()),
+                IpAddr::V4(packet.get_destination()),
+                packet.get_next_level_protocol(),
+                packet.payload().to_vec(),
+            )
+        }
+        6 => {
+            let packet = Ipv6Packet::new(packet)?;
+            (
+                IpAddr::V6(packet.get_source()),
+                IpAddr::V6(packet.get_destination()),
+                packet.get_next_header(),
+                packet.payload().to_vec(),
+            )
+        }
+        _ => return None,
+    };
+    if protocol != IpNextHeaderProtocols::Udp {
+        return None;
+    }
+
+    let udp = UdpPacket::new(&payload)?;
+    if udp.get_destination() != DNS_PORT {
+        return None;
+    }
+    let endpoints = Endpoints {
+        source,
+        source_port: udp.get_source(),
+        destination,
+        destination_port: udp.get_destination(),
+    };
+
+    Some((endpoints, DnsQuery::parse(udp.payload())?))
+}
+
+/// Builds an IP packet with the UDP `message`, going back to the source of the given
+/// `endpoints`.
+fn udp_packet(endpoints: Endpoints, message: Vec<u8>) -> Option<Vec<u8>> {
+    let udp_length = 8 + message.len();
+
+    let mut udp_buffer = vec![0; udp_length];
+    let mut udp = MutableUdpPacket::new(&mut udp_buffer)?;
+    udp.set_source(endpoints.destination_port);
+    udp.set_destination(endpoints.source_port);
+    udp.set_length(u16::try_from(udp_length).ok()?);
+    udp.set_payload(&message);
+
+    match (endpoints.destination, endpoints.source) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            udp.set_checksum(udp::ipv4_checksum(
+                &udp.to_immutable(),
+                &source,
+                &destination,
+            ));
+
+            let mut buffer = vec![0; 20 + udp_length];
+            let mut packet = MutableIpv4Packet::new(&mut buffer)?;
+            packet.set_version(4);
+            packet.set_header_length(5);
+            packet.set_total_length(u16::try_from(20 + udp_length).ok()?);
+            packet.set_ttl(64);
+            packet.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+            packet.set_source(source);
+            packet.set_destination(destination);
+            packet.payload_mut().copy_from_slice(&udp_buffer);
+            packet.set_checksum(ipv4::checksum(&packet.to_immutable()));
+
+            Some(buffer)
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            udp.set_checksum(udp::ipv6_checksum(
+                &udp.to_immutable(),
+                &source,
+                &destination,
+            ));
+
+            let mut buffer = vec![0; 40 + udp_length];
+            let mut packet = MutableIpv6Packet::new(&mut buffer)?;
+            packet.set_version(6);
+            packet.set_payload_length(u16::try_from(udp_length).ok()?);
+            packet.set_next_header(IpNextHeaderProtocols::Udp);
+            packet.set_hop_limit(64);
+            packet.set_source(source);
+            packet.set_destination(destination);
+            packet.payload_mut().copy_from_slice(&udp_buffer);
+
+            Some(buffer)
+        }
+        _ => None,
+    }
+}
+
+/// What to do with a packet sent through the tunnel.
+#[derive(Debug)]
+pub enum DnsOverrideAction {
+    /// Not an overridden DNS query, send it to the agent.
+    Forward,
+    /// Send this response back through the tunnel.
+    Respond(Vec<u8>),
+    /// Send this request to the agent, and pass its response to
+    /// [`DnsOverrideResponder::resolved`].
+    Resolve(GetAddrInfoRequest),
+}
+
+/// Answers the DNS queries for hostnames configured in `feature.network.dns.overrides`.
+///
+/// Addresses are returned right away. A CNAME target is resolved with a [`GetAddrInfoRequest`]
+/// to the agent, and returned with the CNAME record.
+#[derive(Debug)]
+pub struct DnsOverrideResponder {
+    overrides: DnsOverrides,
+    /// Queries waiting for the agent to resolve their CNAME target, in request order.
+    pending: VecDeque<(Endpoints, DnsQuery, String)>,
+}
+
+impl DnsOverrideResponder {
+    pub fn new(overrides: DnsOverrides) -> Self {
+        Self {
+            overrides,
+            pending: Default::default(),
+        }
+    }
+
+    /// Checks a packet sent through the tunnel.
+    pub fn intercept(&mut self, packet: &[u8]) -> DnsOverrideAction {
+        if self.overrides.is_empty() {
+            return DnsOverrideAction::Forward;
+        }
+        let Some((endpoints, query)) = parse_query(packet) else {
+            return DnsOverrideAction::Forward;
+        };
+        let Some(dns_override) = self.overrides.resolve(query.name()) else {
+            return DnsOverrideAction::Forward;
+        };
+        tracing::debug!(name = %query.name(), ?dns_override, "Overriding DNS query");
+
+        let response = match dns_override {
+            DnsOverrideConfig::Addresses(addresses) => query.response(None, addresses),
+            DnsOverrideConfig::Cname { cname } if query.is_address_query() => {
+                let request = GetAddrInfoRequest {
+                    node: cname.clone(),
+                };
+                self.pending.push_back((endpoints, query, cname.clone()));
+                return DnsOverrideAction::Resolve(request);
+            }
+            DnsOverrideConfig::Cname { cname } => query.response(Some(cname), &[]),
+        };
+
+        match response.and_then(|response| udp_packet(endpoints, response)) {
+            Some(response) => DnsOverrideAction::Respond(response),
+            None => DnsOverrideAction::Forward,
+        }
+    }
+
+    /// Handles the agent's response to a [`DnsOverrideAction::Resolve`] request, returns the
+    /// response to send back through the tunnel.
+    pub fn resolved(&mut self, response: GetAddrInfoResponse) -> Option<Vec<u8>> {
+        let (endpoints, query, cname) = self.pending.pop_front()?;
+
+        let addresses = match response.0 {
+            Ok(lookup) => lookup.iter().map(|record| record.ip).collect(),
+            Err(error) => {
+                tracing::warn!(%error, %cname, "Failed to resolve the CNAME of a DNS override");
+                Vec::new()
+            }
+        };
+
+        query
+            .response(Some(&cname), &addresses)
+            .and_then(|response| udp_packet(endpoints, response))
+    }
+}
@@ -8,6 +8,7 @@ pub mod macos;
 
 pub mod agent;
 pub mod config;
+pub mod dns;
 pub mod error;
 pub mod packet;
 pub mod socket;
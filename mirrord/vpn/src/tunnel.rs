@@ -1,13 +1,19 @@
 use std::{io, time::Duration};
 
 use futures::{Sink, SinkExt, Stream, StreamExt};
-use mirrord_protocol::{DaemonMessage, vpn::ServerVpn};
+use mirrord_config::feature::network::dns::DnsOverrides;
+use mirrord_protocol::{ClientMessage, DaemonMessage, vpn::ServerVpn};
 
-use crate::{agent::VpnAgent, error::VpnError};
+use crate::{
+    agent::VpnAgent,
+    dns::{DnsOverrideAction, DnsOverrideResponder},
+    error::VpnError,
+};
 
 pub struct VpnTunnel<S> {
     agent: VpnAgent,
     stream: S,
+    dns: DnsOverrideResponder,
 }
 
 impl<S> VpnTunnel<S>
@@ -15,11 +21,26 @@ where
     S: Stream<Item = io::Result<Vec<u8>>> + Sink<Vec<u8>, Error = io::Error>,
 {
     pub fn new(agent: VpnAgent, stream: S) -> Self {
-        VpnTunnel { agent, stream }
+        VpnTunnel {
+            agent,
+            stream,
+            dns: DnsOverrideResponder::new(Default::default()),
+        }
+    }
+
+    /// Answers the DNS queries for the overridden hostnames, instead of sending them to the
+    /// cluster.
+    pub fn with_dns_overrides(mut self, overrides: DnsOverrides) -> Self {
+        self.dns = DnsOverrideResponder::new(overrides);
+        self
     }
 
     pub async fn start(self) -> Result<(), VpnError> {
-        let VpnTunnel { mut agent, stream } = self;
+        let VpnTunnel {
+            mut agent,
+            stream,
+            mut dns,
+        } = self;
         tokio::pin!(stream);
 
         let mut ping_interval = tokio::time::interval(Duration::from_secs(30));
@@ -33,7 +54,17 @@ where
             tokio::select! {
                 packet = stream.next() => {
                     let packet = packet.unwrap().unwrap();
-                    agent.send_packet(packet).await
+                    match dns.intercept(&packet) {
+                        DnsOverrideAction::Forward => agent.send_packet(packet).await,
+                        DnsOverrideAction::Respond(response) => {
+                            if let Err(error) = stream.send(response).await {
+                                tracing::warn!(%error, "unable to send DNS override response")
+                            }
+                        }
+                        DnsOverrideAction::Resolve(request) => {
+                            agent.send(ClientMessage::GetAddrInfoRequest(request)).await
+                        }
+                    }
                 }
                 message = agent.next() => {
                     match message {
@@ -43,6 +74,13 @@ where
                                 tracing::warn!(%error, "unable to pipe back packet")
                             }
                         }
+                        Some(DaemonMessage::GetAddrInfoResponse(response)) => {
+                            if let Some(response) = dns.resolved(response)
+                                && let Err(error) = stream.send(response).await
+                            {
+                                tracing::warn!(%error, "unable to send DNS override response")
+                            }
+                        }
                         _ => unimplemented!("Unexpected response from agent"),
                     }
                 }